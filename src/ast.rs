@@ -1,115 +1,895 @@
-use crate::source::{Location, HasLoc};
+use std::ops::{Index, IndexMut};
 
+use crate::source::{Span, FileId, HasLoc};
 
+
+/// The files of a program, indexed by their `FileId`s.
 pub struct Program {
     pub files: Vec<File>
 }
 
+/// A parsed file. Its statements, expressions, types and names are allocated in arenas, one for each kind of
+/// node, and nodes refer to their children by id. Each node also knows its parent.
 pub struct File {
-    pub top_levels: Vec<Box<TopLevelNode>>
+    pub file: FileId,
+    pub top_levels: Vec<TopLevelNode>,
+    pub stmts: Nodes<StmtNode>,
+    pub exprs: Nodes<ExprNode>,
+    pub types: Nodes<TypeNode>,
+    pub names: Nodes<QualifiedNameNode>
+}
+
+/// Identifies a top-level declaration by its file and its position in the file.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct TopLevelId {
+    pub file: FileId,
+    pub index: u32
+}
+
+/// Identifies a statement by its file and its position in the file's arena of statements.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct StmtId {
+    pub file: FileId,
+    pub index: u32
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct ExprId {
+    pub file: FileId,
+    pub index: u32
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct TypeId {
+    pub file: FileId,
+    pub index: u32
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct NameId {
+    pub file: FileId,
+    pub index: u32
+}
+
+/// Identifies a node of any kind.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum NodeId {
+    TopLevel(TopLevelId),
+    Stmt(StmtId),
+    Expr(ExprId),
+    Type(TypeId),
+    Name(NameId)
+}
+
+/// Identifies a named declaration: a top-level declaration, or a method of the struct or trait at a top level
+/// by its position among the methods.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum ItemId {
+    TopLevel(TopLevelId),
+    Method(TopLevelId, u32)
+}
+
+/// The nodes of one kind in a file, in the order they were allocated, with the node each one is a child of.
+pub struct Nodes<T> {
+    nodes: Vec<T>,
+    parents: Vec<Option<NodeId>>
+}
+
+impl<T> Nodes<T> {
+    fn new() -> Nodes<T> {
+        Nodes { nodes: Vec::new(), parents: Vec::new() }
+    }
+
+    fn push(&mut self, node: T) -> u32 {
+        self.nodes.push(node);
+        self.parents.push(None);
+        return self.nodes.len() as u32 - 1;
+    }
+}
+
+impl File {
+    pub fn new(file: FileId) -> File {
+        File { file, top_levels: Vec::new(), stmts: Nodes::new(), exprs: Nodes::new(), types: Nodes::new(), names: Nodes::new() }
+    }
+
+    pub fn alloc_stmt(&mut self, node: StmtNode) -> StmtId {
+        StmtId { file: self.file, index: self.stmts.push(node) }
+    }
+
+    pub fn alloc_expr(&mut self, node: ExprNode) -> ExprId {
+        ExprId { file: self.file, index: self.exprs.push(node) }
+    }
+
+    pub fn alloc_type(&mut self, node: TypeNode) -> TypeId {
+        TypeId { file: self.file, index: self.types.push(node) }
+    }
+
+    pub fn alloc_name(&mut self, node: QualifiedNameNode) -> NameId {
+        NameId { file: self.file, index: self.names.push(node) }
+    }
+
+    pub fn top_level_ids(&self) -> impl Iterator<Item=TopLevelId> + Clone + '_ {
+        (0..self.top_levels.len() as u32).map(|index| TopLevelId { file: self.file, index })
+    }
+
+    /// Every node in the file, top-level declarations first and then each arena in allocation order.
+    pub fn node_ids(&self) -> impl Iterator<Item=NodeId> + '_ {
+        let file = self.file;
+        self.top_level_ids().map(NodeId::TopLevel)
+            .chain((0..self.stmts.nodes.len() as u32).map(move |index| NodeId::Stmt(StmtId { file, index })))
+            .chain((0..self.exprs.nodes.len() as u32).map(move |index| NodeId::Expr(ExprId { file, index })))
+            .chain((0..self.types.nodes.len() as u32).map(move |index| NodeId::Type(TypeId { file, index })))
+            .chain((0..self.names.nodes.len() as u32).map(move |index| NodeId::Name(NameId { file, index })))
+    }
+
+    /// The node a node is a child of. Top-level declarations have no parent, and the nodes in the signatures
+    /// and bodies of methods are children of their struct or trait.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        match id {
+            NodeId::TopLevel(_) => None,
+            NodeId::Stmt(id) => self.stmts.parents[id.index as usize],
+            NodeId::Expr(id) => self.exprs.parents[id.index as usize],
+            NodeId::Type(id) => self.types.parents[id.index as usize],
+            NodeId::Name(id) => self.names.parents[id.index as usize]
+        }
+    }
+
+    /// Records the parent of every node. The parser allocates children before their parents, so this is done
+    /// once the whole file has been parsed.
+    pub fn link_parents(&mut self) {
+        let ids: Vec<NodeId> = self.node_ids().collect();
+        let mut children = Vec::new();
+        for parent in ids {
+            children.clear();
+            self.push_children(parent, &mut children);
+            for &child in &children {
+                let parents = match child {
+                    NodeId::TopLevel(_) => continue,
+                    NodeId::Stmt(id) => &mut self.stmts.parents[id.index as usize],
+                    NodeId::Expr(id) => &mut self.exprs.parents[id.index as usize],
+                    NodeId::Type(id) => &mut self.types.parents[id.index as usize],
+                    NodeId::Name(id) => &mut self.names.parents[id.index as usize]
+                };
+                *parents = Some(parent);
+            }
+        }
+    }
+
+    /// The direct children of a node, in the order they appear in the source.
+    pub fn children(&self, id: NodeId) -> Vec<NodeId> {
+        let mut children = Vec::new();
+        self.push_children(id, &mut children);
+        return children;
+    }
+
+    fn push_children(&self, id: NodeId, children: &mut Vec<NodeId>) {
+        match id {
+            NodeId::TopLevel(id) => match &self[id] {
+                TopLevelNode::Import(data) => children.push(NodeId::Name(data.path)),
+                TopLevelNode::Struct(data) => {
+                    generic_children(&data.generic_parameters, children);
+                    children.extend(data.superstruct.map(NodeId::Name));
+                    children.extend(data.interfaces.iter().copied().map(NodeId::Name));
+                    children.extend(data.fields.iter().map(|field| NodeId::Type(field.typ)));
+                    for method in &data.methods {
+                        function_children(method, children);
+                    }
+                },
+                TopLevelNode::Trait(data) => {
+                    for method in &data.methods {
+                        children.extend(method.parameters.iter().map(|p| NodeId::Type(p.typ)));
+                        children.push(NodeId::Type(method.ret));
+                    }
+                },
+                TopLevelNode::Function(data) => function_children(data, children),
+                TopLevelNode::Const(data) => children.extend([NodeId::Type(data.typ), NodeId::Expr(data.value)])
+            },
+            NodeId::Stmt(id) => match &self[id] {
+                StmtNode::Expr(data) => children.push(NodeId::Expr(data.expr)),
+                StmtNode::Let(data) => {
+                    children.extend(data.typ.map(NodeId::Type));
+                    children.extend(data.value.map(NodeId::Expr));
+                },
+                StmtNode::Return(data) => children.push(NodeId::Expr(data.expr)),
+                StmtNode::If(data) => {
+                    children.push(NodeId::Expr(data.condition));
+                    block_children(&data.body, children);
+                    if let Some(else_body) = &data.else_body {
+                        block_children(else_body, children);
+                    }
+                },
+                StmtNode::While(data) => {
+                    children.push(NodeId::Expr(data.condition));
+                    block_children(&data.body, children);
+                },
+                StmtNode::For(data) => {
+                    match data.iterable {
+                        ForIterable::Range(start, end) => children.extend([NodeId::Expr(start), NodeId::Expr(end)]),
+                        ForIterable::Array(array) => children.push(NodeId::Expr(array))
+                    }
+                    block_children(&data.body, children);
+                }
+            },
+            NodeId::Expr(id) => match &self[id] {
+                ExprNode::Name(data) => children.push(NodeId::Name(data.name)),
+                ExprNode::Integer(_) | ExprNode::Float(_) | ExprNode::Bool(_) | ExprNode::Char(_) | ExprNode::String(_) => { },
+                ExprNode::Block(data) => block_children(data, children),
+                ExprNode::Call(data) => {
+                    children.push(NodeId::Expr(data.callee));
+                    children.extend(data.arguments.iter().copied().map(NodeId::Expr));
+                },
+                ExprNode::Field(data) => children.push(NodeId::Expr(data.object)),
+                ExprNode::Assign(data) => children.extend([NodeId::Expr(data.target), NodeId::Expr(data.value)]),
+                ExprNode::StructLiteral(data) => {
+                    children.push(NodeId::Name(data.name));
+                    children.extend(data.fields.iter().map(|field| NodeId::Expr(field.value)));
+                },
+                ExprNode::Unary(data) => children.push(NodeId::Expr(data.operand)),
+                ExprNode::Binary(data) => children.extend([NodeId::Expr(data.left), NodeId::Expr(data.right)]),
+                ExprNode::Cast(data) => children.extend([NodeId::Expr(data.expr), NodeId::Type(data.typ)]),
+                ExprNode::Is(data) => children.extend([NodeId::Expr(data.expr), NodeId::Type(data.typ)]),
+                ExprNode::Lambda(data) => {
+                    children.extend(data.parameters.iter().filter_map(|p| p.typ).map(NodeId::Type));
+                    children.push(NodeId::Expr(data.body));
+                },
+                ExprNode::Array(data) => children.extend(data.elements.iter().copied().map(NodeId::Expr)),
+                ExprNode::Index(data) => children.extend([NodeId::Expr(data.object), NodeId::Expr(data.index)])
+            },
+            NodeId::Type(id) => match &self[id] {
+                TypeNode::Name(data) => {
+                    children.push(NodeId::Name(data.name));
+                    children.extend(data.generic_arguments.iter().flatten().copied().map(NodeId::Type));
+                },
+                TypeNode::Function(data) => {
+                    children.extend(data.arguments.iter().copied().map(NodeId::Type));
+                    children.push(NodeId::Type(data.ret));
+                },
+                TypeNode::Reference(data) => children.push(NodeId::Type(data.typ)),
+                TypeNode::Array(data) => children.extend([NodeId::Type(data.element), NodeId::Expr(data.length)])
+            },
+            NodeId::Name(id) => match &self[id] {
+                QualifiedNameNode::Name(_) => { },
+                QualifiedNameNode::Namespace(data) => children.push(NodeId::Name(data.source))
+            }
+        }
+    }
+
+    /// The span of any node in the file.
+    pub fn loc(&self, id: impl Into<NodeId>) -> Span {
+        match id.into() {
+            NodeId::TopLevel(id) => self[id].get_loc(),
+            NodeId::Stmt(id) => self[id].get_loc(),
+            NodeId::Expr(id) => self[id].get_loc(),
+            NodeId::Type(id) => self[id].get_loc(),
+            NodeId::Name(id) => self[id].get_loc()
+        }
+    }
+}
+
+fn generic_children(parameters: &[GenericParameter], children: &mut Vec<NodeId>) {
+    children.extend(parameters.iter().flat_map(|p| &p.bounds).copied().map(NodeId::Name));
+}
+
+fn function_children(data: &FunctionData, children: &mut Vec<NodeId>) {
+    generic_children(&data.generic_parameters, children);
+    children.extend(data.parameters.iter().map(|p| NodeId::Type(p.typ)));
+    children.push(NodeId::Type(data.ret));
+    block_children(&data.body, children);
+}
+
+fn block_children(block: &BlockData, children: &mut Vec<NodeId>) {
+    children.extend(block.stmts.iter().copied().map(NodeId::Stmt));
+}
+
+impl Program {
+    pub fn file(&self, file: FileId) -> &File {
+        &self.files[file.0 as usize]
+    }
+
+    /// The top-level declarations of every file, in order.
+    pub fn top_level_ids(&self) -> impl Iterator<Item=TopLevelId> + Clone + '_ {
+        self.files.iter().flat_map(|file| file.top_level_ids())
+    }
+
+    /// The node a node is a child of. The compiler's passes walk down the tree and do not need this, but
+    /// tools that start from a node, such as one found by its span, do.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn parent(&self, id: impl Into<NodeId>) -> Option<NodeId> {
+        let id = id.into();
+        self.file(id.file()).parent(id)
+    }
+
+    /// The direct children of a node, in the order they appear in the source.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn children(&self, id: impl Into<NodeId>) -> Vec<NodeId> {
+        let id = id.into();
+        self.file(id.file()).children(id)
+    }
+
+    /// The span of any node in the program.
+    pub fn loc(&self, id: impl Into<NodeId>) -> Span {
+        let id = id.into();
+        self.file(id.file()).loc(id)
+    }
+}
+
+impl From<TopLevelId> for NodeId {
+    fn from(id: TopLevelId) -> NodeId { NodeId::TopLevel(id) }
+}
+
+impl From<StmtId> for NodeId {
+    fn from(id: StmtId) -> NodeId { NodeId::Stmt(id) }
+}
+
+impl From<ExprId> for NodeId {
+    fn from(id: ExprId) -> NodeId { NodeId::Expr(id) }
+}
+
+impl From<TypeId> for NodeId {
+    fn from(id: TypeId) -> NodeId { NodeId::Type(id) }
+}
+
+impl From<NameId> for NodeId {
+    fn from(id: NameId) -> NodeId { NodeId::Name(id) }
+}
+
+impl NodeId {
+    pub fn file(&self) -> FileId {
+        match self {
+            NodeId::TopLevel(id) => id.file,
+            NodeId::Stmt(id) => id.file,
+            NodeId::Expr(id) => id.file,
+            NodeId::Type(id) => id.file,
+            NodeId::Name(id) => id.file
+        }
+    }
+}
+
+impl Index<TopLevelId> for File {
+    type Output = TopLevelNode;
+
+    fn index(&self, id: TopLevelId) -> &TopLevelNode {
+        &self.top_levels[id.index as usize]
+    }
+}
+
+impl Index<StmtId> for File {
+    type Output = StmtNode;
+
+    fn index(&self, id: StmtId) -> &StmtNode {
+        &self.stmts.nodes[id.index as usize]
+    }
+}
+
+impl Index<ExprId> for File {
+    type Output = ExprNode;
+
+    fn index(&self, id: ExprId) -> &ExprNode {
+        &self.exprs.nodes[id.index as usize]
+    }
+}
+
+impl Index<TypeId> for File {
+    type Output = TypeNode;
+
+    fn index(&self, id: TypeId) -> &TypeNode {
+        &self.types.nodes[id.index as usize]
+    }
+}
+
+impl Index<NameId> for File {
+    type Output = QualifiedNameNode;
+
+    fn index(&self, id: NameId) -> &QualifiedNameNode {
+        &self.names.nodes[id.index as usize]
+    }
+}
+
+impl IndexMut<TopLevelId> for File {
+    fn index_mut(&mut self, id: TopLevelId) -> &mut TopLevelNode {
+        &mut self.top_levels[id.index as usize]
+    }
+}
+
+impl IndexMut<StmtId> for File {
+    fn index_mut(&mut self, id: StmtId) -> &mut StmtNode {
+        &mut self.stmts.nodes[id.index as usize]
+    }
+}
+
+impl IndexMut<ExprId> for File {
+    fn index_mut(&mut self, id: ExprId) -> &mut ExprNode {
+        &mut self.exprs.nodes[id.index as usize]
+    }
+}
+
+impl IndexMut<TypeId> for File {
+    fn index_mut(&mut self, id: TypeId) -> &mut TypeNode {
+        &mut self.types.nodes[id.index as usize]
+    }
+}
+
+impl IndexMut<NameId> for File {
+    fn index_mut(&mut self, id: NameId) -> &mut QualifiedNameNode {
+        &mut self.names.nodes[id.index as usize]
+    }
+}
+
+impl Index<TopLevelId> for Program {
+    type Output = TopLevelNode;
+
+    fn index(&self, id: TopLevelId) -> &TopLevelNode {
+        &self.file(id.file)[id]
+    }
+}
+
+impl Index<StmtId> for Program {
+    type Output = StmtNode;
+
+    fn index(&self, id: StmtId) -> &StmtNode {
+        &self.file(id.file)[id]
+    }
+}
+
+impl Index<ExprId> for Program {
+    type Output = ExprNode;
+
+    fn index(&self, id: ExprId) -> &ExprNode {
+        &self.file(id.file)[id]
+    }
+}
+
+impl Index<TypeId> for Program {
+    type Output = TypeNode;
+
+    fn index(&self, id: TypeId) -> &TypeNode {
+        &self.file(id.file)[id]
+    }
+}
+
+impl Index<NameId> for Program {
+    type Output = QualifiedNameNode;
+
+    fn index(&self, id: NameId) -> &QualifiedNameNode {
+        &self.file(id.file)[id]
+    }
+}
+
+impl IndexMut<TopLevelId> for Program {
+    fn index_mut(&mut self, id: TopLevelId) -> &mut TopLevelNode {
+        &mut self.files[id.file.0 as usize][id]
+    }
+}
+
+impl IndexMut<StmtId> for Program {
+    fn index_mut(&mut self, id: StmtId) -> &mut StmtNode {
+        &mut self.files[id.file.0 as usize][id]
+    }
+}
+
+impl IndexMut<ExprId> for Program {
+    fn index_mut(&mut self, id: ExprId) -> &mut ExprNode {
+        &mut self.files[id.file.0 as usize][id]
+    }
+}
+
+impl IndexMut<TypeId> for Program {
+    fn index_mut(&mut self, id: TypeId) -> &mut TypeNode {
+        &mut self.files[id.file.0 as usize][id]
+    }
+}
+
+impl IndexMut<NameId> for Program {
+    fn index_mut(&mut self, id: NameId) -> &mut QualifiedNameNode {
+        &mut self.files[id.file.0 as usize][id]
+    }
 }
 
 pub enum TopLevelNode {
     Import(ImportData),
     Struct(StructData),
-    Function(FunctionData)
+    Trait(TraitData),
+    Function(FunctionData),
+    Const(ConstData)
 }
 
 pub struct ImportData {
-    pub loc: Location,
+    pub loc: Span,
+    pub attributes: Vec<Attribute>,
+    pub path: NameId,
+    /// The file the import refers to, filled in once it has been loaded.
+    pub file: Option<FileId>
+}
+
+impl ImportData {
+    /// The name the imported file is bound to in the importing file, which is the last segment of its path.
+    pub fn bound_name<'a>(&self, file: &'a File) -> &'a str {
+        match &file[self.path] {
+            QualifiedNameNode::Name(n) => &n.name,
+            QualifiedNameNode::Namespace(n) => &n.attr
+        }
+    }
+}
+
+/// An item-level attribute such as `#[allow(unused_locals, unused_imports)]`.
+#[derive(Clone)]
+pub struct Attribute {
+    pub loc: Span,
+    pub name: String,
+    pub arguments: Vec<QualNameData>
 }
 
+/// A generic parameter such as `T: Shape + Named`, with the traits its arguments must implement.
 pub struct GenericParameter {
-    pub loc: Location,
+    pub loc: Span,
     pub name: String,
-    pub bound: Option<Box<TypeNode>>
+    pub bounds: Vec<NameId>
 }
 
 pub struct StructData {
-    pub loc: Location,
+    pub loc: Span,
+    pub attributes: Vec<Attribute>,
     pub name: String,
-    pub generic_parameters: Vec<Box<GenericParameter>>,
-    pub superstruct: Option<Box<QualifiedNameNode>>,
-    pub interfaces: Vec<Box<QualifiedNameNode>>,
-    pub fields: Vec<Box<StructField>>
+    pub name_loc: Span,
+    pub generic_parameters: Vec<GenericParameter>,
+    pub superstruct: Option<NameId>,
+    /// The traits the struct implements, listed after a ':' following its name.
+    pub interfaces: Vec<NameId>,
+    pub fields: Vec<StructField>,
+    pub methods: Vec<FunctionData>
 }
 
 pub struct StructField {
-    pub loc: Location,
+    pub loc: Span,
+    pub name: String,
+    pub typ: TypeId
+}
+
+/// A trait such as `trait Shape { fn area(self: Self&) -> int; }`, which lists the methods that the
+/// structs implementing it must have. `Self` stands for the implementing struct.
+pub struct TraitData {
+    pub loc: Span,
+    pub attributes: Vec<Attribute>,
     pub name: String,
-    pub typ: Box<TypeNode>
+    pub name_loc: Span,
+    pub methods: Vec<TraitMethod>
+}
+
+pub struct TraitMethod {
+    pub loc: Span,
+    pub name: String,
+    pub name_loc: Span,
+    pub parameters: Vec<FunctionParameter>,
+    pub ret: TypeId
 }
 
 pub struct FunctionData {
-    pub loc: Location,
+    pub loc: Span,
+    pub attributes: Vec<Attribute>,
     pub name: String,
-    pub generic_parameters: Vec<Box<GenericParameter>>,
-    pub parameters: Vec<Box<FunctionParameter>>,
-    pub ret: Box<TypeNode>,
+    pub name_loc: Span,
+    pub generic_parameters: Vec<GenericParameter>,
+    pub parameters: Vec<FunctionParameter>,
+    pub ret: TypeId,
     pub body: BlockData
 }
 
+/// A constant such as `const SIZE: u32 = 4 * 1024;`, whose value is computed while compiling. The value may only
+/// use literals, other constants, arithmetic, comparisons and casts.
+pub struct ConstData {
+    pub loc: Span,
+    pub attributes: Vec<Attribute>,
+    pub name: String,
+    pub name_loc: Span,
+    pub typ: TypeId,
+    pub value: ExprId
+}
+
 pub struct FunctionParameter {
-    pub loc: Location,
+    pub loc: Span,
     pub name: String,
-    pub typ: Box<TypeNode>
+    pub typ: TypeId
 }
 
 
 impl HasLoc for TopLevelNode {
-    fn get_loc(& self) -> &Location {
+    fn get_loc(&self) -> Span {
         match self {
-            TopLevelNode::Import(n) => &n.loc,
-            TopLevelNode::Struct(n) => &n.loc,
-            TopLevelNode::Function(n) => &n.loc
+            TopLevelNode::Import(n) => n.loc,
+            TopLevelNode::Struct(n) => n.loc,
+            TopLevelNode::Trait(n) => n.loc,
+            TopLevelNode::Function(n) => n.loc,
+            TopLevelNode::Const(n) => n.loc
         }
     }
 }
 
 pub enum StmtNode {
     Expr(StmtExprData),
-    Return(StmtReturnData)
+    Let(StmtLetData),
+    Return(StmtReturnData),
+    If(StmtIfData),
+    While(StmtWhileData),
+    For(StmtForData)
+}
+
+/// A local variable declaration. A variable declared without a value, as in `let x: int;`, must be
+/// assigned before it is read.
+pub struct StmtLetData {
+    pub loc: Span,
+    pub name: String,
+    pub name_loc: Span,
+    pub typ: Option<TypeId>,
+    pub value: Option<ExprId>
 }
 
 pub struct StmtExprData {
-    pub loc: Location,
-    pub expr: Box<ExprNode>
+    pub loc: Span,
+    pub expr: ExprId
 }
 
 pub struct StmtReturnData {
-    pub loc: Location,
-    pub expr: Box<ExprNode>
+    pub loc: Span,
+    pub expr: ExprId
+}
+
+/// `if condition { ... } else { ... }`. For `else if`, the `else` block holds only the nested `if`.
+pub struct StmtIfData {
+    pub loc: Span,
+    pub condition: ExprId,
+    pub body: BlockData,
+    pub else_body: Option<BlockData>
+}
+
+pub struct StmtWhileData {
+    pub loc: Span,
+    pub condition: ExprId,
+    pub body: BlockData
+}
+
+/// `for name in iterable { ... }`, which runs the body once for each value of the iterable, with `name` holding it.
+pub struct StmtForData {
+    pub loc: Span,
+    pub name: String,
+    pub name_loc: Span,
+    pub iterable: ForIterable,
+    pub body: BlockData
+}
+
+pub enum ForIterable {
+    /// The integers from the first expression up to but not including the second, as in `0..n`. Both are
+    /// evaluated once, before the loop starts.
+    Range(ExprId, ExprId),
+    /// The elements of an array, in order.
+    Array(ExprId)
+}
+
+
+impl HasLoc for StmtNode {
+    fn get_loc(&self) -> Span {
+        match self {
+            StmtNode::Expr(n) => n.loc,
+            StmtNode::Let(n) => n.loc,
+            StmtNode::Return(n) => n.loc,
+            StmtNode::If(n) => n.loc,
+            StmtNode::While(n) => n.loc,
+            StmtNode::For(n) => n.loc
+        }
+    }
 }
 
 
 pub enum ExprNode {
     Name(NameData),
     Integer(IntegerData),
-    Block(BlockData)
+    Float(FloatData),
+    Bool(BoolData),
+    Char(CharData),
+    String(StringData),
+    Block(BlockData),
+    Call(CallData),
+    Field(FieldData),
+    Assign(AssignData),
+    StructLiteral(StructLiteralData),
+    Unary(UnaryData),
+    Binary(BinaryData),
+    Cast(CastData),
+    Is(IsData),
+    Lambda(LambdaData),
+    Array(ArrayData),
+    Index(IndexData)
 }
 
 pub struct NameData {
-    pub loc: Location,
-    pub name: QualNameData
+    pub loc: Span,
+    pub name: NameId
 }
 
 pub struct IntegerData {
-    pub loc: Location,
+    pub loc: Span,
     pub integer: u64
 }
 
+pub struct FloatData {
+    pub loc: Span,
+    pub float: f64
+}
+
+pub struct BoolData {
+    pub loc: Span,
+    pub value: bool
+}
+
+pub struct CharData {
+    pub loc: Span,
+    pub value: char
+}
+
+pub struct StringData {
+    pub loc: Span,
+    pub value: String
+}
+
 pub struct BlockData {
-    pub loc: Location,
-    pub stmts: Vec<Box<StmtNode>>
+    pub loc: Span,
+    pub stmts: Vec<StmtId>
+}
+
+pub struct CallData {
+    pub loc: Span,
+    pub callee: ExprId,
+    pub arguments: Vec<ExprId>
+}
+
+pub struct FieldData {
+    pub loc: Span,
+    pub object: ExprId,
+    pub field: String,
+    pub field_loc: Span
+}
+
+pub struct AssignData {
+    pub loc: Span,
+    /// The operator of a compound assignment such as `x += 1`, which assigns `x + 1` to `x`.
+    pub op: Option<BinaryOp>,
+    pub target: ExprId,
+    pub value: ExprId
+}
+
+/// A struct value such as `Point { x: 1, y: 2 }`.
+pub struct StructLiteralData {
+    pub loc: Span,
+    pub name: NameId,
+    pub fields: Vec<StructLiteralField>
+}
+
+pub struct StructLiteralField {
+    pub loc: Span,
+    pub name: String,
+    pub name_loc: Span,
+    pub value: ExprId
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum UnaryOp {
+    Negate,
+    Not
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!"
+        }
+    }
+}
+
+pub struct UnaryData {
+    pub loc: Span,
+    pub op: UnaryOp,
+    pub operand: ExprId
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum BinaryOp {
+    Add, Subtract, Multiply, Divide, Remainder,
+    Less, LessEqual, Greater, GreaterEqual, Equal, NotEqual,
+    And, Or
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        use BinaryOp::*;
+        match self {
+            Add => "+",
+            Subtract => "-",
+            Multiply => "*",
+            Divide => "/",
+            Remainder => "%",
+            Less => "<",
+            LessEqual => "<=",
+            Greater => ">",
+            GreaterEqual => ">=",
+            Equal => "==",
+            NotEqual => "!=",
+            And => "&&",
+            Or => "||"
+        }
+    }
+}
+
+pub struct BinaryData {
+    pub loc: Span,
+    pub op: BinaryOp,
+    pub left: ExprId,
+    pub right: ExprId
+}
+
+/// An explicit conversion such as `x as i8`.
+pub struct CastData {
+    pub loc: Span,
+    pub expr: ExprId,
+    pub typ: TypeId
+}
+
+/// A check of the struct a value was created as, such as `shape is Circle`.
+pub struct IsData {
+    pub loc: Span,
+    pub expr: ExprId,
+    pub typ: TypeId
+}
+
+/// An anonymous function such as `|x, y: int| x + y`. A block body returns its value with `return`
+/// statements; any other body is the value returned.
+pub struct LambdaData {
+    pub loc: Span,
+    pub parameters: Vec<LambdaParameter>,
+    pub body: ExprId
+}
+
+/// A parameter of a lambda, whose type can be left out when it can be inferred from where the lambda is used.
+pub struct LambdaParameter {
+    pub loc: Span,
+    pub name: String,
+    pub typ: Option<TypeId>
+}
+
+/// An array value such as `[1, 2, 3]`.
+pub struct ArrayData {
+    pub loc: Span,
+    pub elements: Vec<ExprId>
+}
+
+/// An element of an array, such as `values[i]`.
+pub struct IndexData {
+    pub loc: Span,
+    pub object: ExprId,
+    pub index: ExprId
 }
 
 
 impl HasLoc for ExprNode {
-    fn get_loc(&self) -> &Location {
+    fn get_loc(&self) -> Span {
         match self {
-            ExprNode::Name(n) => &n.loc,
-            ExprNode::Integer(n) => &n.loc,
-            ExprNode::Block(n) => &n.loc
+            ExprNode::Name(n) => n.loc,
+            ExprNode::Integer(n) => n.loc,
+            ExprNode::Float(n) => n.loc,
+            ExprNode::Bool(n) => n.loc,
+            ExprNode::Char(n) => n.loc,
+            ExprNode::String(n) => n.loc,
+            ExprNode::Block(n) => n.loc,
+            ExprNode::Call(n) => n.loc,
+            ExprNode::Field(n) => n.loc,
+            ExprNode::Assign(n) => n.loc,
+            ExprNode::StructLiteral(n) => n.loc,
+            ExprNode::Unary(n) => n.loc,
+            ExprNode::Binary(n) => n.loc,
+            ExprNode::Cast(n) => n.loc,
+            ExprNode::Is(n) => n.loc,
+            ExprNode::Lambda(n) => n.loc,
+            ExprNode::Array(n) => n.loc,
+            ExprNode::Index(n) => n.loc
         }
     }
 }
@@ -119,22 +899,23 @@ pub enum QualifiedNameNode {
     Namespace(QualNamespaceData)
 }
 
+#[derive(Clone)]
 pub struct QualNameData {
-    pub loc: Location,
+    pub loc: Span,
     pub name: String,
 }
 
 pub struct QualNamespaceData {
-    pub loc: Location,
-    pub source: Box<QualifiedNameNode>,
+    pub loc: Span,
+    pub source: NameId,
     pub attr: String,
 }
 
 impl HasLoc for QualifiedNameNode {
-    fn get_loc(&self) -> &Location {
+    fn get_loc(&self) -> Span {
         match self {
-            QualifiedNameNode::Name(node) => &node.loc,
-            QualifiedNameNode::Namespace(node) => &node.loc
+            QualifiedNameNode::Name(node) => node.loc,
+            QualifiedNameNode::Namespace(node) => node.loc
         }
     }
 }
@@ -143,33 +924,92 @@ impl HasLoc for QualifiedNameNode {
 pub enum TypeNode {
     Name(TypeNameData),
     Function(TypeFunctionData),
-    Reference(TypeReferenceData)
+    Reference(TypeReferenceData),
+    Array(TypeArrayData)
 }
 
 pub struct TypeNameData {
-    pub loc: Location,
-    pub name: Box<QualifiedNameNode>,
-    pub generic_arguments: Option<Vec<Box<TypeNode>>>
+    pub loc: Span,
+    pub name: NameId,
+    pub generic_arguments: Option<Vec<TypeId>>
 }
 
 pub struct TypeFunctionData {
-    pub loc: Location,
-    pub arguments: Vec<Box<TypeNode>>,
-    pub ret: Box<TypeNode>
+    pub loc: Span,
+    pub arguments: Vec<TypeId>,
+    pub ret: TypeId
 }
 
 pub struct TypeReferenceData {
-    pub loc: Location,
-    pub typ: Box<TypeNode>
+    pub loc: Span,
+    pub typ: TypeId
+}
+
+/// An array type such as `[int; SIZE]`, whose length is a constant expression.
+pub struct TypeArrayData {
+    pub loc: Span,
+    pub element: TypeId,
+    pub length: ExprId
 }
 
 
 impl HasLoc for TypeNode {
-    fn get_loc(&self) -> &Location {
+    fn get_loc(&self) -> Span {
         match self {
-            TypeNode::Name(n) => &n.loc,
-            TypeNode::Function(n) => &n.loc,
-            TypeNode::Reference(n) => &n.loc
+            TypeNode::Name(n) => n.loc,
+            TypeNode::Function(n) => n.loc,
+            TypeNode::Reference(n) => n.loc,
+            TypeNode::Array(n) => n.loc
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_text;
+    use super::*;
+
+    #[test]
+    fn nodes_know_their_parents() {
+        let program = parse_text("fn main() -> int { let x = 1 + 2; return x; }");
+        let function = program.files[0].top_level_ids().next().unwrap();
+        let TopLevelNode::Function(data) = &program[function] else {
+            panic!("expected a function");
+        };
+        assert_eq!(program.parent(function), None);
+        assert_eq!(program.parent(data.ret), Some(NodeId::TopLevel(function)));
+
+        let stmt = data.body.stmts[0];
+        assert_eq!(program.parent(stmt), Some(NodeId::TopLevel(function)));
+        let StmtNode::Let(StmtLetData { value: Some(sum), .. }) = &program[stmt] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(program.parent(*sum), Some(NodeId::Stmt(stmt)));
+        let ExprNode::Binary(binary) = &program[*sum] else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(program.children(*sum), [NodeId::Expr(binary.left), NodeId::Expr(binary.right)]);
+        assert_eq!(program.parent(binary.left), Some(NodeId::Expr(*sum)));
+    }
+
+    #[test]
+    fn every_node_is_a_child_of_its_parent() {
+        let program = parse_text("
+            trait Show { fn show(self: Self&) -> int; }
+            struct Pair<T: Show> { first: T; second: [i8; 2]; fn get(self: Self&) -> T { return self.first; } }
+            const LIMIT: int = 3;
+            fn main() -> int {
+                let f = |x: int| x * 2;
+                for i in 0..LIMIT { if i > 1 { f(i); } else { while false { } } }
+                return 0;
+            }");
+        for file in &program.files {
+            for id in file.node_ids() {
+                match file.parent(id) {
+                    Some(parent) => assert!(file.children(parent).contains(&id)),
+                    None => assert!(matches!(id, NodeId::TopLevel(_)))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,607 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::source::{SourceMap, Span, FileId};
+use crate::typecheck::{ConstValue, Primitive};
+use crate::ir::{Module, Function, Type, InstKind, Terminator, BlockTarget, UnaryOp, BinaryOp, ValueId, InstId, FuncId, StructId, value_numbers};
+
+
+/// What every translation unit starts with: the headers it needs and the type strings are held as.
+const HEADER: &str = r#"#include <inttypes.h>
+#include <math.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct {
+    const uint8_t *bytes;
+    uint64_t len;
+} adze_str;
+"#;
+
+/// The functions the generated code calls to stop the program, to check how deeply calls are nested and to
+/// compare strings. They come after the table of source files that errors are reported in.
+const RUNTIME: &str = r#"
+#define ADZE_MAX_CALL_DEPTH 10000
+#define ADZE_ERROR_STATUS 101
+
+/* The number of calls that are running, including that of main. */
+static uint32_t adze_depth = 1;
+
+static _Noreturn void adze_error(int file, int line, int column, const char *format, ...) {
+    va_list args;
+    va_start(args, format);
+    fputs("Error: ", stderr);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fprintf(stderr, "\n     |> In %s:%d:%d\n", adze_files[file], line, column);
+    exit(ADZE_ERROR_STATUS);
+}
+
+static inline void adze_enter(int file, int line, int column) {
+    if (adze_depth >= ADZE_MAX_CALL_DEPTH) {
+        adze_error(file, line, column, "Calls are nested too deeply.");
+    }
+    adze_depth++;
+}
+
+static inline void *adze_allocate(size_t size) {
+    void *memory = calloc(1, size);
+    if (memory == NULL) {
+        fputs("Error: Out of memory.\n", stderr);
+        exit(ADZE_ERROR_STATUS);
+    }
+    return memory;
+}
+
+static inline int adze_str_compare(adze_str left, adze_str right) {
+    int order = memcmp(left.bytes, right.bytes, left.len < right.len ? left.len : right.len);
+    if (order != 0) {
+        return order;
+    }
+    return (left.len > right.len) - (left.len < right.len);
+}
+"#;
+
+/// Whether a struct was created as another or one of its substructs, from the tag it was created with, for
+/// programs with tagged structs. It follows the `adze_parents` table written before it.
+const TAGS: &str = r#"
+static inline bool adze_is(uint32_t tag, uint32_t target) {
+    for (int32_t id = (int32_t)tag; id >= 0; id = adze_parents[id]) {
+        if ((uint32_t)id == target) {
+            return true;
+        }
+    }
+    return false;
+}
+"#;
+
+
+/// Translates a module to one C translation unit. Structs become C structs with the fields of their parents
+/// first, arrays become structs around a C array, and each function becomes a static C function whose SSA
+/// values are locals and whose blocks are labels, with block parameters assigned before each `goto`. When the
+/// module has an entry, it is called by a C `main` that exits with the low 8 bits of what it returns.
+///
+/// The run-time checks of the IR stop the program with the same messages and exit status as the VM, and the
+/// statements are marked with `#line` directives for the source they were lowered from.
+pub fn emit_c(module: &Module, sources: &SourceMap) -> String {
+    let mut emitter = Emitter { module, sources, arrays: HashMap::new(), out: String::new(), position: None };
+    emitter.out.push_str(HEADER);
+    emitter.emit_files();
+    emitter.out.push_str(RUNTIME);
+    emitter.emit_types();
+    if module.structs.iter().any(|def| def.tagged) {
+        emitter.emit_tags();
+    }
+
+    emitter.out.push('\n');
+    for index in 0..module.functions.len() {
+        let signature = emitter.signature(FuncId(index as u32), None);
+        writeln!(emitter.out, "static {};", signature).unwrap();
+    }
+    if let Some(entry) = module.entry {
+        let name = function_name(module, entry);
+        emitter.out.push_str("\nint main(void) {\n");
+        if module.function(entry).ret == Type::UNIT {
+            writeln!(emitter.out, "    {}();\n    return 0;", name).unwrap();
+        } else {
+            writeln!(emitter.out, "    return (int)(uint8_t){}();", name).unwrap();
+        }
+        emitter.out.push_str("}\n");
+    }
+    for index in 0..module.functions.len() {
+        emitter.emit_function(FuncId(index as u32));
+    }
+    return emitter.out;
+}
+
+
+struct Emitter<'a> {
+    module: &'a Module,
+    sources: &'a SourceMap,
+    /// The names of the structs the array types are wrapped in.
+    arrays: HashMap<Type, String>,
+    out: String,
+    /// The file and zero-based line that the next line of output is attributed to, once a `#line` is written.
+    position: Option<(FileId, usize)>
+}
+
+impl<'a> Emitter<'a> {
+    /// Writes a line, preceded by a `#line` directive when it was lowered from a line of source other than the
+    /// one it would otherwise be attributed to.
+    fn line(&mut self, loc: Option<Span>, text: &str) {
+        if let Some(loc) = loc {
+            let source = self.sources.get(loc.file);
+            let line = source.line_of(loc.lo as usize);
+            if self.position != Some((loc.file, line)) {
+                writeln!(self.out, "#line {} \"{}\"", line + 1, c_string(source.name.as_bytes())).unwrap();
+                self.position = Some((loc.file, line));
+            }
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        if let Some((_, line)) = &mut self.position {
+            *line += 1;
+        }
+    }
+
+    /// The table of source files that the locations of run-time errors refer to, indexed by their `FileId`.
+    fn emit_files(&mut self) {
+        let names: Vec<String> = self.sources.files().map(|(_, source)| format!("\"{}\"", c_string(source.name.as_bytes()))).collect();
+        writeln!(self.out, "\nstatic const char *const adze_files[] = {{ {} }};", names.join(", ")).unwrap();
+    }
+
+    /// Defines the structs, and the array types of the fields and memory of the functions, each after the
+    /// types of its fields.
+    fn emit_types(&mut self) {
+        let module = self.module;
+        let mut defined = vec![false; module.structs.len()];
+        for index in 0..module.structs.len() {
+            self.define(&Type::Struct(StructId(index as u32)), &mut defined);
+        }
+        for function in &module.functions {
+            for block in &function.blocks {
+                for inst in &block.insts {
+                    match &function.inst(*inst).kind {
+                        InstKind::Alloca(typ) | InstKind::Copy { typ, .. } | InstKind::Index { typ, .. } => self.define(typ, &mut defined),
+                        _ => { }
+                    }
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, typ: &Type, defined: &mut [bool]) {
+        match typ {
+            Type::Struct(id) if !defined[id.0 as usize] => {
+                defined[id.0 as usize] = true;
+                let def = self.module.struct_def(*id);
+                for field in &def.fields {
+                    self.define(field, defined);
+                }
+                let mut text = format!("\n{} {{\n", struct_name(self.module, *id));
+                if def.tagged {
+                    text.push_str("    uint32_t tag;\n");
+                }
+                for (index, field) in def.fields.iter().enumerate() {
+                    writeln!(text, "    {};", declaration(&self.c_type(field), &format!("f{}", index))).unwrap();
+                }
+                if def.fields.is_empty() && !def.tagged {
+                    text.push_str("    char empty;\n");
+                }
+                text.push_str("};\n");
+                self.out.push_str(&text);
+            },
+            Type::Array(element, length) if !self.arrays.contains_key(typ) => {
+                self.define(element, defined);
+                let name = format!("adze_array{}", self.arrays.len());
+                // C has no arrays of length zero, but the bounds check keeps the element from being reached.
+                writeln!(self.out, "\ntypedef struct {{\n    {} e[{}];\n}} {};", self.c_type(element), (*length).max(1), name).unwrap();
+                self.arrays.insert(typ.clone(), name);
+            },
+            _ => { }
+        }
+    }
+
+    /// The parents of the structs and their names, which `adze_is` and failed downcasts read.
+    fn emit_tags(&mut self) {
+        let parents: Vec<String> = self.module.structs.iter().map(|def| def.parent.map_or(-1, |parent| parent.0 as i64).to_string()).collect();
+        let names: Vec<String> = self.module.structs.iter().map(|def| format!("\"{}\"", c_string(def.name.as_bytes()))).collect();
+        writeln!(self.out, "\nstatic const int32_t adze_parents[] = {{ {} }};", parents.join(", ")).unwrap();
+        writeln!(self.out, "static const char *const adze_struct_names[] = {{ {} }};", names.join(", ")).unwrap();
+        self.out.push_str(TAGS);
+    }
+
+    fn c_type(&self, typ: &Type) -> String {
+        match typ {
+            Type::Primitive(p) => String::from(primitive_type(*p)),
+            Type::Ptr => String::from("void *"),
+            Type::Struct(id) => struct_name(self.module, *id),
+            Type::Array(..) => self.arrays[typ].clone()
+        }
+    }
+
+    /// The declaration of a function, with the names of its parameters if they are given.
+    fn signature(&self, id: FuncId, params: Option<&[String]>) -> String {
+        let function = self.module.function(id);
+        let params: Vec<String> = match params {
+            Some(names) => function.params.iter().zip(names).map(|(typ, name)| declaration(&self.c_type(typ), name)).collect(),
+            None => function.params.iter().map(|typ| self.c_type(typ)).collect()
+        };
+        let params = if params.is_empty() { String::from("void") } else { params.join(", ") };
+        return declaration(&self.c_type(&function.ret), &format!("{}({})", function_name(self.module, id), params));
+    }
+
+    fn emit_function(&mut self, id: FuncId) {
+        let function = self.module.function(id);
+        let numbers = value_numbers(function);
+        let name = |value: ValueId| format!("v{}", numbers[value.0 as usize].expect("the IR is verified"));
+        let params: Vec<String> = function.block(Function::ENTRY).params.iter().map(|param| name(*param)).collect();
+        let signature = self.signature(id, Some(&params));
+        self.line(None, "");
+        self.line(Some(function.loc), &format!("static {} {{", signature));
+
+        // Every value is declared at the start, so that the gotos between blocks never skip a declaration.
+        for (index, block) in function.blocks.iter().enumerate() {
+            let results = block.insts.iter().filter_map(|inst| function.inst(*inst).result);
+            let values = if index == 0 { Vec::new() } else { block.params.clone() };
+            for value in values.into_iter().chain(results) {
+                let declaration = format!("    {};", declaration(&self.c_type(function.typ(value)), &name(value)));
+                self.line(None, &declaration);
+            }
+            for inst in &block.insts {
+                let inst = function.inst(*inst);
+                if let (InstKind::Alloca(typ), Some(result)) = (&inst.kind, inst.result) {
+                    if !function.escapes(result) {
+                        let memory = format!("m{}", numbers[result.0 as usize].unwrap());
+                        let declaration = format!("    {};", declaration(&self.c_type(typ), &memory));
+                        self.line(None, &declaration);
+                    }
+                }
+            }
+        }
+
+        for (index, block) in function.blocks.iter().enumerate() {
+            if index != 0 {
+                self.line(None, &format!("bb{}:", index));
+            }
+            for inst in &block.insts {
+                let statement = self.statement(function, &numbers, *inst);
+                self.line(Some(function.inst(*inst).loc), &format!("    {}", statement));
+            }
+            let terminator = match &block.terminator {
+                Terminator::Jump(target) => format!("{}goto bb{};", self.copies(function, &numbers, target), target.block.0),
+                Terminator::Branch(condition, then, otherwise) => format!(
+                    "if ({}) {{ {}goto bb{}; }} else {{ {}goto bb{}; }}",
+                    name(*condition), self.copies(function, &numbers, then), then.block.0, self.copies(function, &numbers, otherwise), otherwise.block.0
+                ),
+                Terminator::Return(Some(value)) => format!("return {};", name(*value)),
+                Terminator::Return(None) => String::from("return;"),
+                Terminator::Unreachable => String::from("abort();")
+            };
+            self.line(Some(block.terminator_loc), &format!("    {}", terminator));
+        }
+        self.line(None, "}");
+    }
+
+    /// The C statements that carry out an instruction.
+    fn statement(&self, function: &Function, numbers: &[Option<u32>], id: InstId) -> String {
+        let inst = function.inst(id);
+        let name = |value: &ValueId| format!("v{}", numbers[value.0 as usize].expect("the IR is verified"));
+        let result = inst.result.map(|value| name(&value)).unwrap_or_default();
+        let result_type = || inst.result.map_or(Type::UNIT, |value| function.typ(value).clone());
+        let site = self.site(inst.loc);
+        match &inst.kind {
+            InstKind::Const(value) => format!("{} = {};", result, constant(value, result_type().primitive().expect("constants are primitives"))),
+            InstKind::Unary(op, operand) => {
+                let typ = function.typ(*operand).primitive().expect("operators apply to primitives");
+                let value = name(operand);
+                match op {
+                    UnaryOp::Not => format!("{} = !{};", result, value),
+                    UnaryOp::Neg if typ.is_float() => format!("{} = -{};", result, value),
+                    UnaryOp::Neg => format!("{} = ({})(({})0 - ({}){});", result, primitive_type(typ), wide_type(typ), wide_type(typ), value)
+                }
+            },
+            InstKind::Binary(op, left, right) => {
+                let (l, r) = (name(left), name(right));
+                let Some(typ) = function.typ(*left).primitive() else {
+                    return format!("{} = {} {} {};", result, l, c_operator(*op), r);
+                };
+                let (t, w) = (primitive_type(typ), wide_type(typ));
+                match op {
+                    _ if op.is_comparison() && typ == Primitive::Str => format!("{} = adze_str_compare({}, {}) {} 0;", result, l, r, c_operator(*op)),
+                    _ if op.is_comparison() => format!("{} = {} {} {};", result, l, c_operator(*op), r),
+                    BinaryOp::Rem if typ == Primitive::F32 => format!("{} = fmodf({}, {});", result, l, r),
+                    BinaryOp::Rem if typ == Primitive::F64 => format!("{} = fmod({}, {});", result, l, r),
+                    _ if typ.is_float() => format!("{} = {} {} {};", result, l, c_operator(*op), r),
+                    BinaryOp::Div | BinaryOp::Rem => {
+                        let check = format!("if ({} == 0) adze_error({}, \"Division by zero.\");", r, site);
+                        // The only quotient that overflows is that of the smallest integer by -1, which wraps around.
+                        let value = match op {
+                            BinaryOp::Div if typ.is_signed() => format!("{} == -1 ? ({})(({})0 - ({}){}) : {} / {}", r, t, w, w, l, l, r),
+                            BinaryOp::Rem if typ.is_signed() => format!("{} == -1 ? 0 : {} % {}", r, l, r),
+                            _ => format!("{} {} {}", l, c_operator(*op), r)
+                        };
+                        format!("{} {} = {};", check, result, value)
+                    },
+                    // Arithmetic is done on unsigned integers, which wrap around instead of overflowing.
+                    _ => format!("{} = ({})(({}){} {} ({}){});", result, t, w, l, c_operator(*op), w, r)
+                }
+            },
+            InstKind::Cast(operand) => {
+                let from = function.typ(*operand).primitive().expect("casts are between primitives");
+                let to = result_type().primitive().expect("casts are between primitives");
+                format!("{} = {};", result, cast(&name(operand), from, to))
+            },
+            InstKind::Call(callee, args) => {
+                let args: Vec<String> = args.iter().map(name).collect();
+                let assign = if inst.result.is_some() { format!("{} = ", result) } else { String::new() };
+                format!("adze_enter({}); {}{}({}); adze_depth--;", site, assign, function_name(self.module, *callee), args.join(", "))
+            },
+            InstKind::CallIndirect(callee, args) => {
+                let params: Vec<String> = args.iter().map(|arg| self.c_type(function.typ(*arg))).collect();
+                let params = if params.is_empty() { String::from("void") } else { params.join(", ") };
+                let pointer = format!("({} (*)({})){}", self.c_type(&result_type()), params, name(callee));
+                let args: Vec<String> = args.iter().map(name).collect();
+                let assign = if inst.result.is_some() { format!("{} = ", result) } else { String::new() };
+                format!("adze_enter({}); {}({})({}); adze_depth--;", site, assign, pointer, args.join(", "))
+            },
+            InstKind::Function(callee) => format!("{} = (void *)&{};", result, function_name(self.module, *callee)),
+            InstKind::Alloca(typ) => {
+                // Memory that a pointer to can outlive the call is allocated on the heap, as the VM keeps it
+                // for as long as a reference to it is held.
+                if function.escapes(inst.result.unwrap()) {
+                    format!("{} = adze_allocate(sizeof({}));", result, self.c_type(typ))
+                } else {
+                    format!("{} = &m{};", result, numbers[inst.result.unwrap().0 as usize].unwrap())
+                }
+            },
+            InstKind::Load(ptr) => format!("{} = *({} *){};", result, self.c_type(&result_type()), name(ptr)),
+            InstKind::Store { value, ptr } => format!("*({} *){} = {};", self.c_type(function.typ(*value)), name(ptr), name(value)),
+            InstKind::Copy { typ, dest, src } => format!("memmove({}, {}, sizeof({}));", name(dest), name(src), self.c_type(typ)),
+            InstKind::Field { typ, ptr, index } => format!("{} = &(({} *){})->f{};", result, struct_name(self.module, *typ), name(ptr), index),
+            InstKind::Index { typ, ptr, index } => {
+                let Type::Array(_, length) = typ else {
+                    unreachable!("the IR is verified")
+                };
+                let i = name(index);
+                format!(
+                    "if ({} >= UINT64_C({})) adze_error({}, \"Index %\" PRIu64 \" is out of bounds for an array of length {}.\", {}); {} = &(({} *){})->e[{}];",
+                    i, length, site, length, i, result, self.c_type(typ), name(ptr), i
+                )
+            },
+            InstKind::SetTag(target, ptr) => format!("*(uint32_t *){} = {};", name(ptr), target.0),
+            InstKind::Is(ptr, target) => format!("{} = adze_is(*(uint32_t *){}, {});", result, name(ptr), target.0),
+            InstKind::Downcast(ptr, target) => {
+                let (p, tag) = (name(ptr), format!("*(uint32_t *){}", name(ptr)));
+                format!(
+                    "if (!adze_is({}, {})) adze_error({}, \"Cannot cast to a reference to '%s', since the value was created as '%s'.\", adze_struct_names[{}], adze_struct_names[{}]); {} = {};",
+                    tag, target.0, site, target.0, tag, result, p
+                )
+            }
+        }
+    }
+
+    /// The file, line and column of a span, as the arguments of `adze_error`.
+    fn site(&self, loc: Span) -> String {
+        let (line, column) = self.sources.get(loc.file).line_col(loc.lo as usize);
+        return format!("{}, {}, {}", loc.file.0, line + 1, column + 1);
+    }
+
+    /// The assignments of a branch's arguments to the parameters of its target. When an argument is also one of
+    /// the parameters, every argument is read into a temporary first, so none is overwritten before it is read.
+    fn copies(&self, function: &Function, numbers: &[Option<u32>], target: &BlockTarget) -> String {
+        let name = |value: ValueId| format!("v{}", numbers[value.0 as usize].expect("the IR is verified"));
+        let params = &function.block(target.block).params;
+        let pairs: Vec<(ValueId, ValueId)> = params.iter().copied().zip(target.args.iter().copied()).filter(|(param, arg)| param != arg).collect();
+        if pairs.is_empty() {
+            return String::new();
+        }
+        if !pairs.iter().any(|(_, arg)| pairs.iter().any(|(param, _)| param == arg)) {
+            return pairs.iter().map(|(param, arg)| format!("{} = {}; ", name(*param), name(*arg))).collect();
+        }
+        let mut text = String::from("{ ");
+        for (index, (_, arg)) in pairs.iter().enumerate() {
+            write!(text, "{} t{} = {}; ", self.c_type(function.typ(*arg)), index, name(*arg)).unwrap();
+        }
+        for (index, (param, _)) in pairs.iter().enumerate() {
+            write!(text, "{} = t{}; ", name(*param), index).unwrap();
+        }
+        text.push_str("} ");
+        return text;
+    }
+}
+
+
+/// The declaration of a variable of a C type, with pointers written as `void *name`.
+fn declaration(typ: &str, name: &str) -> String {
+    if typ.ends_with('*') { format!("{}{}", typ, name) } else { format!("{} {}", typ, name) }
+}
+
+fn struct_name(module: &Module, id: StructId) -> String {
+    format!("struct s{}_{}", id.0, identifier(&module.struct_def(id).name))
+}
+
+fn function_name(module: &Module, id: FuncId) -> String {
+    format!("f{}_{}", id.0, identifier(&module.function(id).name))
+}
+
+/// A name made into a C identifier, with the characters C does not allow in one replaced by underscores. The
+/// names it is used for are prefixed with a number, so that names that become the same stay different.
+fn identifier(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+fn primitive_type(typ: Primitive) -> &'static str {
+    use Primitive::*;
+    match typ {
+        I8 => "int8_t",
+        I16 => "int16_t",
+        I32 => "int32_t",
+        I64 => "int64_t",
+        U8 => "uint8_t",
+        U16 => "uint16_t",
+        U32 => "uint32_t",
+        U64 => "uint64_t",
+        F32 => "float",
+        F64 => "double",
+        Bool => "bool",
+        Char => "uint32_t",
+        Str => "adze_str",
+        Unit => "void"
+    }
+}
+
+/// The unsigned type that arithmetic on an integer type is done in, which is at least as wide as `int` so that
+/// the operands are not promoted to a signed type.
+fn wide_type(typ: Primitive) -> &'static str {
+    if typ.size() <= 4 { "uint32_t" } else { "uint64_t" }
+}
+
+/// The prefix of the names of the limits of an integer type in `<stdint.h>`.
+fn limit_prefix(typ: Primitive) -> &'static str {
+    use Primitive::*;
+    match typ {
+        I8 => "INT8",
+        I16 => "INT16",
+        I32 => "INT32",
+        I64 => "INT64",
+        U8 => "UINT8",
+        U16 => "UINT16",
+        U32 => "UINT32",
+        U64 => "UINT64",
+        _ => unreachable!("only integer types have limits")
+    }
+}
+
+fn c_operator(op: BinaryOp) -> &'static str {
+    use BinaryOp::*;
+    match op {
+        Add => "+",
+        Sub => "-",
+        Mul => "*",
+        Div => "/",
+        Rem => "%",
+        Eq => "==",
+        Ne => "!=",
+        Lt => "<",
+        Le => "<=",
+        Gt => ">",
+        Ge => ">="
+    }
+}
+
+/// A cast between primitives, as `fold_cast` does it: integers wrap around, floats saturate at the bounds of
+/// the integer type they are cast to with NaN becoming 0, and integers become the `char` of their low byte.
+fn cast(value: &str, from: Primitive, to: Primitive) -> String {
+    if from == to {
+        return String::from(value);
+    }
+    if to == Primitive::Char {
+        return format!("(uint32_t)(uint8_t){}", value);
+    }
+    if to == Primitive::Bool {
+        return format!("{} != 0", value);
+    }
+    if to.is_integer() && from.is_float() {
+        let (prefix, t) = (limit_prefix(to), primitive_type(to));
+        let min = if to.is_signed() { format!("{}_MIN", prefix) } else { String::from("0") };
+        return format!(
+            "{v} != {v} ? 0 : {v} <= (double){min} ? {min} : {v} >= (double){p}_MAX ? {p}_MAX : ({t}){v}",
+            v = value, min = min, p = prefix, t = t
+        );
+    }
+    return format!("({}){}", primitive_type(to), value);
+}
+
+fn constant(value: &ConstValue, typ: Primitive) -> String {
+    match value {
+        ConstValue::Integer(n) if typ.is_integer() => {
+            let prefix = limit_prefix(typ);
+            // The smallest signed integers cannot be written as the negation of a literal of their type.
+            if typ.integer_range().is_some_and(|(min, _)| *n == min && typ.is_signed()) {
+                format!("{}_MIN", prefix)
+            } else {
+                format!("{}_C({})", prefix, n)
+            }
+        },
+        ConstValue::Integer(n) => n.to_string(),
+        ConstValue::Float(f) if f.is_nan() => String::from("NAN"),
+        ConstValue::Float(f) if f.is_infinite() => String::from(if *f > 0.0 { "INFINITY" } else { "-INFINITY" }),
+        ConstValue::Float(f) if typ == Primitive::F32 => format!("{:?}f", *f as f32),
+        ConstValue::Float(f) => format!("{:?}", f),
+        ConstValue::Bool(b) => b.to_string(),
+        ConstValue::Char(c) => format!("UINT32_C({})", *c as u32),
+        ConstValue::Str(s) => format!("(adze_str){{ (const uint8_t *)\"{}\", {} }}", c_string(s.as_bytes()), s.len())
+    }
+}
+
+/// The contents of a C string literal with the given bytes. Bytes other than printable ASCII are written as
+/// three octal digits, which a following digit cannot be read as part of, and `?` is escaped so that no
+/// trigraph is formed.
+fn c_string(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' | b'?' => { text.push('\\'); text.push(*byte as char); },
+            0x20..=0x7e => text.push(*byte as char),
+            _ => write!(text, "\\{:03o}", byte).unwrap()
+        }
+    }
+    return text;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use super::emit_c;
+
+    /// Checks a program read as if from standard input, lowers it to IR without optimizing it, and translates
+    /// it to C.
+    fn emit(text: &str) -> String {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        let module = crate::ir::lower_program(result.hir.as_ref().expect("the program has errors"));
+        return emit_c(&module, &result.state.sources);
+    }
+
+    #[test]
+    fn structs_hold_the_fields_of_their_parents_first() {
+        let code = emit("
+            struct Shape { id: int; }
+            struct Rect(Shape) { w: i32; h: i8; }
+            fn main() -> int {
+                let r = Rect { id: 1, w: 2, h: 3 };
+                return r.id;
+            }
+        ");
+        assert!(code.contains("\nstruct s0_Rect {\n    uint32_t tag;\n    int64_t f0;\n    int32_t f1;\n    int8_t f2;\n};\n"));
+        assert!(code.contains("static const char *const adze_struct_names[] = { \"Rect\" };"));
+    }
+
+    #[test]
+    fn statements_are_marked_with_the_lines_they_came_from() {
+        let code = emit("fn main() -> int {\n    let x = 1;\n\n    return x;\n}\n");
+        let lines: Vec<&str> = code.lines().skip_while(|line| !line.starts_with("static int64_t f0_main(void) {")).collect();
+        let directive = code.lines().find(|line| line.starts_with("#line 1 ")).expect("the function has no #line");
+        assert!(directive.ends_with("<stdin>\""));
+        assert!(code.contains(&format!("{}\nstatic int64_t f0_main(void) {{", directive)));
+        assert!(lines.iter().any(|line| line.starts_with("#line 4 ")));
+        assert!(!lines.iter().any(|line| line.starts_with("#line 3 ")));
+    }
+
+    #[test]
+    fn main_exits_with_the_low_bits_of_what_the_entry_returns() {
+        assert!(emit("fn main() -> int { return 0; }").contains("\nint main(void) {\n    return (int)(uint8_t)f0_main();\n}\n"));
+        assert!(emit("fn main() -> unit { }").contains("\nint main(void) {\n    f0_main();\n    return 0;\n}\n"));
+        assert!(!emit("fn main(x: int) -> int { return x; }").contains("int main(void)"));
+    }
+
+    #[test]
+    fn string_literals_escape_what_c_would_read_differently() {
+        assert_eq!(super::c_string(b"a\"b\\c??=\n\x7f1"), "a\\\"b\\\\c\\?\\?=\\012\\1771");
+    }
+}
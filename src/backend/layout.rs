@@ -0,0 +1,85 @@
+use crate::typecheck::Primitive;
+use crate::ir::{Module, Type, StructId};
+
+
+/// Where the fields of a struct are in memory. A tagged struct starts with its tag, as a 4-byte integer.
+pub struct StructLayout {
+    pub offsets: Vec<u64>,
+    pub size: u64,
+    pub align: u64
+}
+
+/// The sizes and alignments of the types of a module in memory, with each field at the next offset its
+/// alignment allows, as a C compiler lays structs out. The fields of a parent come first and are laid out the
+/// same in every substruct, so a pointer to a substruct is also a pointer to its parent.
+pub struct Layouts {
+    structs: Vec<Option<StructLayout>>
+}
+
+/// The size of the tag at the start of a tagged struct.
+pub const TAG_SIZE: u64 = 4;
+
+impl Layouts {
+    pub fn new(module: &Module) -> Layouts {
+        let mut layouts = Layouts { structs: (0..module.structs.len()).map(|_| None).collect() };
+        for index in 0..module.structs.len() {
+            layouts.compute(module, StructId(index as u32));
+        }
+        return layouts;
+    }
+
+    fn compute(&mut self, module: &Module, id: StructId) {
+        if self.structs[id.0 as usize].is_some() {
+            return;
+        }
+        let def = module.struct_def(id);
+        let (mut offset, mut align) = if def.tagged { (TAG_SIZE, TAG_SIZE) } else { (0, 1) };
+        let mut offsets = Vec::new();
+        for field in &def.fields {
+            if let Type::Struct(inner) = field {
+                self.compute(module, *inner);
+            }
+            let (field_size, field_align) = self.size_align_of(module, field);
+            offset = offset.next_multiple_of(field_align);
+            offsets.push(offset);
+            offset += field_size;
+            align = align.max(field_align);
+        }
+        self.structs[id.0 as usize] = Some(StructLayout { offsets, size: offset.next_multiple_of(align), align });
+    }
+
+    fn size_align_of(&mut self, module: &Module, typ: &Type) -> (u64, u64) {
+        if let Type::Array(element, _) = typ {
+            self.size_align_of(module, element);
+        }
+        if let Type::Struct(id) = typ {
+            self.compute(module, *id);
+        }
+        return self.size_align(typ);
+    }
+
+    pub fn struct_layout(&self, id: StructId) -> &StructLayout {
+        self.structs[id.0 as usize].as_ref().expect("every struct is laid out")
+    }
+
+    pub fn size_align(&self, typ: &Type) -> (u64, u64) {
+        match typ {
+            Type::Primitive(Primitive::Str) => (16, 8),
+            Type::Primitive(Primitive::Unit) => (0, 1),
+            Type::Primitive(p) => (p.size() as u64, p.size() as u64),
+            Type::Ptr => (8, 8),
+            Type::Struct(id) => {
+                let layout = self.struct_layout(*id);
+                (layout.size, layout.align)
+            },
+            Type::Array(element, length) => {
+                let (size, align) = self.size_align(element);
+                (size * length, align)
+            }
+        }
+    }
+
+    pub fn size(&self, typ: &Type) -> u64 {
+        self.size_align(typ).0
+    }
+}
@@ -0,0 +1,10 @@
+mod c;
+mod layout;
+mod regalloc;
+mod runtime;
+mod toolchain;
+mod x86;
+
+pub use c::emit_c;
+pub use x86::emit_asm;
+pub use toolchain::{compile_c, assemble};
@@ -0,0 +1,219 @@
+use crate::typecheck::Primitive;
+use crate::ir::{Function, Type, ValueId};
+
+
+/// The general-purpose registers of x86-64 that the generated code uses.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Reg {
+    Rax, Rcx, Rdx, Rbx, Rsi, Rdi, R8, R9, R11, R12, R13, R14, R15
+}
+
+impl Reg {
+    /// The name of the part of the register with the given size in bytes, in GNU assembler syntax.
+    pub fn name(&self, size: u64) -> &'static str {
+        use Reg::*;
+        let names = match self {
+            Rax => ["%al", "%ax", "%eax", "%rax"],
+            Rcx => ["%cl", "%cx", "%ecx", "%rcx"],
+            Rdx => ["%dl", "%dx", "%edx", "%rdx"],
+            Rbx => ["%bl", "%bx", "%ebx", "%rbx"],
+            Rsi => ["%sil", "%si", "%esi", "%rsi"],
+            Rdi => ["%dil", "%di", "%edi", "%rdi"],
+            R8 => ["%r8b", "%r8w", "%r8d", "%r8"],
+            R9 => ["%r9b", "%r9w", "%r9d", "%r9"],
+            R11 => ["%r11b", "%r11w", "%r11d", "%r11"],
+            R12 => ["%r12b", "%r12w", "%r12d", "%r12"],
+            R13 => ["%r13b", "%r13w", "%r13d", "%r13"],
+            R14 => ["%r14b", "%r14w", "%r14d", "%r14"],
+            R15 => ["%r15b", "%r15w", "%r15d", "%r15"]
+        };
+        return names[match size { 1 => 0, 2 => 1, 4 => 2, _ => 3 }];
+    }
+
+    pub fn q(&self) -> &'static str {
+        self.name(8)
+    }
+}
+
+/// The registers values are kept in. They are the callee-saved ones, so that values stay in them across
+/// calls, and the code between uses of values can use the others freely.
+pub const ALLOCATABLE: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+
+/// Where a value is kept, for the whole of its function.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Location {
+    Register(Reg),
+    /// A slot in the stack frame, this many bytes below the registers the function saves.
+    Stack(u64)
+}
+
+pub struct Allocation {
+    pub locations: Vec<Option<Location>>,
+    /// The allocatable registers the function uses, which it saves on entry and restores on return.
+    pub saved: Vec<Reg>,
+    /// The bytes of the frame the slots take.
+    pub frame: u64
+}
+
+/// Whether a value of the type fits in a general-purpose register, as integers, `bool`, `char` and pointers do.
+pub fn is_integer_class(typ: &Type) -> bool {
+    match typ {
+        Type::Primitive(p) => !p.is_float() && !matches!(p, Primitive::Str | Primitive::Unit),
+        Type::Ptr => true,
+        Type::Struct(_) | Type::Array(..) => false
+    }
+}
+
+/// The number of quadwords a value of the type takes: two for strings, with their bytes and their length,
+/// and one for everything else. Floats are kept as their bits.
+pub fn quadwords(typ: &Type) -> u64 {
+    if *typ == Type::Primitive(Primitive::Str) { 2 } else { 1 }
+}
+
+
+/// Finds a place for every value of a function by linear scan, after Poletto and Sarkar: the values that fit in
+/// a register are visited in the order their live ranges start, and given a free register if there is one.
+/// When there is not, the value whose range ends last, whether the new one or one that holds a register, is
+/// kept in the stack frame instead. Floats and strings are always kept in the frame.
+pub fn allocate(function: &Function) -> Allocation {
+    let ranges = live_ranges(function);
+    let mut candidates: Vec<ValueId> = (0..function.values.len() as u32).map(ValueId)
+        .filter(|value| ranges[value.0 as usize].is_some() && is_integer_class(function.typ(*value)))
+        .collect();
+    candidates.sort_by_key(|value| ranges[value.0 as usize].unwrap());
+
+    let mut locations: Vec<Option<Location>> = vec![None; function.values.len()];
+    let mut free: Vec<Reg> = ALLOCATABLE.iter().rev().copied().collect();
+    let mut active: Vec<(u32, ValueId, Reg)> = Vec::new();
+    let mut used: Vec<Reg> = Vec::new();
+    for value in candidates {
+        let (start, end) = ranges[value.0 as usize].unwrap();
+        active.retain(|(active_end, _, reg)| {
+            if *active_end < start {
+                free.push(*reg);
+                return false;
+            }
+            return true;
+        });
+        let reg = match free.pop() {
+            Some(reg) => reg,
+            None => {
+                let (index, &(last_end, last, reg)) = active.iter().enumerate().max_by_key(|(_, (end, _, _))| *end).unwrap();
+                if last_end <= end {
+                    continue;
+                }
+                locations[last.0 as usize] = None;
+                active.remove(index);
+                reg
+            }
+        };
+        locations[value.0 as usize] = Some(Location::Register(reg));
+        active.push((end, value, reg));
+        if !used.contains(&reg) {
+            used.push(reg);
+        }
+    }
+
+    let mut frame = 0;
+    for (index, location) in locations.iter_mut().enumerate() {
+        if location.is_none() && ranges[index].is_some() {
+            frame += 8 * quadwords(&function.values[index].typ);
+            *location = Some(Location::Stack(frame));
+        }
+    }
+    let saved = ALLOCATABLE.iter().copied().filter(|reg| used.contains(reg)).collect();
+    return Allocation { locations, saved, frame };
+}
+
+/// The first and last positions at which each value of a function is live, numbering the starts of the blocks,
+/// their instructions and their terminators in the order the blocks are laid out. The range covers every block
+/// the value is live through, so it may include positions between where it is not. Values that no block
+/// defines have no range.
+fn live_ranges(function: &Function) -> Vec<Option<(u32, u32)>> {
+    let count = function.values.len();
+    let blocks = function.blocks.len();
+    // The values each block uses before defining them, and those it defines.
+    let mut uses = vec![vec![false; count]; blocks];
+    let mut defs = vec![vec![false; count]; blocks];
+    for (index, block) in function.blocks.iter().enumerate() {
+        for param in &block.params {
+            defs[index][param.0 as usize] = true;
+        }
+        for inst in &block.insts {
+            let inst = function.inst(*inst);
+            for operand in inst.kind.operands() {
+                if !defs[index][operand.0 as usize] {
+                    uses[index][operand.0 as usize] = true;
+                }
+            }
+            if let Some(result) = inst.result {
+                defs[index][result.0 as usize] = true;
+            }
+        }
+        for operand in block.terminator.operands() {
+            if !defs[index][operand.0 as usize] {
+                uses[index][operand.0 as usize] = true;
+            }
+        }
+    }
+
+    let mut live_in = uses.clone();
+    let mut live_out = vec![vec![false; count]; blocks];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in (0..blocks).rev() {
+            for successor in function.blocks[index].terminator.successors() {
+                for value in 0..count {
+                    if live_in[successor.0 as usize][value] && !live_out[index][value] {
+                        live_out[index][value] = true;
+                        if !defs[index][value] && !live_in[index][value] {
+                            live_in[index][value] = true;
+                        }
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut ranges: Vec<Option<(u32, u32)>> = vec![None; count];
+    let mut extend = |value: ValueId, position: u32| {
+        let range = &mut ranges[value.0 as usize];
+        *range = Some(match *range {
+            Some((start, end)) => (start.min(position), end.max(position)),
+            None => (position, position)
+        });
+    };
+    let mut position = 0;
+    for (index, block) in function.blocks.iter().enumerate() {
+        let start = position;
+        for param in &block.params {
+            extend(*param, start);
+        }
+        for inst in &block.insts {
+            position += 1;
+            let inst = function.inst(*inst);
+            for operand in inst.kind.operands() {
+                extend(operand, position);
+            }
+            if let Some(result) = inst.result {
+                extend(result, position);
+            }
+        }
+        position += 1;
+        for operand in block.terminator.operands() {
+            extend(operand, position);
+        }
+        for value in 0..count {
+            if live_in[index][value] {
+                extend(ValueId(value as u32), start);
+            }
+            if live_out[index][value] {
+                extend(ValueId(value as u32), position);
+            }
+        }
+        position += 1;
+    }
+    return ranges;
+}
@@ -0,0 +1,270 @@
+/// The run-time support of the x86-64 backend, in GNU assembler syntax, which is linked into every program
+/// without a C library: system calls are made directly. The generated code calls these functions to stop the
+/// program with the same messages and exit status as the VM, to allocate memory that outlives a call, to
+/// compare strings and to check tags. They only use the registers the System V ABI lets a function clobber,
+/// and the functions that stop the program take a pointer to the location of the error, as three quadwords
+/// holding the index of the file in `adze_files`, the line and the column.
+///
+/// The program's code defines `adze_main`, which calls its entry function and returns the exit status, and
+/// the tables `adze_files`, `adze_struct_names` and `adze_parents`.
+pub const RUNTIME: &str = r#"    .text
+    .globl _start
+_start:
+    xor %ebp, %ebp
+    and $-16, %rsp
+    call adze_main
+    mov %eax, %edi
+    mov $231, %eax
+    syscall
+
+# Writes %rsi bytes from %rdi to standard error.
+adze_write:
+    mov %rsi, %rdx
+    mov %rdi, %rsi
+    mov $2, %edi
+    mov $1, %eax
+    syscall
+    ret
+
+# Writes %rdi to standard error in decimal.
+adze_write_u64:
+    sub $40, %rsp
+    lea 32(%rsp), %rsi
+    mov %rdi, %rax
+    mov $10, %ecx
+1:
+    xor %edx, %edx
+    div %rcx
+    add $48, %dl
+    dec %rsi
+    mov %dl, (%rsi)
+    test %rax, %rax
+    jnz 1b
+    lea 32(%rsp), %rdx
+    sub %rsi, %rdx
+    mov %rsi, %rdi
+    mov %rdx, %rsi
+    call adze_write
+    add $40, %rsp
+    ret
+
+# Writes the name of the struct with the index %rdi to standard error.
+adze_write_name:
+    shl $4, %rdi
+    lea adze_struct_names(%rip), %rax
+    mov 8(%rax,%rdi), %rsi
+    mov (%rax,%rdi), %rdi
+    jmp adze_write
+
+# Writes where the error at the location %rbx points to happened, and exits.
+adze_fail_at:
+    lea .Lin(%rip), %rdi
+    mov $(.Lin_end - .Lin), %esi
+    call adze_write
+    mov (%rbx), %rax
+    shl $4, %rax
+    lea adze_files(%rip), %rcx
+    mov (%rcx,%rax), %rdi
+    mov 8(%rcx,%rax), %rsi
+    call adze_write
+    lea .Lcolon(%rip), %rdi
+    mov $1, %esi
+    call adze_write
+    mov 8(%rbx), %rdi
+    call adze_write_u64
+    lea .Lcolon(%rip), %rdi
+    mov $1, %esi
+    call adze_write
+    mov 16(%rbx), %rdi
+    call adze_write_u64
+    lea .Lnewline(%rip), %rdi
+    mov $1, %esi
+    call adze_write
+    mov $101, %edi
+    mov $231, %eax
+    syscall
+
+adze_division_by_zero:
+    mov %rdi, %rbx
+    lea .Ldivision(%rip), %rdi
+    mov $(.Ldivision_end - .Ldivision), %esi
+    call adze_write
+    jmp adze_fail_at
+
+adze_too_deep:
+    mov %rdi, %rbx
+    lea .Ldeep(%rip), %rdi
+    mov $(.Ldeep_end - .Ldeep), %esi
+    call adze_write
+    jmp adze_fail_at
+
+# The index is in %rsi and the length of the array in %rdx.
+adze_index_out_of_bounds:
+    mov %rdi, %rbx
+    mov %rsi, %r12
+    mov %rdx, %r13
+    lea .Lindex(%rip), %rdi
+    mov $(.Lindex_end - .Lindex), %esi
+    call adze_write
+    mov %r12, %rdi
+    call adze_write_u64
+    lea .Lbounds(%rip), %rdi
+    mov $(.Lbounds_end - .Lbounds), %esi
+    call adze_write
+    mov %r13, %rdi
+    call adze_write_u64
+    lea .Lperiod(%rip), %rdi
+    mov $1, %esi
+    call adze_write
+    jmp adze_fail_at
+
+# The struct that was cast to is in %esi and the one that was created in %edx.
+adze_failed_downcast:
+    mov %rdi, %rbx
+    mov %esi, %r12d
+    mov %edx, %r13d
+    lea .Lcast(%rip), %rdi
+    mov $(.Lcast_end - .Lcast), %esi
+    call adze_write
+    mov %r12, %rdi
+    call adze_write_name
+    lea .Lcreated(%rip), %rdi
+    mov $(.Lcreated_end - .Lcreated), %esi
+    call adze_write
+    mov %r13, %rdi
+    call adze_write_name
+    lea .Lquote(%rip), %rdi
+    mov $2, %esi
+    call adze_write
+    jmp adze_fail_at
+
+# Returns %rdi bytes of zeroed memory, aligned to 16 bytes, that are never freed.
+adze_allocate:
+    add $15, %rdi
+    and $-16, %rdi
+    mov adze_heap_next(%rip), %rax
+    mov adze_heap_end(%rip), %rcx
+    sub %rax, %rcx
+    cmp %rdi, %rcx
+    jae 1f
+    mov $0x100000, %esi
+    cmp %rsi, %rdi
+    cmova %rdi, %rsi
+    push %rdi
+    push %rsi
+    xor %edi, %edi
+    mov $3, %edx
+    mov $0x22, %r10d
+    mov $-1, %r8
+    xor %r9d, %r9d
+    mov $9, %eax
+    syscall
+    pop %rsi
+    pop %rdi
+    cmp $-4096, %rax
+    ja 2f
+    lea (%rax,%rsi), %rcx
+    mov %rcx, adze_heap_end(%rip)
+1:
+    lea (%rax,%rdi), %rcx
+    mov %rcx, adze_heap_next(%rip)
+    ret
+2:
+    lea .Lmemory(%rip), %rdi
+    mov $(.Lmemory_end - .Lmemory), %esi
+    call adze_write
+    mov $101, %edi
+    mov $231, %eax
+    syscall
+
+# Compares the string with the bytes %rdi and the length %rsi to that with the bytes %rdx and the length %rcx,
+# returning -1, 0 or 1 in %eax.
+adze_str_compare:
+    mov %rcx, %r8
+    mov %rsi, %r10
+    cmp %rcx, %r10
+    cmova %rcx, %r10
+    xor %r11d, %r11d
+1:
+    cmp %r10, %r11
+    je 3f
+    movzbl (%rdi,%r11), %eax
+    movzbl (%rdx,%r11), %ecx
+    inc %r11
+    cmp %ecx, %eax
+    je 1b
+    jb 2f
+    mov $1, %eax
+    ret
+2:
+    mov $-1, %eax
+    ret
+3:
+    xor %eax, %eax
+    cmp %r8, %rsi
+    je 4f
+    mov $1, %eax
+    ja 4f
+    mov $-1, %eax
+4:
+    ret
+
+# Whether the struct with the index %edi is the one with the index %esi or one of its substructs, in %eax.
+adze_is:
+    mov %edi, %edi
+    lea adze_parents(%rip), %rcx
+1:
+    cmp %esi, %edi
+    je 2f
+    movslq (%rcx,%rdi,4), %rdi
+    test %rdi, %rdi
+    jns 1b
+    xor %eax, %eax
+    ret
+2:
+    mov $1, %eax
+    ret
+
+    .data
+# The number of calls that are running, including that of the entry function.
+adze_depth:
+    .quad 1
+adze_heap_next:
+    .quad 0
+adze_heap_end:
+    .quad 0
+
+    .section .rodata
+.Lin:
+    .ascii "\n     |> In "
+.Lin_end:
+.Lcolon:
+    .ascii ":"
+.Lnewline:
+    .ascii "\n"
+.Lperiod:
+    .ascii "."
+.Lquote:
+    .ascii "'."
+.Ldivision:
+    .ascii "Error: Division by zero."
+.Ldivision_end:
+.Ldeep:
+    .ascii "Error: Calls are nested too deeply."
+.Ldeep_end:
+.Lindex:
+    .ascii "Error: Index "
+.Lindex_end:
+.Lbounds:
+    .ascii " is out of bounds for an array of length "
+.Lbounds_end:
+.Lcast:
+    .ascii "Error: Cannot cast to a reference to '"
+.Lcast_end:
+.Lcreated:
+    .ascii "', since the value was created as '"
+.Lcreated_end:
+.Lmemory:
+    .ascii "Error: Out of memory.\n"
+.Lmemory_end:
+"#;
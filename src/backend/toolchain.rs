@@ -0,0 +1,65 @@
+use std::ffi::OsString;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::options::OptLevel;
+use crate::source::Path;
+
+
+/// The environment variable naming the C compiler, with any options it needs, when it is not `cc`.
+pub const C_COMPILER_VARIABLE: &str = "CC";
+
+/// The environment variables naming the assembler and the linker, when they are not `as` and `ld`.
+pub const ASSEMBLER_VARIABLE: &str = "AS";
+pub const LINKER_VARIABLE: &str = "LD";
+
+/// Compiles a C translation unit, passed on standard input, to an executable with the system's C compiler,
+/// optimizing it as hard as the IR was. Strict aliasing is turned off, since the generated code reads the
+/// fields of a struct through pointers to its parents.
+pub fn compile_c(code: &str, output: &Path, level: OptLevel) -> Result<(), String> {
+    let compiler = std::env::var(C_COMPILER_VARIABLE).unwrap_or_else(|_| String::from("cc"));
+    let mut words = compiler.split_whitespace();
+    let program = words.next().unwrap_or("cc");
+    let optimize = match level {
+        OptLevel::O0 => "-O0",
+        OptLevel::O1 => "-O1",
+        OptLevel::O2 => "-O2"
+    };
+    let mut args: Vec<OsString> = words.map(OsString::from).collect();
+    args.extend(["-std=c11", optimize, "-fno-strict-aliasing", "-o"].map(OsString::from));
+    args.push(output.as_os_str().to_owned());
+    args.extend(["-x", "c", "-", "-lm"].map(OsString::from));
+    return run_tool(program, "C compiler", &args, Some(code));
+}
+
+/// Assembles x86-64 assembly, passed on standard input, with the GNU assembler, and links the object it makes
+/// into a static executable for Linux. The assembly includes its runtime, so nothing else is linked in.
+pub fn assemble(code: &str, output: &Path) -> Result<(), String> {
+    let assembler = std::env::var(ASSEMBLER_VARIABLE).unwrap_or_else(|_| String::from("as"));
+    let linker = std::env::var(LINKER_VARIABLE).unwrap_or_else(|_| String::from("ld"));
+    let mut object = output.as_os_str().to_owned();
+    object.push(".o");
+    let result = run_tool(&assembler, "assembler", &[OsString::from("-o"), object.clone(), OsString::from("-")], Some(code))
+        .and_then(|_| run_tool(&linker, "linker", &[OsString::from("-o"), output.as_os_str().to_owned(), object.clone()], None));
+    let _ = std::fs::remove_file(&object);
+    return result;
+}
+
+/// Runs an external program, writing `input` to its standard input if it is given, and fails if it cannot be
+/// started or does not exit successfully. The program reports its own errors.
+pub fn run_tool(program: &str, description: &str, args: &[OsString], input: Option<&str>) -> Result<(), String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .spawn()
+        .map_err(|e| format!("Could not run the {} '{}': {}.", description, program, e))?;
+    if let Some(input) = input {
+        // A program that exits without reading all of its input closes the pipe, which is reported by its status.
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    }
+    let status = child.wait().map_err(|e| format!("Could not run the {} '{}': {}.", description, program, e))?;
+    if !status.success() {
+        return Err(format!("The {} '{}' failed with {}.", description, program, status));
+    }
+    return Ok(());
+}
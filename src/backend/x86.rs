@@ -0,0 +1,1004 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::source::{SourceMap, Span, FileId};
+use crate::typecheck::{ConstValue, Primitive};
+use crate::ir::{Module, Function, Type, InstKind, Terminator, BlockTarget, UnaryOp, BinaryOp, ValueId, InstId, FuncId};
+use crate::backend::layout::Layouts;
+use crate::backend::regalloc::{Reg, Location, Allocation, allocate, is_integer_class, quadwords};
+use crate::backend::runtime::RUNTIME;
+
+
+/// The registers the System V ABI passes integer arguments in, in order.
+const ARGUMENT_REGISTERS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+/// The number of `%xmm` registers the System V ABI passes float arguments in.
+const FLOAT_ARGUMENT_REGISTERS: usize = 8;
+
+/// The most calls that can be nested, as in the VM.
+const MAX_CALL_DEPTH: u64 = 10_000;
+
+
+/// Translates a module to x86-64 assembly for the GNU assembler, to be linked with the runtime it includes into
+/// an executable for Linux that does not need a C library.
+///
+/// Functions follow the System V calling convention: integers, pointers and strings are passed in
+/// general-purpose registers, floats in `%xmm` registers and the rest on the stack. Structs and arrays are only
+/// ever in memory, so they are passed by reference, as pointers. Each function has a frame below `%rbp` with a
+/// slot for each value that is not kept in a register, and the memory of its allocas. Integer values are kept
+/// sign- or zero-extended to 64 bits, and arithmetic on them is done on 64 bits and extended again.
+///
+/// Statements are marked with `.loc` directives for the source they were lowered from, so that debuggers can
+/// show it.
+pub fn emit_asm(module: &Module, sources: &SourceMap) -> String {
+    let mut emitter = Emitter {
+        module,
+        sources,
+        layouts: Layouts::new(module),
+        out: String::new(),
+        strings: Vec::new(),
+        sites: Vec::new(),
+        labels: 0,
+        position: None
+    };
+    emitter.out.push_str(RUNTIME);
+    emitter.out.push_str("\n    .text\n");
+    for (id, source) in sources.files() {
+        writeln!(emitter.out, "    .file {} \"{}\"", id.0 + 1, asm_string(source.name.as_bytes())).unwrap();
+    }
+
+    emitter.out.push_str("\nadze_main:\n");
+    match module.entry {
+        Some(entry) => {
+            emitter.ins("sub $8, %rsp");
+            emitter.ins(format!("call {}", function_name(module, entry)));
+            emitter.ins("add $8, %rsp");
+            if module.function(entry).ret == Type::UNIT {
+                emitter.ins("xor %eax, %eax");
+            } else {
+                emitter.ins("movzbl %al, %eax");
+            }
+        },
+        None => emitter.ins("xor %eax, %eax")
+    }
+    emitter.ins("ret");
+    for index in 0..module.functions.len() {
+        FunctionEmitter::new(&mut emitter, FuncId(index as u32)).emit();
+    }
+    emitter.emit_data();
+    return emitter.out;
+}
+
+
+struct Emitter<'a> {
+    module: &'a Module,
+    sources: &'a SourceMap,
+    layouts: Layouts,
+    out: String,
+    /// The string constants, which are placed in read-only data.
+    strings: Vec<String>,
+    /// The file, line and column of each place the program can stop with an error.
+    sites: Vec<(FileId, usize, usize)>,
+    labels: usize,
+    /// The file and line of the last `.loc` directive in the current function.
+    position: Option<(FileId, usize)>
+}
+
+impl<'a> Emitter<'a> {
+    fn ins(&mut self, text: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        return format!(".L{}", self.labels);
+    }
+
+    /// Marks the code that follows as lowered from a line of source, unless the last mark was for it.
+    fn mark(&mut self, loc: Span) {
+        let source = self.sources.get(loc.file);
+        let (line, column) = source.line_col(loc.lo as usize);
+        if self.position != Some((loc.file, line)) {
+            writeln!(self.out, "    .loc {} {} {}", loc.file.0 + 1, line + 1, column + 1).unwrap();
+            self.position = Some((loc.file, line));
+        }
+    }
+
+    /// The label of the location of an error at a span, for the runtime's error functions.
+    fn site(&mut self, loc: Span) -> String {
+        let (line, column) = self.sources.get(loc.file).line_col(loc.lo as usize);
+        self.sites.push((loc.file, line + 1, column + 1));
+        return format!(".Lsite{}", self.sites.len() - 1);
+    }
+
+    fn string(&mut self, text: &str) -> String {
+        let index = match self.strings.iter().position(|string| string == text) {
+            Some(index) => index,
+            None => {
+                self.strings.push(String::from(text));
+                self.strings.len() - 1
+            }
+        };
+        return format!(".Lstr{}", index);
+    }
+
+    /// The tables the runtime reads, and the constants the code refers to.
+    fn emit_data(&mut self) {
+        self.out.push_str("\n    .section .rodata\n    .p2align 3\nadze_files:\n");
+        let files: Vec<(FileId, usize)> = self.sources.files().map(|(id, source)| (id, source.name.len())).collect();
+        for (id, length) in &files {
+            writeln!(self.out, "    .quad .Lfile{}, {}", id.0, length).unwrap();
+        }
+        self.out.push_str("adze_struct_names:\n");
+        for (index, def) in self.module.structs.iter().enumerate() {
+            writeln!(self.out, "    .quad .Lname{}, {}", index, def.name.len()).unwrap();
+        }
+        for (index, (file, line, column)) in self.sites.iter().enumerate() {
+            writeln!(self.out, ".Lsite{}:\n    .quad {}, {}, {}", index, file.0, line, column).unwrap();
+        }
+        self.out.push_str("adze_parents:\n");
+        for def in &self.module.structs {
+            writeln!(self.out, "    .long {}", def.parent.map_or(-1, |parent| parent.0 as i64)).unwrap();
+        }
+        for (id, source) in self.sources.files() {
+            writeln!(self.out, ".Lfile{}:\n    .ascii \"{}\"", id.0, asm_string(source.name.as_bytes())).unwrap();
+        }
+        for (index, def) in self.module.structs.iter().enumerate() {
+            writeln!(self.out, ".Lname{}:\n    .ascii \"{}\"", index, asm_string(def.name.as_bytes())).unwrap();
+        }
+        for (index, string) in self.strings.iter().enumerate() {
+            writeln!(self.out, ".Lstr{}:\n    .ascii \"{}\"", index, asm_string(string.as_bytes())).unwrap();
+        }
+    }
+}
+
+
+/// How a value is passed to a function: in the general-purpose registers from the given index, in the `%xmm`
+/// register with the given index, or on the stack from the given quadword.
+#[derive(Clone, Copy)]
+enum Passing {
+    Registers(usize),
+    Float(usize),
+    Stack(u64)
+}
+
+/// Where each of a function's parameters or a call's arguments is passed under the System V ABI, and the
+/// number of quadwords passed on the stack. A string that does not fit in the registers that are left is
+/// passed on the stack, while later arguments still take the registers.
+fn classify(types: &[&Type]) -> (Vec<Passing>, u64) {
+    let (mut registers, mut floats, mut stack) = (0, 0, 0);
+    let mut passing = Vec::new();
+    for typ in types {
+        let words = quadwords(typ) as usize;
+        let is_float = typ.primitive().is_some_and(|p| p.is_float());
+        passing.push(if is_float && floats < FLOAT_ARGUMENT_REGISTERS {
+            floats += 1;
+            Passing::Float(floats - 1)
+        } else if !is_float && registers + words <= ARGUMENT_REGISTERS.len() {
+            registers += words;
+            Passing::Registers(registers - words)
+        } else {
+            stack += words as u64;
+            Passing::Stack(stack - words as u64)
+        });
+    }
+    return (passing, stack);
+}
+
+
+struct FunctionEmitter<'e, 'a> {
+    emitter: &'e mut Emitter<'a>,
+    id: FuncId,
+    function: &'a Function,
+    allocation: Allocation,
+    /// The memory of the allocas that do not escape, as their depth in the frame.
+    memory: HashMap<ValueId, u64>,
+    /// Where the arguments of a branch are held while they are assigned to the target's parameters.
+    scratch: u64,
+    /// The size of the frame below the saved registers, which keeps the stack aligned to 16 bytes.
+    frame: u64
+}
+
+impl<'e, 'a> FunctionEmitter<'e, 'a> {
+    fn new(emitter: &'e mut Emitter<'a>, id: FuncId) -> FunctionEmitter<'e, 'a> {
+        let function = emitter.module.function(id);
+        let allocation = allocate(function);
+        let mut depth = allocation.frame;
+        let mut memory = HashMap::new();
+        for inst in &function.block(Function::ENTRY).insts {
+            let inst = function.inst(*inst);
+            if let (InstKind::Alloca(typ), Some(result)) = (&inst.kind, inst.result) {
+                if !function.escapes(result) {
+                    // Nothing needs more alignment than the 8 bytes every slot has.
+                    depth = (depth + emitter.layouts.size(typ).max(1)).next_multiple_of(8);
+                    memory.insert(result, depth);
+                }
+            }
+        }
+        let arguments = function.blocks.iter().flat_map(|block| block.terminator.targets()).map(|target| target.args.len()).max().unwrap_or(0);
+        depth += 16 * arguments as u64;
+        let scratch = depth;
+        let saved = allocation.saved.len() as u64;
+        let frame = depth.next_multiple_of(16) + if saved % 2 == 1 { 8 } else { 0 };
+        return FunctionEmitter { emitter, id, function, allocation, memory, scratch, frame };
+    }
+
+    fn ins(&mut self, text: impl AsRef<str>) {
+        self.emitter.ins(text);
+    }
+
+    /// The operand for a slot at a depth below the saved registers, or for a quadword of it.
+    fn slot(&self, depth: u64, word: u64) -> String {
+        let offset = -((8 * self.allocation.saved.len() as u64 + depth) as i64) + 8 * word as i64;
+        return format!("{}(%rbp)", offset);
+    }
+
+    /// The operand for a quadword of a value.
+    fn operand(&self, value: ValueId, word: u64) -> String {
+        match self.allocation.locations[value.0 as usize].expect("every value that is used is allocated") {
+            Location::Register(reg) => String::from(reg.q()),
+            Location::Stack(depth) => self.slot(depth, word)
+        }
+    }
+
+    fn typ(&self, value: ValueId) -> &'a Type {
+        self.function.typ(value)
+    }
+
+    fn primitive(&self, value: ValueId) -> Primitive {
+        self.function.typ(value).primitive().expect("operators apply to primitives")
+    }
+
+    fn load(&mut self, value: ValueId, reg: Reg) {
+        let operand = self.operand(value, 0);
+        if operand != reg.q() {
+            self.ins(format!("mov {}, {}", operand, reg.q()));
+        }
+    }
+
+    fn store(&mut self, value: ValueId, reg: Reg) {
+        let operand = self.operand(value, 0);
+        if operand != reg.q() {
+            self.ins(format!("mov {}, {}", reg.q(), operand));
+        }
+    }
+
+    fn load_str(&mut self, value: ValueId, bytes: Reg, length: Reg) {
+        let (first, second) = (self.operand(value, 0), self.operand(value, 1));
+        self.ins(format!("mov {}, {}", first, bytes.q()));
+        self.ins(format!("mov {}, {}", second, length.q()));
+    }
+
+    fn store_str(&mut self, value: ValueId, bytes: Reg, length: Reg) {
+        let (first, second) = (self.operand(value, 0), self.operand(value, 1));
+        self.ins(format!("mov {}, {}", bytes.q(), first));
+        self.ins(format!("mov {}, {}", length.q(), second));
+    }
+
+    /// Loads a float into an `%xmm` register. Floats are always kept in the frame.
+    fn load_float(&mut self, value: ValueId, xmm: usize) {
+        let instruction = if self.primitive(value) == Primitive::F32 { "movss" } else { "movsd" };
+        let operand = self.operand(value, 0);
+        self.ins(format!("{} {}, %xmm{}", instruction, operand, xmm));
+    }
+
+    fn store_float(&mut self, value: ValueId, xmm: usize) {
+        let instruction = if self.primitive(value) == Primitive::F32 { "movss" } else { "movsd" };
+        let operand = self.operand(value, 0);
+        self.ins(format!("{} %xmm{}, {}", instruction, xmm, operand));
+    }
+
+    /// Sign- or zero-extends the low bits of `%rax` that hold an integer of the type to the whole register.
+    fn extend(&mut self, typ: Primitive) {
+        match typ {
+            Primitive::I8 => self.ins("movsbq %al, %rax"),
+            Primitive::I16 => self.ins("movswq %ax, %rax"),
+            Primitive::I32 => self.ins("movslq %eax, %rax"),
+            Primitive::U8 | Primitive::Bool => self.ins("movzbl %al, %eax"),
+            Primitive::U16 => self.ins("movzwl %ax, %eax"),
+            Primitive::U32 | Primitive::Char => self.ins("mov %eax, %eax"),
+            _ => { }
+        }
+    }
+
+    fn immediate(&mut self, value: i64, reg: Reg) {
+        if i32::try_from(value).is_ok() {
+            self.ins(format!("mov ${}, {}", value, reg.q()));
+        } else {
+            self.ins(format!("movabs ${}, {}", value, reg.q()));
+        }
+    }
+
+    fn emit(mut self) {
+        let function = self.function;
+        let name = function_name(self.emitter.module, self.id);
+        self.emitter.position = None;
+        writeln!(self.emitter.out, "\n{}:", name).unwrap();
+        self.emitter.mark(function.loc);
+        self.ins("push %rbp");
+        self.ins("mov %rsp, %rbp");
+        for reg in self.allocation.saved.clone() {
+            self.ins(format!("push {}", reg.q()));
+        }
+        if self.frame != 0 {
+            self.ins(format!("sub ${}, %rsp", self.frame));
+        }
+
+        let params = &function.block(Function::ENTRY).params;
+        let types: Vec<&Type> = params.iter().map(|param| function.typ(*param)).collect();
+        let (passing, _) = classify(&types);
+        for (param, passing) in params.iter().zip(passing) {
+            let typ = function.typ(*param);
+            match passing {
+                Passing::Registers(index) if quadwords(typ) == 2 => self.store_str(*param, ARGUMENT_REGISTERS[index], ARGUMENT_REGISTERS[index + 1]),
+                Passing::Registers(index) => self.store(*param, ARGUMENT_REGISTERS[index]),
+                Passing::Float(index) => self.store_float(*param, index),
+                Passing::Stack(word) => {
+                    for part in 0..quadwords(typ) {
+                        self.ins(format!("mov {}(%rbp), %rax", 16 + 8 * (word + part)));
+                        let operand = self.operand(*param, part);
+                        self.ins(format!("mov %rax, {}", operand));
+                    }
+                }
+            }
+        }
+
+        for (index, block) in function.blocks.iter().enumerate() {
+            if index != 0 {
+                writeln!(self.emitter.out, "{}:", self.block_label(index)).unwrap();
+            }
+            for inst in &block.insts {
+                self.emitter.mark(function.inst(*inst).loc);
+                self.instruction(*inst);
+            }
+            self.emitter.mark(block.terminator_loc);
+            self.terminator(&block.terminator);
+        }
+    }
+
+    fn block_label(&self, block: usize) -> String {
+        format!(".Lf{}_{}", self.id.0, block)
+    }
+
+    fn epilogue(&mut self) {
+        if !self.allocation.saved.is_empty() {
+            self.ins(format!("lea -{}(%rbp), %rsp", 8 * self.allocation.saved.len()));
+        } else if self.frame != 0 {
+            self.ins("mov %rbp, %rsp");
+        }
+        for reg in self.allocation.saved.clone().iter().rev() {
+            self.ins(format!("pop {}", reg.q()));
+        }
+        self.ins("pop %rbp");
+        self.ins("ret");
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Jump(target) => self.jump(target),
+            Terminator::Branch(condition, then, otherwise) => {
+                let label = self.emitter.label();
+                self.load(*condition, Reg::Rax);
+                self.ins("test %al, %al");
+                self.ins(format!("jz {}", label));
+                self.jump(then);
+                writeln!(self.emitter.out, "{}:", label).unwrap();
+                self.jump(otherwise);
+            },
+            Terminator::Return(value) => {
+                match value {
+                    Some(value) if quadwords(self.typ(*value)) == 2 => self.load_str(*value, Reg::Rax, Reg::Rdx),
+                    Some(value) if self.typ(*value).primitive().is_some_and(|p| p.is_float()) => self.load_float(*value, 0),
+                    Some(value) => self.load(*value, Reg::Rax),
+                    None => { }
+                }
+                self.epilogue();
+            },
+            Terminator::Unreachable => self.ins("ud2")
+        }
+    }
+
+    /// Assigns the arguments of a branch to the parameters of its target and goes there. When a parameter is
+    /// kept where one of the arguments is, every argument is copied to scratch space first, so none is
+    /// overwritten before it is read.
+    fn jump(&mut self, target: &BlockTarget) {
+        let params = &self.function.block(target.block).params;
+        let mut moves = Vec::new();
+        for (param, arg) in params.iter().zip(&target.args) {
+            for word in 0..quadwords(self.typ(*arg)) {
+                let (from, to) = (self.operand(*arg, word), self.operand(*param, word));
+                if from != to {
+                    moves.push((from, to));
+                }
+            }
+        }
+        let overlapping = moves.iter().any(|(_, to)| moves.iter().any(|(from, _)| from == to));
+        if overlapping {
+            for (index, (from, _)) in moves.iter_mut().enumerate() {
+                let scratch = self.slot(self.scratch, index as u64);
+                self.emitter.ins(format!("mov {}, %rax", from));
+                self.emitter.ins(format!("mov %rax, {}", scratch));
+                *from = scratch;
+            }
+        }
+        for (from, to) in moves {
+            if from.starts_with('%') || to.starts_with('%') {
+                self.ins(format!("mov {}, {}", from, to));
+            } else {
+                self.ins(format!("mov {}, %rax", from));
+                self.ins(format!("mov %rax, {}", to));
+            }
+        }
+        let label = self.block_label(target.block.0 as usize);
+        self.ins(format!("jmp {}", label));
+    }
+
+    fn instruction(&mut self, id: InstId) {
+        let inst = self.function.inst(id);
+        let Some(result) = inst.result else {
+            return self.effect(id);
+        };
+        let typ = self.function.typ(result).clone();
+        match &inst.kind {
+            InstKind::Const(value) => self.constant(value, &typ, result),
+            InstKind::Unary(op, operand) => {
+                let operand_type = self.primitive(*operand);
+                self.load(*operand, Reg::Rax);
+                match op {
+                    UnaryOp::Not => self.ins("xor $1, %eax"),
+                    UnaryOp::Neg if operand_type == Primitive::F32 => self.ins("btc $31, %eax"),
+                    UnaryOp::Neg if operand_type == Primitive::F64 => self.ins("btc $63, %rax"),
+                    UnaryOp::Neg => {
+                        self.ins("neg %rax");
+                        self.extend(operand_type);
+                    }
+                }
+                self.store(result, Reg::Rax);
+            },
+            InstKind::Binary(op, left, right) => self.binary(*op, *left, *right, result, inst.loc),
+            InstKind::Cast(operand) => self.cast(*operand, result),
+            InstKind::Call(..) | InstKind::CallIndirect(..) => self.call(id),
+            InstKind::Function(callee) => {
+                let name = function_name(self.emitter.module, *callee);
+                self.ins(format!("lea {}(%rip), %rax", name));
+                self.store(result, Reg::Rax);
+            },
+            InstKind::Alloca(allocated) => {
+                match self.memory.get(&result) {
+                    Some(depth) => {
+                        let slot = self.slot(*depth, 0);
+                        self.ins(format!("lea {}, %rax", slot));
+                    },
+                    None => {
+                        // Memory that a pointer to can outlive the call is allocated on the heap, as the VM
+                        // keeps it for as long as a reference to it is held.
+                        let size = self.emitter.layouts.size(allocated);
+                        self.ins(format!("mov ${}, %edi", size));
+                        self.ins("call adze_allocate");
+                    }
+                }
+                self.store(result, Reg::Rax);
+            },
+            InstKind::Load(ptr) => {
+                self.load(*ptr, Reg::Rcx);
+                match typ.primitive() {
+                    Some(Primitive::Str) => {
+                        self.ins("mov (%rcx), %rax");
+                        self.ins("mov 8(%rcx), %rdx");
+                        self.store_str(result, Reg::Rax, Reg::Rdx);
+                        return;
+                    },
+                    Some(Primitive::I8) => self.ins("movsbq (%rcx), %rax"),
+                    Some(Primitive::I16) => self.ins("movswq (%rcx), %rax"),
+                    Some(Primitive::I32) => self.ins("movslq (%rcx), %rax"),
+                    Some(Primitive::U8 | Primitive::Bool) => self.ins("movzbl (%rcx), %eax"),
+                    Some(Primitive::U16) => self.ins("movzwl (%rcx), %eax"),
+                    Some(Primitive::U32 | Primitive::Char | Primitive::F32) => self.ins("mov (%rcx), %eax"),
+                    _ => self.ins("mov (%rcx), %rax")
+                }
+                self.store(result, Reg::Rax);
+            },
+            InstKind::Field { typ: owner, ptr, index } => {
+                let offset = self.emitter.layouts.struct_layout(*owner).offsets[*index];
+                self.load(*ptr, Reg::Rax);
+                if offset != 0 {
+                    self.ins(format!("add ${}, %rax", offset));
+                }
+                self.store(result, Reg::Rax);
+            },
+            InstKind::Index { typ: array, ptr, index } => {
+                let Type::Array(element, length) = array else {
+                    unreachable!("the IR is verified")
+                };
+                let stride = self.emitter.layouts.size(element);
+                let site = self.emitter.site(inst.loc);
+                let label = self.emitter.label();
+                self.load(*index, Reg::Rcx);
+                self.immediate(*length as i64, Reg::Rdx);
+                self.ins("cmp %rdx, %rcx");
+                self.ins(format!("jb {}", label));
+                self.ins("mov %rcx, %rsi");
+                self.ins(format!("lea {}(%rip), %rdi", site));
+                self.ins("call adze_index_out_of_bounds");
+                writeln!(self.emitter.out, "{}:", label).unwrap();
+                self.immediate(stride as i64, Reg::Rdx);
+                self.ins("imul %rdx, %rcx");
+                self.load(*ptr, Reg::Rax);
+                self.ins("add %rcx, %rax");
+                self.store(result, Reg::Rax);
+            },
+            InstKind::Is(ptr, target) => {
+                self.load(*ptr, Reg::Rax);
+                self.ins("mov (%rax), %edi");
+                self.ins(format!("mov ${}, %esi", target.0));
+                self.ins("call adze_is");
+                self.store(result, Reg::Rax);
+            },
+            InstKind::Downcast(ptr, target) => {
+                let site = self.emitter.site(inst.loc);
+                let label = self.emitter.label();
+                self.load(*ptr, Reg::R8);
+                self.ins("mov (%r8), %edi");
+                self.ins(format!("mov ${}, %esi", target.0));
+                self.ins("call adze_is");
+                self.ins("test %eax, %eax");
+                self.ins(format!("jnz {}", label));
+                self.ins("mov (%r8), %edx");
+                self.ins(format!("mov ${}, %esi", target.0));
+                self.ins(format!("lea {}(%rip), %rdi", site));
+                self.ins("call adze_failed_downcast");
+                writeln!(self.emitter.out, "{}:", label).unwrap();
+                self.store(result, Reg::R8);
+            },
+            InstKind::Store { .. } | InstKind::Copy { .. } | InstKind::SetTag(..) => unreachable!("these instructions have no result")
+        }
+    }
+
+    /// The instructions that have no result.
+    fn effect(&mut self, id: InstId) {
+        let inst = self.function.inst(id);
+        match &inst.kind {
+            InstKind::Store { value, ptr } => {
+                let typ = self.typ(*value);
+                self.load(*ptr, Reg::Rcx);
+                if quadwords(typ) == 2 {
+                    self.load_str(*value, Reg::Rax, Reg::Rdx);
+                    self.ins("mov %rax, (%rcx)");
+                    self.ins("mov %rdx, 8(%rcx)");
+                    return;
+                }
+                self.load(*value, Reg::Rax);
+                let size = self.emitter.layouts.size(typ);
+                self.ins(format!("mov {}, (%rcx)", Reg::Rax.name(size)));
+            },
+            InstKind::Copy { typ, dest, src } => {
+                let size = self.emitter.layouts.size(typ);
+                self.load(*dest, Reg::Rdi);
+                self.load(*src, Reg::Rsi);
+                self.ins(format!("mov ${}, %ecx", size));
+                self.ins("rep movsb");
+            },
+            InstKind::SetTag(target, ptr) => {
+                self.load(*ptr, Reg::Rax);
+                self.ins(format!("movl ${}, (%rax)", target.0));
+            },
+            InstKind::Call(..) | InstKind::CallIndirect(..) => self.call(id),
+            _ => unreachable!("every other instruction has a result")
+        }
+    }
+
+    fn constant(&mut self, value: &ConstValue, typ: &Type, result: ValueId) {
+        match value {
+            ConstValue::Integer(n) => self.immediate(*n as i64, Reg::Rax),
+            ConstValue::Float(f) if *typ == Type::Primitive(Primitive::F32) => self.immediate((*f as f32).to_bits() as i64, Reg::Rax),
+            ConstValue::Float(f) => self.immediate(f.to_bits() as i64, Reg::Rax),
+            ConstValue::Bool(b) => self.immediate(*b as i64, Reg::Rax),
+            ConstValue::Char(c) => self.immediate(*c as i64, Reg::Rax),
+            ConstValue::Str(s) => {
+                let label = self.emitter.string(s);
+                self.ins(format!("lea {}(%rip), %rax", label));
+                self.immediate(s.len() as i64, Reg::Rdx);
+                return self.store_str(result, Reg::Rax, Reg::Rdx);
+            }
+        }
+        self.store(result, Reg::Rax);
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: ValueId, right: ValueId, result: ValueId, loc: Span) {
+        let typ = self.function.typ(left).primitive();
+        if typ == Some(Primitive::Str) {
+            self.load_str(left, Reg::Rdi, Reg::Rsi);
+            self.load_str(right, Reg::Rdx, Reg::Rcx);
+            self.ins("call adze_str_compare");
+            self.ins("test %eax, %eax");
+            self.ins(format!("set{} %al", condition(op, true)));
+            self.ins("movzbl %al, %eax");
+            return self.store(result, Reg::Rax);
+        }
+        if let Some(typ) = typ.filter(|typ| typ.is_float()) {
+            return self.float_binary(op, typ, left, right, result);
+        }
+
+        let signed = typ.is_some_and(|typ| typ.is_signed());
+        self.load(left, Reg::Rax);
+        self.load(right, Reg::Rcx);
+        if op.is_comparison() {
+            self.ins("cmp %rcx, %rax");
+            self.ins(format!("set{} %al", condition(op, signed)));
+            self.ins("movzbl %al, %eax");
+            return self.store(result, Reg::Rax);
+        }
+        let typ = typ.expect("arithmetic is on primitives");
+        match op {
+            BinaryOp::Add => self.ins("add %rcx, %rax"),
+            BinaryOp::Sub => self.ins("sub %rcx, %rax"),
+            BinaryOp::Mul => self.ins("imul %rcx, %rax"),
+            _ => {
+                let site = self.emitter.site(loc);
+                let (checked, done) = (self.emitter.label(), self.emitter.label());
+                self.ins("test %rcx, %rcx");
+                self.ins(format!("jnz {}", checked));
+                self.ins(format!("lea {}(%rip), %rdi", site));
+                self.ins("call adze_division_by_zero");
+                writeln!(self.emitter.out, "{}:", checked).unwrap();
+                if signed {
+                    // Dividing the smallest integer by -1 overflows, so -1 is handled by itself: the quotient
+                    // wraps around and the remainder is 0.
+                    let divide = self.emitter.label();
+                    self.ins("cmp $-1, %rcx");
+                    self.ins(format!("jne {}", divide));
+                    self.ins(if op == BinaryOp::Div { "neg %rax" } else { "xor %eax, %eax" });
+                    self.ins(format!("jmp {}", done));
+                    writeln!(self.emitter.out, "{}:", divide).unwrap();
+                    self.ins("cqo");
+                    self.ins("idiv %rcx");
+                } else {
+                    self.ins("xor %edx, %edx");
+                    self.ins("div %rcx");
+                }
+                if op == BinaryOp::Rem {
+                    self.ins("mov %rdx, %rax");
+                }
+                writeln!(self.emitter.out, "{}:", done).unwrap();
+            }
+        }
+        self.extend(typ);
+        self.store(result, Reg::Rax);
+    }
+
+    fn float_binary(&mut self, op: BinaryOp, typ: Primitive, left: ValueId, right: ValueId, result: ValueId) {
+        let suffix = if typ == Primitive::F32 { "ss" } else { "sd" };
+        if op == BinaryOp::Rem {
+            // The x87 partial remainder is repeated until it is complete, which gives `fmod` exactly, so it is
+            // also the remainder of `f32`s.
+            let (load, store) = if typ == Primitive::F32 { ("flds", "fstps") } else { ("fldl", "fstpl") };
+            let (right, left, result) = (self.operand(right, 0), self.operand(left, 0), self.operand(result, 0));
+            let again = self.emitter.label();
+            self.ins(format!("{} {}", load, right));
+            self.ins(format!("{} {}", load, left));
+            writeln!(self.emitter.out, "{}:", again).unwrap();
+            self.ins("fprem");
+            self.ins("fnstsw %ax");
+            self.ins("testb $4, %ah");
+            self.ins(format!("jnz {}", again));
+            self.ins(format!("{} {}", store, result));
+            self.ins("fstp %st(0)");
+            return;
+        }
+        self.load_float(left, 0);
+        self.load_float(right, 1);
+        // `ucomis` sets the flags as an unsigned comparison would, with every flag set when either is NaN.
+        let compare = format!("ucomi{}", suffix);
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                let name = match op { BinaryOp::Add => "add", BinaryOp::Sub => "sub", BinaryOp::Mul => "mul", _ => "div" };
+                self.ins(format!("{}{} %xmm1, %xmm0", name, suffix));
+                return self.store_float(result, 0);
+            },
+            BinaryOp::Eq => {
+                self.ins(format!("{} %xmm1, %xmm0", compare));
+                self.ins("sete %al");
+                self.ins("setnp %cl");
+                self.ins("and %cl, %al");
+            },
+            BinaryOp::Ne => {
+                self.ins(format!("{} %xmm1, %xmm0", compare));
+                self.ins("setne %al");
+                self.ins("setp %cl");
+                self.ins("or %cl, %al");
+            },
+            BinaryOp::Gt | BinaryOp::Ge => {
+                self.ins(format!("{} %xmm1, %xmm0", compare));
+                self.ins(if op == BinaryOp::Gt { "seta %al" } else { "setae %al" });
+            },
+            BinaryOp::Lt | BinaryOp::Le => {
+                self.ins(format!("{} %xmm0, %xmm1", compare));
+                self.ins(if op == BinaryOp::Lt { "seta %al" } else { "setae %al" });
+            },
+            BinaryOp::Rem => unreachable!()
+        }
+        self.ins("movzbl %al, %eax");
+        self.store(result, Reg::Rax);
+    }
+
+    /// A cast between primitives, as `fold_cast` does it: integers wrap around, floats saturate at the bounds
+    /// of the integer type they are cast to with NaN becoming 0, and integers become the `char` of their low
+    /// byte.
+    fn cast(&mut self, operand: ValueId, result: ValueId) {
+        let (from, to) = (self.primitive(operand), self.primitive(result));
+        if from.is_float() && to.is_float() {
+            self.load_float(operand, 0);
+            if from != to {
+                self.ins(if to == Primitive::F32 { "cvtsd2ss %xmm0, %xmm0" } else { "cvtss2sd %xmm0, %xmm0" });
+            }
+            return self.store_float(result, 0);
+        }
+        if from.is_float() {
+            return self.float_to_integer(operand, from, to, result);
+        }
+        self.load(operand, Reg::Rax);
+        if to.is_float() {
+            let suffix = if to == Primitive::F32 { "ss" } else { "sd" };
+            self.ins("xorps %xmm0, %xmm0");
+            if from == Primitive::U64 {
+                // Integers from 2^63 up are halved, keeping the lowest bit so that they round the same, then
+                // converted and doubled.
+                let (large, done) = (self.emitter.label(), self.emitter.label());
+                self.ins("test %rax, %rax");
+                self.ins(format!("js {}", large));
+                self.ins(format!("cvtsi2{}q %rax, %xmm0", suffix));
+                self.ins(format!("jmp {}", done));
+                writeln!(self.emitter.out, "{}:", large).unwrap();
+                self.ins("mov %rax, %rcx");
+                self.ins("shr %rcx");
+                self.ins("and $1, %eax");
+                self.ins("or %rax, %rcx");
+                self.ins(format!("cvtsi2{}q %rcx, %xmm0", suffix));
+                self.ins(format!("add{} %xmm0, %xmm0", suffix));
+                writeln!(self.emitter.out, "{}:", done).unwrap();
+            } else {
+                self.ins(format!("cvtsi2{}q %rax, %xmm0", suffix));
+            }
+            return self.store_float(result, 0);
+        }
+        match to {
+            Primitive::Char => self.extend(Primitive::U8),
+            Primitive::Bool => {
+                self.ins("test %rax, %rax");
+                self.ins("setne %al");
+                self.extend(Primitive::Bool);
+            },
+            _ => self.extend(to)
+        }
+        self.store(result, Reg::Rax);
+    }
+
+    fn float_to_integer(&mut self, operand: ValueId, from: Primitive, to: Primitive, result: ValueId) {
+        let (min, max) = to.integer_range().unwrap_or((0, 255));
+        let (in_range, done) = (self.emitter.label(), self.emitter.label());
+        let (at_min, at_max) = (self.emitter.label(), self.emitter.label());
+        self.load_float(operand, 0);
+        if from == Primitive::F32 {
+            self.ins("cvtss2sd %xmm0, %xmm0");
+        }
+        self.ins("xor %eax, %eax");
+        self.ins("ucomisd %xmm0, %xmm0");
+        self.ins(format!("jp {}", done));
+        self.immediate((min as f64).to_bits() as i64, Reg::Rcx);
+        self.ins("movq %rcx, %xmm1");
+        self.ins("ucomisd %xmm1, %xmm0");
+        self.ins(format!("jbe {}", at_min));
+        self.immediate((max as f64).to_bits() as i64, Reg::Rcx);
+        self.ins("movq %rcx, %xmm1");
+        self.ins("ucomisd %xmm1, %xmm0");
+        self.ins(format!("jae {}", at_max));
+        writeln!(self.emitter.out, "{}:", in_range).unwrap();
+        if to == Primitive::U64 {
+            // Only integers below 2^63 can be converted directly, so larger ones are converted less 2^63.
+            let small = self.emitter.label();
+            self.immediate((2f64.powi(63)).to_bits() as i64, Reg::Rcx);
+            self.ins("movq %rcx, %xmm1");
+            self.ins("ucomisd %xmm1, %xmm0");
+            self.ins(format!("jb {}", small));
+            self.ins("subsd %xmm1, %xmm0");
+            self.ins("cvttsd2si %xmm0, %rax");
+            self.ins("btc $63, %rax");
+            self.ins(format!("jmp {}", done));
+            writeln!(self.emitter.out, "{}:", small).unwrap();
+        }
+        self.ins("cvttsd2si %xmm0, %rax");
+        self.ins(format!("jmp {}", done));
+        writeln!(self.emitter.out, "{}:", at_min).unwrap();
+        self.immediate(min as i64, Reg::Rax);
+        self.ins(format!("jmp {}", done));
+        writeln!(self.emitter.out, "{}:", at_max).unwrap();
+        self.immediate(max as i64, Reg::Rax);
+        writeln!(self.emitter.out, "{}:", done).unwrap();
+        if to == Primitive::Char {
+            self.extend(Primitive::U8);
+        }
+        self.store(result, Reg::Rax);
+    }
+
+    /// A call, after checking that it is not nested too deeply. The arguments that go on the stack are pushed
+    /// first, from the last, so that loading the others into the argument registers cannot overwrite them.
+    fn call(&mut self, id: InstId) {
+        let inst = self.function.inst(id);
+        let (callee, args) = match &inst.kind {
+            InstKind::Call(callee, args) => (Some(*callee), args),
+            InstKind::CallIndirect(_, args) => (None, args),
+            _ => unreachable!("only calls are called")
+        };
+        let site = self.emitter.site(inst.loc);
+        let label = self.emitter.label();
+        self.ins(format!("cmpq ${}, adze_depth(%rip)", MAX_CALL_DEPTH));
+        self.ins(format!("jb {}", label));
+        self.ins(format!("lea {}(%rip), %rdi", site));
+        self.ins("call adze_too_deep");
+        writeln!(self.emitter.out, "{}:", label).unwrap();
+        self.ins("incq adze_depth(%rip)");
+
+        let types: Vec<&Type> = args.iter().map(|arg| self.typ(*arg)).collect();
+        let (passing, stack) = classify(&types);
+        let padding = if stack % 2 == 1 { 8 } else { 0 };
+        if padding != 0 {
+            self.ins("sub $8, %rsp");
+        }
+        for (arg, passing) in args.iter().zip(&passing).rev() {
+            if let Passing::Stack(_) = passing {
+                for word in (0..quadwords(self.typ(*arg))).rev() {
+                    let operand = self.operand(*arg, word);
+                    self.ins(format!("pushq {}", operand));
+                }
+            }
+        }
+        for (arg, passing) in args.iter().zip(&passing) {
+            match passing {
+                Passing::Registers(index) if quadwords(self.typ(*arg)) == 2 => self.load_str(*arg, ARGUMENT_REGISTERS[*index], ARGUMENT_REGISTERS[index + 1]),
+                Passing::Registers(index) => self.load(*arg, ARGUMENT_REGISTERS[*index]),
+                Passing::Float(index) => self.load_float(*arg, *index),
+                Passing::Stack(_) => { }
+            }
+        }
+        match (callee, &inst.kind) {
+            (Some(callee), _) => {
+                let name = function_name(self.emitter.module, callee);
+                self.ins(format!("call {}", name));
+            },
+            (None, InstKind::CallIndirect(pointer, _)) => {
+                self.load(*pointer, Reg::R11);
+                self.ins("call *%r11");
+            },
+            _ => unreachable!()
+        }
+        if stack != 0 {
+            self.ins(format!("add ${}, %rsp", 8 * stack + padding));
+        } else if padding != 0 {
+            self.ins("add $8, %rsp");
+        }
+        self.ins("decq adze_depth(%rip)");
+
+        let Some(result) = inst.result else {
+            return;
+        };
+        let typ = self.typ(result);
+        if *typ == Type::UNIT {
+            return;
+        } else if quadwords(typ) == 2 {
+            self.store_str(result, Reg::Rax, Reg::Rdx);
+        } else if is_integer_class(typ) {
+            self.store(result, Reg::Rax);
+        } else {
+            self.store_float(result, 0);
+        }
+    }
+}
+
+
+/// The condition code of a comparison of integers, or of the result of comparing strings with 0.
+fn condition(op: BinaryOp, signed: bool) -> &'static str {
+    match (op, signed) {
+        (BinaryOp::Eq, _) => "e",
+        (BinaryOp::Ne, _) => "ne",
+        (BinaryOp::Lt, true) => "l",
+        (BinaryOp::Le, true) => "le",
+        (BinaryOp::Gt, true) => "g",
+        (BinaryOp::Ge, true) => "ge",
+        (BinaryOp::Lt, false) => "b",
+        (BinaryOp::Le, false) => "be",
+        (BinaryOp::Gt, false) => "a",
+        (BinaryOp::Ge, false) => "ae",
+        _ => unreachable!("only comparisons have conditions")
+    }
+}
+
+fn function_name(module: &Module, id: FuncId) -> String {
+    let name: String = module.function(id).name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    return format!("f{}_{}", id.0, name);
+}
+
+/// The contents of a string for the assembler's `.ascii` and `.file`, with bytes other than printable ASCII
+/// written as three octal digits.
+fn asm_string(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' => { text.push('\\'); text.push(*byte as char); },
+            0x20..=0x7e => text.push(*byte as char),
+            _ => write!(text, "\\{:03o}", byte).unwrap()
+        }
+    }
+    return text;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use crate::typecheck::Primitive;
+    use crate::ir::Type;
+    use super::{Passing, emit_asm, classify, asm_string};
+
+    /// Checks a program read as if from standard input, lowers it to IR without optimizing it, and translates
+    /// it to assembly.
+    fn emit(text: &str) -> String {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        let module = crate::ir::lower_program(result.hir.as_ref().expect("the program has errors"));
+        return emit_asm(&module, &result.state.sources);
+    }
+
+    /// Where each of the given types is passed, as `r` for general-purpose registers, `x` for `%xmm` registers
+    /// and `s` for the stack, followed by the index of the first, and the number of quadwords on the stack.
+    fn passing(types: &[Type]) -> (Vec<String>, u64) {
+        let (passing, stack) = classify(&types.iter().collect::<Vec<&Type>>());
+        let passing = passing.iter().map(|passing| match passing {
+            Passing::Registers(index) => format!("r{}", index),
+            Passing::Float(index) => format!("x{}", index),
+            Passing::Stack(index) => format!("s{}", index)
+        }).collect();
+        return (passing, stack);
+    }
+
+    #[test]
+    fn arguments_are_passed_as_the_system_v_abi_says() {
+        let int = Type::Primitive(Primitive::I64);
+        let float = Type::Primitive(Primitive::F64);
+        let string = Type::Primitive(Primitive::Str);
+        let (passing_of, stack) = passing(&[int.clone(), float.clone(), Type::Ptr, float.clone()]);
+        assert_eq!(passing_of, ["r0", "x0", "r1", "x1"]);
+        assert_eq!(stack, 0);
+
+        // A string that does not fit in the registers that are left goes on the stack, and later integers
+        // still take the registers.
+        let types = [int.clone(), int.clone(), int.clone(), int.clone(), int.clone(), string.clone(), int, string];
+        let (passing_of, stack) = passing(&types);
+        assert_eq!(passing_of, ["r0", "r1", "r2", "r3", "r4", "s0", "r5", "s2"]);
+        assert_eq!(stack, 4);
+
+        let (passing_of, stack) = passing(&vec![float; 9]);
+        assert_eq!(passing_of[8], "s0");
+        assert_eq!(stack, 1);
+    }
+
+    #[test]
+    fn the_entry_is_called_and_its_result_is_the_exit_status() {
+        assert!(emit("fn main() -> int { return 0; }").contains("\nadze_main:\n    sub $8, %rsp\n    call f0_main\n    add $8, %rsp\n    movzbl %al, %eax\n    ret\n"));
+        assert!(emit("fn main() -> unit { }").contains("\n    call f0_main\n    add $8, %rsp\n    xor %eax, %eax\n    ret\n"));
+        assert!(emit("fn main(x: int) -> int { return x; }").contains("\nadze_main:\n    xor %eax, %eax\n    ret\n"));
+    }
+
+    #[test]
+    fn statements_are_marked_with_the_lines_they_came_from() {
+        let code = emit("fn main() -> int {\n    let x = 1;\n\n    return x;\n}\n");
+        assert!(code.contains("    .file 1 \""));
+        let marks: Vec<&str> = code.lines().filter(|line| line.starts_with("    .loc ")).collect();
+        assert_eq!(marks, ["    .loc 1 1 1", "    .loc 1 2 13", "    .loc 1 4 5"]);
+    }
+
+    #[test]
+    fn strings_escape_quotes_backslashes_and_other_bytes() {
+        assert_eq!(asm_string(b"a\"b\\c\n\xff"), "a\\\"b\\\\c\\012\\377");
+    }
+}
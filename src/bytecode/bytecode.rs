@@ -0,0 +1,252 @@
+use std::rc::Rc;
+
+use crate::source::Span;
+use crate::resolve::DeclId;
+use crate::typecheck::{Type, Primitive};
+
+
+/// The instructions of the virtual machine. Each is one byte, followed by its operands, which are little-endian
+/// and as wide as the comment on the instruction says. Jump targets are offsets in the code of the function.
+///
+/// Instructions take their operands from the top of the stack and push their results. Integers of every type
+/// are held in 64 bits, sign-extended for signed types and zero-extended for unsigned ones, so arithmetic
+/// instructions are told the type of their operands, as a `Primitive` operand.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[repr(u8)]
+pub enum Op {
+    /// `index: u32`. Pushes a constant from the pool.
+    Const,
+    /// Pushes the unit value.
+    Unit,
+    Pop,
+    /// `function: u32`. Pushes a function as a value.
+    Function,
+    /// `slot: u32`. Pushes the value of a local. A struct or array is pushed as the object itself, which
+    /// `Copy` copies when it is used as a value.
+    Load,
+    /// `slot: u32`. Pops a value into a local.
+    Store,
+    /// `slot: u32`. Pops a value into a new cell and stores a reference to it in a local. Locals that are
+    /// referenced live in cells, so that references to them can outlive the slot being reused.
+    NewCell,
+    /// Replaces a struct or array with a copy of it, and of the structs and arrays inside it.
+    Copy,
+    /// `struct: u32`. Pushes a struct of the given layout whose fields are not yet set.
+    NewStruct,
+    /// `field: u32`. Pops a value and sets it as a field of the struct below it, which stays on the stack.
+    InitField,
+    /// `count: u32`. Pops that many values and pushes an array of them.
+    Array,
+    /// `field: u32`. Replaces a struct with one of its fields.
+    GetField,
+    /// `field: u32`. Pops a value and a struct, and sets the field of the struct.
+    SetField,
+    /// Stops the program if the index on top of the stack is out of bounds for the array below it.
+    CheckIndex,
+    /// Pops an index and an array, and pushes the element.
+    GetIndex,
+    /// Pops a value, an index and an array, and sets the element.
+    SetIndex,
+    /// `field: u32`. Replaces a struct with a reference to one of its fields.
+    RefField,
+    /// Pops an index and an array, and pushes a reference to the element.
+    RefIndex,
+    /// Pops a value into a new cell and pushes a reference to it.
+    NewRef,
+    /// Replaces a reference with the value it refers to.
+    Deref,
+    /// Pops a value and a reference, and stores the value where the reference refers.
+    StoreRef,
+    /// `type: u8`.
+    Neg,
+    Not,
+    /// `type: u8`. Arithmetic wraps around on overflow.
+    Add,
+    /// `type: u8`.
+    Sub,
+    /// `type: u8`.
+    Mul,
+    /// `type: u8`. Stops the program when dividing an integer by zero.
+    Div,
+    /// `type: u8`.
+    Rem,
+    /// `type: u8`.
+    Lt,
+    /// `type: u8`.
+    Le,
+    /// `type: u8`.
+    Gt,
+    /// `type: u8`.
+    Ge,
+    Eq,
+    Ne,
+    /// `from: u8, to: u8`. Converts a number, bool or char as `as` does.
+    Cast,
+    /// `struct: u32`. Pops a reference to a struct and pushes it back, stopping the program if the struct was
+    /// not created as the layout's struct or one of its substructs.
+    Downcast,
+    /// `struct: u32`, the `DeclId` of a struct. Replaces a struct with whether it was created as that struct or
+    /// one of its substructs.
+    Is,
+    /// `target: u32`.
+    Jump,
+    /// `target: u32`. Pops a bool and jumps if it is false.
+    JumpIfFalse,
+    /// `function: u32`. Pops the function's arguments and pushes the value it returns.
+    Call,
+    /// `arguments: u32`. Pops that many arguments and the function below them, and calls it.
+    CallIndirect,
+    /// Pops the value to return and returns to the caller.
+    Return
+}
+
+const OPS: [Op; 42] = [
+    Op::Const, Op::Unit, Op::Pop, Op::Function, Op::Load, Op::Store, Op::NewCell, Op::Copy, Op::NewStruct,
+    Op::InitField, Op::Array, Op::GetField, Op::SetField, Op::CheckIndex, Op::GetIndex, Op::SetIndex, Op::RefField,
+    Op::RefIndex, Op::NewRef, Op::Deref, Op::StoreRef, Op::Neg, Op::Not, Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Rem,
+    Op::Lt, Op::Le, Op::Gt, Op::Ge, Op::Eq, Op::Ne, Op::Cast, Op::Downcast, Op::Is, Op::Jump, Op::JumpIfFalse,
+    Op::Call, Op::CallIndirect, Op::Return
+];
+
+impl Op {
+    pub fn from_byte(byte: u8) -> Op {
+        OPS[byte as usize]
+    }
+
+    /// The name of the instruction in disassembly.
+    pub fn name(&self) -> &'static str {
+        use Op::*;
+        match self {
+            Const => "const",
+            Unit => "unit",
+            Pop => "pop",
+            Function => "function",
+            Load => "load",
+            Store => "store",
+            NewCell => "new_cell",
+            Copy => "copy",
+            NewStruct => "new_struct",
+            InitField => "init_field",
+            Array => "array",
+            GetField => "get_field",
+            SetField => "set_field",
+            CheckIndex => "check_index",
+            GetIndex => "get_index",
+            SetIndex => "set_index",
+            RefField => "ref_field",
+            RefIndex => "ref_index",
+            NewRef => "new_ref",
+            Deref => "deref",
+            StoreRef => "store_ref",
+            Neg => "neg",
+            Not => "not",
+            Add => "add",
+            Sub => "sub",
+            Mul => "mul",
+            Div => "div",
+            Rem => "rem",
+            Lt => "lt",
+            Le => "le",
+            Gt => "gt",
+            Ge => "ge",
+            Eq => "eq",
+            Ne => "ne",
+            Cast => "cast",
+            Downcast => "downcast",
+            Is => "is",
+            Jump => "jump",
+            JumpIfFalse => "jump_if_false",
+            Call => "call",
+            CallIndirect => "call_indirect",
+            Return => "return"
+        }
+    }
+
+    /// The widths in bytes of the instruction's operands.
+    pub fn operands(&self) -> &'static [usize] {
+        use Op::*;
+        match self {
+            Const | Function | Load | Store | NewCell | NewStruct | InitField | Array | GetField | SetField | RefField
+            | Downcast | Is | Jump | JumpIfFalse | Call | CallIndirect => &[4],
+            Neg | Add | Sub | Mul | Div | Rem | Lt | Le | Gt | Ge => &[1],
+            Cast => &[1, 1],
+            Unit | Pop | Copy | CheckIndex | GetIndex | SetIndex | RefIndex | NewRef | Deref | StoreRef | Not | Eq | Ne
+            | Return => &[]
+        }
+    }
+}
+
+const PRIMITIVES: [Primitive; 14] = [
+    Primitive::I8, Primitive::I16, Primitive::I32, Primitive::I64, Primitive::U8, Primitive::U16, Primitive::U32,
+    Primitive::U64, Primitive::F32, Primitive::F64, Primitive::Bool, Primitive::Char, Primitive::Str, Primitive::Unit
+];
+
+/// The byte a primitive type is encoded as in an operand.
+pub fn primitive_code(primitive: Primitive) -> u8 {
+    PRIMITIVES.iter().position(|p| *p == primitive).unwrap() as u8
+}
+
+pub fn primitive_from_code(code: u8) -> Primitive {
+    PRIMITIVES[code as usize]
+}
+
+
+/// A value in the constant pool.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Constant {
+    /// An integer of any type, as its 64 bits.
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(Rc<str>)
+}
+
+/// The compiled program: a constant pool shared by every function, the code of each function, and the layout of
+/// each struct. Functions and structs are numbered as they are in the HIR.
+pub struct Module {
+    pub constants: Vec<Constant>,
+    pub functions: Vec<Code>,
+    pub structs: Vec<Layout>,
+    /// The function the program starts from, which is `main` if it can be one.
+    pub main: Option<u32>
+}
+
+pub struct Layout {
+    pub name: String,
+    pub fields: usize,
+    /// The struct and the structs it inherits from, nearest first.
+    pub ancestors: Vec<DeclId>
+}
+
+/// The code of a function, with a line table that maps the code back to the source.
+pub struct Code {
+    pub name: String,
+    pub parameters: usize,
+    /// The number of local slots, including the parameters, which come first.
+    pub locals: usize,
+    /// The names of the locals, for disassembly.
+    pub local_names: Vec<String>,
+    pub ret: Type,
+    pub code: Vec<u8>,
+    /// The span that each run of instructions was compiled from, as the offset of the first instruction of the
+    /// run, in increasing order.
+    pub lines: Vec<(u32, Span)>,
+    pub loc: Span
+}
+
+impl Code {
+    /// The span the instruction at `offset` was compiled from.
+    pub fn span_at(&self, offset: usize) -> Span {
+        let index = self.lines.partition_point(|(start, _)| *start as usize <= offset);
+        return self.lines[index.saturating_sub(1)].1;
+    }
+
+    pub fn read_u8(&self, offset: usize) -> u8 {
+        self.code[offset]
+    }
+
+    pub fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes([self.code[offset], self.code[offset + 1], self.code[offset + 2], self.code[offset + 3]])
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{UnaryOp, BinaryOp};
+use crate::source::Span;
+use crate::hir::*;
+use crate::typecheck::{Type, ConstValue};
+use crate::bytecode::bytecode::{Op, Constant, Module, Code, Layout, primitive_code};
+
+
+/// Compiles the lowered program to bytecode.
+pub fn compile_program(program: &Program) -> Module {
+    let mut pool = ConstantPool { constants: Vec::new(), indices: HashMap::new() };
+    let functions = program.functions.iter()
+        .map(|(_, function)| FunctionCompiler::new(program, function, &mut pool).compile())
+        .collect();
+    let structs = program.structs.iter().map(|s| Layout {
+        name: s.name.clone(),
+        fields: s.fields.len(),
+        ancestors: ancestors(program, s)
+    }).collect();
+    return Module { constants: pool.constants, functions, structs, main: program.main.filter(|main| program.functions[*main].can_be_entry()).map(|main| main.0) };
+}
+
+/// The declarations of a struct and of the structs it inherits from, nearest first.
+fn ancestors(program: &Program, s: &Struct) -> Vec<crate::resolve::DeclId> {
+    let mut ancestors = Vec::new();
+    let mut current = match s.typ {
+        Type::Struct(id, _) => Some(id),
+        _ => None
+    };
+    while let Some(id) = current {
+        ancestors.push(id);
+        current = program.structs.iter().find(|s| matches!(s.typ, Type::Struct(s_id, _) if s_id == id)).and_then(|s| s.superstruct);
+    }
+    return ancestors;
+}
+
+
+/// The constants of the module, each stored once.
+struct ConstantPool {
+    constants: Vec<Constant>,
+    indices: HashMap<ConstantKey, u32>
+}
+
+/// A constant compared by its bits, so that floats can be looked up.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Int(i64),
+    Float(u64),
+    Bool(bool),
+    Char(char),
+    Str(Rc<str>)
+}
+
+impl ConstantPool {
+    fn add(&mut self, value: &ConstValue) -> u32 {
+        let constant = match value {
+            ConstValue::Integer(n) => Constant::Int(*n as i64),
+            ConstValue::Float(f) => Constant::Float(*f),
+            ConstValue::Bool(b) => Constant::Bool(*b),
+            ConstValue::Char(c) => Constant::Char(*c),
+            ConstValue::Str(s) => Constant::Str(Rc::from(s.as_str()))
+        };
+        let key = match &constant {
+            Constant::Int(n) => ConstantKey::Int(*n),
+            Constant::Float(f) => ConstantKey::Float(f.to_bits()),
+            Constant::Bool(b) => ConstantKey::Bool(*b),
+            Constant::Char(c) => ConstantKey::Char(*c),
+            Constant::Str(s) => ConstantKey::Str(s.clone())
+        };
+        return *self.indices.entry(key).or_insert_with(|| {
+            self.constants.push(constant);
+            self.constants.len() as u32 - 1
+        });
+    }
+}
+
+
+/// Compiles one function. Locals are numbered as they are in the HIR, with the parameters first. A local that
+/// is referenced anywhere in the function lives in a cell, whose reference its slot holds.
+struct FunctionCompiler<'a> {
+    program: &'a Program,
+    function: &'a Function,
+    pool: &'a mut ConstantPool,
+    addressed: Vec<bool>,
+    code: Vec<u8>,
+    lines: Vec<(u32, Span)>
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(program: &'a Program, function: &'a Function, pool: &'a mut ConstantPool) -> FunctionCompiler<'a> {
+        let mut addressed = vec![false; function.locals.len()];
+        for (_, expr) in function.exprs.iter() {
+            if let ExprKind::Ref(place) = expr.kind {
+                if let Some(local) = root_local(function, place) {
+                    addressed[local.index()] = true;
+                }
+            }
+        }
+        return FunctionCompiler { program, function, pool, addressed, code: Vec::new(), lines: Vec::new() };
+    }
+
+    fn compile(mut self) -> Code {
+        let function = self.function;
+        for parameter in &function.parameters {
+            if self.addressed[parameter.index()] {
+                let slot = parameter.index() as u32;
+                self.op(Op::Load, function.loc);
+                self.u32(slot);
+                self.op(Op::NewCell, function.loc);
+                self.u32(slot);
+            }
+        }
+        self.block(&function.body);
+        if !function.body.stmts.last().is_some_and(|stmt| matches!(function.stmts[*stmt], Stmt::Return(_))) {
+            self.op(Op::Unit, function.loc);
+            self.op(Op::Return, function.loc);
+        }
+        return Code {
+            name: function.name.clone(),
+            parameters: function.parameters.len(),
+            locals: function.locals.len(),
+            local_names: function.locals.iter().map(|(_, local)| local.name.clone()).collect(),
+            ret: function.ret.clone(),
+            code: self.code,
+            lines: self.lines,
+            loc: function.loc
+        };
+    }
+
+    /// Emits an instruction compiled from `span`, whose operands are emitted after it.
+    fn op(&mut self, op: Op, span: Span) {
+        if self.lines.last().is_none_or(|(_, last)| *last != span) {
+            self.lines.push((self.code.len() as u32, span));
+        }
+        self.code.push(op as u8);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.code.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Emits a jump whose target is not known yet, and returns the offset of its target, for `patch`.
+    fn jump(&mut self, op: Op, span: Span) -> usize {
+        self.op(op, span);
+        self.u32(0);
+        return self.code.len() - 4;
+    }
+
+    /// Points the jump whose target is at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len() as u32;
+        self.code[at..at + 4].copy_from_slice(&target.to_le_bytes());
+    }
+
+    fn slot(&self, local: LocalId) -> u32 {
+        local.index() as u32
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(*stmt);
+        }
+    }
+
+    fn stmt(&mut self, id: StmtId) {
+        let function = self.function;
+        let span = function.stmts.span(id);
+        match &function.stmts[id] {
+            Stmt::Expr(expr) => self.effect(*expr),
+            Stmt::Let { local, value } => {
+                match value {
+                    Some(value) => self.value(*value),
+                    None if self.addressed[local.index()] => self.op(Op::Unit, span),
+                    None => return
+                }
+                self.op(if self.addressed[local.index()] { Op::NewCell } else { Op::Store }, span);
+                self.u32(self.slot(*local));
+            },
+            Stmt::Return(value) => {
+                self.value(*value);
+                self.op(Op::Return, span);
+            },
+            Stmt::If { condition, then, otherwise } => {
+                self.value(*condition);
+                let to_otherwise = self.jump(Op::JumpIfFalse, span);
+                self.block(then);
+                match otherwise {
+                    Some(otherwise) => {
+                        let to_end = self.jump(Op::Jump, span);
+                        self.patch(to_otherwise);
+                        self.block(otherwise);
+                        self.patch(to_end);
+                    },
+                    None => self.patch(to_otherwise)
+                }
+            },
+            Stmt::While { condition, body } => {
+                let start = self.code.len() as u32;
+                self.value(*condition);
+                let to_end = self.jump(Op::JumpIfFalse, span);
+                self.block(body);
+                self.op(Op::Jump, span);
+                self.u32(start);
+                self.patch(to_end);
+            }
+        }
+    }
+
+    /// Compiles an expression whose value is not used.
+    fn effect(&mut self, id: ExprId) {
+        match &self.function.exprs[id].kind {
+            ExprKind::Assign { place, value } => self.assign(*place, *value),
+            ExprKind::Block(block) => self.block(block),
+            _ => {
+                self.value(id);
+                self.op(Op::Pop, self.function.exprs.span(id));
+            }
+        }
+    }
+
+    /// Compiles an expression that pushes its value. A struct or array read from a place is copied.
+    fn value(&mut self, id: ExprId) {
+        let function = self.function;
+        let expr = &function.exprs[id];
+        let span = function.exprs.span(id);
+        match &expr.kind {
+            ExprKind::Literal(value) => {
+                let index = self.pool.add(value);
+                self.op(Op::Const, span);
+                self.u32(index);
+            },
+            ExprKind::Local(_) | ExprKind::Field { .. } | ExprKind::Index { .. } | ExprKind::Deref(_) => {
+                self.object(id);
+                if matches!(expr.typ, Type::Struct(..) | Type::Array(..)) {
+                    self.op(Op::Copy, span);
+                }
+            },
+            ExprKind::Function(callee) => {
+                self.op(Op::Function, span);
+                self.u32(callee.0);
+            },
+            ExprKind::Call { function: callee, arguments } => {
+                for argument in arguments {
+                    self.value(*argument);
+                }
+                self.op(Op::Call, span);
+                self.u32(callee.0);
+            },
+            ExprKind::CallIndirect { callee, arguments } => {
+                self.value(*callee);
+                for argument in arguments {
+                    self.value(*argument);
+                }
+                self.op(Op::CallIndirect, span);
+                self.u32(arguments.len() as u32);
+            },
+            ExprKind::Ref(place) => self.reference(*place),
+            ExprKind::Cast { expr: value, kind } => {
+                self.value(*value);
+                match kind {
+                    CastKind::Numeric => {
+                        let (from, to) = (function.exprs[*value].typ.primitive().unwrap(), expr.typ.primitive().unwrap());
+                        if from != to {
+                            self.op(Op::Cast, span);
+                            self.u8(primitive_code(from));
+                            self.u8(primitive_code(to));
+                        }
+                    },
+                    CastKind::Upcast => { },
+                    CastKind::Downcast => {
+                        let target = self.program.structs.iter().position(|s| &s.typ == expr.typ.dereferenced()).unwrap();
+                        self.op(Op::Downcast, span);
+                        self.u32(target as u32);
+                    }
+                }
+            },
+            ExprKind::Is { expr: value, target } => {
+                self.object(*value);
+                self.op(Op::Is, span);
+                self.u32(target.0);
+            },
+            ExprKind::Unary { op: UnaryOp::Negate, operand } => {
+                self.value(*operand);
+                self.op(Op::Neg, span);
+                self.u8(primitive_code(expr.typ.primitive().unwrap()));
+            },
+            ExprKind::Unary { op: UnaryOp::Not, operand } => {
+                self.value(*operand);
+                self.op(Op::Not, span);
+            },
+            ExprKind::Binary { op, left, right } => self.binary(*op, *left, *right, span),
+            ExprKind::Assign { place, value } => {
+                self.assign(*place, *value);
+                self.op(Op::Unit, span);
+            },
+            ExprKind::StructLiteral { fields } => {
+                let layout = self.program.structs.iter().position(|s| s.typ == expr.typ).unwrap();
+                self.op(Op::NewStruct, span);
+                self.u32(layout as u32);
+                for (index, value) in fields {
+                    self.value(*value);
+                    self.op(Op::InitField, span);
+                    self.u32(*index as u32);
+                }
+            },
+            ExprKind::Array(elements) => {
+                for element in elements {
+                    self.value(*element);
+                }
+                self.op(Op::Array, span);
+                self.u32(elements.len() as u32);
+            },
+            ExprKind::Block(block) => {
+                self.block(block);
+                self.op(Op::Unit, span);
+            }
+        }
+    }
+
+    /// Compiles an expression that pushes its value without copying it out of the place it is in, for reading a
+    /// part of it or setting one.
+    fn object(&mut self, id: ExprId) {
+        let function = self.function;
+        let span = function.exprs.span(id);
+        match &function.exprs[id].kind {
+            ExprKind::Local(local) => {
+                self.op(Op::Load, span);
+                self.u32(self.slot(*local));
+                if self.addressed[local.index()] {
+                    self.op(Op::Deref, span);
+                }
+            },
+            ExprKind::Field { object, index } => {
+                self.object(*object);
+                self.op(Op::GetField, span);
+                self.u32(*index as u32);
+            },
+            ExprKind::Index { object, index } => {
+                self.object(*object);
+                self.value(*index);
+                self.op(Op::GetIndex, span);
+            },
+            ExprKind::Deref(reference) => {
+                self.value(*reference);
+                self.op(Op::Deref, span);
+            },
+            _ => self.value(id)
+        }
+    }
+
+    /// Compiles an expression that pushes a reference to the place an expression denotes. An expression that is
+    /// not a place is evaluated into a new cell.
+    fn reference(&mut self, id: ExprId) {
+        let function = self.function;
+        let span = function.exprs.span(id);
+        match &function.exprs[id].kind {
+            ExprKind::Local(local) => {
+                self.op(Op::Load, span);
+                self.u32(self.slot(*local));
+            },
+            ExprKind::Field { object, index } => {
+                self.reference(*object);
+                self.op(Op::RefField, span);
+                self.u32(*index as u32);
+            },
+            ExprKind::Index { object, index } => {
+                self.reference(*object);
+                self.value(*index);
+                self.op(Op::RefIndex, span);
+            },
+            ExprKind::Deref(reference) => self.value(*reference),
+            _ => {
+                self.value(id);
+                self.op(Op::NewRef, span);
+            }
+        }
+    }
+
+    /// Compiles an assignment, which finds its place before evaluating its value.
+    fn assign(&mut self, place: ExprId, value: ExprId) {
+        let function = self.function;
+        let span = function.exprs.span(place);
+        match &function.exprs[place].kind {
+            ExprKind::Local(local) if self.addressed[local.index()] => {
+                self.op(Op::Load, span);
+                self.u32(self.slot(*local));
+                self.value(value);
+                self.op(Op::StoreRef, span);
+            },
+            ExprKind::Local(local) => {
+                self.value(value);
+                self.op(Op::Store, span);
+                self.u32(self.slot(*local));
+            },
+            ExprKind::Field { object, index } => {
+                self.object(*object);
+                self.value(value);
+                self.op(Op::SetField, span);
+                self.u32(*index as u32);
+            },
+            ExprKind::Index { object, index } => {
+                self.object(*object);
+                self.value(*index);
+                self.op(Op::CheckIndex, span);
+                self.value(value);
+                self.op(Op::SetIndex, span);
+            },
+            ExprKind::Deref(reference) => {
+                self.value(*reference);
+                self.value(value);
+                self.op(Op::StoreRef, span);
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: ExprId, right: ExprId, span: Span) {
+        use BinaryOp::*;
+        self.value(left);
+        if let And | Or = op {
+            // The left operand decides the result when it is false for `&&` and true for `||`.
+            if op == Or {
+                self.op(Op::Not, span);
+            }
+            let to_short = self.jump(Op::JumpIfFalse, span);
+            self.value(right);
+            let to_end = self.jump(Op::Jump, span);
+            self.patch(to_short);
+            let index = self.pool.add(&ConstValue::Bool(op == Or));
+            self.op(Op::Const, span);
+            self.u32(index);
+            self.patch(to_end);
+            return;
+        }
+        self.value(right);
+        let typ = self.function.exprs[left].typ.primitive().unwrap();
+        let instruction = match op {
+            Add => Op::Add,
+            Subtract => Op::Sub,
+            Multiply => Op::Mul,
+            Divide => Op::Div,
+            Remainder => Op::Rem,
+            Less => Op::Lt,
+            LessEqual => Op::Le,
+            Greater => Op::Gt,
+            GreaterEqual => Op::Ge,
+            Equal => Op::Eq,
+            NotEqual => Op::Ne,
+            And | Or => unreachable!()
+        };
+        self.op(instruction, span);
+        if !matches!(op, Equal | NotEqual) {
+            self.u8(primitive_code(typ));
+        }
+    }
+}
+
+/// The local that a place is part of, if it is not reached through a reference.
+fn root_local(function: &Function, place: ExprId) -> Option<LocalId> {
+    match &function.exprs[place].kind {
+        ExprKind::Local(local) => Some(*local),
+        ExprKind::Field { object, .. } | ExprKind::Index { object, .. } => root_local(function, *object),
+        _ => None
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::Write;
+
+use crate::source::SourceMap;
+use crate::bytecode::bytecode::{Op, Constant, Module, Code, primitive_from_code};
+
+
+/// Prints the module as text: the constant pool, the struct layouts, and the instructions of each function,
+/// each run of which is headed by the source line it was compiled from.
+pub fn disassemble(module: &Module, sources: &SourceMap) -> String {
+    let mut out = String::new();
+    out.push_str("constants:\n");
+    for (index, constant) in module.constants.iter().enumerate() {
+        writeln!(out, "    #{:<5} {}", index, describe_constant(constant)).unwrap();
+    }
+    out.push_str("\nstructs:\n");
+    for (index, layout) in module.structs.iter().enumerate() {
+        writeln!(out, "    {:<6} {} ({} field{})", index, layout.name, layout.fields, if layout.fields == 1 { "" } else { "s" }).unwrap();
+    }
+    for (index, code) in module.functions.iter().enumerate() {
+        out.push('\n');
+        function(module, index, code, sources, &mut out);
+    }
+    return out;
+}
+
+fn describe_constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Int(n) => format!("int {}", n),
+        Constant::Float(f) => format!("float {:?}", f),
+        Constant::Bool(b) => format!("bool {}", b),
+        Constant::Char(c) => format!("char {:?}", c),
+        Constant::Str(s) => format!("str {:?}", s)
+    }
+}
+
+fn function(module: &Module, index: usize, code: &Code, sources: &SourceMap, out: &mut String) {
+    let source = sources.get(code.loc.file);
+    let (line, _) = source.line_col(code.loc.lo as usize);
+    writeln!(out, "fn {} {} ({}:{}, {} parameter{}, {} local{}){}", index, code.name, source.name, line + 1,
+        code.parameters, if code.parameters == 1 { "" } else { "s" },
+        code.locals, if code.locals == 1 { "" } else { "s" },
+        if Some(index as u32) == module.main { " main" } else { "" }).unwrap();
+
+    let mut last_line = None;
+    let mut offset = 0;
+    while offset < code.code.len() {
+        let span = code.span_at(offset);
+        let source = sources.get(span.file);
+        let line = source.line_of(span.lo as usize);
+        if last_line != Some((span.file, line)) {
+            last_line = Some((span.file, line));
+            writeln!(out, "  {:>5} | {}", line + 1, source.get_line(line).trim_end()).unwrap();
+        }
+
+        let op = Op::from_byte(code.code[offset]);
+        let mut operands = Vec::new();
+        let mut at = offset + 1;
+        for width in op.operands() {
+            operands.push(match width {
+                1 => code.read_u8(at) as u32,
+                _ => code.read_u32(at)
+            });
+            at += width;
+        }
+        let text = instruction(module, code, op, &operands);
+        writeln!(out, "        {:04}  {}", offset, text.trim_end()).unwrap();
+        offset = at;
+    }
+}
+
+/// The text of an instruction, with a comment naming what its operands refer to.
+fn instruction(module: &Module, code: &Code, op: Op, operands: &[u32]) -> String {
+    let name = op.name();
+    let typ = |i: usize| primitive_from_code(operands[i] as u8).name();
+    return match op {
+        Op::Const => format!("{:<14} #{:<8} ; {}", name, operands[0], describe_constant(&module.constants[operands[0] as usize])),
+        Op::Function | Op::Call => format!("{:<14} {:<9} ; {}", name, operands[0], module.functions[operands[0] as usize].name),
+        Op::Load | Op::Store | Op::NewCell => format!("{:<14} {:<9} ; {}", name, operands[0], code.local_names[operands[0] as usize]),
+        Op::NewStruct | Op::Downcast => format!("{:<14} {:<9} ; {}", name, operands[0], module.structs[operands[0] as usize].name),
+        Op::Cast => format!("{:<14} {} {}", name, typ(0), typ(1)),
+        Op::Neg | Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Rem | Op::Lt | Op::Le | Op::Gt | Op::Ge => format!("{:<14} {}", name, typ(0)),
+        Op::Is => {
+            let target = module.structs.iter().find(|s| s.ancestors[0].0 == operands[0]).map_or("", |s| s.name.as_str());
+            format!("{:<14} {:<9} ; {}", name, operands[0], target)
+        },
+        _ => format!("{:<14} {}", name, operands.iter().map(u32::to_string).collect::<Vec<_>>().join(" "))
+    };
+}
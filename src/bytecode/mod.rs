@@ -0,0 +1,10 @@
+#[allow(clippy::module_inception)]
+mod bytecode;
+mod value;
+mod compiler;
+mod vm;
+mod disasm;
+
+pub use compiler::compile_program;
+pub use vm::run_module;
+pub use disasm::disassemble;
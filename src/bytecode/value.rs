@@ -0,0 +1,183 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::bytecode::bytecode::Constant;
+use crate::typecheck::{ConstValue, Primitive};
+
+
+/// A value on the virtual machine's stack or in a local. Integers of every type are held as their 64 bits, and
+/// `f32` values as the `f64` with the same value.
+///
+/// Structs and arrays are objects that the stack shares with the place they were loaded from, so that a field
+/// or element can be read or written without copying the whole value. Code that uses one as a value copies it
+/// first, with `Op::Copy`.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(Rc<str>),
+    Unit,
+    Struct(Rc<Object>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Function(u32),
+    Ref(Reference)
+}
+
+/// A struct, with the index of the layout it was created as, and its fields in layout order. A struct assigned
+/// over it through a reference to a superstruct can change its layout.
+#[derive(Debug)]
+pub struct Object {
+    pub layout: Cell<u32>,
+    pub fields: RefCell<Vec<Value>>
+}
+
+/// A reference to a place: a cell holding a variable or a value that was referenced, or a field or element of
+/// an object. Assignments store structs and arrays into the objects already in their places, so an object stays
+/// where it is for as long as anything refers to it.
+#[derive(Debug, Clone)]
+pub enum Reference {
+    Cell(Rc<RefCell<Value>>),
+    Field(Rc<Object>, u32),
+    Element(Rc<RefCell<Vec<Value>>>, u32)
+}
+
+impl Value {
+    pub fn from_constant(constant: &Constant) -> Value {
+        match constant {
+            Constant::Int(n) => Value::Int(*n),
+            Constant::Float(f) => Value::Float(*f),
+            Constant::Bool(b) => Value::Bool(*b),
+            Constant::Char(c) => Value::Char(*c),
+            Constant::Str(s) => Value::Str(s.clone())
+        }
+    }
+
+    /// The value of a primitive type as a constant, for the conversions that constants and run-time values share.
+    pub fn to_const(&self, typ: Primitive) -> ConstValue {
+        match self {
+            Value::Int(n) if typ == Primitive::U64 => ConstValue::Integer(*n as u64 as i128),
+            Value::Int(n) => ConstValue::Integer(*n as i128),
+            Value::Float(f) => ConstValue::Float(*f),
+            Value::Bool(b) => ConstValue::Bool(*b),
+            Value::Char(c) => ConstValue::Char(*c),
+            Value::Str(s) => ConstValue::Str(String::from(&**s)),
+            _ => unreachable!()
+        }
+    }
+
+    pub fn from_const(value: &ConstValue) -> Value {
+        match value {
+            ConstValue::Integer(n) => Value::Int(*n as i64),
+            ConstValue::Float(f) => Value::Float(*f),
+            ConstValue::Bool(b) => Value::Bool(*b),
+            ConstValue::Char(c) => Value::Char(*c),
+            ConstValue::Str(s) => Value::Str(Rc::from(s.as_str()))
+        }
+    }
+
+    pub fn int(&self) -> i64 {
+        match self {
+            Value::Int(n) => *n,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn bool(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            _ => unreachable!()
+        }
+    }
+
+    /// A copy of the value that shares no struct or array with it.
+    pub fn copy(&self) -> Value {
+        match self {
+            Value::Struct(object) => Value::Struct(Rc::new(Object {
+                layout: object.layout.clone(),
+                fields: RefCell::new(object.fields.borrow().iter().map(Value::copy).collect())
+            })),
+            Value::Array(elements) => Value::Array(Rc::new(RefCell::new(elements.borrow().iter().map(Value::copy).collect()))),
+            value => value.clone()
+        }
+    }
+
+    /// The field or element at `index` of a struct or array, shared with it.
+    pub fn part(&self, index: usize) -> Value {
+        match self {
+            Value::Struct(object) => object.fields.borrow()[index].clone(),
+            Value::Array(elements) => elements.borrow()[index].clone(),
+            _ => unreachable!()
+        }
+    }
+
+    pub fn set_part(&self, index: usize, value: Value) {
+        match self {
+            Value::Struct(object) => assign(&mut object.fields.borrow_mut()[index], value),
+            Value::Array(elements) => assign(&mut elements.borrow_mut()[index], value),
+            _ => unreachable!()
+        }
+    }
+}
+
+/// Stores a value in a place. A struct or array is stored into the object already in the place, so that
+/// references into it, and objects loaded from the place that are still waiting on the stack to have a field
+/// set, see the new value.
+pub fn assign(place: &mut Value, value: Value) {
+    match (&*place, &value) {
+        (Value::Struct(old), Value::Struct(new)) => {
+            if !Rc::ptr_eq(old, new) {
+                old.layout.set(new.layout.get());
+                let mut new = new.fields.borrow().clone();
+                let mut fields = old.fields.borrow_mut();
+                let rest = new.split_off(new.len().min(fields.len()));
+                fields.truncate(new.len());
+                for (old, new) in fields.iter_mut().zip(new) {
+                    assign(old, new);
+                }
+                fields.extend(rest);
+            }
+        },
+        (Value::Array(old), Value::Array(new)) => {
+            if !Rc::ptr_eq(old, new) {
+                let new = new.borrow().clone();
+                for (old, new) in old.borrow_mut().iter_mut().zip(new) {
+                    assign(old, new);
+                }
+            }
+        },
+        _ => *place = value
+    }
+}
+
+impl Reference {
+    pub fn new(value: Value) -> Reference {
+        Reference::Cell(Rc::new(RefCell::new(value)))
+    }
+
+    /// A reference to the field or element at `index` of the struct or array this one refers to.
+    pub fn part(&self, index: u32) -> Reference {
+        match self.read() {
+            Value::Struct(object) => Reference::Field(object, index),
+            Value::Array(elements) => Reference::Element(elements, index),
+            _ => unreachable!()
+        }
+    }
+
+    pub fn read(&self) -> Value {
+        match self {
+            Reference::Cell(cell) => cell.borrow().clone(),
+            Reference::Field(object, index) => object.fields.borrow()[*index as usize].clone(),
+            Reference::Element(elements, index) => elements.borrow()[*index as usize].clone()
+        }
+    }
+
+    pub fn write(&self, value: Value) {
+        match self {
+            Reference::Cell(cell) => assign(&mut cell.borrow_mut(), value),
+            Reference::Field(object, index) => assign(&mut object.fields.borrow_mut()[*index as usize], value),
+            Reference::Element(elements, index) => assign(&mut elements.borrow_mut()[*index as usize], value)
+        }
+    }
+}
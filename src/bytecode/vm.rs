@@ -0,0 +1,428 @@
+use std::cmp::Ordering;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::source::Span;
+use crate::typecheck::{Primitive, fold_cast, truncate, round};
+use crate::interp::RuntimeError;
+use crate::bytecode::bytecode::{Op, Module, Code, primitive_from_code};
+use crate::bytecode::value::{Value, Object, Reference, assign};
+
+
+/// The most calls that can be nested before the program is stopped, as in the interpreter.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+
+/// Runs the module's `main` function and returns the value it returns, or 0 if it returns `unit`. A `main` that
+/// cannot be run is not part of the module, so it is reported by `check_main` before the program is compiled.
+pub fn run_module(module: &Module) -> Result<i128, RuntimeError> {
+    let main = module.main.ok_or(RuntimeError::MissingMain)?;
+    let code = &module.functions[main as usize];
+
+    let mut vm = Vm { module, stack: Vec::new(), locals: vec![Value::Unit; code.locals], frames: Vec::new() };
+    let value = vm.execute(code)?;
+    return match (value, code.ret.primitive()) {
+        (Value::Int(n), Some(Primitive::U64)) => Ok(n as u64 as i128),
+        (Value::Int(n), _) => Ok(n as i128),
+        _ => Ok(0)
+    };
+}
+
+
+/// A call that is waiting for another to return: its code, the offset of the instruction to continue from,
+/// where its locals start, and the offset of the call instruction.
+struct Frame<'a> {
+    code: &'a Code,
+    pc: usize,
+    base: usize,
+    call: usize
+}
+
+/// Executes bytecode with an operand stack, and the locals of every running call on a stack of their own.
+struct Vm<'a> {
+    module: &'a Module,
+    stack: Vec<Value>,
+    locals: Vec<Value>,
+    /// The calls that are waiting for the current one to return, outermost first.
+    frames: Vec<Frame<'a>>
+}
+
+impl<'a> Vm<'a> {
+    /// The calls that led to the current one, innermost first, for the trace of an error.
+    fn trace(&self) -> Vec<Span> {
+        self.frames.iter().rev().map(|frame| frame.code.span_at(frame.call)).collect()
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn execute(&mut self, mut code: &'a Code) -> Result<Value, RuntimeError> {
+        let module = self.module;
+        let (mut pc, mut base) = (0, 0);
+        loop {
+            let start = pc;
+            let op = Op::from_byte(code.read_u8(start));
+            pc += 1;
+            match op {
+                Op::Const => {
+                    let index = code.read_u32(pc) as usize;
+                    pc += 4;
+                    self.stack.push(Value::from_constant(&module.constants[index]));
+                },
+                Op::Unit => self.stack.push(Value::Unit),
+                Op::Pop => {
+                    self.pop();
+                },
+                Op::Function => {
+                    let function = code.read_u32(pc);
+                    pc += 4;
+                    self.stack.push(Value::Function(function));
+                },
+                Op::Load => {
+                    let slot = code.read_u32(pc) as usize;
+                    pc += 4;
+                    self.stack.push(self.locals[base + slot].clone());
+                },
+                Op::Store => {
+                    let slot = code.read_u32(pc) as usize;
+                    pc += 4;
+                    let value = self.pop();
+                    assign(&mut self.locals[base + slot], value);
+                },
+                Op::NewCell => {
+                    let slot = code.read_u32(pc) as usize;
+                    pc += 4;
+                    let value = self.pop();
+                    self.locals[base + slot] = Value::Ref(Reference::new(value));
+                },
+                Op::Copy => {
+                    let value = self.pop().copy();
+                    self.stack.push(value);
+                },
+                Op::NewStruct => {
+                    let layout = code.read_u32(pc);
+                    pc += 4;
+                    let fields = vec![Value::Unit; module.structs[layout as usize].fields];
+                    self.stack.push(Value::Struct(Rc::new(Object { layout: Cell::new(layout), fields: RefCell::new(fields) })));
+                },
+                Op::InitField => {
+                    let index = code.read_u32(pc) as usize;
+                    pc += 4;
+                    let value = self.pop();
+                    self.stack.last().unwrap().set_part(index, value);
+                },
+                Op::Array => {
+                    let count = code.read_u32(pc) as usize;
+                    pc += 4;
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(Value::Array(Rc::new(RefCell::new(elements))));
+                },
+                Op::GetField => {
+                    let index = code.read_u32(pc) as usize;
+                    pc += 4;
+                    let field = self.pop().part(index);
+                    self.stack.push(field);
+                },
+                Op::SetField => {
+                    let index = code.read_u32(pc) as usize;
+                    pc += 4;
+                    let value = self.pop();
+                    self.pop().set_part(index, value);
+                },
+                Op::CheckIndex => {
+                    let index = self.stack[self.stack.len() - 1].int() as u64;
+                    self.check_index(&self.stack[self.stack.len() - 2], index, code, start)?;
+                },
+                Op::GetIndex => {
+                    let index = self.pop().int() as u64;
+                    let array = self.pop();
+                    self.check_index(&array, index, code, start)?;
+                    self.stack.push(array.part(index as usize));
+                },
+                Op::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop().int() as usize;
+                    self.pop().set_part(index, value);
+                },
+                Op::RefField => {
+                    let index = code.read_u32(pc);
+                    pc += 4;
+                    let reference = match self.pop() {
+                        Value::Ref(reference) => reference.part(index),
+                        _ => unreachable!()
+                    };
+                    self.stack.push(Value::Ref(reference));
+                },
+                Op::RefIndex => {
+                    let index = self.pop().int() as u64;
+                    let reference = match self.pop() {
+                        Value::Ref(reference) => reference,
+                        _ => unreachable!()
+                    };
+                    self.check_index(&reference.read(), index, code, start)?;
+                    self.stack.push(Value::Ref(reference.part(index as u32)));
+                },
+                Op::NewRef => {
+                    let value = self.pop();
+                    self.stack.push(Value::Ref(Reference::new(value)));
+                },
+                Op::Deref => {
+                    let value = match self.pop() {
+                        Value::Ref(reference) => reference.read(),
+                        _ => unreachable!()
+                    };
+                    self.stack.push(value);
+                },
+                Op::StoreRef => {
+                    let value = self.pop();
+                    match self.pop() {
+                        Value::Ref(reference) => reference.write(value),
+                        _ => unreachable!()
+                    }
+                },
+                Op::Neg => {
+                    let typ = primitive_from_code(code.read_u8(pc));
+                    pc += 1;
+                    let result = match self.pop() {
+                        Value::Int(n) => Value::Int(truncate(n.wrapping_neg() as i128, typ) as i64),
+                        Value::Float(f) => Value::Float(round(-f, typ)),
+                        _ => unreachable!()
+                    };
+                    self.stack.push(result);
+                },
+                Op::Not => {
+                    let value = !self.pop().bool();
+                    self.stack.push(Value::Bool(value));
+                },
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Rem => {
+                    let typ = primitive_from_code(code.read_u8(pc));
+                    pc += 1;
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result = match (left, right) {
+                        (Value::Int(l), Value::Int(r)) => Value::Int(match op {
+                            Op::Add => truncate(l.wrapping_add(r) as i128, typ) as i64,
+                            Op::Sub => truncate(l.wrapping_sub(r) as i128, typ) as i64,
+                            Op::Mul => truncate(l.wrapping_mul(r) as i128, typ) as i64,
+                            _ if r == 0 => {
+                                return Err(RuntimeError::DivisionByZero { loc: code.span_at(start), trace: self.trace() });
+                            },
+                            // Only `u64` values can be out of the range of `i64`, and the division of the others
+                            // can only overflow for `i64::MIN / -1`, which wraps around as the result does.
+                            Op::Div if typ == Primitive::U64 => ((l as u64) / (r as u64)) as i64,
+                            Op::Div => truncate(l.wrapping_div(r) as i128, typ) as i64,
+                            _ if typ == Primitive::U64 => ((l as u64) % (r as u64)) as i64,
+                            _ => l.wrapping_rem(r)
+                        }),
+                        (Value::Float(l), Value::Float(r)) => Value::Float(round(match op {
+                            Op::Add => l + r,
+                            Op::Sub => l - r,
+                            Op::Mul => l * r,
+                            Op::Div => l / r,
+                            _ => l % r
+                        }, typ)),
+                        _ => unreachable!()
+                    };
+                    self.stack.push(result);
+                },
+                Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+                    let typ = primitive_from_code(code.read_u8(pc));
+                    pc += 1;
+                    let right = self.pop();
+                    let left = self.pop();
+                    let ordering = match (left, right) {
+                        (Value::Int(l), Value::Int(r)) if typ == Primitive::U64 => Some((l as u64).cmp(&(r as u64))),
+                        (Value::Int(l), Value::Int(r)) => Some(l.cmp(&r)),
+                        (Value::Float(l), Value::Float(r)) => l.partial_cmp(&r),
+                        (Value::Char(l), Value::Char(r)) => Some(l.cmp(&r)),
+                        _ => unreachable!()
+                    };
+                    let result = match op {
+                        Op::Lt => ordering == Some(Ordering::Less),
+                        Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                        Op::Gt => ordering == Some(Ordering::Greater),
+                        _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+                    };
+                    self.stack.push(Value::Bool(result));
+                },
+                Op::Eq | Op::Ne => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let equal = match (left, right) {
+                        (Value::Int(l), Value::Int(r)) => l == r,
+                        (Value::Float(l), Value::Float(r)) => l == r,
+                        (Value::Bool(l), Value::Bool(r)) => l == r,
+                        (Value::Char(l), Value::Char(r)) => l == r,
+                        (Value::Str(l), Value::Str(r)) => l == r,
+                        _ => unreachable!()
+                    };
+                    self.stack.push(Value::Bool(equal == (op == Op::Eq)));
+                },
+                Op::Cast => {
+                    let from = primitive_from_code(code.read_u8(pc));
+                    let to = primitive_from_code(code.read_u8(pc + 1));
+                    pc += 2;
+                    let result = match self.pop() {
+                        // Integers are held as their bits, so a cast between them only needs to truncate them.
+                        Value::Int(n) if to.is_integer() => Value::Int(truncate(n as i128, to) as i64),
+                        value => Value::from_const(&fold_cast(&value.to_const(from), from, to))
+                    };
+                    self.stack.push(result);
+                },
+                Op::Downcast => {
+                    let target = code.read_u32(pc) as usize;
+                    pc += 4;
+                    let layout = match self.stack.last().unwrap() {
+                        Value::Ref(reference) => match reference.read() {
+                            Value::Struct(object) => object.layout.get() as usize,
+                            _ => unreachable!()
+                        },
+                        _ => unreachable!()
+                    };
+                    let target = &module.structs[target];
+                    if !module.structs[layout].ancestors.contains(&target.ancestors[0]) {
+                        let (to, created) = (target.name.clone(), module.structs[layout].name.clone());
+                        return Err(RuntimeError::FailedDowncast { to, created, loc: code.span_at(start), trace: self.trace() });
+                    }
+                },
+                Op::Is => {
+                    let target = code.read_u32(pc);
+                    pc += 4;
+                    let result = match self.pop() {
+                        Value::Struct(object) => module.structs[object.layout.get() as usize].ancestors.iter().any(|id| id.0 == target),
+                        _ => unreachable!()
+                    };
+                    self.stack.push(Value::Bool(result));
+                },
+                Op::Jump => pc = code.read_u32(pc) as usize,
+                Op::JumpIfFalse => {
+                    if self.pop().bool() {
+                        pc += 4;
+                    } else {
+                        pc = code.read_u32(pc) as usize;
+                    }
+                },
+                Op::Call | Op::CallIndirect => {
+                    let function = if op == Op::Call {
+                        pc += 4;
+                        code.read_u32(start + 1)
+                    } else {
+                        pc += 4;
+                        let arguments = code.read_u32(start + 1) as usize;
+                        match &self.stack[self.stack.len() - arguments - 1] {
+                            Value::Function(function) => *function,
+                            _ => unreachable!()
+                        }
+                    };
+                    if self.frames.len() + 1 >= MAX_CALL_DEPTH {
+                        return Err(RuntimeError::StackOverflow { loc: code.span_at(start), trace: self.trace() });
+                    }
+                    let callee = &module.functions[function as usize];
+                    self.frames.push(Frame { code, pc, base, call: start });
+                    base = self.locals.len();
+                    self.locals.extend(self.stack.drain(self.stack.len() - callee.parameters..));
+                    self.locals.resize(base + callee.locals, Value::Unit);
+                    if op == Op::CallIndirect {
+                        self.pop();
+                    }
+                    (code, pc) = (callee, 0);
+                },
+                Op::Return => {
+                    let value = self.pop();
+                    self.locals.truncate(base);
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            (code, pc, base) = (caller.code, caller.pc, caller.base);
+                            self.stack.push(value);
+                        },
+                        None => return Ok(value)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stops the program if `index` is out of bounds for an array.
+    fn check_index(&self, array: &Value, index: u64, code: &Code, start: usize) -> Result<(), RuntimeError> {
+        let length = match array {
+            Value::Array(elements) => elements.borrow().len() as u64,
+            _ => unreachable!()
+        };
+        if index >= length {
+            return Err(RuntimeError::IndexOutOfBounds { index, length, loc: code.span_at(start), trace: self.trace() });
+        }
+        return Ok(());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use crate::bytecode::compile_program;
+    use super::run_module;
+
+    /// Checks a program read as if from standard input, runs it on the virtual machine and returns what `main`
+    /// returns, or `None` if the program stops with an error. The interpreter must agree.
+    fn run(text: &str) -> Option<i128> {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        let hir = result.hir.as_ref().expect("the program has errors");
+        let status = run_module(&compile_program(hir)).ok();
+        assert_eq!(status, crate::interp::run_program(hir).ok(), "the interpreter disagrees");
+        return status;
+    }
+
+    #[test]
+    fn locals_past_the_first_65536_have_slots_of_their_own() {
+        let mut text = String::from("fn main() -> int {\n");
+        for i in 0..70_000 {
+            text.push_str(&format!("    let _v{}: int = {};\n", i, i % 7));
+        }
+        text.push_str("    return _v1 + _v65537;\n}\n");
+        assert_eq!(run(&text), Some(1 + 65537 % 7));
+    }
+
+    #[test]
+    fn division_truncates_towards_zero() {
+        assert_eq!(run("fn main() -> int { return -7 / 2; }"), Some(-3));
+        assert_eq!(run("fn main() -> int { return -7 % 2; }"), Some(-1));
+        assert_eq!(run("fn main() -> int { return 7 % -2; }"), Some(1));
+        assert_eq!(run("fn main() -> u64 { let max = 0 as u64 - 1 as u64; return max / 2 as u64; }"), Some(i64::MAX as i128));
+        assert_eq!(run("fn main() -> u64 { let max = 0 as u64 - 1 as u64; return max % 10 as u64; }"), Some(5));
+    }
+
+    #[test]
+    fn division_by_zero_stops_the_program() {
+        assert_eq!(run("fn main() -> int { let zero = 0; return 1 / zero; }"), None);
+        assert_eq!(run("fn main() -> int { let zero = 0; return 1 % zero; }"), None);
+    }
+
+    #[test]
+    fn integer_arithmetic_wraps_around_in_its_type() {
+        assert_eq!(run("fn main() -> i8 { let x = 127 as i8; return x + 1 as i8; }"), Some(-128));
+        assert_eq!(run("fn main() -> u8 { let x = 0 as u8; return x - 1 as u8; }"), Some(255));
+        assert_eq!(run("fn main() -> i16 { let x = 256 as i16; return x * 128 as i16; }"), Some(-32768));
+        assert_eq!(run("fn main() -> u64 { let x = 0 as u64; return x - 1 as u64; }"), Some(u64::MAX as i128));
+        assert_eq!(run("fn main() -> int { let min = -9223372036854775807 - 1; return min / -1; }"), Some(i64::MIN as i128));
+        assert_eq!(run("fn main() -> int { let min = -9223372036854775807 - 1; return min % -1; }"), Some(0));
+    }
+
+    #[test]
+    fn casts_truncate_integers_and_saturate_floats() {
+        assert_eq!(run("fn main() -> u8 { let x = 300; return x as u8; }"), Some(44));
+        assert_eq!(run("fn main() -> i8 { let x = 200; return x as i8; }"), Some(-56));
+        assert_eq!(run("fn main() -> int { let x = -2.7; return x as int; }"), Some(-2));
+        assert_eq!(run("fn main() -> i8 { let x = 1000.0; return x as i8; }"), Some(127));
+        assert_eq!(run("fn main() -> u8 { let x = -1.0; return x as u8; }"), Some(0));
+    }
+
+    #[test]
+    fn downcasts_check_what_a_struct_was_created_as() {
+        let structs = "struct Base { id: int; } struct Rect(Base) { w: int; } struct Square(Rect) { side: int; }";
+        let run_main = |body: &str| run(&format!("{} fn main() -> int {{ {} }}", structs, body));
+        assert_eq!(run_main("let s = Square { id: 1, w: 2, side: 3 }; let b: Base& = s; let r = b as Rect&; return r.w + b.id;"), Some(3));
+        assert_eq!(run_main("let r = Rect { id: 1, w: 2 }; let b: Base& = r; if (b is Rect) && !(b is Square) { return 1; } return 0;"), Some(1));
+        assert_eq!(run_main("let r = Rect { id: 1, w: 2 }; let b: Base& = r; let s = b as Square&; return s.side;"), None);
+    }
+}
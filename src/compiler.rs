@@ -1,12 +1,19 @@
-use std::rc::Rc;
-use crate::source::Source;
+use crate::source::SourceMap;
+use crate::options::Options;
+use crate::vfs::{FileSystem, RealFileSystem};
 
 pub struct CompilerState {
-    pub sources: Vec<Rc<Source>>
+    pub options: Options,
+    pub sources: SourceMap,
+    pub fs: Box<dyn FileSystem>
 }
 
 impl CompilerState {
-    pub fn new() -> CompilerState {
-        CompilerState { sources: Vec::new() }
+    pub fn new(options: Options) -> CompilerState {
+        CompilerState::with_file_system(options, Box::new(RealFileSystem))
     }
-}
\ No newline at end of file
+
+    pub fn with_file_system(options: Options, fs: Box<dyn FileSystem>) -> CompilerState {
+        CompilerState { options, sources: SourceMap::new(), fs }
+    }
+}
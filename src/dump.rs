@@ -0,0 +1,534 @@
+use std::fmt::Write;
+
+use crate::ast::*;
+use crate::parser::Token;
+use crate::source::{Span, SourceMap};
+
+
+/// The format that `--dump-tokens` and `--dump-ast` print in.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DumpFormat {
+    Sexp,
+    Json
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<DumpFormat> {
+        match name {
+            "sexp" => Some(DumpFormat::Sexp),
+            "json" => Some(DumpFormat::Json),
+            _ => None
+        }
+    }
+}
+
+
+/// A tree that both formats are printed from. A node is written as `(Kind @lo..hi :field value ...)` in
+/// S-expressions and as `{"kind": "Kind", "span": [lo, hi], "field": value, ...}` in JSON, where the span is a
+/// range of bytes in the node's file. Nodes whose fields are all scalars are printed on one line.
+enum Value {
+    Node { kind: String, span: Option<Span>, fields: Vec<(&'static str, Value)> },
+    List(Vec<Value>),
+    Str(String),
+    Int(u64),
+    Float(f64),
+    Bool(bool),
+    Null
+}
+
+impl Value {
+    fn node(kind: &str, span: Span, fields: Vec<(&'static str, Value)>) -> Value {
+        Value::Node { kind: String::from(kind), span: Some(span), fields }
+    }
+
+    fn str(text: &str) -> Value {
+        Value::Str(String::from(text))
+    }
+
+    fn list<T>(items: impl IntoIterator<Item=T>, f: impl FnMut(T) -> Value) -> Value {
+        Value::List(items.into_iter().map(f).collect())
+    }
+
+    fn option<T>(value: Option<T>, f: impl FnOnce(T) -> Value) -> Value {
+        value.map_or(Value::Null, f)
+    }
+
+    /// Whether the value is printed on one line: anything but a node or a list holding nodes or lists.
+    fn is_scalar(&self) -> bool {
+        match self {
+            Value::Node { .. } => false,
+            Value::List(items) => items.iter().all(|item| !matches!(item, Value::Node { .. } | Value::List(_))),
+            _ => true
+        }
+    }
+
+    fn sexp(&self, indent: usize, out: &mut String) {
+        match self {
+            Value::Node { kind, span, fields } => {
+                write!(out, "({}", kind).unwrap();
+                if let Some(span) = span {
+                    write!(out, " @{}..{}", span.lo, span.hi).unwrap();
+                }
+                let one_line = fields.iter().all(|(_, value)| value.is_scalar());
+                for (name, value) in fields {
+                    if one_line {
+                        out.push(' ');
+                    } else {
+                        write!(out, "\n{:width$}", "", width = indent + 2).unwrap();
+                    }
+                    write!(out, ":{} ", name).unwrap();
+                    value.sexp(indent + 2, out);
+                }
+                out.push(')');
+            },
+            Value::List(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if self.is_scalar() {
+                        if i > 0 {
+                            out.push(' ');
+                        }
+                    } else {
+                        write!(out, "\n{:width$}", "", width = indent + 2).unwrap();
+                    }
+                    item.sexp(indent + 2, out);
+                }
+                out.push(']');
+            },
+            Value::Str(text) => write!(out, "{:?}", text).unwrap(),
+            Value::Int(value) => write!(out, "{}", value).unwrap(),
+            Value::Float(value) => write!(out, "{:?}", value).unwrap(),
+            Value::Bool(value) => write!(out, "{}", value).unwrap(),
+            Value::Null => out.push_str("nil")
+        }
+    }
+
+    fn json(&self, indent: usize, out: &mut String) {
+        match self {
+            Value::Node { kind, span, fields } => {
+                let kind = Value::Str(kind.clone());
+                let span = span.map(span_value);
+                let mut entries: Vec<(&str, &Value)> = vec![("kind", &kind)];
+                if let Some(span) = &span {
+                    entries.push(("span", span));
+                }
+                entries.extend(fields.iter().map(|(name, value)| (*name, value)));
+
+                let one_line = fields.iter().all(|(_, value)| value.is_scalar());
+                out.push('{');
+                for (i, (name, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    if one_line {
+                        if i > 0 {
+                            out.push(' ');
+                        }
+                    } else {
+                        write!(out, "\n{:width$}", "", width = indent + 2).unwrap();
+                    }
+                    json_string(name, out);
+                    out.push_str(": ");
+                    value.json(indent + 2, out);
+                }
+                if !one_line {
+                    write!(out, "\n{:width$}", "", width = indent).unwrap();
+                }
+                out.push('}');
+            },
+            Value::List(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    if self.is_scalar() {
+                        if i > 0 {
+                            out.push(' ');
+                        }
+                    } else {
+                        write!(out, "\n{:width$}", "", width = indent + 2).unwrap();
+                    }
+                    item.json(indent + 2, out);
+                }
+                if !self.is_scalar() {
+                    write!(out, "\n{:width$}", "", width = indent).unwrap();
+                }
+                out.push(']');
+            },
+            Value::Str(text) => json_string(text, out),
+            Value::Int(value) => write!(out, "{}", value).unwrap(),
+            Value::Float(value) if value.is_finite() => write!(out, "{:?}", value).unwrap(),
+            Value::Float(_) | Value::Null => out.push_str("null"),
+            Value::Bool(value) => write!(out, "{}", value).unwrap()
+        }
+    }
+
+    fn print(&self, format: DumpFormat) -> String {
+        let mut out = String::new();
+        match format {
+            DumpFormat::Sexp => self.sexp(0, &mut out),
+            DumpFormat::Json => self.json(0, &mut out)
+        }
+        return out;
+    }
+}
+
+fn json_string(text: &str, out: &mut String) {
+    out.push('"');
+    for chr in text.chars() {
+        match chr {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            chr if (chr as u32) < 0x20 => write!(out, "\\u{:04x}", chr as u32).unwrap(),
+            chr => out.push(chr)
+        }
+    }
+    out.push('"');
+}
+
+
+/// Prints the tokens of a file, one per line in S-expressions and as an array in JSON.
+pub fn dump_tokens(tokens: &[Token], format: DumpFormat) -> String {
+    let values = tokens.iter().map(|token| Value::node(&format!("{:?}", token.token_type), token.loc, vec![
+        ("text", Value::str(&token.text)),
+        ("leading_ws", Value::Bool(token.leading_ws))
+    ]));
+    match format {
+        DumpFormat::Sexp => values.map(|value| value.print(format) + "\n").collect(),
+        DumpFormat::Json => {
+            let mut out = String::from("[");
+            for (i, value) in values.enumerate() {
+                out.push_str(if i == 0 { "\n  " } else { ",\n  " });
+                value.json(2, &mut out);
+            }
+            out.push_str("\n]\n");
+            out
+        }
+    }
+}
+
+/// Prints every file of a program. Each file is named by its path relative to the working directory, when it
+/// is inside it, so that dumps can be compared across machines.
+pub fn dump_program(program: &Program, sources: &SourceMap, format: DumpFormat) -> String {
+    let working_directory = std::env::current_dir().ok().and_then(|dir| dunce::canonicalize(dir).ok());
+    let files = Value::list(&program.files, |file| {
+        let source = sources.get(file.file);
+        let name = match (&source.path, &working_directory) {
+            (Some(path), Some(directory)) => path.strip_prefix(directory).map_or(source.name.clone(), |p| p.display().to_string()),
+            _ => source.name.clone()
+        };
+        Value::Node { kind: String::from("File"), span: None, fields: vec![
+            ("file", Value::Int(file.file.0 as u64)),
+            ("name", Value::Str(name)),
+            ("top_levels", Value::list(file.top_level_ids(), |id| top_level(program, id)))
+        ] }
+    });
+    let mut out = Value::Node { kind: String::from("Program"), span: None, fields: vec![("files", files)] }.print(format);
+    out.push('\n');
+    return out;
+}
+
+fn attributes(attributes: &[Attribute]) -> Value {
+    Value::list(attributes, |attribute| Value::node("Attribute", attribute.loc, vec![
+        ("name", Value::str(&attribute.name)),
+        ("arguments", Value::list(&attribute.arguments, |argument| Value::node("QualName", argument.loc, vec![("name", Value::str(&argument.name))])))
+    ]))
+}
+
+fn generic_parameters(program: &Program, parameters: &[GenericParameter]) -> Value {
+    Value::list(parameters, |parameter| Value::node("GenericParameter", parameter.loc, vec![
+        ("name", Value::str(&parameter.name)),
+        ("bounds", Value::list(&parameter.bounds, |bound| name(program, *bound)))
+    ]))
+}
+
+fn parameters(program: &Program, parameters: &[FunctionParameter]) -> Value {
+    Value::list(parameters, |parameter| Value::node("FunctionParameter", parameter.loc, vec![
+        ("name", Value::str(&parameter.name)),
+        ("type", typ(program, parameter.typ))
+    ]))
+}
+
+fn span_value(span: Span) -> Value {
+    Value::List(vec![Value::Int(span.lo as u64), Value::Int(span.hi as u64)])
+}
+
+fn top_level(program: &Program, id: TopLevelId) -> Value {
+    match &program[id] {
+        TopLevelNode::Import(data) => Value::node("Import", data.loc, vec![
+            ("attributes", attributes(&data.attributes)),
+            ("path", name(program, data.path)),
+            ("file", Value::option(data.file, |file| Value::Int(file.0 as u64)))
+        ]),
+        TopLevelNode::Struct(data) => Value::node("Struct", data.loc, vec![
+            ("attributes", attributes(&data.attributes)),
+            ("name", Value::str(&data.name)),
+            ("name_loc", span_value(data.name_loc)),
+            ("generic_parameters", generic_parameters(program, &data.generic_parameters)),
+            ("superstruct", Value::option(data.superstruct, |superstruct| name(program, superstruct))),
+            ("interfaces", Value::list(&data.interfaces, |interface| name(program, *interface))),
+            ("fields", Value::list(&data.fields, |field| Value::node("StructField", field.loc, vec![
+                ("name", Value::str(&field.name)),
+                ("type", typ(program, field.typ))
+            ]))),
+            ("methods", Value::list(&data.methods, |method| function(program, method)))
+        ]),
+        TopLevelNode::Trait(data) => Value::node("Trait", data.loc, vec![
+            ("attributes", attributes(&data.attributes)),
+            ("name", Value::str(&data.name)),
+            ("name_loc", span_value(data.name_loc)),
+            ("methods", Value::list(&data.methods, |method| Value::node("TraitMethod", method.loc, vec![
+                ("name", Value::str(&method.name)),
+                ("name_loc", span_value(method.name_loc)),
+                ("parameters", parameters(program, &method.parameters)),
+                ("ret", typ(program, method.ret))
+            ])))
+        ]),
+        TopLevelNode::Function(data) => function(program, data),
+        TopLevelNode::Const(data) => Value::node("Const", data.loc, vec![
+            ("attributes", attributes(&data.attributes)),
+            ("name", Value::str(&data.name)),
+            ("name_loc", span_value(data.name_loc)),
+            ("type", typ(program, data.typ)),
+            ("value", expr(program, data.value))
+        ])
+    }
+}
+
+fn function(program: &Program, data: &FunctionData) -> Value {
+    Value::node("Function", data.loc, vec![
+        ("attributes", attributes(&data.attributes)),
+        ("name", Value::str(&data.name)),
+        ("name_loc", span_value(data.name_loc)),
+        ("generic_parameters", generic_parameters(program, &data.generic_parameters)),
+        ("parameters", parameters(program, &data.parameters)),
+        ("ret", typ(program, data.ret)),
+        ("body", block(program, &data.body))
+    ])
+}
+
+fn block(program: &Program, data: &BlockData) -> Value {
+    Value::node("Block", data.loc, vec![("stmts", Value::list(&data.stmts, |id| stmt(program, *id)))])
+}
+
+fn stmt(program: &Program, id: StmtId) -> Value {
+    match &program[id] {
+        StmtNode::Expr(data) => Value::node("StmtExpr", data.loc, vec![("expr", expr(program, data.expr))]),
+        StmtNode::Let(data) => Value::node("StmtLet", data.loc, vec![
+            ("name", Value::str(&data.name)),
+            ("name_loc", span_value(data.name_loc)),
+            ("type", Value::option(data.typ, |t| typ(program, t))),
+            ("value", Value::option(data.value, |value| expr(program, value)))
+        ]),
+        StmtNode::Return(data) => Value::node("StmtReturn", data.loc, vec![("expr", expr(program, data.expr))]),
+        StmtNode::If(data) => Value::node("StmtIf", data.loc, vec![
+            ("condition", expr(program, data.condition)),
+            ("body", block(program, &data.body)),
+            ("else_body", Value::option(data.else_body.as_ref(), |else_body| block(program, else_body)))
+        ]),
+        StmtNode::While(data) => Value::node("StmtWhile", data.loc, vec![
+            ("condition", expr(program, data.condition)),
+            ("body", block(program, &data.body))
+        ]),
+        StmtNode::For(data) => {
+            let iterable = match data.iterable {
+                ForIterable::Range(start, end) => Value::Node { kind: String::from("Range"), span: None, fields: vec![
+                    ("start", expr(program, start)),
+                    ("end", expr(program, end))
+                ] },
+                ForIterable::Array(array) => expr(program, array)
+            };
+            Value::node("StmtFor", data.loc, vec![
+                ("name", Value::str(&data.name)),
+                ("name_loc", span_value(data.name_loc)),
+                ("iterable", iterable),
+                ("body", block(program, &data.body))
+            ])
+        }
+    }
+}
+
+fn expr(program: &Program, id: ExprId) -> Value {
+    match &program[id] {
+        ExprNode::Name(data) => Value::node("Name", data.loc, vec![("name", name(program, data.name))]),
+        ExprNode::Integer(data) => Value::node("Integer", data.loc, vec![("value", Value::Int(data.integer))]),
+        ExprNode::Float(data) => Value::node("Float", data.loc, vec![("value", Value::Float(data.float))]),
+        ExprNode::Bool(data) => Value::node("Bool", data.loc, vec![("value", Value::Bool(data.value))]),
+        ExprNode::Char(data) => Value::node("Char", data.loc, vec![("value", Value::Str(data.value.to_string()))]),
+        ExprNode::String(data) => Value::node("String", data.loc, vec![("value", Value::str(&data.value))]),
+        ExprNode::Block(data) => block(program, data),
+        ExprNode::Call(data) => Value::node("Call", data.loc, vec![
+            ("callee", expr(program, data.callee)),
+            ("arguments", Value::list(&data.arguments, |argument| expr(program, *argument)))
+        ]),
+        ExprNode::Field(data) => Value::node("Field", data.loc, vec![
+            ("object", expr(program, data.object)),
+            ("field", Value::str(&data.field)),
+            ("field_loc", span_value(data.field_loc))
+        ]),
+        ExprNode::Assign(data) => Value::node("Assign", data.loc, vec![
+            ("op", Value::option(data.op, |op| Value::str(op.symbol()))),
+            ("target", expr(program, data.target)),
+            ("value", expr(program, data.value))
+        ]),
+        ExprNode::StructLiteral(data) => Value::node("StructLiteral", data.loc, vec![
+            ("name", name(program, data.name)),
+            ("fields", Value::list(&data.fields, |field| Value::node("StructLiteralField", field.loc, vec![
+                ("name", Value::str(&field.name)),
+                ("name_loc", span_value(field.name_loc)),
+                ("value", expr(program, field.value))
+            ])))
+        ]),
+        ExprNode::Unary(data) => Value::node("Unary", data.loc, vec![
+            ("op", Value::str(data.op.symbol())),
+            ("operand", expr(program, data.operand))
+        ]),
+        ExprNode::Binary(data) => Value::node("Binary", data.loc, vec![
+            ("op", Value::str(data.op.symbol())),
+            ("left", expr(program, data.left)),
+            ("right", expr(program, data.right))
+        ]),
+        ExprNode::Cast(data) => Value::node("Cast", data.loc, vec![
+            ("expr", expr(program, data.expr)),
+            ("type", typ(program, data.typ))
+        ]),
+        ExprNode::Is(data) => Value::node("Is", data.loc, vec![
+            ("expr", expr(program, data.expr)),
+            ("type", typ(program, data.typ))
+        ]),
+        ExprNode::Lambda(data) => Value::node("Lambda", data.loc, vec![
+            ("parameters", Value::list(&data.parameters, |parameter| Value::node("LambdaParameter", parameter.loc, vec![
+                ("name", Value::str(&parameter.name)),
+                ("type", Value::option(parameter.typ, |t| typ(program, t)))
+            ]))),
+            ("body", expr(program, data.body))
+        ]),
+        ExprNode::Array(data) => Value::node("Array", data.loc, vec![
+            ("elements", Value::list(&data.elements, |element| expr(program, *element)))
+        ]),
+        ExprNode::Index(data) => Value::node("Index", data.loc, vec![
+            ("object", expr(program, data.object)),
+            ("index", expr(program, data.index))
+        ])
+    }
+}
+
+fn typ(program: &Program, id: TypeId) -> Value {
+    match &program[id] {
+        TypeNode::Name(data) => Value::node("TypeName", data.loc, vec![
+            ("name", name(program, data.name)),
+            ("generic_arguments", Value::option(data.generic_arguments.as_ref(), |arguments| Value::list(arguments, |argument| typ(program, *argument))))
+        ]),
+        TypeNode::Function(data) => Value::node("TypeFunction", data.loc, vec![
+            ("arguments", Value::list(&data.arguments, |argument| typ(program, *argument))),
+            ("ret", typ(program, data.ret))
+        ]),
+        TypeNode::Reference(data) => Value::node("TypeReference", data.loc, vec![("type", typ(program, data.typ))]),
+        TypeNode::Array(data) => Value::node("TypeArray", data.loc, vec![
+            ("element", typ(program, data.element)),
+            ("length", expr(program, data.length))
+        ])
+    }
+}
+
+fn name(program: &Program, id: NameId) -> Value {
+    match &program[id] {
+        QualifiedNameNode::Name(data) => Value::node("QualName", data.loc, vec![("name", Value::str(&data.name))]),
+        QualifiedNameNode::Namespace(data) => Value::node("QualNamespace", data.loc, vec![
+            ("source", name(program, data.source)),
+            ("attr", Value::str(&data.attr))
+        ])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::CompilerState;
+    use crate::options::Options;
+    use crate::parser::{lex_file, parse_program};
+    use crate::source::{Path, PathBuf};
+    use crate::vfs::OverlayFileSystem;
+    use super::*;
+
+    fn state(text: &str) -> (CompilerState, PathBuf) {
+        let mut fs = OverlayFileSystem::in_memory();
+        let path = fs.add_file(Path::new("/test/main.adze"), String::from(text));
+        return (CompilerState::with_file_system(Options::from_args(Vec::new()).unwrap(), Box::new(fs)), path);
+    }
+
+    fn tokens(text: &str, format: DumpFormat) -> String {
+        let (mut state, path) = state(text);
+        let Ok(tokens) = lex_file(&mut state, path) else {
+            panic!("the text could not be lexed");
+        };
+        return dump_tokens(&tokens, format);
+    }
+
+    fn program(text: &str, format: DumpFormat) -> String {
+        let (mut state, path) = state(text);
+        let Ok(program) = parse_program(&mut state, path) else {
+            panic!("the program could not be parsed");
+        };
+        return dump_program(&program, &state.sources, format);
+    }
+
+    #[test]
+    fn tokens_are_printed_with_their_spans_and_text() {
+        assert_eq!(tokens("x = \"a\\\"b\";", DumpFormat::Sexp), concat!(
+            "(Identifier @0..1 :text \"x\" :leading_ws false)\n",
+            "(Equal @2..3 :text \"=\" :leading_ws true)\n",
+            r#"(String @4..10 :text "\"a\\\"b\"" :leading_ws true)"#, "\n",
+            "(Semicolon @10..11 :text \";\" :leading_ws false)\n"
+        ));
+        assert_eq!(tokens("x =\ty", DumpFormat::Json), concat!(
+            "[\n",
+            "  {\"kind\": \"Identifier\", \"span\": [0, 1], \"text\": \"x\", \"leading_ws\": false},\n",
+            "  {\"kind\": \"Equal\", \"span\": [2, 3], \"text\": \"=\", \"leading_ws\": true},\n",
+            "  {\"kind\": \"Identifier\", \"span\": [4, 5], \"text\": \"y\", \"leading_ws\": true}\n",
+            "]\n"
+        ));
+    }
+
+    #[test]
+    fn programs_are_printed_as_nested_nodes() {
+        let text = "fn main() -> int { return 1; }";
+        assert_eq!(program(text, DumpFormat::Sexp), concat!(
+            "(Program\n",
+            "  :files [\n",
+            "    (File\n",
+            "      :file 0\n",
+            "      :name \"/test/main.adze\"\n",
+            "      :top_levels [\n",
+            "        (Function @0..30\n",
+            "          :attributes []\n",
+            "          :name \"main\"\n",
+            "          :name_loc [3 7]\n",
+            "          :generic_parameters []\n",
+            "          :parameters []\n",
+            "          :ret (TypeName @13..16\n",
+            "            :name (QualName @13..16 :name \"int\")\n",
+            "            :generic_arguments nil)\n",
+            "          :body (Block @17..30\n",
+            "            :stmts [\n",
+            "              (StmtReturn @19..28\n",
+            "                :expr (Integer @26..27 :value 1))]))])])\n"
+        ));
+        let json = program(text, DumpFormat::Json);
+        assert!(json.starts_with("{\n  \"kind\": \"Program\",\n  \"files\": [\n    {\n      \"kind\": \"File\",\n"));
+        assert!(json.contains("\n          \"name_loc\": [3, 7],\n"));
+        assert!(json.contains("\n                \"expr\": {\"kind\": \"Integer\", \"span\": [26, 27], \"value\": 1}\n"));
+        assert!(json.ends_with("\n  ]\n}\n"));
+    }
+
+    #[test]
+    fn json_escapes_strings_and_has_no_infinities() {
+        let value = Value::List(vec![Value::str("a\"\\\n\u{1}"), Value::Float(f64::INFINITY), Value::Float(0.5), Value::Null]);
+        assert_eq!(value.print(DumpFormat::Json), "[\"a\\\"\\\\\\n\\u0001\", null, 0.5, null]");
+        assert_eq!(value.print(DumpFormat::Sexp), "[\"a\\\"\\\\\\n\\u{1}\" inf 0.5 nil]");
+    }
+}
@@ -1,38 +1,128 @@
+use std::collections::HashSet;
 use std::fmt::{Display, Formatter};
-use crate::source::Location;
+use crate::source::{Span, SourceMap};
+
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Severity::Note => "Note",
+            Severity::Warning => "Warning",
+            Severity::Error => "Error"
+        })
+    }
+}
 
 pub trait CompilerError {
     fn render(&self, display: &mut ErrorDisplay) -> String;
+
+    /// The span the diagnostic points at, if it has one. Diagnostics are ordered by it.
+    fn location(&self) -> Option<Span>;
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn fix(&self) -> Option<&Fix> {
+        None
+    }
+}
+
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Applicability {
+    /// The fix is certainly what was intended, and can be applied without review.
+    MachineApplicable,
+    /// The fix is a likely repair, but should be reviewed by a person.
+    MaybeIncorrect
+}
+
+/// Replaces the text covered by `loc` with `replacement`. A zero-length `loc` inserts the replacement.
+#[derive(Clone)]
+pub struct TextEdit {
+    pub loc: Span,
+    pub replacement: String
+}
+
+#[derive(Clone)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+    pub applicability: Applicability
+}
+
+impl Fix {
+    /// A fix consisting of a single edit.
+    pub fn new(message: &str, loc: Span, replacement: &str, applicability: Applicability) -> Fix {
+        Fix { message: String::from(message), edits: vec![TextEdit { loc, replacement: String::from(replacement) }], applicability }
+    }
 }
 
-pub struct ErrorDisplay {
+pub struct ErrorDisplay<'a> {
+    sources: &'a SourceMap,
     indent: usize
 }
 
-impl ErrorDisplay {
-    pub fn new() -> ErrorDisplay {
-        ErrorDisplay { indent: 0 }
+impl<'a> ErrorDisplay<'a> {
+    pub fn new(sources: &'a SourceMap) -> ErrorDisplay<'a> {
+        ErrorDisplay { sources, indent: 0 }
     }
 
-    pub fn with_indent<F>(&mut self, func: F)
-        where F: FnOnce() -> () {
+    pub fn with_indent<F, T>(&mut self, func: F) -> T
+        where F: FnOnce(&mut Self) -> T {
         self.indent += 1;
-        func();
+        let result = func(self);
         self.indent -= 1;
+        return result;
+    }
+
+    pub fn error_without_location(&self, level: Severity, message: &str) -> String {
+        let indent = "  | ".repeat(self.indent);
+        return format!("{indent}{level}: {message}\n");
     }
 
-    pub fn error_with_location(&self, level: &str, message: &str, loc: &Location) -> String {
+    pub fn error_with_location(&self, level: Severity, message: &str, loc: &Span) -> String {
         let indent = "  | ".repeat(self.indent);
+        let source = self.sources.get(loc.file);
+        let (line, start) = source.line_col(loc.lo as usize);
+        let (end_line, end) = source.line_col(loc.hi as usize);
+        let line_text = source.get_line(line).trim_end_matches(['\r', '\n']);
+
         let mut msg = format!("{indent}{level}: {message}\n");
-        msg.push_str(&format!("     |> In {}\n", loc.source.name));
-        msg.push_str(&format!("{: >4} | {}", loc.line+1, loc.source.get_line(loc.line)));
-        if loc.multiline {
-            msg.push_str(&format!("       {}{}>\n", " ".repeat(loc.offset), "^".repeat(loc.length)));
+        msg.push_str(&format!("{indent}     |> In {}\n", source.name));
+        msg.push_str(&format!("{indent}{: >4} | {}\n", line+1, line_text));
+        if end_line != line {
+            let length = line_text.chars().count().saturating_sub(start).max(1);
+            msg.push_str(&format!("{indent}       {}{}>\n", " ".repeat(start), "^".repeat(length)));
         } else {
-            msg.push_str(&format!("       {}{}\n", " ".repeat(loc.offset), "^".repeat(loc.length)));
+            msg.push_str(&format!("{indent}       {}{}\n", " ".repeat(start), "^".repeat((end - start).max(1))));
         }
         return msg;
     }
+
+    pub fn note(&self, message: &str) -> String {
+        let indent = "  | ".repeat(self.indent);
+        return format!("{indent}     = {}: {message}\n", Severity::Note);
+    }
+
+    pub fn help(&self, message: &str) -> String {
+        let indent = "  | ".repeat(self.indent);
+        return format!("{indent}     = Help: {message}\n");
+    }
+
+    pub fn fix(&self, fix: &Fix) -> String {
+        return match fix.applicability {
+            Applicability::MachineApplicable => self.help(&format!("{} (run 'adze fix' to apply)", fix.message)),
+            Applicability::MaybeIncorrect => self.help(&fix.message)
+        }
+    }
 }
 
 
@@ -51,6 +141,11 @@ impl<E> ErrorSet<E> where E: CompilerError {
         return self.errors.is_empty();
     }
 
+    /// Whether any diagnostic in the set is a hard error, as opposed to a warning or note.
+    pub fn has_errors(&self) -> bool {
+        return self.errors.iter().any(|e| e.severity() == Severity::Error);
+    }
+
     pub fn add_errors(&mut self, errors: &mut ErrorSet<E>) {
         self.errors.append(&mut errors.errors);
     }
@@ -58,14 +153,106 @@ impl<E> ErrorSet<E> where E: CompilerError {
     pub fn add_error(&mut self, error: E) {
         self.errors.push(error);
     }
+
+    /// The fixes attached to the diagnostics in this set that are safe to apply without review.
+    pub fn machine_applicable_fixes(&self) -> Vec<Fix> {
+        return self.errors.iter()
+            .filter_map(|e| e.fix())
+            .filter(|f| f.applicability == Applicability::MachineApplicable)
+            .cloned()
+            .collect();
+    }
 }
 
-impl<E> Display for ErrorSet<E> where E: CompilerError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut display = ErrorDisplay::new();
-        for error in &self.errors {
-            write!(f, "{}\n", error.render(&mut display))?;
-        };
-        Ok(())
+impl<E> ErrorSet<E> where E: CompilerError {
+    /// Renders the diagnostics ordered by source and position, leaving out exact duplicates.
+    /// At most `max_errors` hard errors are shown, followed by a count of the ones left out.
+    pub fn render(&self, sources: &SourceMap, max_errors: Option<usize>) -> String {
+        let mut sorted: Vec<&E> = self.errors.iter().collect();
+        sorted.sort_by_key(|e| e.location().map(|loc| (loc.file, loc.lo)));
+
+        let mut display = ErrorDisplay::new(sources);
+        let mut seen = HashSet::new();
+        let mut text = String::new();
+        let mut shown_errors = 0;
+        let mut hidden_errors = 0;
+        for error in sorted {
+            let rendered = error.render(&mut display);
+            if !seen.insert(rendered.clone()) {
+                continue;
+            }
+            if error.severity() == Severity::Error {
+                if max_errors.is_some_and(|max| shown_errors >= max) {
+                    hidden_errors += 1;
+                    continue;
+                }
+                shown_errors += 1;
+            }
+            text.push_str(&rendered);
+            text.push('\n');
+        }
+        if hidden_errors > 0 {
+            let plural = if hidden_errors == 1 { "error" } else { "errors" };
+            text.push_str(&format!("{} more {} not shown.\n", hidden_errors, plural));
+        }
+        return text;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use crate::source::{Span, Source, SourceMap};
+    use super::{CompilerError, ErrorDisplay, ErrorSet, Severity};
+
+    struct TestError {
+        message: &'static str,
+        severity: Severity,
+        loc: Span
+    }
+
+    impl CompilerError for TestError {
+        fn render(&self, display: &mut ErrorDisplay) -> String {
+            display.error_with_location(self.severity, self.message, &self.loc)
+        }
+
+        fn location(&self) -> Option<Span> {
+            Some(self.loc)
+        }
+
+        fn severity(&self) -> Severity {
+            self.severity
+        }
     }
-}
\ No newline at end of file
+
+    /// The first line of each diagnostic in a rendered report, and the line that counts those left out.
+    fn messages(report: &str) -> Vec<&str> {
+        return report.lines().filter(|line| !line.starts_with(' ') && !line.is_empty()).collect();
+    }
+
+    #[test]
+    fn diagnostics_are_sorted_deduplicated_and_capped() {
+        let mut sources = SourceMap::new();
+        let file = sources.add(Source::from_string("test.adze", String::from("let a = 1;\nlet b = 2;\nlet c = 3;\n")));
+        let error = |message, severity, line: usize| TestError { message, severity, loc: Span::new(file, line * 11, line * 11 + 3) };
+
+        let mut errors = ErrorSet::new();
+        errors.add_error(error("third", Severity::Error, 2));
+        errors.add_error(error("first", Severity::Error, 0));
+        errors.add_error(error("second", Severity::Warning, 1));
+        errors.add_error(error("first", Severity::Error, 0));
+        assert_eq!(messages(&errors.render(&sources, None)), ["Error: first", "Warning: second", "Error: third"]);
+        // Warnings do not count towards the cap.
+        assert_eq!(messages(&errors.render(&sources, Some(1))), ["Error: first", "Warning: second", "1 more error not shown."]);
+        assert_eq!(messages(&errors.render(&sources, Some(0))), ["Warning: second", "2 more errors not shown."]);
+    }
+
+    #[test]
+    fn max_errors_caps_a_check() {
+        let options = Options::from_args(vec![String::from("--max-errors=1")]).unwrap();
+        let text = "fn f() -> int { return true; }\nfn g() -> bool { return 1; }\nfn h() -> int { return 'c'; }";
+        let result = crate::check(&options, &Some(String::from(text)));
+        assert_eq!(messages(&result.report), ["Error: Mismatched types: expected 'i64', found 'bool'.", "2 more errors not shown."]);
+    }
+}
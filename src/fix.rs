@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::error::{Fix, TextEdit};
+use crate::source::{SourceMap, FileId};
+use crate::vfs::FileSystem;
+
+
+/// Applies the edits of every fix to the files they refer to, writing them through the file system they were
+/// read from, and returns how many fixes were applied.
+/// A fix with an edit that overlaps an edit of an earlier fix is skipped, so that it can be
+/// reconsidered once the file has been checked again.
+pub fn apply_fixes(fs: &dyn FileSystem, sources: &SourceMap, fixes: &[Fix]) -> io::Result<usize> {
+    let mut by_file: HashMap<FileId, Vec<&Fix>> = HashMap::new();
+    for fix in fixes {
+        if let Some(edit) = fix.edits.first() {
+            by_file.entry(edit.loc.file).or_default().push(fix);
+        }
+    }
+
+    let mut applied = 0;
+    for (file, fixes) in by_file {
+        let source = sources.get(file);
+        let path = match &source.path {
+            Some(path) => path,
+            None => continue
+        };
+
+        let mut accepted: Vec<&TextEdit> = Vec::new();
+        for fix in fixes {
+            let overlaps = fix.edits.iter().any(|edit| {
+                accepted.iter().any(|other| (edit.loc.lo < other.loc.hi && other.loc.lo < edit.loc.hi) || edit.loc.lo == other.loc.lo)
+            });
+            if !overlaps {
+                accepted.extend(&fix.edits);
+                applied += 1;
+            }
+        }
+
+        accepted.sort_by_key(|edit| edit.loc.lo);
+        let mut text = source.text.clone();
+        for edit in accepted.iter().rev() {
+            text.replace_range(edit.loc.lo as usize..edit.loc.hi as usize, &edit.replacement);
+        }
+        fs.write(path, &text)?;
+    }
+    return Ok(applied);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::CompilerState;
+    use crate::options::Options;
+    use crate::parser::parse_program;
+    use crate::source::{Path, PathBuf};
+    use crate::vfs::{FileSystem, OverlayFileSystem};
+    use super::apply_fixes;
+
+    fn compiler_state(fs: Box<dyn FileSystem>) -> CompilerState {
+        CompilerState::with_file_system(Options::from_args(Vec::new()).unwrap(), fs)
+    }
+
+    /// A file after one round of the machine-applicable fixes for its syntax errors.
+    fn fixed(text: &str) -> String {
+        let mut fs = OverlayFileSystem::in_memory();
+        let path = fs.add_file(Path::new("/test/main.adze"), String::from(text));
+        let mut state = compiler_state(Box::new(fs));
+        let fixes = match parse_program(&mut state, path.clone()) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.machine_applicable_fixes()
+        };
+        apply_fixes(state.fs.as_ref(), &state.sources, &fixes).unwrap();
+        return state.fs.read_to_string(&path).unwrap();
+    }
+
+    #[test]
+    fn syntax_errors_with_an_obvious_repair_are_fixed() {
+        assert_eq!(fixed("fn f() -> int { return 0 }"), "fn f() -> int { return 0; }");
+        assert_eq!(fixed("fn f() int { return 0; }"), "fn f() -> int { return 0; }");
+        assert_eq!(fixed("fn f(): int { return 0; }"), "fn f() -> int { return 0; }");
+        assert_eq!(fixed("fn f() -> int { return a:b(); }"), "fn f() -> int { return a::b(); }");
+        assert_eq!(fixed("fn f() -> int { if p == P { x: 1 } { } return 0; }"), "fn f() -> int { if p == (P { x: 1 }) { } return 0; }");
+    }
+
+    #[test]
+    fn fixes_that_may_be_wrong_are_not_applied() {
+        // A ';' missing anywhere but before a '}' may be a sign of another mistake, so it is only suggested.
+        let text = "fn f() -> int { let x = 1 let y = 2; return 0; }";
+        assert_eq!(fixed(text), text);
+    }
+
+    #[test]
+    fn fixes_files_in_memory() {
+        let mut fs = OverlayFileSystem::in_memory();
+        let main = fs.add_file(Path::new("/project/main.adze"), String::from("import util;\nfn main() -> int { return one(); }\n"));
+        fs.add_file(Path::new("/project/util.adze"), String::from("fn one(): int { return 1; }\n"));
+
+        let mut state = compiler_state(Box::new(fs));
+        let errors = match parse_program(&mut state, main.clone()) {
+            Ok(_) => panic!("the imported file has a syntax error"),
+            Err(errors) => errors
+        };
+        assert_eq!(state.sources.files().count(), 2);
+        let fixes = errors.machine_applicable_fixes();
+        assert_eq!(apply_fixes(state.fs.as_ref(), &state.sources, &fixes).unwrap(), 1);
+        assert_eq!(state.fs.read_to_string(Path::new("/project/util.adze")).unwrap(), "fn one() -> int { return 1; }\n");
+
+        let mut state = compiler_state(state.fs);
+        assert!(parse_program(&mut state, main).is_ok());
+        assert!(state.fs.read_to_string(&PathBuf::from("/project/missing.adze")).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::source::Span;
+use crate::resolve::{Resolution, DeclId, DeclKind, Site};
+
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct BlockId(pub u32);
+
+/// Something that happens to a local variable while a basic block runs.
+#[derive(Copy, Clone)]
+pub enum Event {
+    /// The variable's value is read by the name at `loc`.
+    Read { local: DeclId, loc: Span },
+    /// A value is assigned to the variable.
+    Write { local: DeclId },
+    /// The variable is declared without a value, so it has none until it is assigned, even if a previous
+    /// iteration of a loop assigned it.
+    Declare { local: DeclId }
+}
+
+/// How control leaves a basic block.
+#[derive(Copy, Clone)]
+pub enum Terminator {
+    Goto(BlockId),
+    /// Continues with `then` if the condition is true, and with `otherwise` if it is false.
+    Branch { then: BlockId, otherwise: BlockId },
+    Return,
+    /// Reaches the closing brace of the body without returning.
+    End
+}
+
+pub struct BasicBlock {
+    pub events: Vec<Event>,
+    pub terminator: Terminator
+}
+
+/// The control-flow graph of a function or lambda body. Expressions are evaluated left to right, and the
+/// right operand of `&&` and `||` is only evaluated when it is needed. Lambdas in the body are not part of
+/// its graph, since they cannot use its local variables; each lambda body has a graph of its own.
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub entry: BlockId,
+    /// The block each statement starts in.
+    pub statements: HashMap<ast::StmtId, BlockId>,
+    /// The statements of each block in the source, in order.
+    pub sequences: Vec<Vec<ast::StmtId>>
+}
+
+impl Cfg {
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0 as usize]
+    }
+
+    pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+        match self.block(id).terminator {
+            Terminator::Goto(target) => vec![target],
+            Terminator::Branch { then, otherwise } => vec![then, otherwise],
+            Terminator::Return | Terminator::End => Vec::new()
+        }
+    }
+
+    /// Whether each block can be reached from the entry, by block id.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![self.entry];
+        while let Some(id) = pending.pop() {
+            if !reachable[id.0 as usize] {
+                reachable[id.0 as usize] = true;
+                pending.extend(self.successors(id));
+            }
+        }
+        return reachable;
+    }
+
+    /// The statements that cannot be reached because a statement before them in the same block always returns,
+    /// each with the last statement of its block, which is just as unreachable. Statements in blocks that cannot
+    /// be reached at all are covered by the statement containing the block.
+    pub fn unreachable_statements(&self) -> Vec<(ast::StmtId, ast::StmtId)> {
+        let reachable = self.reachable();
+        let is_reachable = |stmt: &ast::StmtId| reachable[self.statements[stmt].0 as usize];
+        let mut unreachable = Vec::new();
+        for sequence in &self.sequences {
+            for (i, stmt) in sequence.iter().enumerate().skip(1) {
+                if !is_reachable(stmt) && is_reachable(&sequence[i - 1]) {
+                    unreachable.push((*stmt, *sequence.last().unwrap()));
+                    break;
+                }
+            }
+        }
+        return unreachable;
+    }
+}
+
+
+/// Builds the graph of a function body, or of a lambda body that is a block.
+pub fn build_cfg(program: &ast::Program, block: &ast::BlockData, resolution: &Resolution) -> Cfg {
+    let mut builder = Builder::new(program, resolution);
+    builder.block(block);
+    return builder.into_cfg();
+}
+
+/// Builds the graph of a lambda body that is a single expression, whose value is returned.
+pub fn build_expr_cfg(program: &ast::Program, expr: ast::ExprId, resolution: &Resolution) -> Cfg {
+    let mut builder = Builder::new(program, resolution);
+    builder.expr(expr);
+    let next = builder.new_block();
+    builder.finish(Terminator::Return, next);
+    return builder.into_cfg();
+}
+
+
+struct Builder<'a> {
+    program: &'a ast::Program,
+    resolution: &'a Resolution,
+    blocks: Vec<BasicBlock>,
+    /// The block that statements are being added to. It ends with `End` until it is finished.
+    current: BlockId,
+    statements: HashMap<ast::StmtId, BlockId>,
+    sequences: Vec<Vec<ast::StmtId>>
+}
+
+impl<'a> Builder<'a> {
+    fn new(program: &'a ast::Program, resolution: &'a Resolution) -> Builder<'a> {
+        Builder {
+            program,
+            resolution,
+            blocks: vec![BasicBlock { events: Vec::new(), terminator: Terminator::End }],
+            current: BlockId(0),
+            statements: HashMap::new(),
+            sequences: Vec::new()
+        }
+    }
+
+    fn into_cfg(self) -> Cfg {
+        Cfg { blocks: self.blocks, entry: BlockId(0), statements: self.statements, sequences: self.sequences }
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock { events: Vec::new(), terminator: Terminator::End });
+        return BlockId(self.blocks.len() as u32 - 1);
+    }
+
+    /// Ends the current block with `terminator` and continues in `next`.
+    fn finish(&mut self, terminator: Terminator, next: BlockId) {
+        self.blocks[self.current.0 as usize].terminator = terminator;
+        self.current = next;
+    }
+
+    fn event(&mut self, event: Event) {
+        self.blocks[self.current.0 as usize].events.push(event);
+    }
+
+    /// The local variable that a name refers to, if it refers to one.
+    fn local(&self, name: ast::NameId) -> Option<DeclId> {
+        let id = self.resolution.use_of(name)?;
+        return matches!(self.resolution.declaration(id).kind, DeclKind::Local).then_some(id);
+    }
+
+    fn block(&mut self, block: &ast::BlockData) {
+        self.sequences.push(block.stmts.clone());
+        for stmt in &block.stmts {
+            self.statements.insert(*stmt, self.current);
+            self.stmt(*stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: ast::StmtId) {
+        match &self.program[stmt] {
+            ast::StmtNode::Expr(data) => self.expr(data.expr),
+            ast::StmtNode::Let(data) => {
+                if let Some(value) = data.value {
+                    self.expr(value);
+                }
+                if let Some(local) = self.resolution.definition(Site::Local(stmt)) {
+                    self.event(if data.value.is_some() { Event::Write { local } } else { Event::Declare { local } });
+                }
+            },
+            ast::StmtNode::Return(data) => {
+                self.expr(data.expr);
+                // Whatever follows a `return` is unreachable, so it goes in a block that nothing leads to.
+                let next = self.new_block();
+                self.finish(Terminator::Return, next);
+            },
+            ast::StmtNode::If(data) => {
+                self.expr(data.condition);
+                let (then, join) = (self.new_block(), self.new_block());
+                let otherwise = if data.else_body.is_some() { self.new_block() } else { join };
+                self.finish(Terminator::Branch { then, otherwise }, then);
+                self.block(&data.body);
+                self.finish(Terminator::Goto(join), otherwise);
+                if let Some(else_body) = &data.else_body {
+                    self.block(else_body);
+                    self.finish(Terminator::Goto(join), join);
+                }
+            },
+            ast::StmtNode::While(data) => {
+                let (header, body, exit) = (self.new_block(), self.new_block(), self.new_block());
+                self.finish(Terminator::Goto(header), header);
+                self.expr(data.condition);
+                // A loop whose condition is `true` only ends by returning, so nothing leads to its exit.
+                let forever = matches!(self.program[data.condition], ast::ExprNode::Bool(ast::BoolData { value: true, .. }));
+                let terminator = if forever { Terminator::Goto(body) } else { Terminator::Branch { then: body, otherwise: exit } };
+                self.finish(terminator, body);
+                self.block(&data.body);
+                self.finish(Terminator::Goto(header), exit);
+            },
+            ast::StmtNode::For(data) => {
+                match data.iterable {
+                    ast::ForIterable::Range(start, end) => {
+                        self.expr(start);
+                        self.expr(end);
+                    },
+                    ast::ForIterable::Array(array) => self.expr(array)
+                }
+                // Like a `while` loop whose condition is whether there is another value, which the loop variable
+                // is set to at the start of each iteration.
+                let (header, body, exit) = (self.new_block(), self.new_block(), self.new_block());
+                self.finish(Terminator::Goto(header), header);
+                self.finish(Terminator::Branch { then: body, otherwise: exit }, body);
+                if let Some(local) = self.resolution.definition(Site::Local(stmt)) {
+                    self.event(Event::Write { local });
+                }
+                self.block(&data.body);
+                self.finish(Terminator::Goto(header), exit);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: ast::ExprId) {
+        match &self.program[expr] {
+            ast::ExprNode::Name(data) => {
+                if let Some(local) = self.local(data.name) {
+                    self.event(Event::Read { local, loc: data.loc });
+                }
+            },
+            // A compound assignment reads its target before writing it.
+            ast::ExprNode::Assign(data) if data.op.is_some() => {
+                self.expr(data.value);
+                self.expr(data.target);
+            },
+            ast::ExprNode::Integer(_) | ast::ExprNode::Float(_) | ast::ExprNode::Bool(_)
+            | ast::ExprNode::Char(_) | ast::ExprNode::String(_) | ast::ExprNode::Lambda(_) => { },
+            ast::ExprNode::Block(data) => self.block(data),
+            ast::ExprNode::Call(data) => {
+                self.expr(data.callee);
+                for argument in &data.arguments {
+                    self.expr(*argument);
+                }
+            },
+            ast::ExprNode::Field(data) => self.expr(data.object),
+            ast::ExprNode::Assign(data) => {
+                self.expr(data.value);
+                // Assigning to a variable is not a read of it, but assigning to one of its fields or elements is.
+                match &self.program[data.target] {
+                    ast::ExprNode::Name(name) => match self.local(name.name) {
+                        Some(local) => self.event(Event::Write { local }),
+                        None => self.expr(data.target)
+                    },
+                    _ => self.expr(data.target)
+                }
+            },
+            ast::ExprNode::StructLiteral(data) => {
+                for field in &data.fields {
+                    self.expr(field.value);
+                }
+            },
+            ast::ExprNode::Unary(data) => self.expr(data.operand),
+            ast::ExprNode::Binary(data) if matches!(data.op, ast::BinaryOp::And | ast::BinaryOp::Or) => {
+                self.expr(data.left);
+                let (right, join) = (self.new_block(), self.new_block());
+                let (then, otherwise) = if data.op == ast::BinaryOp::And { (right, join) } else { (join, right) };
+                self.finish(Terminator::Branch { then, otherwise }, right);
+                self.expr(data.right);
+                self.finish(Terminator::Goto(join), join);
+            },
+            ast::ExprNode::Binary(data) => {
+                self.expr(data.left);
+                self.expr(data.right);
+            },
+            ast::ExprNode::Cast(data) => self.expr(data.expr),
+            ast::ExprNode::Is(data) => self.expr(data.expr),
+            ast::ExprNode::Array(data) => {
+                for element in &data.elements {
+                    self.expr(*element);
+                }
+            },
+            ast::ExprNode::Index(data) => {
+                self.expr(data.object);
+                self.expr(data.index);
+            }
+        }
+    }
+}
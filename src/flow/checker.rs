@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast;
+use crate::source::Span;
+use crate::error::ErrorSet;
+use crate::resolve::{Resolution, DeclId};
+use crate::typecheck::{TypeInfo, Type};
+use crate::flow::cfg::{Cfg, BlockId, Event, Terminator, build_cfg, build_expr_cfg};
+use crate::flow::error::FlowError;
+use crate::visit::{Visitor, walk_expr};
+
+
+/// The control-flow graphs of a function or method body and of the lambdas in it.
+pub struct FunctionFlow {
+    pub body: Cfg,
+    pub lambdas: Vec<Cfg>
+}
+
+/// The result of control-flow analysis, keyed by each function and method.
+pub struct FlowInfo {
+    pub functions: HashMap<ast::ItemId, FunctionFlow>
+}
+
+
+/// Builds the control-flow graph of every body, and checks that bodies which return a value do so on every
+/// path, and that local variables are assigned before they are read.
+pub fn check_program(program: &ast::Program, resolution: &Resolution, types: &TypeInfo) -> Result<FlowInfo, ErrorSet<FlowError>> {
+    let mut checker = FlowChecker { program, resolution, types, errors: ErrorSet::new() };
+    let mut functions = HashMap::new();
+    for id in program.top_level_ids() {
+        match &program[id] {
+            ast::TopLevelNode::Function(data) => {
+                let item = ast::ItemId::TopLevel(id);
+                functions.insert(item, checker.check_function(item, data));
+            },
+            ast::TopLevelNode::Struct(data) => {
+                for (i, method) in data.methods.iter().enumerate() {
+                    let item = ast::ItemId::Method(id, i as u32);
+                    functions.insert(item, checker.check_function(item, method));
+                }
+            },
+            ast::TopLevelNode::Import(_) | ast::TopLevelNode::Trait(_) | ast::TopLevelNode::Const(_) => { }
+        }
+    }
+
+    if checker.errors.is_empty() {
+        Ok(FlowInfo { functions })
+    } else {
+        Err(checker.errors)
+    }
+}
+
+
+struct FlowChecker<'a> {
+    program: &'a ast::Program,
+    resolution: &'a Resolution,
+    types: &'a TypeInfo,
+    errors: ErrorSet<FlowError>
+}
+
+impl<'a> FlowChecker<'a> {
+    fn check_function(&mut self, item: ast::ItemId, data: &'a ast::FunctionData) -> FunctionFlow {
+        let returns_value = self.resolution.item(item)
+            .and_then(|id| self.types.functions.get(&id))
+            .is_some_and(|signature| signature.ret != Type::unit());
+        let body = build_cfg(self.program, &data.body, self.resolution);
+        if returns_value {
+            self.check_returns(&body, data.body.loc, Some(self.program.loc(data.ret)));
+        }
+        self.check_initialization(&body);
+
+        let mut collector = LambdaCollector { lambdas: Vec::new() };
+        collector.visit_block(self.program, &data.body);
+        let lambdas = collector.lambdas.into_iter().map(|lambda| self.check_lambda(lambda)).collect();
+        return FunctionFlow { body, lambdas };
+    }
+
+    fn check_lambda(&mut self, lambda: ast::ExprId) -> Cfg {
+        let ast::ExprNode::Lambda(data) = &self.program[lambda] else {
+            unreachable!();
+        };
+        let cfg = match &self.program[data.body] {
+            ast::ExprNode::Block(block) => {
+                let cfg = build_cfg(self.program, block, self.resolution);
+                if self.lambda_returns_value(lambda) {
+                    self.check_returns(&cfg, block.loc, None);
+                }
+                cfg
+            },
+            _ => build_expr_cfg(self.program, data.body, self.resolution)
+        };
+        self.check_initialization(&cfg);
+        return cfg;
+    }
+
+    /// Whether a lambda returns something other than `unit` in any instance of the function it is in.
+    fn lambda_returns_value(&self, lambda: ast::ExprId) -> bool {
+        self.types.instances.iter().any(|instance| match instance.expressions.get(&lambda) {
+            Some(Type::Function(_, ret)) => **ret != Type::unit() && !ret.is_error(),
+            _ => false
+        })
+    }
+
+    /// Reports a body, whose braces span `body`, if it can reach its closing brace without returning.
+    fn check_returns(&mut self, cfg: &Cfg, body: Span, declared: Option<Span>) {
+        let reachable = cfg.reachable();
+        let falls_through = cfg.blocks.iter().enumerate()
+            .any(|(i, block)| reachable[i] && matches!(block.terminator, Terminator::End));
+        if falls_through {
+            let loc = Span::new(body.file, body.hi as usize - 1, body.hi as usize);
+            self.errors.add_error(FlowError::MissingReturn { loc, declared });
+        }
+    }
+
+    /// Reports reads of local variables that are not assigned on every path from the start of the body. The
+    /// variables assigned on every path and on some path to the start of each block are found by iterating
+    /// over the graph until they stop changing.
+    fn check_initialization(&mut self, cfg: &Cfg) {
+        let count = cfg.blocks.len();
+        // A block that has not been reached yet has no set of definitely assigned variables, rather than an
+        // empty one, so that it does not restrict the sets of the blocks it joins.
+        let mut definitely: Vec<Option<HashSet<DeclId>>> = vec![None; count];
+        let mut possibly: Vec<HashSet<DeclId>> = vec![HashSet::new(); count];
+        definitely[cfg.entry.0 as usize] = Some(HashSet::new());
+        let mut pending = vec![cfg.entry];
+        while let Some(id) = pending.pop() {
+            let mut state = (definitely[id.0 as usize].clone().unwrap(), possibly[id.0 as usize].clone());
+            for event in &cfg.block(id).events {
+                apply(event, &mut state);
+            }
+            for successor in cfg.successors(id) {
+                let i = successor.0 as usize;
+                let joined = match &definitely[i] {
+                    Some(assigned) => assigned.intersection(&state.0).copied().collect(),
+                    None => state.0.clone()
+                };
+                let changed = definitely[i].as_ref() != Some(&joined) || !state.1.is_subset(&possibly[i]);
+                if changed {
+                    definitely[i] = Some(joined);
+                    possibly[i].extend(state.1.iter().copied());
+                    pending.push(successor);
+                }
+            }
+        }
+
+        let mut reported = HashSet::new();
+        for id in 0..count {
+            let mut state = match &definitely[id] {
+                Some(assigned) => (assigned.clone(), possibly[id].clone()),
+                None => continue
+            };
+            for event in &cfg.block(BlockId(id as u32)).events {
+                if let Event::Read { local, loc } = event {
+                    if !state.0.contains(local) && reported.insert(*loc) {
+                        let declaration = self.resolution.declaration(*local);
+                        self.errors.add_error(FlowError::Uninitialized {
+                            name: declaration.name.clone(), loc: *loc, declared: declaration.loc, never: !state.1.contains(local)
+                        });
+                    }
+                }
+                apply(event, &mut state);
+            }
+        }
+    }
+}
+
+
+/// Updates the variables that are definitely and possibly assigned after an event.
+fn apply(event: &Event, (definitely, possibly): &mut (HashSet<DeclId>, HashSet<DeclId>)) {
+    match event {
+        Event::Read { .. } => { },
+        Event::Write { local } => {
+            definitely.insert(*local);
+            possibly.insert(*local);
+        },
+        Event::Declare { local } => {
+            definitely.remove(local);
+            possibly.remove(local);
+        }
+    }
+}
+
+/// Collects the lambdas in a function body, outer lambdas before the lambdas inside them.
+struct LambdaCollector {
+    lambdas: Vec<ast::ExprId>
+}
+
+impl<'a> Visitor<'a> for LambdaCollector {
+    fn visit_expr(&mut self, program: &'a ast::Program, id: ast::ExprId) {
+        if let ast::ExprNode::Lambda(_) = &program[id] {
+            self.lambdas.push(id);
+        }
+        walk_expr(self, program, id);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+
+    /// The messages of the errors for a program read as if from standard input.
+    fn errors(text: &str) -> Vec<String> {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        return result.report.lines()
+            .filter_map(|line| line.strip_prefix("Error: "))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn bodies_that_return_a_value_return_on_every_path() {
+        assert_eq!(errors("fn f(x: bool) -> int { if x { return 1; } else { return 2; } }"), Vec::<String>::new());
+        assert_eq!(errors("fn f() -> unit { }"), Vec::<String>::new());
+        assert_eq!(errors("fn f(x: bool) -> int { if x { return 1; } }"), ["Not all paths return a value."]);
+        // A `for` loop may run no times, however many values its range has.
+        assert_eq!(errors("fn f() -> int { for i in 0..3 { return i; } }"), ["Not all paths return a value."]);
+        assert_eq!(errors("fn f() -> int { let g = |x: int| { if x > 0 { return 1; } }; return g(1); }"), ["Not all paths return a value."]);
+    }
+
+    #[test]
+    fn locals_are_assigned_before_they_are_read() {
+        assert_eq!(errors("fn f(x: bool) -> int { let y: int; if x { y = 1; } else { y = 2; } return y; }"), Vec::<String>::new());
+        assert_eq!(errors("fn f() -> int { let y: int; return y; }"), ["'y' is used before it is assigned a value."]);
+        assert_eq!(errors("fn f(x: bool) -> int { let y: int; if x { y = 1; } return y; }"), [
+            "'y' may be used before it is assigned a value, since some paths to here do not assign it."
+        ]);
+        // A declaration without a value makes the variable unassigned again, so the previous iteration's value does not count.
+        assert_eq!(errors("fn f() -> int { let n = 0; while n < 2 { let y: int; if n == 1 { return y; } y = n; n += 1; } return 0; }"), [
+            "'y' is used before it is assigned a value."
+        ]);
+    }
+
+    #[test]
+    fn loops_whose_condition_is_true_never_fall_through() {
+        assert_eq!(errors("fn f() -> int { while true { return 1; } }"), Vec::<String>::new());
+        assert_eq!(errors("fn f(x: bool) -> int { while x { return 1; } }"), vec!["Not all paths return a value."]);
+        assert_eq!(errors("fn f() -> int { while !false { return 1; } }"), vec!["Not all paths return a value."]);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::source::Span;
+use crate::error::{CompilerError, ErrorDisplay, Severity};
+
+
+pub enum FlowError {
+    /// A body that can reach its closing brace, at `loc`, without returning a value. `declared` is the
+    /// annotation of the return type, if it was written in the source.
+    MissingReturn { loc: Span, declared: Option<Span> },
+    /// A read of a local variable that has not been assigned on every path to it. `never` is whether it has
+    /// not been assigned on any of them.
+    Uninitialized { name: String, loc: Span, declared: Option<Span>, never: bool }
+}
+
+impl CompilerError for FlowError {
+    fn render(&self, display: &mut ErrorDisplay) -> String {
+        use FlowError::*;
+        match self {
+            MissingReturn { loc, declared } => {
+                let mut msg = display.error_with_location(Severity::Error, "Not all paths return a value.", loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, "The return type is declared here.", declared)
+                    }));
+                }
+                msg
+            },
+            Uninitialized { name, loc, declared, never } => {
+                let message = if *never {
+                    format!("'{}' is used before it is assigned a value.", name)
+                } else {
+                    format!("'{}' may be used before it is assigned a value, since some paths to here do not assign it.", name)
+                };
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, &format!("'{}' is declared here without a value.", name), declared)
+                    }));
+                }
+                msg
+            }
+        }
+    }
+
+    fn location(&self) -> Option<Span> {
+        use FlowError::*;
+        match self {
+            MissingReturn { loc, .. } => Some(*loc),
+            Uninitialized { loc, .. } => Some(*loc)
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod cfg;
+mod error;
+mod checker;
+
+pub use checker::{check_program, FlowInfo};
\ No newline at end of file
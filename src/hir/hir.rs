@@ -0,0 +1,233 @@
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+
+use crate::ast::{UnaryOp, BinaryOp};
+use crate::source::Span;
+use crate::resolve::DeclId;
+use crate::typecheck::{Type, Primitive, ConstValue};
+
+
+/// An id that numbers the nodes of an arena in the order they were allocated.
+pub trait ArenaId: Copy {
+    fn from_index(index: usize) -> Self;
+    fn index(self) -> usize;
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct ExprId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct StmtId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct LocalId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct FunctionId(pub u32);
+
+impl ArenaId for ExprId {
+    fn from_index(index: usize) -> Self { ExprId(index as u32) }
+    fn index(self) -> usize { self.0 as usize }
+}
+
+impl ArenaId for StmtId {
+    fn from_index(index: usize) -> Self { StmtId(index as u32) }
+    fn index(self) -> usize { self.0 as usize }
+}
+
+impl ArenaId for LocalId {
+    fn from_index(index: usize) -> Self { LocalId(index as u32) }
+    fn index(self) -> usize { self.0 as usize }
+}
+
+impl ArenaId for FunctionId {
+    fn from_index(index: usize) -> Self { FunctionId(index as u32) }
+    fn index(self) -> usize { self.0 as usize }
+}
+
+
+/// Nodes of one kind, indexed by their ids, with a side table holding the span of source each node came from.
+/// Nodes that desugaring introduces have the span of the construct they implement.
+#[derive(Debug)]
+pub struct Arena<I, T> {
+    nodes: Vec<T>,
+    spans: Vec<Span>,
+    id: PhantomData<I>
+}
+
+impl<I: ArenaId, T> Arena<I, T> {
+    pub fn new() -> Arena<I, T> {
+        Arena { nodes: Vec::new(), spans: Vec::new(), id: PhantomData }
+    }
+
+    pub fn alloc(&mut self, node: T, span: Span) -> I {
+        self.nodes.push(node);
+        self.spans.push(span);
+        return I::from_index(self.nodes.len() - 1);
+    }
+
+    pub fn span(&self, id: I) -> Span {
+        self.spans[id.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item=I> {
+        (0..self.nodes.len()).map(I::from_index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(I, &T)> {
+        self.nodes.iter().enumerate().map(|(i, node)| (I::from_index(i), node))
+    }
+}
+
+impl<I: ArenaId, T> Index<I> for Arena<I, T> {
+    type Output = T;
+
+    fn index(&self, id: I) -> &T {
+        &self.nodes[id.index()]
+    }
+}
+
+impl<I: ArenaId, T> IndexMut<I> for Arena<I, T> {
+    fn index_mut(&mut self, id: I) -> &mut T {
+        &mut self.nodes[id.index()]
+    }
+}
+
+
+/// The checked program with every generic function instantiated. Each instance of a function is a function of
+/// its own, numbered as the type checker numbers instances, followed by the functions that implement lambdas.
+#[derive(Debug)]
+pub struct Program {
+    pub functions: Arena<FunctionId, Function>,
+    /// Every struct type with concrete generic arguments that the functions use.
+    pub structs: Vec<Struct>,
+    /// The `main` function of the file the program was compiled from, if it has one.
+    pub main: Option<FunctionId>
+}
+
+impl Program {
+    pub fn struct_of(&self, typ: &Type) -> Option<&Struct> {
+        self.structs.iter().find(|s| &s.typ == typ)
+    }
+}
+
+#[derive(Debug)]
+pub struct Struct {
+    pub typ: Type,
+    /// The struct's name with its generic arguments, such as `Pair<int, bool>`.
+    pub name: String,
+    /// The fields, with those of the superstruct first, so that a field's index is the same in every substruct.
+    pub fields: Vec<Field>,
+    pub superstruct: Option<DeclId>,
+    /// Whether values of the struct carry a type tag, as they do when the struct is part of a hierarchy.
+    pub tagged: bool
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub typ: Type
+}
+
+/// A function, method or lambda. A method takes its receiver as its first parameter.
+#[derive(Debug)]
+pub struct Function {
+    /// The function's name with its generic arguments, such as `max<int>` or `Point.length`.
+    pub name: String,
+    pub parameters: Vec<LocalId>,
+    pub ret: Type,
+    /// The statements of the body. A function that returns `unit` may reach the end without returning.
+    pub body: Block,
+    pub locals: Arena<LocalId, Local>,
+    pub exprs: Arena<ExprId, Expr>,
+    pub stmts: Arena<StmtId, Stmt>,
+    pub loc: Span
+}
+
+impl Function {
+    /// Whether a program can start from the function, which it can if the function takes nothing and returns an
+    /// integer, the exit status, or `unit`, for an exit status of 0.
+    pub fn can_be_entry(&self) -> bool {
+        let returns_integer = self.ret.primitive().is_some_and(|p| p.is_integer() || p == Primitive::Unit);
+        return self.parameters.is_empty() && returns_integer;
+    }
+}
+
+/// A parameter or local variable, including the hidden ones that desugaring introduces.
+#[derive(Debug)]
+pub struct Local {
+    pub name: String,
+    pub typ: Type
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub stmts: Vec<StmtId>
+}
+
+#[derive(Debug)]
+pub enum Stmt {
+    Expr(ExprId),
+    Let { local: LocalId, value: Option<ExprId> },
+    Return(ExprId),
+    If { condition: ExprId, then: Block, otherwise: Option<Block> },
+    While { condition: ExprId, body: Block }
+}
+
+/// An expression and the type of its value. The types of the operands of every expression are exactly the
+/// types the expression needs: the conversions that the source leaves implicit are `Deref`, `Ref` and `Cast`
+/// expressions of their own.
+#[derive(Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub typ: Type
+}
+
+/// The kinds of expressions. Expressions that denote places, which can be assigned to or referenced, are
+/// locals, fields and elements of places, and dereferenced references.
+#[derive(Debug)]
+pub enum ExprKind {
+    /// A literal or the value of a constant.
+    Literal(ConstValue),
+    Local(LocalId),
+    /// A function used as a value.
+    Function(FunctionId),
+    Call { function: FunctionId, arguments: Vec<ExprId> },
+    /// A call of a function value.
+    CallIndirect { callee: ExprId, arguments: Vec<ExprId> },
+    /// The field of a struct with the given index in its layout.
+    Field { object: ExprId, index: usize },
+    /// The element of an array, whose index is a `u64`.
+    Index { object: ExprId, index: ExprId },
+    /// The place a reference refers to.
+    Deref(ExprId),
+    /// A reference to a place.
+    Ref(ExprId),
+    Cast { expr: ExprId, kind: CastKind },
+    /// Whether a struct value was created as the given struct or one of its substructs.
+    Is { expr: ExprId, target: DeclId },
+    Unary { op: UnaryOp, operand: ExprId },
+    /// A binary operator on operands of the same type. The right operand of `&&` and `||` is only evaluated
+    /// when it decides the result.
+    Binary { op: BinaryOp, left: ExprId, right: ExprId },
+    Assign { place: ExprId, value: ExprId },
+    /// A struct value. The values of its fields are evaluated in the order they were written, and each is
+    /// paired with the index of its field in the layout.
+    StructLiteral { fields: Vec<(usize, ExprId)> },
+    Array(Vec<ExprId>),
+    Block(Block)
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum CastKind {
+    /// A conversion between primitive types, as by `as`.
+    Numeric,
+    /// A reference to a struct used as a reference to one of its superstructs.
+    Upcast,
+    /// A reference to a struct converted to a reference to one of its substructs, which stops the program if
+    /// the value was not created as that substruct.
+    Downcast
+}
@@ -0,0 +1,699 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::source::Span;
+use crate::resolve::{Resolution, DeclId, DeclKind, Site};
+use crate::typecheck::{TypeInfo, Type, Primitive, Instance, InstanceId, ConstValue, bind};
+use crate::hir::hir::*;
+
+
+/// Lowers a program that has been checked without errors. Every instance of a function becomes a function with
+/// the types of its generic parameters filled in, and every lambda a function of its own.
+///
+/// Method calls become calls of the method with the receiver as the first argument, `for` loops become `while`
+/// loops over hidden variables, and compound assignments such as `x += 1` become assignments that evaluate
+/// their target once.
+pub fn lower_program(program: &ast::Program, resolution: &Resolution, types: &TypeInfo) -> Program {
+    let mut bodies: HashMap<DeclId, (ast::ItemId, &ast::FunctionData)> = HashMap::new();
+    for id in program.top_level_ids() {
+        let functions: Vec<(ast::ItemId, &ast::FunctionData)> = match &program[id] {
+            ast::TopLevelNode::Function(data) => vec![(ast::ItemId::TopLevel(id), data)],
+            ast::TopLevelNode::Struct(data) => {
+                data.methods.iter().enumerate().map(|(i, method)| (ast::ItemId::Method(id, i as u32), method)).collect()
+            },
+            ast::TopLevelNode::Import(_) | ast::TopLevelNode::Trait(_) | ast::TopLevelNode::Const(_) => Vec::new()
+        };
+        for (item, data) in functions {
+            if let Some(id) = resolution.item(item) {
+                bodies.insert(id, (item, data));
+            }
+        }
+    }
+
+    let mut lowerer = Lowerer {
+        program,
+        resolution,
+        types,
+        instance: InstanceId(0),
+        bindings: HashMap::new(),
+        body: Body::new(String::new(), Type::unit()),
+        lambdas: Vec::new()
+    };
+    let mut functions = Arena::new();
+    for (i, instance) in types.instances.iter().enumerate() {
+        let (item, data) = bodies[&instance.function];
+        let function = lowerer.lower_function(InstanceId(i as u32), item, data);
+        functions.alloc(function, data.loc);
+    }
+    for function in lowerer.lambdas.into_iter().flatten() {
+        let loc = function.loc;
+        functions.alloc(function, loc);
+    }
+
+    let structs = types.struct_instances.iter().filter_map(|typ| {
+        let (id, arguments) = match typ {
+            Type::Struct(id, arguments) => (id, arguments),
+            _ => return None
+        };
+        let struct_type = &types.structs[id];
+        let bindings = bind(&struct_type.generic_parameters, arguments);
+        Some(Struct {
+            typ: typ.clone(),
+            name: typ.display(resolution),
+            fields: struct_type.fields.iter().map(|f| Field { typ: f.typ.substitute(&bindings) }).collect(),
+            superstruct: struct_type.superstruct,
+            tagged: struct_type.tagged
+        })
+    }).collect();
+
+    let main = program.files.first()
+        .and_then(|file| file.top_level_ids().find_map(|id| match &file[id] {
+            ast::TopLevelNode::Function(data) if data.name == "main" => resolution.item(ast::ItemId::TopLevel(id)),
+            _ => None
+        }))
+        .and_then(|id| types.find_instance(id, &[]))
+        .map(|instance| FunctionId(instance.0));
+
+    return Program { functions, structs, main };
+}
+
+
+/// The parts of the function or lambda being lowered.
+struct Body {
+    name: String,
+    ret: Type,
+    locals: Arena<LocalId, Local>,
+    exprs: Arena<ExprId, Expr>,
+    stmts: Arena<StmtId, Stmt>,
+    /// The local each parameter and variable of the source was lowered to.
+    variables: HashMap<DeclId, LocalId>,
+    /// The number of lambdas in the body so far, for naming them.
+    lambdas: usize
+}
+
+impl Body {
+    fn new(name: String, ret: Type) -> Body {
+        Body {
+            name, ret, locals: Arena::new(), exprs: Arena::new(), stmts: Arena::new(), variables: HashMap::new(), lambdas: 0
+        }
+    }
+}
+
+struct Lowerer<'a> {
+    program: &'a ast::Program,
+    resolution: &'a Resolution,
+    types: &'a TypeInfo,
+    /// The instance being lowered, whose types the expressions have, and the types its function's generic
+    /// parameters are bound to.
+    instance: InstanceId,
+    bindings: HashMap<DeclId, Type>,
+    body: Body,
+    /// The functions for the lambdas found so far, which are numbered after the instances. A lambda's function
+    /// is only filled in once its body has been lowered.
+    lambdas: Vec<Option<Function>>
+}
+
+impl<'a> Lowerer<'a> {
+    fn instance(&self) -> &'a Instance {
+        self.types.instance(self.instance)
+    }
+
+    /// The type the type checker found for an expression.
+    fn type_of(&self, expr: ast::ExprId) -> Type {
+        self.instance().expressions.get(&expr).cloned().unwrap_or(Type::Error)
+    }
+
+    /// The type the type checker found for a variable.
+    fn variable_type(&self, site: Site) -> Type {
+        self.resolution.definition(site).and_then(|id| self.instance().variables.get(&id)).cloned().unwrap_or(Type::Error)
+    }
+
+    fn lower_function(&mut self, id: InstanceId, item: ast::ItemId, data: &ast::FunctionData) -> Function {
+        self.instance = id;
+        let instance = self.instance();
+        let signature = &self.types.functions[&instance.function];
+        self.bindings = bind(&signature.generic_parameters, &instance.arguments);
+        self.body = Body::new(self.function_name(instance), signature.ret.substitute(&self.bindings));
+
+        let parameters = data.parameters.iter().enumerate()
+            .map(|(i, p)| self.declare(&p.name, Site::Parameter(item, i as u32), p.loc))
+            .collect();
+        let body = self.lower_block(&data.body);
+        return self.finish(parameters, body, data.loc);
+    }
+
+    /// The name of an instance, such as `max<int>`. Methods are named after their structs, such as `Point.length`.
+    fn function_name(&self, instance: &Instance) -> String {
+        let declaration = self.resolution.declaration(instance.function);
+        let owner = self.types.structs.iter().find(|(_, s)| s.methods.values().any(|m| *m == instance.function));
+        let mut name = match owner {
+            Some((owner, _)) => format!("{}.{}", self.resolution.declaration(*owner).name, declaration.name),
+            None => declaration.name.clone()
+        };
+        if !instance.arguments.is_empty() {
+            let arguments: Vec<String> = instance.arguments.iter().map(|a| a.display(self.resolution)).collect();
+            name = format!("{}<{}>", name, arguments.join(", "));
+        }
+        return name;
+    }
+
+    /// Turns the body being lowered into a function.
+    fn finish(&mut self, parameters: Vec<LocalId>, body: Block, loc: Span) -> Function {
+        let parts = std::mem::replace(&mut self.body, Body::new(String::new(), Type::unit()));
+        return Function {
+            name: parts.name,
+            parameters,
+            ret: parts.ret,
+            body,
+            locals: parts.locals,
+            exprs: parts.exprs,
+            stmts: parts.stmts,
+            loc
+        };
+    }
+
+    /// Lowers the declaration of a parameter or variable made at a site, whose name is at `loc`.
+    fn declare(&mut self, name: &str, site: Site, loc: Span) -> LocalId {
+        let decl = self.resolution.definition(site);
+        let typ = decl.and_then(|id| self.instance().variables.get(&id)).cloned().unwrap_or(Type::Error);
+        let local = self.body.locals.alloc(Local { name: String::from(name), typ }, loc);
+        if let Some(decl) = decl {
+            self.body.variables.insert(decl, local);
+        }
+        return local;
+    }
+
+    /// Declares a variable that desugaring needs, which the source cannot name.
+    fn hidden(&mut self, name: &str, typ: Type, loc: Span) -> LocalId {
+        self.body.locals.alloc(Local { name: format!("${}", name), typ }, loc)
+    }
+
+    fn alloc(&mut self, kind: ExprKind, typ: Type, loc: Span) -> ExprId {
+        self.body.exprs.alloc(Expr { kind, typ }, loc)
+    }
+
+    fn typ(&self, id: ExprId) -> &Type {
+        &self.body.exprs[id].typ
+    }
+
+    fn local(&mut self, local: LocalId, loc: Span) -> ExprId {
+        let typ = self.body.locals[local].typ.clone();
+        self.alloc(ExprKind::Local(local), typ, loc)
+    }
+
+    fn stmt(&mut self, stmt: Stmt, loc: Span) -> StmtId {
+        self.body.stmts.alloc(stmt, loc)
+    }
+
+    fn lower_block(&mut self, block: &ast::BlockData) -> Block {
+        let stmts = block.stmts.iter().map(|stmt| self.lower_stmt(*stmt)).collect();
+        return Block { stmts };
+    }
+
+    fn lower_stmt(&mut self, stmt: ast::StmtId) -> StmtId {
+        let lowered = match &self.program[stmt] {
+            ast::StmtNode::Expr(data) => Stmt::Expr(self.lower_expr(data.expr)),
+            ast::StmtNode::Return(data) => {
+                let ret = self.body.ret.clone();
+                Stmt::Return(self.lower_value(data.expr, &ret))
+            },
+            ast::StmtNode::Let(data) => {
+                let value = match data.value {
+                    Some(value) => {
+                        let typ = self.variable_type(Site::Local(stmt));
+                        Some(self.lower_value(value, &typ))
+                    },
+                    None => None
+                };
+                Stmt::Let { local: self.declare(&data.name, Site::Local(stmt), data.name_loc), value }
+            },
+            ast::StmtNode::If(data) => Stmt::If {
+                condition: self.lower_value(data.condition, &Type::bool()),
+                then: self.lower_block(&data.body),
+                otherwise: data.else_body.as_ref().map(|else_body| self.lower_block(else_body))
+            },
+            ast::StmtNode::While(data) => Stmt::While {
+                condition: self.lower_value(data.condition, &Type::bool()),
+                body: self.lower_block(&data.body)
+            },
+            ast::StmtNode::For(data) => Stmt::Expr(self.lower_for(stmt, data))
+        };
+        return self.stmt(lowered, self.program.loc(stmt));
+    }
+
+    /// Lowers a `for` loop to a block with a `while` loop, which counts through the range or the indices of the
+    /// array in a hidden variable and sets the loop variable at the start of each iteration.
+    fn lower_for(&mut self, stmt: ast::StmtId, data: &ast::StmtForData) -> ExprId {
+        let loc = data.loc;
+        let mut stmts = Vec::new();
+        let (index, condition, element) = match data.iterable {
+            ast::ForIterable::Range(start, end) => {
+                let typ = self.variable_type(Site::Local(stmt));
+                let start = self.lower_value(start, &typ);
+                let end = self.lower_value(end, &typ);
+                let index = self.hidden("index", typ.clone(), loc);
+                let end_local = self.hidden("end", typ.clone(), loc);
+                stmts.push(self.stmt(Stmt::Let { local: index, value: Some(start) }, loc));
+                stmts.push(self.stmt(Stmt::Let { local: end_local, value: Some(end) }, loc));
+                let (left, right) = (self.local(index, loc), self.local(end_local, loc));
+                let condition = self.alloc(ExprKind::Binary { op: ast::BinaryOp::Less, left, right }, Type::bool(), loc);
+                (index, condition, self.local(index, loc))
+            },
+            ast::ForIterable::Array(array) => {
+                let array = self.lower_expr(array);
+                let array_type = self.typ(array).clone();
+                let (element_type, length) = match array_type.dereferenced() {
+                    Type::Array(element, length) => (element.as_ref().clone(), *length),
+                    _ => (Type::Error, 0)
+                };
+                let array_local = self.hidden("array", array_type, loc);
+                let u64_type = Type::Primitive(Primitive::U64);
+                let index = self.hidden("index", u64_type.clone(), loc);
+                stmts.push(self.stmt(Stmt::Let { local: array_local, value: Some(array) }, loc));
+                let zero = self.alloc(ExprKind::Literal(ConstValue::Integer(0)), u64_type.clone(), loc);
+                stmts.push(self.stmt(Stmt::Let { local: index, value: Some(zero) }, loc));
+                let left = self.local(index, loc);
+                let right = self.alloc(ExprKind::Literal(ConstValue::Integer(length as i128)), u64_type, loc);
+                let condition = self.alloc(ExprKind::Binary { op: ast::BinaryOp::Less, left, right }, Type::bool(), loc);
+                let object = self.local(array_local, loc);
+                let object = self.read(object);
+                let index_expr = self.local(index, loc);
+                (index, condition, self.alloc(ExprKind::Index { object, index: index_expr }, element_type, loc))
+            }
+        };
+
+        let variable_type = self.variable_type(Site::Local(stmt));
+        let element = self.coerce(element, &variable_type);
+        let variable = self.declare(&data.name, Site::Local(stmt), data.name_loc);
+        let mut body = vec![self.stmt(Stmt::Let { local: variable, value: Some(element) }, data.name_loc)];
+        body.extend(self.lower_block(&data.body).stmts);
+        let typ = self.body.locals[index].typ.clone();
+        let (place, left) = (self.local(index, loc), self.local(index, loc));
+        let right = self.alloc(ExprKind::Literal(ConstValue::Integer(1)), typ.clone(), loc);
+        let value = self.alloc(ExprKind::Binary { op: ast::BinaryOp::Add, left, right }, typ, loc);
+        let increment = self.alloc(ExprKind::Assign { place, value }, Type::unit(), loc);
+        body.push(self.stmt(Stmt::Expr(increment), loc));
+
+        stmts.push(self.stmt(Stmt::While { condition, body: Block { stmts: body } }, loc));
+        return self.alloc(ExprKind::Block(Block { stmts }), Type::unit(), loc);
+    }
+
+    /// Lowers an expression where a value of type `expected` is needed.
+    fn lower_value(&mut self, expr: ast::ExprId, expected: &Type) -> ExprId {
+        let id = self.lower_expr(expr);
+        return self.coerce(id, expected);
+    }
+
+    /// Makes the conversions that the type checker allows implicitly explicit, so that a lowered expression
+    /// has the type `expected`.
+    fn coerce(&mut self, id: ExprId, expected: &Type) -> ExprId {
+        let found = self.typ(id).clone();
+        let loc = self.body.exprs.span(id);
+        if &found == expected || expected.is_error() {
+            return id;
+        }
+        match (&found, expected) {
+            (Type::Reference(_), Type::Reference(_)) => self.alloc(ExprKind::Cast { expr: id, kind: CastKind::Upcast }, expected.clone(), loc),
+            (_, Type::Reference(inner)) => {
+                let reference = self.alloc(ExprKind::Ref(id), Type::Reference(Box::new(found.clone())), loc);
+                if &found == inner.as_ref() {
+                    reference
+                } else {
+                    self.alloc(ExprKind::Cast { expr: reference, kind: CastKind::Upcast }, expected.clone(), loc)
+                }
+            },
+            (Type::Reference(inner), _) => {
+                let value = self.alloc(ExprKind::Deref(id), inner.as_ref().clone(), loc);
+                self.coerce(value, expected)
+            },
+            (Type::Primitive(_), Type::Primitive(_)) => self.alloc(ExprKind::Cast { expr: id, kind: CastKind::Numeric }, expected.clone(), loc),
+            _ => id
+        }
+    }
+
+    /// The place or value a reference refers to, or the expression itself if it is not a reference.
+    fn read(&mut self, id: ExprId) -> ExprId {
+        match self.typ(id).clone() {
+            Type::Reference(inner) => {
+                let loc = self.body.exprs.span(id);
+                self.alloc(ExprKind::Deref(id), *inner, loc)
+            },
+            _ => id
+        }
+    }
+
+    fn lower_expr(&mut self, expr: ast::ExprId) -> ExprId {
+        let loc = self.program.loc(expr);
+        let (kind, typ) = match &self.program[expr] {
+            ast::ExprNode::Name(data) => self.lower_name(expr, data),
+            ast::ExprNode::Integer(data) => (ExprKind::Literal(ConstValue::Integer(data.integer as i128)), self.type_of(expr)),
+            ast::ExprNode::Float(data) => {
+                let typ = self.type_of(expr);
+                let value = if typ == Type::Primitive(Primitive::F32) { data.float as f32 as f64 } else { data.float };
+                (ExprKind::Literal(ConstValue::Float(value)), typ)
+            },
+            ast::ExprNode::Bool(data) => (ExprKind::Literal(ConstValue::Bool(data.value)), Type::bool()),
+            ast::ExprNode::Char(data) => (ExprKind::Literal(ConstValue::Char(data.value)), Type::Primitive(Primitive::Char)),
+            ast::ExprNode::String(data) => (ExprKind::Literal(ConstValue::Str(data.value.clone())), Type::Primitive(Primitive::Str)),
+            ast::ExprNode::Block(data) => (ExprKind::Block(self.lower_block(data)), Type::unit()),
+            ast::ExprNode::Call(data) => return self.lower_call(expr, data),
+            ast::ExprNode::Field(data) => {
+                let object = self.lower_expr(data.object);
+                let object = self.read(object);
+                let index = match self.typ(object) {
+                    Type::Struct(id, _) => self.types.structs[id].fields.iter().position(|f| f.name == data.field).unwrap_or(0),
+                    _ => 0
+                };
+                (ExprKind::Field { object, index }, self.type_of(expr))
+            },
+            ast::ExprNode::Assign(data) => return self.lower_assign(data),
+            ast::ExprNode::StructLiteral(data) => {
+                let typ = self.type_of(expr);
+                let (fields, bindings) = match &typ {
+                    Type::Struct(id, arguments) => {
+                        let struct_type = &self.types.structs[id];
+                        (&struct_type.fields, bind(&struct_type.generic_parameters, arguments))
+                    },
+                    _ => return self.alloc(ExprKind::StructLiteral { fields: Vec::new() }, typ, loc)
+                };
+                let mut values = Vec::new();
+                for field in &data.fields {
+                    let index = fields.iter().position(|f| f.name == field.name).unwrap_or(0);
+                    let field_type = fields[index].typ.substitute(&bindings);
+                    values.push((index, self.lower_value(field.value, &field_type)));
+                }
+                (ExprKind::StructLiteral { fields: values }, typ)
+            },
+            ast::ExprNode::Unary(data) => {
+                let typ = self.type_of(expr);
+                // A negated literal is a negative number, so that `-128` fits in an `i8`.
+                if let (ast::UnaryOp::Negate, ast::ExprNode::Integer(literal)) = (data.op, &self.program[data.operand]) {
+                    (ExprKind::Literal(ConstValue::Integer(-(literal.integer as i128))), typ)
+                } else {
+                    (ExprKind::Unary { op: data.op, operand: self.lower_value(data.operand, &typ) }, typ)
+                }
+            },
+            ast::ExprNode::Binary(data) => {
+                use ast::BinaryOp::*;
+                let typ = self.type_of(expr);
+                // The operands of a comparison have the type of the wider operand.
+                let operand_type = match data.op {
+                    Add | Subtract | Multiply | Divide | Remainder | And | Or => typ.clone(),
+                    Less | LessEqual | Greater | GreaterEqual | Equal | NotEqual => {
+                        let left = self.type_of(data.left).dereferenced().clone();
+                        let right = self.type_of(data.right).dereferenced().clone();
+                        match (left.primitive(), right.primitive()) {
+                            (Some(l), Some(r)) if l != r && l.widens_to(r) => right,
+                            _ => left
+                        }
+                    }
+                };
+                let left = self.lower_value(data.left, &operand_type);
+                let right = self.lower_value(data.right, &operand_type);
+                (ExprKind::Binary { op: data.op, left, right }, typ)
+            },
+            ast::ExprNode::Cast(data) => return self.lower_cast(expr, data),
+            ast::ExprNode::Is(data) => {
+                let value = self.lower_expr(data.expr);
+                let value = self.read(value);
+                (ExprKind::Is { expr: value, target: self.struct_of(data.typ) }, Type::bool())
+            },
+            ast::ExprNode::Lambda(data) => (ExprKind::Function(self.lower_lambda(expr, data)), self.type_of(expr)),
+            ast::ExprNode::Array(data) => {
+                let typ = self.type_of(expr);
+                let element = match &typ {
+                    Type::Array(element, _) => element.as_ref().clone(),
+                    _ => Type::Error
+                };
+                (ExprKind::Array(data.elements.iter().map(|e| self.lower_value(*e, &element)).collect()), typ)
+            },
+            ast::ExprNode::Index(data) => {
+                let object = self.lower_expr(data.object);
+                let object = self.read(object);
+                let index = self.lower_value(data.index, &Type::Primitive(Primitive::U64));
+                (ExprKind::Index { object, index }, self.type_of(expr))
+            }
+        };
+        return self.alloc(kind, typ, loc);
+    }
+
+    fn lower_name(&mut self, expr: ast::ExprId, data: &ast::NameData) -> (ExprKind, Type) {
+        let typ = self.type_of(expr);
+        let id = match self.resolution.use_of(data.name) {
+            Some(id) => id,
+            None => return (ExprKind::Literal(ConstValue::Bool(false)), Type::Error)
+        };
+        match self.resolution.declaration(id).kind {
+            DeclKind::Const => {
+                let constant = &self.types.constants[&id];
+                (ExprKind::Literal(constant.value.clone()), Type::Primitive(constant.typ))
+            },
+            DeclKind::Function => (ExprKind::Function(self.function(id, expr)), typ),
+            _ => (ExprKind::Local(self.body.variables[&id]), typ)
+        }
+    }
+
+    /// The function that an expression naming the function `id` refers to, which is the instance with the type
+    /// arguments inferred there.
+    fn function(&self, id: DeclId, expr: ast::ExprId) -> FunctionId {
+        let instance = self.instance().functions.get(&expr).copied().or_else(|| self.types.find_instance(id, &[]));
+        return FunctionId(instance.map_or(0, |instance| instance.0));
+    }
+
+    /// The parameter types of a function type, seen through a reference.
+    fn parameters(typ: &Type) -> Vec<Type> {
+        match typ.dereferenced() {
+            Type::Function(parameters, _) => parameters.clone(),
+            _ => Vec::new()
+        }
+    }
+
+    fn lower_arguments(&mut self, arguments: &[ast::ExprId], parameters: &[Type]) -> Vec<ExprId> {
+        arguments.iter().zip(parameters).map(|(argument, parameter)| self.lower_value(*argument, parameter)).collect()
+    }
+
+    fn lower_call(&mut self, expr: ast::ExprId, data: &ast::CallData) -> ExprId {
+        let typ = self.type_of(expr);
+        match &self.program[data.callee] {
+            // A method call passes the object the method is called on as the first argument.
+            ast::ExprNode::Field(field) if self.instance().functions.contains_key(&data.callee) => {
+                let function = FunctionId(self.instance().functions[&data.callee].0);
+                let parameters = Self::parameters(&self.type_of(data.callee));
+                let mut arguments = vec![self.lower_value(field.object, &parameters[0])];
+                arguments.extend(self.lower_arguments(&data.arguments, &parameters[1..]));
+                return self.alloc(ExprKind::Call { function, arguments }, typ, data.loc);
+            },
+            ast::ExprNode::Name(name) => {
+                if let Some(id) = self.resolution.use_of(name.name) {
+                    if self.resolution.declaration(id).kind == DeclKind::Function {
+                        let function = self.function(id, data.callee);
+                        let parameters = Self::parameters(&self.type_of(data.callee));
+                        let arguments = self.lower_arguments(&data.arguments, &parameters);
+                        return self.alloc(ExprKind::Call { function, arguments }, typ, data.loc);
+                    }
+                }
+            },
+            _ => { }
+        }
+        let callee = self.lower_expr(data.callee);
+        let callee = self.read(callee);
+        let parameters = Self::parameters(self.typ(callee));
+        let arguments = self.lower_arguments(&data.arguments, &parameters);
+        return self.alloc(ExprKind::CallIndirect { callee, arguments }, typ, data.loc);
+    }
+
+    /// Lowers the target of an assignment, which is a place, or a reference to the place to assign to.
+    fn lower_place(&mut self, expr: ast::ExprId) -> ExprId {
+        let place = self.lower_expr(expr);
+        return self.read(place);
+    }
+
+    fn lower_assign(&mut self, data: &ast::AssignData) -> ExprId {
+        let op = match data.op {
+            Some(op) => op,
+            None => {
+                let place = self.lower_place(data.target);
+                let typ = self.typ(place).clone();
+                let value = self.lower_value(data.value, &typ);
+                return self.alloc(ExprKind::Assign { place, value }, Type::unit(), data.loc);
+            }
+        };
+        // `x += y` becomes `x = x + y`. A target that evaluating twice could give a different place, or that has
+        // effects, is evaluated once to a hidden reference instead.
+        if is_stable(self.program, data.target) {
+            let place = self.lower_place(data.target);
+            let current = self.lower_place(data.target);
+            let typ = self.typ(place).clone();
+            let value = self.lower_value(data.value, &typ);
+            let value = self.alloc(ExprKind::Binary { op, left: current, right: value }, typ, data.loc);
+            return self.alloc(ExprKind::Assign { place, value }, Type::unit(), data.loc);
+        }
+        let target = self.lower_place(data.target);
+        let typ = self.typ(target).clone();
+        let reference_type = Type::Reference(Box::new(typ.clone()));
+        let loc = self.program.loc(data.target);
+        let reference = self.alloc(ExprKind::Ref(target), reference_type.clone(), loc);
+        let local = self.hidden("target", reference_type, loc);
+        let declare = self.stmt(Stmt::Let { local, value: Some(reference) }, loc);
+        let (place, current) = (self.local(local, loc), self.local(local, loc));
+        let (place, current) = (self.read(place), self.read(current));
+        let value = self.lower_value(data.value, &typ);
+        let value = self.alloc(ExprKind::Binary { op, left: current, right: value }, typ, data.loc);
+        let assign = self.alloc(ExprKind::Assign { place, value }, Type::unit(), data.loc);
+        let assign = self.stmt(Stmt::Expr(assign), data.loc);
+        return self.alloc(ExprKind::Block(Block { stmts: vec![declare, assign] }), Type::unit(), data.loc);
+    }
+
+    fn lower_cast(&mut self, expr: ast::ExprId, data: &ast::CastData) -> ExprId {
+        let to = self.type_of(expr);
+        let value = self.lower_expr(data.expr);
+        if let (Type::Struct(from_id, _), Type::Reference(target)) = (self.typ(value).dereferenced(), &to) {
+            if let Type::Struct(to_id, _) = target.as_ref() {
+                let kind = if self.types.is_substruct(*from_id, *to_id) { CastKind::Upcast } else { CastKind::Downcast };
+                let same = from_id == to_id;
+                let reference = match self.typ(value) {
+                    Type::Reference(_) => value,
+                    found => {
+                        let typ = Type::Reference(Box::new(found.clone()));
+                        self.alloc(ExprKind::Ref(value), typ, self.program.loc(data.expr))
+                    }
+                };
+                return if same { reference } else { self.alloc(ExprKind::Cast { expr: reference, kind }, to, data.loc) };
+            }
+        }
+        let value = self.read(value);
+        if self.typ(value) == &to {
+            return value;
+        }
+        return self.alloc(ExprKind::Cast { expr: value, kind: CastKind::Numeric }, to, data.loc);
+    }
+
+    /// The struct that the type in an `is` check names.
+    fn struct_of(&self, typ: ast::TypeId) -> DeclId {
+        let name = match &self.program[typ] {
+            ast::TypeNode::Name(data) => data.name,
+            _ => return DeclId(0)
+        };
+        let id = self.resolution.use_of(name).unwrap_or(DeclId(0));
+        match self.resolution.declaration(id).kind {
+            DeclKind::SelfType(owner) => owner,
+            DeclKind::GenericParameter => match self.bindings.get(&id) {
+                Some(Type::Struct(id, _)) => *id,
+                _ => id
+            },
+            _ => id
+        }
+    }
+
+    /// Lowers a lambda to a function of its own, which is numbered after the instances and the lambdas before it.
+    fn lower_lambda(&mut self, expr: ast::ExprId, data: &ast::LambdaData) -> FunctionId {
+        let (parameter_types, ret) = match self.type_of(expr) {
+            Type::Function(parameters, ret) => (parameters, *ret),
+            _ => (Vec::new(), Type::Error)
+        };
+        let index = self.lambdas.len();
+        self.lambdas.push(None);
+        let name = format!("{}.lambda{}", self.body.name, self.body.lambdas);
+        self.body.lambdas += 1;
+
+        let outer = std::mem::replace(&mut self.body, Body::new(name, ret.clone()));
+        let parameters = data.parameters.iter().zip(parameter_types).enumerate().map(|(i, (parameter, typ))| {
+            let decl = self.resolution.definition(Site::LambdaParameter(expr, i as u32));
+            let local = self.body.locals.alloc(Local { name: parameter.name.clone(), typ }, parameter.loc);
+            if let Some(decl) = decl {
+                self.body.variables.insert(decl, local);
+            }
+            local
+        }).collect();
+        let body = match &self.program[data.body] {
+            ast::ExprNode::Block(block) => self.lower_block(block),
+            _ => {
+                let value = self.lower_value(data.body, &ret);
+                Block { stmts: vec![self.stmt(Stmt::Return(value), self.program.loc(data.body))] }
+            }
+        };
+        let function = self.finish(parameters, body, data.loc);
+        self.body = outer;
+        self.lambdas[index] = Some(function);
+        return FunctionId((self.types.instances.len() + index) as u32);
+    }
+}
+
+
+/// Whether evaluating an assignment target twice gives the same place without any effects: a variable, or a field
+/// or element of one, with indices that are variables or literals.
+fn is_stable(program: &ast::Program, expr: ast::ExprId) -> bool {
+    match &program[expr] {
+        ast::ExprNode::Name(_) => true,
+        ast::ExprNode::Field(data) => is_stable(program, data.object),
+        ast::ExprNode::Index(data) => {
+            is_stable(program, data.object) && matches!(program[data.index], ast::ExprNode::Name(_) | ast::ExprNode::Integer(_))
+        },
+        _ => false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::BinaryOp;
+    use crate::options::Options;
+    use crate::hir::*;
+
+    /// Checks and lowers a program, then calls `check` with it, its function with the given name, and a function
+    /// that gives the source text an expression of that function was lowered from.
+    fn lower(text: &str, name: &str, check: impl FnOnce(&Program, &Function, &dyn Fn(ExprId) -> String)) {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        let hir = result.hir.as_ref().expect("the program has errors");
+        let function = hir.functions.iter().map(|(_, function)| function).find(|function| function.name == name).unwrap();
+        check(hir, function, &|expr| String::from(result.state.sources.text(function.exprs.span(expr))));
+    }
+
+    #[test]
+    fn for_loops_become_while_loops() {
+        lower("fn main() -> int { let total = 0; for i in 0..3 { total += i; } return total; }", "main", |_, main, text| {
+            let loops: Vec<StmtId> = main.stmts.ids().filter(|stmt| matches!(main.stmts[*stmt], Stmt::While { .. })).collect();
+            assert_eq!(loops.len(), 1);
+            let Stmt::While { condition, .. } = main.stmts[loops[0]] else {
+                unreachable!();
+            };
+            assert!(matches!(main.exprs[condition].kind, ExprKind::Binary { op: BinaryOp::Less, .. }));
+            assert_eq!(text(condition), "for i in 0..3 { total += i; }");
+            // The loop keeps the end of the range and the next value in hidden locals of its own.
+            assert!(main.locals.len() > 2);
+        });
+    }
+
+    #[test]
+    fn compound_assignments_are_desugared() {
+        lower("fn main() -> int { let x = 1; x += 2; return x; }", "main", |_, main, text| {
+            let assign = main.exprs.ids().find(|expr| matches!(main.exprs[*expr].kind, ExprKind::Assign { .. })).unwrap();
+            let ExprKind::Assign { place, value } = main.exprs[assign].kind else {
+                unreachable!();
+            };
+            let ExprKind::Binary { op: BinaryOp::Add, left, right } = main.exprs[value].kind else {
+                panic!("expected an addition");
+            };
+            assert!(matches!(main.exprs[place].kind, ExprKind::Local(_)));
+            assert!(matches!(main.exprs[left].kind, ExprKind::Local(_)));
+            assert_eq!((text(assign), text(left), text(right)), (String::from("x += 2"), String::from("x"), String::from("2")));
+        });
+    }
+
+    #[test]
+    fn method_calls_pass_their_receiver_first() {
+        let text = "struct P { x: int; fn get(self: Self&, y: int) -> int { return self.x + y; } }
+        fn main() -> int { let p = P { x: 1 }; return p.get(2); }";
+        lower(text, "main", |program, main, text| {
+            let call = main.exprs.ids().find(|expr| matches!(main.exprs[*expr].kind, ExprKind::Call { .. })).unwrap();
+            let ExprKind::Call { function, arguments } = &main.exprs[call].kind else {
+                unreachable!();
+            };
+            assert_eq!(program.functions[*function].name, "P.get");
+            assert_eq!(arguments.len(), 2);
+            assert!(matches!(main.exprs[arguments[0]].kind, ExprKind::Ref(_)));
+            assert_eq!(text(arguments[0]), "p");
+            assert_eq!(text(call), "p.get(2)");
+        });
+    }
+}
@@ -0,0 +1,6 @@
+#[allow(clippy::module_inception)]
+mod hir;
+mod lower;
+
+pub use hir::*;
+pub use lower::lower_program;
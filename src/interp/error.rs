@@ -0,0 +1,95 @@
+use crate::source::Span;
+use crate::error::{CompilerError, ErrorDisplay, Severity};
+
+
+/// The most places the trace of a run-time error shows calls from.
+const MAX_TRACE: usize = 8;
+
+
+/// A reason the program could not be run, or stopped while running. Errors that stop a running program hold
+/// the spans of the calls that led to them, innermost first.
+pub enum RuntimeError {
+    /// The program has no `main` function in the file it was run from.
+    MissingMain,
+    /// `main` takes parameters, or returns something other than an integer or `unit`.
+    MainSignature { loc: Span },
+    DivisionByZero { loc: Span, trace: Vec<Span> },
+    IndexOutOfBounds { index: u64, length: u64, loc: Span, trace: Vec<Span> },
+    /// A reference cast to a substruct that the value it refers to was not created as.
+    FailedDowncast { to: String, created: String, loc: Span, trace: Vec<Span> },
+    /// Calls nested too deeply, usually by unbounded recursion.
+    StackOverflow { loc: Span, trace: Vec<Span> }
+}
+
+impl RuntimeError {
+    /// Renders the calls of a trace, with each run of calls from the same place, as in recursion, shown once.
+    fn render_trace(trace: &[Span], display: &mut ErrorDisplay) -> String {
+        let mut runs: Vec<(Span, usize)> = Vec::new();
+        for call in trace {
+            match runs.last_mut() {
+                Some((last, count)) if last == call => *count += 1,
+                _ => runs.push((*call, 1))
+            }
+        }
+        let mut msg = String::new();
+        display.with_indent(|display| {
+            for (call, count) in runs.iter().take(MAX_TRACE) {
+                let message = if *count == 1 { String::from("Called from here.") } else { format!("Called from here {} times.", count) };
+                msg.push_str(&display.error_with_location(Severity::Note, &message, call));
+            }
+            if runs.len() > MAX_TRACE {
+                let hidden: usize = runs[MAX_TRACE..].iter().map(|(_, count)| count).sum();
+                msg.push_str(&display.note(&format!("{} more calls not shown.", hidden)));
+            }
+        });
+        return msg;
+    }
+}
+
+impl CompilerError for RuntimeError {
+    fn render(&self, display: &mut ErrorDisplay) -> String {
+        use RuntimeError::*;
+        match self {
+            MissingMain => display.error_without_location(Severity::Error, "The program has no 'main' function."),
+            MainSignature { loc } => {
+                let mut msg = display.error_with_location(Severity::Error, "'main' must take nothing and return an integer or unit.", loc);
+                msg.push_str(&display.help("Declare it as 'fn main() -> int'."));
+                msg
+            },
+            DivisionByZero { loc, trace } => {
+                let mut msg = display.error_with_location(Severity::Error, "Division by zero.", loc);
+                msg.push_str(&RuntimeError::render_trace(trace, display));
+                msg
+            },
+            IndexOutOfBounds { index, length, loc, trace } => {
+                let message = format!("Index {} is out of bounds for an array of length {}.", index, length);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                msg.push_str(&RuntimeError::render_trace(trace, display));
+                msg
+            },
+            FailedDowncast { to, created, loc, trace } => {
+                let message = format!("Cannot cast to a reference to '{}', since the value was created as '{}'.", to, created);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                msg.push_str(&RuntimeError::render_trace(trace, display));
+                msg
+            },
+            StackOverflow { loc, trace } => {
+                let mut msg = display.error_with_location(Severity::Error, "Calls are nested too deeply.", loc);
+                msg.push_str(&RuntimeError::render_trace(trace, display));
+                msg
+            }
+        }
+    }
+
+    fn location(&self) -> Option<Span> {
+        use RuntimeError::*;
+        match self {
+            MissingMain => None,
+            MainSignature { loc } => Some(*loc),
+            DivisionByZero { loc, .. } => Some(*loc),
+            IndexOutOfBounds { loc, .. } => Some(*loc),
+            FailedDowncast { loc, .. } => Some(*loc),
+            StackOverflow { loc, .. } => Some(*loc)
+        }
+    }
+}
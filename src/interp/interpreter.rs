@@ -0,0 +1,300 @@
+use crate::ast::{UnaryOp, BinaryOp};
+use crate::source::Span;
+use crate::resolve::DeclId;
+use crate::hir::*;
+use crate::typecheck::{Type, fold_unary, fold_binary, fold_cast, truncate};
+use crate::interp::value::{Value, Place};
+use crate::interp::error::RuntimeError;
+
+
+/// The most calls that can be nested before the program is stopped.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// The size of the stack the interpreter runs on, which is enough for `MAX_CALL_DEPTH` nested calls.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+
+/// The program's `main` function, if it has one that a program can start from. Both `run` and `build` check
+/// this, so that they reject the same programs with the same error.
+pub fn check_main(program: &Program) -> Result<FunctionId, RuntimeError> {
+    let main = program.main.ok_or(RuntimeError::MissingMain)?;
+    let function = &program.functions[main];
+    if !function.can_be_entry() {
+        return Err(RuntimeError::MainSignature { loc: function.loc });
+    }
+    return Ok(main);
+}
+
+/// Runs the program's `main` function and returns the value it returns, or 0 if it returns `unit`.
+pub fn run_program(program: &Program) -> Result<i128, RuntimeError> {
+    let main = check_main(program)?;
+    let function = &program.functions[main];
+
+    let run = || {
+        let mut interpreter = Interpreter { program, calls: Vec::new() };
+        return match interpreter.call(main, Vec::new(), function.loc)? {
+            Value::Int(n) => Ok(n),
+            _ => Ok(0)
+        };
+    };
+    return std::thread::scope(|scope| {
+        std::thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, run)
+            .expect("could not start the interpreter's thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+}
+
+
+/// The locals of a call that is running. Each `let` gives its variable a new cell, so that references to the
+/// variable from earlier iterations of a loop keep referring to the value they referred to.
+struct Frame<'a> {
+    function: &'a Function,
+    locals: Vec<Place>
+}
+
+/// Why evaluation stopped before finishing a statement or expression: a `return`, which ends the call it is in,
+/// or an error, which ends the program.
+enum Unwind {
+    Return(Value),
+    Error(RuntimeError)
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Unwind {
+        Unwind::Error(error)
+    }
+}
+
+/// Evaluates the lowered program directly, one expression at a time.
+///
+/// Operands are evaluated from left to right, and an assignment finds its place before evaluating its value.
+struct Interpreter<'a> {
+    program: &'a Program,
+    /// The spans of the calls that are running, outermost first.
+    calls: Vec<Span>
+}
+
+impl<'a> Interpreter<'a> {
+    /// The calls that led to the current one, innermost first, for the trace of an error. The first call is
+    /// the one that started `main`, which is not in the source.
+    fn trace(&self) -> Vec<Span> {
+        self.calls.iter().skip(1).rev().copied().collect()
+    }
+
+    fn call(&mut self, id: FunctionId, arguments: Vec<Value>, loc: Span) -> Result<Value, RuntimeError> {
+        if self.calls.len() >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow { loc, trace: self.trace() });
+        }
+        let function = &self.program.functions[id];
+        let mut frame = Frame { function, locals: function.locals.ids().map(|_| Place::new(Value::Unit)).collect() };
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            frame.locals[parameter.index()] = Place::new(argument);
+        }
+
+        self.calls.push(loc);
+        let result = self.exec_block(&mut frame, &function.body);
+        self.calls.pop();
+        return match result {
+            Ok(()) => Ok(Value::Unit),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error)
+        };
+    }
+
+    fn exec_block(&mut self, frame: &mut Frame<'a>, block: &Block) -> Result<(), Unwind> {
+        for stmt in &block.stmts {
+            self.exec_stmt(frame, *stmt)?;
+        }
+        return Ok(());
+    }
+
+    fn exec_stmt(&mut self, frame: &mut Frame<'a>, id: StmtId) -> Result<(), Unwind> {
+        match &frame.function.stmts[id] {
+            Stmt::Expr(expr) => {
+                self.eval(frame, *expr)?;
+            },
+            Stmt::Let { local, value } => {
+                let value = match value {
+                    Some(value) => self.eval(frame, *value)?,
+                    None => Value::Unit
+                };
+                frame.locals[local.index()] = Place::new(value);
+            },
+            Stmt::Return(value) => return Err(Unwind::Return(self.eval(frame, *value)?)),
+            Stmt::If { condition, then, otherwise } => {
+                if self.eval(frame, *condition)?.bool() {
+                    return self.exec_block(frame, then);
+                } else if let Some(otherwise) = otherwise {
+                    return self.exec_block(frame, otherwise);
+                }
+            },
+            Stmt::While { condition, body } => {
+                while self.eval(frame, *condition)?.bool() {
+                    self.exec_block(frame, body)?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Finds the place an expression denotes. An expression that is not a place is evaluated into a new cell.
+    fn place(&mut self, frame: &mut Frame<'a>, id: ExprId) -> Result<Place, Unwind> {
+        let function = frame.function;
+        match &function.exprs[id].kind {
+            ExprKind::Local(local) => Ok(frame.locals[local.index()].clone()),
+            ExprKind::Field { object, index } => Ok(self.place(frame, *object)?.part(*index)),
+            ExprKind::Index { object, index } => {
+                let array = self.place(frame, *object)?;
+                let index = self.eval(frame, *index)?.int() as u64;
+                let length = array.with(|value| match value {
+                    Value::Array(elements) => elements.len() as u64,
+                    _ => unreachable!()
+                });
+                if index >= length {
+                    return Err(RuntimeError::IndexOutOfBounds { index, length, loc: function.exprs.span(id), trace: self.trace() }.into());
+                }
+                Ok(array.part(index as usize))
+            },
+            ExprKind::Deref(reference) => match self.eval(frame, *reference)? {
+                Value::Ref(place) => Ok(place),
+                _ => unreachable!()
+            },
+            _ => Ok(Place::new(self.eval(frame, id)?))
+        }
+    }
+
+    fn eval(&mut self, frame: &mut Frame<'a>, id: ExprId) -> Result<Value, Unwind> {
+        let function = frame.function;
+        let expr = &function.exprs[id];
+        let loc = function.exprs.span(id);
+        match &expr.kind {
+            ExprKind::Literal(value) => Ok(Value::from_const(value)),
+            ExprKind::Local(local) => Ok(frame.locals[local.index()].read()),
+            ExprKind::Function(function) => Ok(Value::Function(*function)),
+            ExprKind::Call { function, arguments } => {
+                let arguments = self.eval_all(frame, arguments)?;
+                Ok(self.call(*function, arguments, loc)?)
+            },
+            ExprKind::CallIndirect { callee, arguments } => {
+                let callee = match self.eval(frame, *callee)? {
+                    Value::Function(function) => function,
+                    _ => unreachable!()
+                };
+                let arguments = self.eval_all(frame, arguments)?;
+                Ok(self.call(callee, arguments, loc)?)
+            },
+            ExprKind::Field { .. } | ExprKind::Index { .. } | ExprKind::Deref(_) => Ok(self.place(frame, id)?.read()),
+            ExprKind::Ref(place) => Ok(Value::Ref(self.place(frame, *place)?)),
+            ExprKind::Cast { expr: value, kind } => {
+                let from = function.exprs[*value].typ.clone();
+                let value = self.eval(frame, *value)?;
+                match kind {
+                    CastKind::Numeric => {
+                        let (from, to) = (from.primitive().unwrap(), expr.typ.primitive().unwrap());
+                        Ok(Value::from_const(&fold_cast(&value.to_const(), from, to)))
+                    },
+                    CastKind::Upcast => Ok(value),
+                    CastKind::Downcast => {
+                        let target = match expr.typ.dereferenced() {
+                            Type::Struct(target, _) => *target,
+                            _ => unreachable!()
+                        };
+                        let layout = match &value {
+                            Value::Ref(place) => place.with(|value| match value {
+                                Value::Struct { layout, .. } => *layout,
+                                _ => unreachable!()
+                            }),
+                            _ => unreachable!()
+                        };
+                        if !self.is_substruct(layout, target) {
+                            let to = self.program.struct_of(expr.typ.dereferenced()).map_or(String::new(), |s| s.name.clone());
+                            let created = self.program.structs[layout].name.clone();
+                            return Err(RuntimeError::FailedDowncast { to, created, loc, trace: self.trace() }.into());
+                        }
+                        Ok(value)
+                    }
+                }
+            },
+            ExprKind::Is { expr: value, target } => match self.eval(frame, *value)? {
+                Value::Struct { layout, .. } => Ok(Value::Bool(self.is_substruct(layout, *target))),
+                _ => unreachable!()
+            },
+            ExprKind::Unary { op, operand } => {
+                let value = self.eval(frame, *operand)?;
+                match (op, value) {
+                    (UnaryOp::Negate, Value::Int(n)) => Ok(Value::Int(truncate(n.wrapping_neg(), expr.typ.primitive().unwrap()))),
+                    (op, value) => {
+                        let result = fold_unary(*op, &value.to_const(), expr.typ.primitive().unwrap());
+                        Ok(Value::from_const(&result.unwrap_or_else(|_| unreachable!())))
+                    }
+                }
+            },
+            ExprKind::Binary { op, left, right } => self.eval_binary(frame, *op, *left, *right, loc),
+            ExprKind::Assign { place, value } => {
+                let place = self.place(frame, *place)?;
+                let value = self.eval(frame, *value)?;
+                place.write(value);
+                Ok(Value::Unit)
+            },
+            ExprKind::StructLiteral { fields } => {
+                let layout = self.program.structs.iter().position(|s| s.typ == expr.typ).unwrap();
+                let mut values = vec![Value::Unit; self.program.structs[layout].fields.len()];
+                for (index, value) in fields {
+                    values[*index] = self.eval(frame, *value)?;
+                }
+                Ok(Value::Struct { layout, fields: values })
+            },
+            ExprKind::Array(elements) => Ok(Value::Array(self.eval_all(frame, elements)?)),
+            ExprKind::Block(block) => {
+                self.exec_block(frame, block)?;
+                Ok(Value::Unit)
+            }
+        }
+    }
+
+    fn eval_all(&mut self, frame: &mut Frame<'a>, exprs: &[ExprId]) -> Result<Vec<Value>, Unwind> {
+        exprs.iter().map(|expr| self.eval(frame, *expr)).collect()
+    }
+
+    fn eval_binary(&mut self, frame: &mut Frame<'a>, op: BinaryOp, left: ExprId, right: ExprId, loc: Span) -> Result<Value, Unwind> {
+        use BinaryOp::*;
+        let typ = frame.function.exprs[left].typ.primitive().unwrap();
+        let left = self.eval(frame, left)?;
+        match op {
+            And if !left.bool() => return Ok(Value::Bool(false)),
+            Or if left.bool() => return Ok(Value::Bool(true)),
+            And | Or => return self.eval(frame, right),
+            _ => { }
+        }
+        let right = self.eval(frame, right)?;
+        if let (Value::Int(l), Value::Int(r), Add | Subtract | Multiply | Divide | Remainder) = (&left, &right, op) {
+            let result = match op {
+                Add => l.wrapping_add(*r),
+                Subtract => l.wrapping_sub(*r),
+                Multiply => l.wrapping_mul(*r),
+                Divide | Remainder if *r == 0 => return Err(RuntimeError::DivisionByZero { loc, trace: self.trace() }.into()),
+                Divide => l / r,
+                _ => l % r
+            };
+            return Ok(Value::Int(truncate(result, typ)));
+        }
+        let result = fold_binary(op, &left.to_const(), &right.to_const(), typ);
+        return Ok(Value::from_const(&result.unwrap_or_else(|_| unreachable!())));
+    }
+
+    /// Whether the struct at `layout` is the struct `target` or inherits from it.
+    fn is_substruct(&self, layout: usize, target: DeclId) -> bool {
+        let mut current = match self.program.structs[layout].typ {
+            Type::Struct(id, _) => Some(id),
+            _ => None
+        };
+        while let Some(id) = current {
+            if id == target {
+                return true;
+            }
+            current = self.program.structs.iter().find(|s| matches!(s.typ, Type::Struct(s_id, _) if s_id == id)).and_then(|s| s.superstruct);
+        }
+        return false;
+    }
+}
@@ -0,0 +1,6 @@
+mod value;
+mod error;
+mod interpreter;
+
+pub use interpreter::{run_program, check_main};
+pub use error::RuntimeError;
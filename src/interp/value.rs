@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::hir::FunctionId;
+use crate::typecheck::ConstValue;
+
+
+/// A value while the program runs. Integers of every integer type are held as `i128`, and `f32` values as the
+/// `f64` with the same value, as constants are.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Unit,
+    /// A struct value, with the index of its struct in `hir::Program::structs`, which is the struct it was
+    /// created as, and its fields in layout order.
+    Struct { layout: usize, fields: Vec<Value> },
+    Array(Vec<Value>),
+    Function(FunctionId),
+    Ref(Place)
+}
+
+impl Value {
+    pub fn from_const(value: &ConstValue) -> Value {
+        match value {
+            ConstValue::Integer(n) => Value::Int(*n),
+            ConstValue::Float(f) => Value::Float(*f),
+            ConstValue::Bool(b) => Value::Bool(*b),
+            ConstValue::Char(c) => Value::Char(*c),
+            ConstValue::Str(s) => Value::Str(s.clone())
+        }
+    }
+
+    /// The value as a constant, for the operators that constants and run-time values share.
+    pub fn to_const(&self) -> ConstValue {
+        match self {
+            Value::Int(n) => ConstValue::Integer(*n),
+            Value::Float(f) => ConstValue::Float(*f),
+            Value::Bool(b) => ConstValue::Bool(*b),
+            Value::Char(c) => ConstValue::Char(*c),
+            Value::Str(s) => ConstValue::Str(s.clone()),
+            _ => unreachable!()
+        }
+    }
+
+    pub fn int(&self) -> i128 {
+        match self {
+            Value::Int(n) => *n,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn bool(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            _ => unreachable!()
+        }
+    }
+
+    /// The field or element at `index` of a struct or array.
+    fn part(&self, index: usize) -> &Value {
+        match self {
+            Value::Struct { fields, .. } => &fields[index],
+            Value::Array(elements) => &elements[index],
+            _ => unreachable!()
+        }
+    }
+
+    fn part_mut(&mut self, index: usize) -> &mut Value {
+        match self {
+            Value::Struct { fields, .. } => &mut fields[index],
+            Value::Array(elements) => &mut elements[index],
+            _ => unreachable!()
+        }
+    }
+}
+
+
+/// A place that holds a value: a variable, or a field or element inside one, reached by following `path` from
+/// the variable's cell. References are places. Each variable has a cell of its own that lives as long as
+/// anything refers to it.
+#[derive(Debug, Clone)]
+pub struct Place {
+    cell: Rc<RefCell<Value>>,
+    path: Vec<usize>
+}
+
+impl Place {
+    /// A new cell holding a value.
+    pub fn new(value: Value) -> Place {
+        Place { cell: Rc::new(RefCell::new(value)), path: Vec::new() }
+    }
+
+    /// The field or element at `index` of the struct or array in this place.
+    pub fn part(&self, index: usize) -> Place {
+        let mut path = self.path.clone();
+        path.push(index);
+        return Place { cell: self.cell.clone(), path };
+    }
+
+    pub fn read(&self) -> Value {
+        self.with(Value::clone)
+    }
+
+    /// Calls `f` with the value in this place, without copying it.
+    pub fn with<T>(&self, f: impl FnOnce(&Value) -> T) -> T {
+        let cell = self.cell.borrow();
+        let mut value = &*cell;
+        for index in &self.path {
+            value = value.part(*index);
+        }
+        return f(value);
+    }
+
+    pub fn write(&self, new: Value) {
+        let mut cell = self.cell.borrow_mut();
+        let mut value = &mut *cell;
+        for index in &self.path {
+            value = value.part_mut(*index);
+        }
+        *value = new;
+    }
+}
@@ -0,0 +1,91 @@
+use crate::ir::ir::{Function, BlockId};
+
+
+/// The dominator tree of a function's reachable blocks: block `a` dominates block `b` when every path from the
+/// entry block to `b` goes through `a`. It is computed with the algorithm of Cooper, Harvey and Kennedy, which
+/// improves each block's immediate dominator in reverse postorder until nothing changes.
+pub struct Dominators {
+    /// The immediate dominator of each block, which is the entry block itself for the entry block, and nothing
+    /// for unreachable blocks.
+    idom: Vec<Option<BlockId>>,
+    /// The position of each reachable block in reverse postorder.
+    order: Vec<usize>,
+    postorder: Vec<BlockId>
+}
+
+impl Dominators {
+    pub fn new(function: &Function) -> Dominators {
+        let rpo = function.reverse_postorder();
+        let mut order = vec![usize::MAX; function.blocks.len()];
+        for (index, block) in rpo.iter().enumerate() {
+            order[block.0 as usize] = index;
+        }
+        let predecessors = function.predecessors();
+        let mut idom: Vec<Option<BlockId>> = vec![None; function.blocks.len()];
+        idom[Function::ENTRY.0 as usize] = Some(Function::ENTRY);
+
+        let intersect = |idom: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+            while a != b {
+                while order[a.0 as usize] > order[b.0 as usize] {
+                    a = idom[a.0 as usize].unwrap();
+                }
+                while order[b.0 as usize] > order[a.0 as usize] {
+                    b = idom[b.0 as usize].unwrap();
+                }
+            }
+            return a;
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in &rpo[1..] {
+                let mut new = None;
+                for predecessor in &predecessors[block.0 as usize] {
+                    if idom[predecessor.0 as usize].is_none() {
+                        continue;
+                    }
+                    new = Some(match new {
+                        None => *predecessor,
+                        Some(current) => intersect(&idom, *predecessor, current)
+                    });
+                }
+                if new.is_some() && idom[block.0 as usize] != new {
+                    idom[block.0 as usize] = new;
+                    changed = true;
+                }
+            }
+        }
+        let mut postorder = rpo;
+        postorder.reverse();
+        return Dominators { idom, order, postorder };
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.idom[block.0 as usize].is_some()
+    }
+
+    /// The closest block that strictly dominates a reachable block other than the entry block.
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        if block == Function::ENTRY {
+            return None;
+        }
+        return self.idom[block.0 as usize];
+    }
+
+    /// Whether `a` dominates `b`, which every block does to itself. Unreachable blocks dominate nothing and
+    /// are dominated by nothing.
+    pub fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        while self.order[b.0 as usize] > self.order[a.0 as usize] {
+            b = self.idom[b.0 as usize].unwrap();
+        }
+        return a == b;
+    }
+
+    /// The reachable blocks in reverse postorder.
+    pub fn reverse_postorder(&self) -> impl Iterator<Item=BlockId> + '_ {
+        self.postorder.iter().rev().copied()
+    }
+}
@@ -0,0 +1,47 @@
+use crate::source::Span;
+use crate::error::{CompilerError, ErrorDisplay, Severity};
+
+
+/// An error in IR read from text, or found in a module by the verifier.
+pub enum IrError {
+    /// The text does not follow the syntax of the IR.
+    Syntax { message: String, loc: Span },
+    /// A value, block, function or struct that is used but never defined.
+    Undefined { name: String, loc: Span },
+    /// A value, block, function or struct that is defined twice.
+    Redefined { name: String, loc: Span },
+    /// A value used where a value of another type is needed.
+    TypeMismatch { function: String, expected: String, found: String, loc: Span },
+    /// A value used somewhere its definition does not always run before.
+    NotDominated { function: String, value: String, loc: Span },
+    /// Any other rule of the IR that an instruction, block or function breaks.
+    Invalid { function: String, message: String, loc: Span }
+}
+
+impl CompilerError for IrError {
+    fn render(&self, display: &mut ErrorDisplay) -> String {
+        use IrError::*;
+        match self {
+            Syntax { message, loc } => display.error_with_location(Severity::Error, message, loc),
+            Undefined { name, loc } => display.error_with_location(Severity::Error, &format!("'{}' is not defined.", name), loc),
+            Redefined { name, loc } => display.error_with_location(Severity::Error, &format!("'{}' is already defined.", name), loc),
+            TypeMismatch { function, expected, found, loc } => {
+                let message = format!("In {}: expected a value of type {}, found {}.", function, expected, found);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            NotDominated { function, value, loc } => {
+                let message = format!("In {}: {} is used where its definition does not always run first.", function, value);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            Invalid { function, message, loc } => display.error_with_location(Severity::Error, &format!("In {}: {}", function, message), loc)
+        }
+    }
+
+    fn location(&self) -> Option<Span> {
+        use IrError::*;
+        match self {
+            Syntax { loc, .. } | Undefined { loc, .. } | Redefined { loc, .. } => Some(*loc),
+            TypeMismatch { loc, .. } | NotDominated { loc, .. } | Invalid { loc, .. } => Some(*loc)
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use std::collections::HashMap;
+
+use crate::source::Span;
+use crate::typecheck::{ConstValue, Primitive};
+
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct ValueId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct InstId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct FuncId(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
+pub struct StructId(pub u32);
+
+
+/// The type of a value or of memory. Values are primitives other than `unit`, which is only the return type
+/// of functions that return nothing, or pointers, which refer to memory or to a function. Structs and arrays
+/// only exist in memory, where they are created by `alloca` and reached through `field` and `index`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Type {
+    Primitive(Primitive),
+    Ptr,
+    Struct(StructId),
+    Array(Box<Type>, u64)
+}
+
+impl Type {
+    pub const UNIT: Type = Type::Primitive(Primitive::Unit);
+    pub const BOOL: Type = Type::Primitive(Primitive::Bool);
+    pub const U64: Type = Type::Primitive(Primitive::U64);
+
+    pub fn primitive(&self) -> Option<Primitive> {
+        match self {
+            Type::Primitive(p) => Some(*p),
+            _ => None
+        }
+    }
+
+    /// Whether values of the type can be held in SSA values, rather than only in memory.
+    pub fn is_scalar(&self) -> bool {
+        match self {
+            Type::Primitive(p) => *p != Primitive::Unit,
+            Type::Ptr => true,
+            Type::Struct(_) | Type::Array(..) => false
+        }
+    }
+
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self, Type::Struct(_) | Type::Array(..))
+    }
+}
+
+
+/// A program in SSA form, with the structs its memory is laid out as and its functions.
+#[derive(Debug)]
+pub struct Module {
+    pub structs: Vec<StructDef>,
+    pub functions: Vec<Function>,
+    /// The function the program starts at, which takes nothing and returns an integer or `unit`.
+    pub entry: Option<FuncId>
+}
+
+impl Module {
+    pub fn function(&self, id: FuncId) -> &Function {
+        &self.functions[id.0 as usize]
+    }
+
+    pub fn struct_def(&self, id: StructId) -> &StructDef {
+        &self.structs[id.0 as usize]
+    }
+
+    /// Whether a struct is the same as another or one of its substructs.
+    pub fn is_substruct(&self, sub: StructId, of: StructId) -> bool {
+        let mut current = Some(sub);
+        while let Some(id) = current {
+            if id == of {
+                return true;
+            }
+            current = self.struct_def(id).parent;
+        }
+        return false;
+    }
+}
+
+/// The layout of a struct. The fields of the parent come first, so a field has the same index in every
+/// substruct, and a pointer to a substruct can be used as a pointer to its parent.
+#[derive(Debug)]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<Type>,
+    pub parent: Option<StructId>,
+    /// Whether the struct starts with a hidden tag recording which struct of its hierarchy it was created as,
+    /// which `settag` writes and `is` and `downcast` read.
+    pub tagged: bool
+}
+
+
+/// A function, as a graph of basic blocks. The entry block is the first, its parameters are the function's
+/// parameters, and no block branches to it.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Type>,
+    /// The return type, which is `unit` for functions that return nothing.
+    pub ret: Type,
+    pub blocks: Vec<Block>,
+    pub values: Vec<ValueData>,
+    /// Every instruction created for the function, including those that passes have since removed from
+    /// their blocks.
+    pub insts: Vec<Inst>,
+    pub loc: Span
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueData {
+    pub typ: Type,
+    pub def: ValueDef
+}
+
+/// Where a value is defined: as the parameter of a block with the given index, or by an instruction.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ValueDef {
+    Param(BlockId, usize),
+    Inst(InstId)
+}
+
+/// A sequence of instructions that is entered at the top, with the values its predecessors pass as its
+/// parameters, and left by its terminator.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub params: Vec<ValueId>,
+    pub insts: Vec<InstId>,
+    pub terminator: Terminator,
+    pub terminator_loc: Span
+}
+
+#[derive(Debug, Clone)]
+pub struct Inst {
+    pub kind: InstKind,
+    pub result: Option<ValueId>,
+    /// The span of source the instruction was lowered or parsed from.
+    pub loc: Span
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum UnaryOp {
+    Neg,
+    Not
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum BinaryOp {
+    Add, Sub, Mul, Div, Rem,
+    Eq, Ne, Lt, Le, Gt, Ge
+}
+
+impl UnaryOp {
+    pub const ALL: [UnaryOp; 2] = [UnaryOp::Neg, UnaryOp::Not];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "neg",
+            UnaryOp::Not => "not"
+        }
+    }
+}
+
+impl BinaryOp {
+    pub const ALL: [BinaryOp; 11] = [
+        BinaryOp::Add, BinaryOp::Sub, BinaryOp::Mul, BinaryOp::Div, BinaryOp::Rem,
+        BinaryOp::Eq, BinaryOp::Ne, BinaryOp::Lt, BinaryOp::Le, BinaryOp::Gt, BinaryOp::Ge
+    ];
+
+    pub fn name(&self) -> &'static str {
+        use BinaryOp::*;
+        match self {
+            Add => "add",
+            Sub => "sub",
+            Mul => "mul",
+            Div => "div",
+            Rem => "rem",
+            Eq => "eq",
+            Ne => "ne",
+            Lt => "lt",
+            Le => "le",
+            Gt => "gt",
+            Ge => "ge"
+        }
+    }
+
+    /// Whether the operator compares its operands, giving a `bool`, rather than computing a value of their type.
+    pub fn is_comparison(&self) -> bool {
+        !matches!(self, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem)
+    }
+}
+
+/// The instructions. Integer arithmetic wraps around. The instructions that can stop the program do so
+/// themselves: `div` and `rem` of integers when the divisor is zero, `index` when the index is out of bounds,
+/// and `downcast` when the struct was not created as the target.
+#[derive(Debug, Clone)]
+pub enum InstKind {
+    /// A constant of the result's type.
+    Const(ConstValue),
+    Unary(UnaryOp, ValueId),
+    /// An operator on two values of the same type, which is also the result's type unless the operator is a
+    /// comparison.
+    Binary(BinaryOp, ValueId, ValueId),
+    /// A conversion between primitives, as by `as`, to the result's type.
+    Cast(ValueId),
+    /// A call, whose result is missing when the function returns `unit`.
+    Call(FuncId, Vec<ValueId>),
+    /// A call of a function pointer, with the result's type, if any, giving the function's return type.
+    CallIndirect(ValueId, Vec<ValueId>),
+    /// A pointer to a function.
+    Function(FuncId),
+    /// A pointer to new memory for a value of the type, which lives until the function returns. Allocas are
+    /// only placed in the entry block.
+    Alloca(Type),
+    /// Reads a scalar of the result's type from memory.
+    Load(ValueId),
+    Store { value: ValueId, ptr: ValueId },
+    /// Copies a struct or array from one place in memory to another.
+    Copy { typ: Type, dest: ValueId, src: ValueId },
+    /// A pointer to the field with the given index of a struct in memory.
+    Field { typ: StructId, ptr: ValueId, index: usize },
+    /// A pointer to the element of an array in memory, with a `u64` index that is checked against its length.
+    Index { typ: Type, ptr: ValueId, index: ValueId },
+    /// Records that a tagged struct in memory was created as the given struct.
+    SetTag(StructId, ValueId),
+    /// Whether a tagged struct in memory was created as the given struct or one of its substructs.
+    Is(ValueId, StructId),
+    /// The same pointer, after checking that the struct it points to was created as the given struct or one
+    /// of its substructs.
+    Downcast(ValueId, StructId)
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockTarget {
+    pub block: BlockId,
+    pub args: Vec<ValueId>
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Jump(BlockTarget),
+    /// Goes to the first target if the `bool` is true, and to the second otherwise.
+    Branch(ValueId, BlockTarget, BlockTarget),
+    /// Returns a value, or nothing from a function that returns `unit`.
+    Return(Option<ValueId>),
+    Unreachable
+}
+
+
+impl InstKind {
+    pub fn operands(&self) -> Vec<ValueId> {
+        use InstKind::*;
+        match self {
+            Const(_) | Function(_) | Alloca(_) => vec![],
+            Unary(_, v) | Cast(v) | Load(v) | SetTag(_, v) | Is(v, _) | Downcast(v, _) => vec![*v],
+            Field { ptr, .. } => vec![*ptr],
+            Binary(_, l, r) => vec![*l, *r],
+            Store { value, ptr } => vec![*value, *ptr],
+            Copy { dest, src, .. } => vec![*dest, *src],
+            Index { ptr, index, .. } => vec![*ptr, *index],
+            Call(_, args) => args.clone(),
+            CallIndirect(callee, args) => std::iter::once(*callee).chain(args.iter().copied()).collect()
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        use InstKind::*;
+        match self {
+            Const(_) | Function(_) | Alloca(_) => vec![],
+            Unary(_, v) | Cast(v) | Load(v) | SetTag(_, v) | Is(v, _) | Downcast(v, _) => vec![v],
+            Field { ptr, .. } => vec![ptr],
+            Binary(_, l, r) => vec![l, r],
+            Store { value, ptr } => vec![value, ptr],
+            Copy { dest, src, .. } => vec![dest, src],
+            Index { ptr, index, .. } => vec![ptr, index],
+            Call(_, args) => args.iter_mut().collect(),
+            CallIndirect(callee, args) => std::iter::once(callee).chain(args.iter_mut()).collect()
+        }
+    }
+}
+
+impl Terminator {
+    pub fn targets(&self) -> Vec<&BlockTarget> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, then, otherwise) => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => vec![]
+        }
+    }
+
+    pub fn targets_mut(&mut self) -> Vec<&mut BlockTarget> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, then, otherwise) => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => vec![]
+        }
+    }
+
+    pub fn successors(&self) -> Vec<BlockId> {
+        self.targets().iter().map(|target| target.block).collect()
+    }
+
+    /// The values the terminator uses, including the arguments it passes to its targets.
+    pub fn operands(&self) -> Vec<ValueId> {
+        let mut operands = match self {
+            Terminator::Branch(condition, _, _) => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+            _ => vec![]
+        };
+        for target in self.targets() {
+            operands.extend(&target.args);
+        }
+        return operands;
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Terminator::Jump(target) => target.args.iter_mut().collect(),
+            Terminator::Branch(condition, then, otherwise) => std::iter::once(condition).chain(then.args.iter_mut()).chain(otherwise.args.iter_mut()).collect(),
+            Terminator::Return(Some(value)) => vec![value],
+            Terminator::Return(None) | Terminator::Unreachable => vec![]
+        }
+    }
+}
+
+
+impl Function {
+    pub const ENTRY: BlockId = BlockId(0);
+
+    /// A function with an entry block holding its parameters, which returns nothing until it is given a body.
+    pub fn new(name: String, params: Vec<Type>, ret: Type, loc: Span) -> Function {
+        let mut function = Function { name, params: params.clone(), ret, blocks: Vec::new(), values: Vec::new(), insts: Vec::new(), loc };
+        let entry = function.add_block(loc);
+        for typ in params {
+            function.add_param(entry, typ);
+        }
+        return function;
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id.0 as usize]
+    }
+
+    pub fn inst(&self, id: InstId) -> &Inst {
+        &self.insts[id.0 as usize]
+    }
+
+    pub fn inst_mut(&mut self, id: InstId) -> &mut Inst {
+        &mut self.insts[id.0 as usize]
+    }
+
+    pub fn value(&self, id: ValueId) -> &ValueData {
+        &self.values[id.0 as usize]
+    }
+
+    pub fn typ(&self, id: ValueId) -> &Type {
+        &self.values[id.0 as usize].typ
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item=BlockId> {
+        (0..self.blocks.len() as u32).map(BlockId)
+    }
+
+    /// Adds an empty block that does not return until it is given a terminator.
+    pub fn add_block(&mut self, loc: Span) -> BlockId {
+        self.blocks.push(Block { params: Vec::new(), insts: Vec::new(), terminator: Terminator::Unreachable, terminator_loc: loc });
+        return BlockId(self.blocks.len() as u32 - 1);
+    }
+
+    pub fn add_param(&mut self, block: BlockId, typ: Type) -> ValueId {
+        let index = self.block(block).params.len();
+        let value = self.new_value(typ, ValueDef::Param(block, index));
+        self.block_mut(block).params.push(value);
+        return value;
+    }
+
+    pub fn new_value(&mut self, typ: Type, def: ValueDef) -> ValueId {
+        self.values.push(ValueData { typ, def });
+        return ValueId(self.values.len() as u32 - 1);
+    }
+
+    /// Creates an instruction, with a result of the given type if it has one, without placing it in a block.
+    pub fn create_inst(&mut self, kind: InstKind, result: Option<Type>, loc: Span) -> InstId {
+        let id = InstId(self.insts.len() as u32);
+        let result = result.map(|typ| self.new_value(typ, ValueDef::Inst(id)));
+        self.insts.push(Inst { kind, result, loc });
+        return id;
+    }
+
+    /// Appends an instruction to a block, and returns its result.
+    pub fn push_inst(&mut self, block: BlockId, kind: InstKind, result: Option<Type>, loc: Span) -> Option<ValueId> {
+        let id = self.create_inst(kind, result, loc);
+        self.block_mut(block).insts.push(id);
+        return self.inst(id).result;
+    }
+
+    pub fn set_terminator(&mut self, block: BlockId, terminator: Terminator, loc: Span) {
+        let block = self.block_mut(block);
+        block.terminator = terminator;
+        block.terminator_loc = loc;
+    }
+
+    /// The blocks that branch to each block, each listed once.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for id in self.block_ids() {
+            for successor in self.block(id).terminator.successors() {
+                let list: &mut Vec<BlockId> = &mut predecessors[successor.0 as usize];
+                if !list.contains(&id) {
+                    list.push(id);
+                }
+            }
+        }
+        return predecessors;
+    }
+
+    /// The blocks reachable from the entry block, in reverse postorder, which lists each block before its
+    /// successors except along the edges that close loops.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        let mut stack = vec![(Function::ENTRY, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.block(block).terminator.successors();
+            if next < successors.len() {
+                stack.push((block, next + 1));
+                let successor = successors[next];
+                if !visited[successor.0 as usize] {
+                    visited[successor.0 as usize] = true;
+                    stack.push((successor, 0));
+                }
+            } else {
+                order.push(block);
+            }
+        }
+        order.reverse();
+        return order;
+    }
+
+    /// Replaces every use of each value in `replacements` with the value it maps to, following chains of
+    /// replacements to their end.
+    pub fn replace_uses(&mut self, replacements: &HashMap<ValueId, ValueId>) {
+        if replacements.is_empty() {
+            return;
+        }
+        for index in 0..self.blocks.len() {
+            for i in 0..self.blocks[index].insts.len() {
+                let inst = self.blocks[index].insts[i];
+                for operand in self.insts[inst.0 as usize].kind.operands_mut() {
+                    *operand = resolve(replacements, *operand);
+                }
+            }
+            for operand in self.blocks[index].terminator.operands_mut() {
+                *operand = resolve(replacements, *operand);
+            }
+        }
+    }
+
+    /// Keeps only the given blocks, in the given order, renumbering them and the branches between them. The
+    /// entry block must come first, and no block that is kept may branch to one that is not.
+    pub fn reorder_blocks(&mut self, order: &[BlockId]) {
+        debug_assert!(order.first() == Some(&Function::ENTRY));
+        let mut numbers = vec![None; self.blocks.len()];
+        for (number, block) in order.iter().enumerate() {
+            numbers[block.0 as usize] = Some(BlockId(number as u32));
+        }
+        let mut blocks = Vec::with_capacity(order.len());
+        for block in order {
+            let mut block = self.block(*block).clone();
+            for target in block.terminator.targets_mut() {
+                target.block = numbers[target.block.0 as usize].expect("a kept block only branches to kept blocks");
+            }
+            blocks.push(block);
+        }
+        for (number, block) in blocks.iter().enumerate() {
+            for (index, param) in block.params.iter().enumerate() {
+                self.values[param.0 as usize].def = ValueDef::Param(BlockId(number as u32), index);
+            }
+        }
+        self.blocks = blocks;
+    }
+
+    /// Removes a parameter of a block other than the entry block, along with the argument every branch to the
+    /// block passes for it. The parameter must no longer be used.
+    pub fn remove_param(&mut self, block: BlockId, index: usize) {
+        debug_assert!(block != Function::ENTRY);
+        self.block_mut(block).params.remove(index);
+        for (later, param) in self.block(block).params.clone().into_iter().enumerate().skip(index) {
+            self.values[param.0 as usize].def = ValueDef::Param(block, later);
+        }
+        for other in &mut self.blocks {
+            for target in other.terminator.targets_mut() {
+                if target.block == block {
+                    target.args.remove(index);
+                }
+            }
+        }
+    }
+
+    /// Removes the block parameters that are passed the same value by every branch, or only themselves besides
+    /// one other value, as SSA construction adds parameters for every local that is read where paths meet before
+    /// it knows whether their values differ. Returns whether any were removed.
+    pub fn remove_trivial_params(&mut self) -> bool {
+        let predecessors = self.predecessors();
+        let mut removed = false;
+        loop {
+            let mut replacements: HashMap<ValueId, ValueId> = HashMap::new();
+            for block in self.block_ids().skip(1) {
+                let mut index = 0;
+                while index < self.block(block).params.len() {
+                    let param = self.block(block).params[index];
+                    let mut unique = None;
+                    let mut trivial = true;
+                    for predecessor in &predecessors[block.0 as usize] {
+                        for target in self.block(*predecessor).terminator.targets() {
+                            let argument = if target.block == block { target.args[index] } else { continue };
+                            if argument == param || Some(argument) == unique {
+                                continue;
+                            }
+                            trivial &= unique.is_none();
+                            unique = Some(argument);
+                        }
+                    }
+                    let replacement = unique.filter(|value| trivial && resolve(&replacements, *value) != param);
+                    let Some(replacement) = replacement else {
+                        index += 1;
+                        continue;
+                    };
+                    replacements.insert(param, replacement);
+                    self.remove_param(block, index);
+                }
+            }
+            if replacements.is_empty() {
+                return removed;
+            }
+            self.replace_uses(&replacements);
+            removed = true;
+        }
+    }
+
+    /// Removes the blocks that cannot be reached from the entry block, keeping the others in their order.
+    pub fn remove_unreachable_blocks(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        for block in self.reverse_postorder() {
+            reachable[block.0 as usize] = true;
+        }
+        let order: Vec<BlockId> = self.block_ids().filter(|block| reachable[block.0 as usize]).collect();
+        if order.len() != self.blocks.len() {
+            self.reorder_blocks(&order);
+        }
+    }
+
+    /// Whether a pointer to memory, such as the result of an alloca, can outlive the call it belongs to: it, or a
+    /// pointer derived from it, is returned, passed to a call, stored or passed to a block, rather than only
+    /// loaded from, stored to and copied.
+    pub fn escapes(&self, memory: ValueId) -> bool {
+        let mut pointers = vec![memory];
+        while let Some(ptr) = pointers.pop() {
+            for block in &self.blocks {
+                if block.terminator.operands().contains(&ptr) {
+                    return true;
+                }
+                for inst in &block.insts {
+                    let inst = self.inst(*inst);
+                    match &inst.kind {
+                        InstKind::Field { ptr: base, .. } | InstKind::Index { ptr: base, .. } | InstKind::Downcast(base, _) if *base == ptr => {
+                            pointers.extend(inst.result);
+                        },
+                        InstKind::Load(_) | InstKind::Copy { .. } | InstKind::SetTag(..) | InstKind::Is(..) | InstKind::Index { .. } => { },
+                        InstKind::Store { value, .. } if *value != ptr => { },
+                        kind if kind.operands().contains(&ptr) => return true,
+                        _ => { }
+                    }
+                }
+            }
+        }
+        return false;
+    }
+}
+
+
+/// Follows a chain of replacements to the value at its end.
+fn resolve(replacements: &HashMap<ValueId, ValueId>, mut value: ValueId) -> ValueId {
+    while let Some(next) = replacements.get(&value) {
+        value = *next;
+    }
+    return value;
+}
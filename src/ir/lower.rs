@@ -0,0 +1,753 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast;
+use crate::hir;
+use crate::hir::{ArenaId, ExprId, StmtId, LocalId, ExprKind, Stmt, CastKind};
+use crate::source::Span;
+use crate::typecheck::{ConstValue, Primitive};
+use crate::ir::ir::{Module, StructDef, Function, Type, InstKind, Terminator, BlockTarget, BlockId, ValueId, InstId, FuncId, StructId, UnaryOp, BinaryOp};
+
+
+/// Lowers the checked program to SSA form.
+///
+/// Locals of primitive, reference and function types become SSA values, unless they are referenced, and the
+/// others live in memory from `alloca`s. Structs and arrays are passed to functions as pointers to copies that
+/// the callee owns, and a function that returns one is passed a pointer to write it to as its first parameter.
+/// Memory for a local lives until its function returns, so a reference to it is only valid until then. The
+/// module's entry is `main`, if the program has one that can be run.
+pub fn lower_program(program: &hir::Program) -> Module {
+    let mut structs_by_type = HashMap::new();
+    for (index, s) in program.structs.iter().enumerate() {
+        structs_by_type.insert(s.typ.clone(), StructId(index as u32));
+    }
+    let names = unique_names(program.structs.iter().map(|s| s.name.as_str()));
+    let mut context = Context { program, structs_by_type, parents: Vec::new(), signatures: Vec::new() };
+    let structs: Vec<StructDef> = program.structs.iter().zip(names).map(|(s, name)| StructDef {
+        name,
+        fields: s.fields.iter().map(|field| context.typ(&field.typ)).collect(),
+        parent: s.superstruct.and_then(|decl| context.parent(s, decl)),
+        tagged: s.tagged
+    }).collect();
+    context.parents = structs.iter().map(|def| def.parent).collect();
+    context.signatures = program.functions.iter().map(|(_, function)| {
+        let params: Vec<crate::typecheck::Type> = function.parameters.iter().map(|local| function.locals[*local].typ.clone()).collect();
+        context.signature(&params, &function.ret)
+    }).collect();
+
+    let names = unique_names(program.functions.iter().map(|(_, function)| function.name.as_str()));
+    let functions = program.functions.iter().zip(names)
+        .map(|((id, function), name)| FunctionLowerer::new(&context, function, name, id).lower())
+        .collect();
+    return Module { structs, functions, entry: program.main.filter(|main| program.functions[*main].can_be_entry()).map(|main| FuncId(main.0)) };
+}
+
+/// Makes each name distinct from those before it by adding a number, as a program that imports several files
+/// can have functions and structs with the same name.
+fn unique_names<'a>(names: impl Iterator<Item=&'a str>) -> Vec<String> {
+    let mut used = HashSet::new();
+    let mut unique = Vec::new();
+    for name in names {
+        let mut candidate = String::from(name);
+        let mut number = 1;
+        while !used.insert(candidate.clone()) {
+            number += 1;
+            candidate = format!("{}.{}", name, number);
+        }
+        unique.push(candidate);
+    }
+    return unique;
+}
+
+
+/// The parameters and return type of a function in the IR, and whether it returns a struct or array through
+/// a pointer passed as its first parameter.
+struct Signature {
+    params: Vec<Type>,
+    ret: Type,
+    sret: bool
+}
+
+struct Context<'a> {
+    program: &'a hir::Program,
+    structs_by_type: HashMap<crate::typecheck::Type, StructId>,
+    parents: Vec<Option<StructId>>,
+    signatures: Vec<Signature>
+}
+
+impl<'a> Context<'a> {
+    fn typ(&self, typ: &crate::typecheck::Type) -> Type {
+        use crate::typecheck::Type as HirType;
+        match typ {
+            HirType::Primitive(p) => Type::Primitive(*p),
+            HirType::Struct(..) => Type::Struct(self.struct_id(typ)),
+            HirType::Reference(_) | HirType::Function(..) => Type::Ptr,
+            HirType::Array(element, length) => Type::Array(Box::new(self.typ(element)), *length),
+            HirType::GenericParameter(_) | HirType::Variable(_) | HirType::Error => unreachable!("a lowered program only has concrete types")
+        }
+    }
+
+    fn struct_id(&self, typ: &crate::typecheck::Type) -> StructId {
+        self.structs_by_type[typ]
+    }
+
+    /// The instance of the superstruct `decl` that a struct extends, which is the one whose fields its own
+    /// start with.
+    fn parent(&self, s: &hir::Struct, decl: crate::resolve::DeclId) -> Option<StructId> {
+        let candidates = self.program.structs.iter().enumerate()
+            .filter(|(_, candidate)| matches!(candidate.typ, crate::typecheck::Type::Struct(id, _) if id == decl));
+        let mut first = None;
+        for (index, candidate) in candidates {
+            first = first.or(Some(StructId(index as u32)));
+            let prefix = candidate.fields.len() <= s.fields.len() && candidate.fields.iter().zip(&s.fields).all(|(a, b)| a.typ == b.typ);
+            if prefix {
+                return Some(StructId(index as u32));
+            }
+        }
+        return first;
+    }
+
+    fn is_substruct(&self, sub: StructId, of: StructId) -> bool {
+        let mut current = Some(sub);
+        while let Some(id) = current {
+            if id == of {
+                return true;
+            }
+            current = self.parents[id.0 as usize];
+        }
+        return false;
+    }
+
+    fn signature(&self, params: &[crate::typecheck::Type], ret: &crate::typecheck::Type) -> Signature {
+        let mut lowered: Vec<Type> = params.iter()
+            .map(|param| self.typ(param))
+            .filter(|param| *param != Type::UNIT)
+            .map(|param| if param.is_aggregate() { Type::Ptr } else { param })
+            .collect();
+        let ret = self.typ(ret);
+        if ret.is_aggregate() {
+            lowered.insert(0, Type::Ptr);
+            return Signature { params: lowered, ret: Type::UNIT, sret: true };
+        }
+        return Signature { params: lowered, ret, sret: false };
+    }
+}
+
+
+/// Where the value of a local is kept.
+#[derive(Clone, Copy)]
+enum Storage {
+    /// In SSA values, which the local's assignments define and its uses read.
+    Value,
+    /// In memory at the pointer.
+    Memory(ValueId),
+    /// Nowhere, as the local is a `unit`.
+    Unit
+}
+
+/// Lowers one function, building SSA form as it goes with the algorithm of Braun et al.: the value of a local
+/// is looked up in the block it is read in, then in its predecessors, and a block parameter is added where
+/// different values meet. Blocks whose predecessors are not all known yet, as loop headers are until the end
+/// of the loop, are sealed once they are, and the parameters their reads needed are given arguments then.
+struct FunctionLowerer<'a> {
+    context: &'a Context<'a>,
+    hir: &'a hir::Function,
+    function: Function,
+    current: BlockId,
+    /// Whether the current block already has its terminator, as it does after a `return`.
+    terminated: bool,
+    storage: Vec<Storage>,
+    sret: Option<ValueId>,
+    /// The value each local has at the end of each block it is known in.
+    definitions: HashMap<(LocalId, BlockId), ValueId>,
+    sealed: Vec<bool>,
+    predecessors: Vec<Vec<BlockId>>,
+    /// The parameters added to each unsealed block for locals read in it, which need arguments once it is sealed.
+    incomplete: Vec<Vec<(LocalId, ValueId)>>,
+    /// The allocas and constants to place at the start of the entry block, where they dominate every use.
+    prelude: Vec<InstId>
+}
+
+impl<'a> FunctionLowerer<'a> {
+    fn new(context: &'a Context<'a>, hir: &'a hir::Function, name: String, id: hir::FunctionId) -> FunctionLowerer<'a> {
+        let signature = &context.signatures[id.index()];
+        let function = Function::new(name, signature.params.clone(), signature.ret.clone(), hir.loc);
+        let sret = if signature.sret { function.block(Function::ENTRY).params.first().copied() } else { None };
+        return FunctionLowerer {
+            context, hir, function, current: Function::ENTRY, terminated: false,
+            storage: Vec::new(), sret, definitions: HashMap::new(),
+            sealed: vec![true], predecessors: vec![Vec::new()], incomplete: vec![Vec::new()], prelude: Vec::new()
+        };
+    }
+
+    fn lower(mut self) -> Function {
+        let hir = self.hir;
+        let loc = hir.loc;
+        let mut addressed = vec![false; hir.locals.len()];
+        for (_, expr) in hir.exprs.iter() {
+            if let ExprKind::Ref(place) = expr.kind {
+                if let Some(local) = root_local(hir, place) {
+                    addressed[local.index()] = true;
+                }
+            }
+        }
+
+        let mut params = self.function.block(Function::ENTRY).params.clone().into_iter();
+        if self.sret.is_some() {
+            params.next();
+        }
+        for (local, data) in hir.locals.iter() {
+            let typ = self.context.typ(&data.typ);
+            let parameter = hir.parameters.contains(&local);
+            let storage = if typ == Type::UNIT {
+                Storage::Unit
+            } else if parameter && typ.is_aggregate() {
+                Storage::Memory(params.next().unwrap())
+            } else if typ.is_aggregate() || addressed[local.index()] {
+                let slot = self.alloca(typ, loc);
+                if parameter {
+                    let value = params.next().unwrap();
+                    self.emit(InstKind::Store { value, ptr: slot }, None, loc);
+                }
+                Storage::Memory(slot)
+            } else {
+                if parameter {
+                    let value = params.next().unwrap();
+                    self.write_local(local, Function::ENTRY, value);
+                }
+                Storage::Value
+            };
+            self.storage.push(storage);
+        }
+
+        self.block(&hir.body);
+        if !self.terminated {
+            let terminator = if self.function.ret == Type::UNIT { Terminator::Return(None) } else { Terminator::Unreachable };
+            self.function.set_terminator(self.current, terminator, loc);
+        }
+
+        let mut function = self.function;
+        let mut entry = self.prelude;
+        entry.append(&mut function.block_mut(Function::ENTRY).insts);
+        function.block_mut(Function::ENTRY).insts = entry;
+        function.remove_unreachable_blocks();
+        function.remove_trivial_params();
+        return function;
+    }
+
+    fn expr_type(&self, expr: ExprId) -> Type {
+        self.context.typ(&self.hir.exprs[expr].typ)
+    }
+
+
+    // Blocks and SSA construction.
+
+    fn new_block(&mut self, sealed: bool, loc: Span) -> BlockId {
+        self.sealed.push(sealed);
+        self.predecessors.push(Vec::new());
+        self.incomplete.push(Vec::new());
+        return self.function.add_block(loc);
+    }
+
+    /// Continues in `block`, which the branches to it have already been made to.
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = block;
+        self.terminated = false;
+    }
+
+    fn terminate(&mut self, terminator: Terminator, loc: Span) {
+        self.open();
+        for successor in terminator.successors() {
+            let predecessors = &mut self.predecessors[successor.0 as usize];
+            if !predecessors.contains(&self.current) {
+                predecessors.push(self.current);
+            }
+        }
+        self.function.set_terminator(self.current, terminator, loc);
+        self.terminated = true;
+    }
+
+    fn jump(&mut self, block: BlockId, args: Vec<ValueId>, loc: Span) {
+        self.terminate(Terminator::Jump(BlockTarget { block, args }), loc);
+    }
+
+    fn branch(&mut self, condition: ValueId, then: BlockTarget, otherwise: BlockTarget, loc: Span) {
+        self.terminate(Terminator::Branch(condition, then, otherwise), loc);
+    }
+
+    /// Starts a block that nothing branches to if the current one has its terminator, for code after a
+    /// `return`, which is removed with the other unreachable blocks at the end.
+    fn open(&mut self) {
+        if self.terminated {
+            let block = self.new_block(true, self.hir.loc);
+            self.switch_to(block);
+        }
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        self.sealed[block.0 as usize] = true;
+        for (local, param) in std::mem::take(&mut self.incomplete[block.0 as usize]) {
+            self.add_param_arguments(local, block, param);
+        }
+    }
+
+    fn write_local(&mut self, local: LocalId, block: BlockId, value: ValueId) {
+        self.definitions.insert((local, block), value);
+    }
+
+    fn read_local(&mut self, local: LocalId, block: BlockId) -> ValueId {
+        if let Some(value) = self.definitions.get(&(local, block)) {
+            return *value;
+        }
+        let typ = self.context.typ(&self.hir.locals[local].typ);
+        let predecessors = self.predecessors[block.0 as usize].clone();
+        let value = if !self.sealed[block.0 as usize] {
+            let param = self.function.add_param(block, typ);
+            self.incomplete[block.0 as usize].push((local, param));
+            param
+        } else if predecessors.len() == 1 {
+            self.read_local(local, predecessors[0])
+        } else if predecessors.is_empty() {
+            // Only the entry block and unreachable blocks have no predecessors, and the entry block defines
+            // every local that is read before it is assigned, so the value is never used.
+            self.undefined(typ)
+        } else {
+            let param = self.function.add_param(block, typ);
+            self.write_local(local, block, param);
+            self.add_param_arguments(local, block, param);
+            param
+        };
+        self.write_local(local, block, value);
+        return value;
+    }
+
+    /// Passes a new parameter of `block` the value the local has at the end of each predecessor.
+    fn add_param_arguments(&mut self, local: LocalId, block: BlockId, param: ValueId) {
+        self.write_local(local, block, param);
+        for predecessor in self.predecessors[block.0 as usize].clone() {
+            let argument = self.read_local(local, predecessor);
+            for target in self.function.block_mut(predecessor).terminator.targets_mut() {
+                if target.block == block {
+                    target.args.push(argument);
+                }
+            }
+        }
+    }
+
+    /// A value of the type for a local that has none, as on a path from an unreachable block.
+    fn undefined(&mut self, typ: Type) -> ValueId {
+        let loc = self.hir.loc;
+        let value = match typ.primitive() {
+            Some(p) if p.is_integer() => ConstValue::Integer(0),
+            Some(p) if p.is_float() => ConstValue::Float(0.0),
+            Some(Primitive::Bool) => ConstValue::Bool(false),
+            Some(Primitive::Char) => ConstValue::Char('\0'),
+            Some(Primitive::Str) => ConstValue::Str(String::new()),
+            _ => return self.alloca(Type::Primitive(Primitive::U8), loc)
+        };
+        let inst = self.function.create_inst(InstKind::Const(value), Some(typ), loc);
+        self.prelude.push(inst);
+        return self.function.inst(inst).result.unwrap();
+    }
+
+
+    // Instructions.
+
+    fn emit(&mut self, kind: InstKind, result: Option<Type>, loc: Span) -> Option<ValueId> {
+        self.open();
+        return self.function.push_inst(self.current, kind, result, loc);
+    }
+
+    fn emit_value(&mut self, kind: InstKind, result: Type, loc: Span) -> ValueId {
+        self.emit(kind, Some(result), loc).unwrap()
+    }
+
+    fn constant(&mut self, value: ConstValue, typ: Type, loc: Span) -> ValueId {
+        self.emit_value(InstKind::Const(value), typ, loc)
+    }
+
+    fn alloca(&mut self, typ: Type, loc: Span) -> ValueId {
+        let inst = self.function.create_inst(InstKind::Alloca(typ), Some(Type::Ptr), loc);
+        self.prelude.push(inst);
+        return self.function.inst(inst).result.unwrap();
+    }
+
+
+    // Statements.
+
+    fn block(&mut self, block: &hir::Block) {
+        for stmt in &block.stmts {
+            if self.terminated {
+                // The rest of the block follows a `return`, and can never run.
+                break;
+            }
+            self.stmt(*stmt);
+        }
+    }
+
+    fn stmt(&mut self, id: StmtId) {
+        let hir = self.hir;
+        let loc = hir.stmts.span(id);
+        match &hir.stmts[id] {
+            Stmt::Expr(expr) => {
+                self.value(*expr);
+            },
+            Stmt::Let { local, value: Some(value) } => match self.storage[local.index()] {
+                Storage::Value => {
+                    let value = self.value(*value).unwrap();
+                    self.write_local(*local, self.current, value);
+                },
+                Storage::Memory(slot) => self.store_into(*value, slot),
+                Storage::Unit => {
+                    self.value(*value);
+                }
+            },
+            Stmt::Let { value: None, .. } => { },
+            Stmt::Return(value) => {
+                let result = match self.sret {
+                    Some(sret) => {
+                        self.value_into(*value, sret);
+                        None
+                    },
+                    None => self.value(*value)
+                };
+                self.terminate(Terminator::Return(result), loc);
+            },
+            Stmt::If { condition, then, otherwise } => {
+                let condition = self.value(*condition).unwrap();
+                let then_block = self.new_block(true, loc);
+                let else_block = otherwise.as_ref().map(|_| self.new_block(true, loc));
+                let join = self.new_block(false, loc);
+                let else_block = else_block.unwrap_or(join);
+                self.branch(condition, BlockTarget { block: then_block, args: vec![] }, BlockTarget { block: else_block, args: vec![] }, loc);
+                self.switch_to(then_block);
+                self.block(then);
+                if !self.terminated {
+                    self.jump(join, vec![], loc);
+                }
+                if let Some(otherwise) = otherwise {
+                    self.switch_to(else_block);
+                    self.block(otherwise);
+                    if !self.terminated {
+                        self.jump(join, vec![], loc);
+                    }
+                }
+                self.seal(join);
+                self.switch_to(join);
+            },
+            Stmt::While { condition, body } => {
+                let header = self.new_block(false, loc);
+                self.jump(header, vec![], loc);
+                self.switch_to(header);
+                let condition = self.value(*condition).unwrap();
+                let body_block = self.new_block(true, loc);
+                let exit = self.new_block(false, loc);
+                self.branch(condition, BlockTarget { block: body_block, args: vec![] }, BlockTarget { block: exit, args: vec![] }, loc);
+                self.switch_to(body_block);
+                self.block(body);
+                if !self.terminated {
+                    self.jump(header, vec![], loc);
+                }
+                self.seal(header);
+                self.seal(exit);
+                self.switch_to(exit);
+            }
+        }
+    }
+
+
+    // Expressions.
+
+    /// Evaluates an expression, returning nothing for a `unit`, and a pointer to a copy of its value that
+    /// nothing else refers to for a struct or array.
+    fn value(&mut self, id: ExprId) -> Option<ValueId> {
+        let hir = self.hir;
+        let loc = hir.exprs.span(id);
+        let typ = self.expr_type(id);
+        if typ.is_aggregate() {
+            if matches!(hir.exprs[id].kind, ExprKind::Call { .. } | ExprKind::CallIndirect { .. }) {
+                return self.call(id, None);
+            }
+            let temp = self.alloca(typ, loc);
+            self.value_into(id, temp);
+            return Some(temp);
+        }
+        match &hir.exprs[id].kind {
+            ExprKind::Literal(value) => Some(self.constant(value.clone(), typ, loc)),
+            ExprKind::Local(local) => match self.storage[local.index()] {
+                Storage::Value => Some(self.read_local(*local, self.current)),
+                Storage::Memory(slot) => Some(self.emit_value(InstKind::Load(slot), typ, loc)),
+                Storage::Unit => None
+            },
+            ExprKind::Function(function) => Some(self.emit_value(InstKind::Function(FuncId(function.0)), Type::Ptr, loc)),
+            ExprKind::Call { .. } | ExprKind::CallIndirect { .. } => self.call(id, None),
+            ExprKind::Field { .. } | ExprKind::Index { .. } | ExprKind::Deref(_) => {
+                let ptr = self.place(id);
+                if typ == Type::UNIT {
+                    return None;
+                }
+                Some(self.emit_value(InstKind::Load(ptr), typ, loc))
+            },
+            ExprKind::Ref(place) => Some(self.place(*place)),
+            ExprKind::Cast { expr, kind: CastKind::Numeric } => {
+                let value = self.value(*expr).unwrap();
+                if *self.function.typ(value) == typ {
+                    return Some(value);
+                }
+                Some(self.emit_value(InstKind::Cast(value), typ, loc))
+            },
+            ExprKind::Cast { expr, kind: CastKind::Upcast } => self.value(*expr),
+            ExprKind::Cast { expr, kind: CastKind::Downcast } => {
+                let ptr = self.value(*expr).unwrap();
+                let target = self.context.struct_id(hir.exprs[id].typ.dereferenced());
+                Some(self.emit_value(InstKind::Downcast(ptr, target), Type::Ptr, loc))
+            },
+            ExprKind::Is { expr, target } => {
+                let ptr = self.place(*expr);
+                let static_type = self.context.struct_id(&hir.exprs[*expr].typ);
+                let target = self.is_target(static_type, *target);
+                Some(self.emit_value(InstKind::Is(ptr, target), Type::BOOL, loc))
+            },
+            ExprKind::Unary { op, operand } => {
+                let operand = self.value(*operand).unwrap();
+                let op = match op {
+                    ast::UnaryOp::Negate => UnaryOp::Neg,
+                    ast::UnaryOp::Not => UnaryOp::Not
+                };
+                Some(self.emit_value(InstKind::Unary(op, operand), typ, loc))
+            },
+            ExprKind::Binary { op: op @ (ast::BinaryOp::And | ast::BinaryOp::Or), left, right } => {
+                // The left operand decides the result when it is false for `&&` and true for `||`, and the
+                // right operand is only evaluated in a block of its own otherwise.
+                let left = self.value(*left).unwrap();
+                let decided = self.constant(ConstValue::Bool(*op == ast::BinaryOp::Or), Type::BOOL, loc);
+                let rhs = self.new_block(true, loc);
+                let join = self.new_block(false, loc);
+                let result = self.function.add_param(join, Type::BOOL);
+                let to_rhs = BlockTarget { block: rhs, args: vec![] };
+                let to_join = BlockTarget { block: join, args: vec![decided] };
+                if *op == ast::BinaryOp::And {
+                    self.branch(left, to_rhs, to_join, loc);
+                } else {
+                    self.branch(left, to_join, to_rhs, loc);
+                }
+                self.switch_to(rhs);
+                let right = self.value(*right).unwrap();
+                self.jump(join, vec![right], loc);
+                self.seal(join);
+                self.switch_to(join);
+                Some(result)
+            },
+            ExprKind::Binary { op, left, right } => {
+                let left = self.value(*left).unwrap();
+                let right = self.value(*right).unwrap();
+                let op = binary_op(*op);
+                Some(self.emit_value(InstKind::Binary(op, left, right), typ, loc))
+            },
+            ExprKind::Assign { place, value } => {
+                self.assign(*place, *value, loc);
+                None
+            },
+            ExprKind::Block(block) => {
+                self.block(block);
+                None
+            },
+            ExprKind::StructLiteral { .. } | ExprKind::Array(_) => unreachable!("structs and arrays are aggregates")
+        }
+    }
+
+    /// The struct an `is` tests for: the instance of `target` in the hierarchy of the value's static type.
+    fn is_target(&self, static_type: StructId, target: crate::resolve::DeclId) -> StructId {
+        let candidates: Vec<StructId> = self.context.program.structs.iter().enumerate()
+            .filter(|(_, s)| matches!(s.typ, crate::typecheck::Type::Struct(id, _) if id == target))
+            .map(|(index, _)| StructId(index as u32))
+            .collect();
+        return candidates.iter().copied()
+            .find(|candidate| self.context.is_substruct(*candidate, static_type))
+            .unwrap_or(candidates[0]);
+    }
+
+    /// Writes the value of a struct or array expression to memory at `dest`, building literals in place.
+    fn value_into(&mut self, id: ExprId, dest: ValueId) {
+        let hir = self.hir;
+        let loc = hir.exprs.span(id);
+        let typ = self.expr_type(id);
+        match &hir.exprs[id].kind {
+            ExprKind::StructLiteral { fields } => {
+                let Type::Struct(owner) = typ else { unreachable!() };
+                if self.context.program.structs[owner.0 as usize].tagged {
+                    self.emit(InstKind::SetTag(owner, dest), None, loc);
+                }
+                for (index, value) in fields {
+                    let field = self.emit_value(InstKind::Field { typ: owner, ptr: dest, index: *index }, Type::Ptr, loc);
+                    self.store_into(*value, field);
+                }
+            },
+            ExprKind::Array(elements) => {
+                for (index, element) in elements.iter().enumerate() {
+                    let index = self.constant(ConstValue::Integer(index as i128), Type::U64, loc);
+                    let ptr = self.emit_value(InstKind::Index { typ: typ.clone(), ptr: dest, index }, Type::Ptr, loc);
+                    self.store_into(*element, ptr);
+                }
+            },
+            ExprKind::Call { .. } | ExprKind::CallIndirect { .. } => {
+                self.call(id, Some(dest));
+            },
+            _ => {
+                let src = self.place(id);
+                self.emit(InstKind::Copy { typ, dest, src }, None, loc);
+            }
+        }
+    }
+
+    /// Writes the value of an expression of any type to memory at `ptr`.
+    fn store_into(&mut self, id: ExprId, ptr: ValueId) {
+        let typ = self.expr_type(id);
+        if typ.is_aggregate() {
+            self.value_into(id, ptr);
+        } else if let Some(value) = self.value(id) {
+            self.emit(InstKind::Store { value, ptr }, None, self.hir.exprs.span(id));
+        }
+    }
+
+    /// A pointer to the place an expression denotes. An expression that is not a place is evaluated into
+    /// memory of its own.
+    fn place(&mut self, id: ExprId) -> ValueId {
+        let hir = self.hir;
+        let loc = hir.exprs.span(id);
+        match &hir.exprs[id].kind {
+            ExprKind::Local(local) if matches!(self.storage[local.index()], Storage::Memory(_)) => {
+                let Storage::Memory(slot) = self.storage[local.index()] else { unreachable!() };
+                return slot;
+            },
+            ExprKind::Field { object, index } => {
+                let owner = self.context.struct_id(&hir.exprs[*object].typ);
+                let object = self.place(*object);
+                return self.emit_value(InstKind::Field { typ: owner, ptr: object, index: *index }, Type::Ptr, loc);
+            },
+            ExprKind::Index { object, index } => {
+                let array = self.expr_type(*object);
+                let object = self.place(*object);
+                let index = self.value(*index).unwrap();
+                return self.emit_value(InstKind::Index { typ: array, ptr: object, index }, Type::Ptr, loc);
+            },
+            ExprKind::Deref(reference) => return self.value(*reference).unwrap(),
+            _ => { }
+        }
+        let typ = self.expr_type(id);
+        if typ.is_aggregate() {
+            return self.value(id).unwrap();
+        }
+        let temp = self.alloca(typ, loc);
+        if let Some(value) = self.value(id) {
+            self.emit(InstKind::Store { value, ptr: temp }, None, loc);
+        }
+        return temp;
+    }
+
+    fn assign(&mut self, place: ExprId, value: ExprId, loc: Span) {
+        if let ExprKind::Local(local) = self.hir.exprs[place].kind {
+            match self.storage[local.index()] {
+                Storage::Value => {
+                    let value = self.value(value).unwrap();
+                    self.write_local(local, self.current, value);
+                    return;
+                },
+                Storage::Unit => {
+                    self.value(value);
+                    return;
+                },
+                Storage::Memory(_) => { }
+            }
+        }
+        // The place is evaluated before the value. A struct or array is evaluated into memory of its own
+        // first, since it may read the place it is assigned to.
+        let ptr = self.place(place);
+        let typ = self.expr_type(place);
+        match self.value(value) {
+            Some(src) if typ.is_aggregate() => {
+                self.emit(InstKind::Copy { typ, dest: ptr, src }, None, loc);
+            },
+            Some(value) => {
+                self.emit(InstKind::Store { value, ptr }, None, loc);
+            },
+            None => { }
+        }
+    }
+
+    /// Calls a function, returning its result. A struct or array result is written to `dest`, or to memory of
+    /// its own, which is returned, if there is no `dest`.
+    fn call(&mut self, id: ExprId, dest: Option<ValueId>) -> Option<ValueId> {
+        let hir = self.hir;
+        let loc = hir.exprs.span(id);
+        let typ = self.expr_type(id);
+        let callee = match &hir.exprs[id].kind {
+            ExprKind::CallIndirect { callee, .. } => Some(self.value(*callee).unwrap()),
+            _ => None
+        };
+        let (ExprKind::Call { arguments, .. } | ExprKind::CallIndirect { arguments, .. }) = &hir.exprs[id].kind else { unreachable!() };
+        let sret = if typ.is_aggregate() { Some(dest.unwrap_or_else(|| self.alloca(typ.clone(), loc))) } else { None };
+        let mut values: Vec<ValueId> = sret.into_iter().collect();
+        for argument in arguments {
+            values.extend(self.value(*argument));
+        }
+        let result = if typ.is_scalar() { Some(typ) } else { None };
+        let returned = match (&hir.exprs[id].kind, callee) {
+            (ExprKind::Call { function, .. }, _) => self.emit(InstKind::Call(FuncId(function.0), values), result, loc),
+            (_, Some(callee)) => self.emit(InstKind::CallIndirect(callee, values), result, loc),
+            _ => unreachable!()
+        };
+        return match sret {
+            Some(sret) if dest.is_none() => Some(sret),
+            Some(_) => None,
+            None => returned
+        };
+    }
+}
+
+fn binary_op(op: ast::BinaryOp) -> BinaryOp {
+    use ast::BinaryOp::*;
+    match op {
+        Add => BinaryOp::Add,
+        Subtract => BinaryOp::Sub,
+        Multiply => BinaryOp::Mul,
+        Divide => BinaryOp::Div,
+        Remainder => BinaryOp::Rem,
+        Less => BinaryOp::Lt,
+        LessEqual => BinaryOp::Le,
+        Greater => BinaryOp::Gt,
+        GreaterEqual => BinaryOp::Ge,
+        Equal => BinaryOp::Eq,
+        NotEqual => BinaryOp::Ne,
+        And | Or => unreachable!("'&&' and '||' are lowered to branches")
+    }
+}
+
+/// The local a place is part of, if it is a local or a field or element of one.
+fn root_local(function: &hir::Function, place: ExprId) -> Option<LocalId> {
+    match &function.exprs[place].kind {
+        ExprKind::Local(local) => Some(*local),
+        ExprKind::Field { object, .. } | ExprKind::Index { object, .. } => root_local(function, *object),
+        _ => None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use super::lower_program;
+
+    /// Checks a program read as if from standard input and lowers it to IR.
+    fn lower(text: &str) -> crate::ir::Module {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        return lower_program(result.hir.as_ref().expect("the program has errors"));
+    }
+
+    #[test]
+    fn main_is_entry_only_when_it_can_be_run() {
+        assert!(lower("fn main() -> int { return 0; }").entry.is_some());
+        assert!(lower("fn main() -> unit { }").entry.is_some());
+        assert!(lower("fn main() -> bool { return true; }").entry.is_none());
+        assert!(lower("fn main(x: int) -> int { return x; }").entry.is_none());
+    }
+}
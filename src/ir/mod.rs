@@ -0,0 +1,15 @@
+#[allow(clippy::module_inception)]
+mod ir;
+mod error;
+mod dominators;
+mod lower;
+mod printer;
+mod parser;
+mod verify;
+
+pub use ir::*;
+pub use dominators::Dominators;
+pub use lower::lower_program;
+pub use printer::{print_module, value_numbers};
+pub use parser::parse_module;
+pub use verify::verify_module;
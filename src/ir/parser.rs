@@ -0,0 +1,842 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::source::{FileId, Span};
+use crate::error::ErrorSet;
+use crate::typecheck::{ConstValue, Primitive};
+use crate::ir::error::IrError;
+use crate::ir::ir::{Module, StructDef, Function, Type, InstKind, Terminator, BlockTarget, ValueId, ValueDef, ValueData, BlockId, FuncId, StructId, UnaryOp, BinaryOp};
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// `%name`
+    Value(String),
+    /// `^name`
+    Block(String),
+    /// `@name`
+    Function(String),
+    /// `$name`
+    Struct(String),
+    Word(String),
+    Integer(i128),
+    Float(f64),
+    Char(char),
+    Str(String),
+    Symbol(&'static str),
+    End
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Value(name) => format!("'%{}'", name),
+            Token::Block(name) => format!("'^{}'", name),
+            Token::Function(name) => format!("'@{}'", name),
+            Token::Struct(name) => format!("'${}'", name),
+            Token::Word(word) => format!("'{}'", word),
+            Token::Integer(n) => format!("'{}'", n),
+            Token::Float(f) => format!("'{}'", f),
+            Token::Char(c) => format!("{:?}", c),
+            Token::Str(s) => format!("{:?}", s),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+            Token::End => String::from("the end of the file")
+        }
+    }
+}
+
+const SYMBOLS: [&str; 11] = ["->", "(", ")", "{", "}", "[", "]", ":", ";", ",", "="];
+
+
+/// Reads a module from the IR's text syntax, as `print_module` writes it. Names may be anything, and may be
+/// used before they are defined; blocks are numbered in the order they are written. The module is not
+/// verified, but every name it uses is defined and the types written on instructions match their operands.
+pub fn parse_module(text: &str, file: FileId) -> Result<Module, ErrorSet<IrError>> {
+    let single = |error: IrError| {
+        let mut errors = ErrorSet::new();
+        errors.add_error(error);
+        return errors;
+    };
+    let tokens = lex(text, file).map_err(single)?;
+    let mut parser = Parser { tokens, pos: 0, structs: HashMap::new(), functions: HashMap::new(), returns: Vec::new() };
+    parser.declare().map_err(single)?;
+    let mut module = Module { structs: Vec::new(), functions: Vec::new(), entry: None };
+    let bodies = parser.headers(&mut module).map_err(single)?;
+
+    let mut errors = ErrorSet::new();
+    for (function, start) in module.functions.iter_mut().zip(bodies) {
+        parser.pos = start;
+        let mut names = Names::new();
+        match parser.body(function, &mut names) {
+            Ok(()) => {
+                for error in names.finish(function) {
+                    errors.add_error(error);
+                }
+            },
+            Err(error) => errors.add_error(error)
+        }
+    }
+    return if errors.is_empty() { Ok(module) } else { Err(errors) };
+}
+
+
+fn lex(text: &str, file: FileId) -> Result<Vec<(Token, Span)>, IrError> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(text.len(), |(offset, _)| *offset);
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1).map(|(_, c)| *c) == Some('/') {
+            while i < chars.len() && chars[i].1 != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        let token = if let Some(symbol) = SYMBOLS.iter().find(|symbol| text[offset(i)..].starts_with(**symbol)) {
+            i += symbol.len();
+            Token::Symbol(symbol)
+        } else if matches!(c, '%' | '^' | '@' | '$') {
+            i += 1;
+            let name = if chars.get(i).map(|(_, c)| *c) == Some('"') {
+                let (name, end) = quoted(&chars, i, text, file)?;
+                i = end;
+                name
+            } else {
+                let begin = i;
+                while i < chars.len() && is_name(chars[i].1) {
+                    i += 1;
+                }
+                if begin == i {
+                    return Err(IrError::Syntax { message: format!("Expected a name after '{}'.", c), loc: Span::new(file, offset(start), offset(i)) });
+                }
+                String::from(&text[offset(begin)..offset(i)])
+            };
+            match c {
+                '%' => Token::Value(name),
+                '^' => Token::Block(name),
+                '@' => Token::Function(name),
+                _ => Token::Struct(name)
+            }
+        } else if c == '"' {
+            let (string, end) = quoted(&chars, i, text, file)?;
+            i = end;
+            Token::Str(string)
+        } else if c == '\'' {
+            let (string, end) = quoted(&chars, i, text, file)?;
+            i = end;
+            let mut string = string.chars();
+            match (string.next(), string.next()) {
+                (Some(c), None) => Token::Char(c),
+                _ => return Err(IrError::Syntax { message: String::from("A char literal must hold one character."), loc: Span::new(file, offset(start), offset(i)) })
+            }
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|(_, c)| c.is_ascii_digit())) {
+            i += 1;
+            let mut float = false;
+            while i < chars.len() {
+                let c = chars[i].1;
+                let exponent_sign = matches!(c, '+' | '-') && matches!(chars[i - 1].1, 'e' | 'E');
+                if c.is_ascii_digit() || exponent_sign {
+                    i += 1;
+                } else if matches!(c, '.' | 'e' | 'E') {
+                    float = true;
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            let number = &text[offset(start)..offset(i)];
+            let loc = Span::new(file, offset(start), offset(i));
+            if float {
+                Token::Float(number.parse().map_err(|_| IrError::Syntax { message: format!("Invalid number '{}'.", number), loc })?)
+            } else {
+                Token::Integer(number.parse().map_err(|_| IrError::Syntax { message: format!("Invalid number '{}'.", number), loc })?)
+            }
+        } else if c.is_alphabetic() || c == '_' || (c == '-' && chars.get(i + 1).is_some_and(|(_, c)| c.is_alphabetic())) {
+            i += 1;
+            while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            Token::Word(String::from(&text[offset(start)..offset(i)]))
+        } else {
+            return Err(IrError::Syntax { message: format!("Unexpected character {:?}.", c), loc: Span::new(file, offset(start), offset(i + 1)) });
+        };
+        tokens.push((token, Span::new(file, offset(start), offset(i))));
+    }
+    tokens.push((Token::End, Span::new(file, text.len(), text.len())));
+    return Ok(tokens);
+}
+
+/// Reads a string quoted with the character at `start`, with the escapes Rust's debug formatting writes, and
+/// returns it with the index of the character after the closing quote.
+fn quoted(chars: &[(usize, char)], start: usize, text: &str, file: FileId) -> Result<(String, usize), IrError> {
+    let quote = chars[start].1;
+    let offset = |i: usize| chars.get(i).map_or(text.len(), |(offset, _)| *offset);
+    let error = |message: &str, from: usize, to: usize| IrError::Syntax { message: String::from(message), loc: Span::new(file, offset(from), offset(to)) };
+    let mut string = String::new();
+    let mut i = start + 1;
+    loop {
+        let Some((_, c)) = chars.get(i).copied() else {
+            return Err(error("The quoted text is never closed.", start, i));
+        };
+        i += 1;
+        if c == quote {
+            return Ok((string, i));
+        }
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        let escape = chars.get(i).map(|(_, c)| *c);
+        i += 1;
+        string.push(match escape {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '\'' | '"')) => c,
+            Some('u') if chars.get(i).map(|(_, c)| *c) == Some('{') => {
+                let begin = i + 1;
+                while i < chars.len() && chars[i].1 != '}' {
+                    i += 1;
+                }
+                let code = u32::from_str_radix(&text[offset(begin)..offset(i)], 16).ok().and_then(char::from_u32);
+                i += 1;
+                code.ok_or_else(|| error("Invalid unicode escape.", begin - 3, i))?
+            },
+            _ => return Err(error("Unknown escape.", i - 2, i))
+        });
+    }
+}
+
+
+struct Parser {
+    tokens: Vec<(Token, Span)>,
+    pos: usize,
+    structs: HashMap<String, StructId>,
+    functions: HashMap<String, FuncId>,
+    /// The return type of each function, which gives calls their results.
+    returns: Vec<Type>
+}
+
+/// The names of one function body, which are created when they are first used and defined later.
+struct Names {
+    values: HashMap<String, ValueId>,
+    defined_values: HashSet<ValueId>,
+    blocks: HashMap<String, BlockId>,
+    /// The blocks in the order they are written.
+    defined_blocks: Vec<BlockId>,
+    first_uses: HashMap<ValueId, (String, Span)>,
+    block_uses: HashMap<BlockId, (String, Span)>,
+    /// The types written for operands, which are checked once every value is defined.
+    expected: Vec<(ValueId, Type, Span)>
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.pos].1
+    }
+
+    fn previous_span(&self) -> Span {
+        self.tokens[self.pos.saturating_sub(1)].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        return token;
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, IrError> {
+        return Err(IrError::Syntax { message: format!("Expected {}, found {}.", expected, self.peek().describe()), loc: self.span() });
+    }
+
+    fn symbol(&mut self, symbol: &'static str) -> Result<(), IrError> {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.next();
+            return Ok(());
+        }
+        return self.error(&format!("'{}'", symbol));
+    }
+
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.next();
+            return true;
+        }
+        return false;
+    }
+
+    fn word(&mut self, word: &str) -> Result<(), IrError> {
+        if *self.peek() == Token::Word(String::from(word)) {
+            self.next();
+            return Ok(());
+        }
+        return self.error(&format!("'{}'", word));
+    }
+
+    /// Numbers the structs and functions in the order they are written, so that they can be used before they
+    /// are defined.
+    fn declare(&mut self) -> Result<(), IrError> {
+        let mut depth = 0;
+        for window in self.tokens.windows(2) {
+            match (&window[0].0, &window[1].0) {
+                (Token::Symbol("{"), _) => depth += 1,
+                (Token::Symbol("}"), _) => depth -= 1,
+                (Token::Word(word), Token::Struct(name)) if depth == 0 && word == "struct" => {
+                    let id = StructId(self.structs.len() as u32);
+                    if self.structs.insert(name.clone(), id).is_some() {
+                        return Err(IrError::Redefined { name: format!("${}", name), loc: window[1].1 });
+                    }
+                },
+                (Token::Word(word), Token::Function(name)) if depth == 0 && word == "fn" => {
+                    let id = FuncId(self.functions.len() as u32);
+                    if self.functions.insert(name.clone(), id).is_some() {
+                        return Err(IrError::Redefined { name: format!("@{}", name), loc: window[1].1 });
+                    }
+                },
+                _ => { }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Reads the structs, the signatures of the functions and the entry function, and returns where the
+    /// body of each function starts.
+    fn headers(&mut self, module: &mut Module) -> Result<Vec<usize>, IrError> {
+        let mut bodies = Vec::new();
+        loop {
+            match self.peek().clone() {
+                Token::Word(word) if word == "struct" => {
+                    self.next();
+                    let Token::Struct(name) = self.peek().clone() else {
+                        return self.error("a struct name");
+                    };
+                    self.next();
+                    let parent = if self.eat(":") { Some(self.struct_name()?) } else { None };
+                    let tagged = *self.peek() == Token::Word(String::from("tagged"));
+                    if tagged {
+                        self.next();
+                    }
+                    self.symbol("{")?;
+                    let mut fields = Vec::new();
+                    while !self.eat("}") {
+                        if !fields.is_empty() {
+                            self.symbol(",")?;
+                        }
+                        fields.push(self.typ()?);
+                    }
+                    module.structs.push(StructDef { name, fields, parent, tagged });
+                },
+                Token::Word(word) if word == "fn" => {
+                    let start = self.span();
+                    self.next();
+                    let Token::Function(name) = self.peek().clone() else {
+                        return self.error("a function name");
+                    };
+                    self.next();
+                    let loc = start.combine(&self.previous_span());
+                    self.symbol("(")?;
+                    let mut params = Vec::new();
+                    while !self.eat(")") {
+                        if !params.is_empty() {
+                            self.symbol(",")?;
+                        }
+                        params.push(self.typ()?);
+                    }
+                    self.symbol("->")?;
+                    let ret = self.typ()?;
+                    self.returns.push(ret.clone());
+                    module.functions.push(Function { name, params, ret, blocks: Vec::new(), values: Vec::new(), insts: Vec::new(), loc });
+                    self.symbol("{")?;
+                    bodies.push(self.pos);
+                    while !matches!(self.peek(), Token::Symbol("}") | Token::End) {
+                        self.next();
+                    }
+                    self.symbol("}")?;
+                },
+                Token::Word(word) if word == "entry" => {
+                    self.next();
+                    module.entry = Some(self.function_name()?);
+                },
+                Token::End => return Ok(bodies),
+                _ => return self.error("'struct', 'fn' or 'entry'")
+            }
+        }
+    }
+
+    fn struct_name(&mut self) -> Result<StructId, IrError> {
+        let loc = self.span();
+        match self.next() {
+            Token::Struct(name) => self.structs.get(&name).copied().ok_or(IrError::Undefined { name: format!("${}", name), loc }),
+            _ => { self.pos -= 1; self.error("a struct name") }
+        }
+    }
+
+    fn function_name(&mut self) -> Result<FuncId, IrError> {
+        let loc = self.span();
+        match self.next() {
+            Token::Function(name) => self.functions.get(&name).copied().ok_or(IrError::Undefined { name: format!("@{}", name), loc }),
+            _ => { self.pos -= 1; self.error("a function name") }
+        }
+    }
+
+    fn typ(&mut self) -> Result<Type, IrError> {
+        match self.peek().clone() {
+            Token::Word(word) if word == "ptr" => {
+                self.next();
+                return Ok(Type::Ptr);
+            },
+            Token::Word(word) => match Primitive::from_name(&word) {
+                Some(p) => {
+                    self.next();
+                    return Ok(Type::Primitive(p));
+                },
+                None => self.error("a type")
+            },
+            Token::Struct(_) => Ok(Type::Struct(self.struct_name()?)),
+            Token::Symbol("[") => {
+                self.next();
+                let element = self.typ()?;
+                self.symbol(";")?;
+                let length = match self.next() {
+                    Token::Integer(n) if n >= 0 && n <= u64::MAX as i128 => n as u64,
+                    _ => { self.pos -= 1; return self.error("an array length"); }
+                };
+                self.symbol("]")?;
+                return Ok(Type::Array(Box::new(element), length));
+            },
+            _ => self.error("a type")
+        }
+    }
+}
+
+impl Names {
+    fn new() -> Names {
+        Names {
+            values: HashMap::new(), defined_values: HashSet::new(), blocks: HashMap::new(), defined_blocks: Vec::new(),
+            first_uses: HashMap::new(), block_uses: HashMap::new(), expected: Vec::new()
+        }
+    }
+
+    /// The value with a name, which is created with a placeholder type if it has not been used before.
+    fn value(&mut self, function: &mut Function, name: String, loc: Span) -> ValueId {
+        if let Some(value) = self.values.get(&name) {
+            return *value;
+        }
+        let value = function.new_value(Type::UNIT, ValueDef::Param(BlockId(u32::MAX), 0));
+        self.first_uses.insert(value, (format!("%{}", name), loc));
+        self.values.insert(name, value);
+        return value;
+    }
+
+    fn define_value(&mut self, function: &mut Function, name: String, data: ValueData, loc: Span) -> Result<ValueId, IrError> {
+        let value = self.value(function, name.clone(), loc);
+        if !self.defined_values.insert(value) {
+            return Err(IrError::Redefined { name: format!("%{}", name), loc });
+        }
+        function.values[value.0 as usize] = data;
+        return Ok(value);
+    }
+
+    fn block(&mut self, function: &mut Function, name: String, loc: Span) -> BlockId {
+        if let Some(block) = self.blocks.get(&name) {
+            return *block;
+        }
+        let block = function.add_block(loc);
+        self.block_uses.insert(block, (format!("^{}", name), loc));
+        self.blocks.insert(name, block);
+        return block;
+    }
+
+    /// Reports the names used but never defined and the operands whose types differ from those written for
+    /// them, then numbers the blocks in the order they were written.
+    fn finish(self, function: &mut Function) -> Vec<IrError> {
+        let mut errors = Vec::new();
+        let mut undefined: Vec<(String, Span)> = self.first_uses.iter()
+            .filter(|(value, _)| !self.defined_values.contains(value))
+            .map(|(_, use_)| use_.clone())
+            .collect();
+        undefined.extend(self.block_uses.iter().filter(|(block, _)| !self.defined_blocks.contains(block)).map(|(_, use_)| use_.clone()));
+        undefined.sort_by_key(|(_, loc)| loc.lo);
+        for (name, loc) in undefined {
+            errors.push(IrError::Undefined { name, loc });
+        }
+        if self.defined_blocks.is_empty() {
+            errors.push(IrError::Syntax { message: String::from("A function must have at least one block."), loc: function.loc });
+        }
+        if !errors.is_empty() {
+            return errors;
+        }
+        for (value, typ, loc) in self.expected {
+            if function.typ(value) != &typ {
+                errors.push(IrError::Syntax { message: String::from("The type written here is not the type of the value."), loc });
+            }
+        }
+        function.reorder_blocks(&self.defined_blocks);
+        return errors;
+    }
+}
+
+impl Parser {
+    fn body(&mut self, function: &mut Function, names: &mut Names) -> Result<(), IrError> {
+        while !self.eat("}") {
+            let loc = self.span();
+            let Token::Block(name) = self.next() else {
+                self.pos -= 1;
+                return self.error("a block");
+            };
+            let block = names.block(function, name.clone(), loc);
+            if names.defined_blocks.contains(&block) {
+                return Err(IrError::Redefined { name: format!("^{}", name), loc });
+            }
+            names.defined_blocks.push(block);
+            if self.eat("(") {
+                while !self.eat(")") {
+                    if !function.block(block).params.is_empty() {
+                        self.symbol(",")?;
+                    }
+                    let loc = self.span();
+                    let Token::Value(name) = self.next() else {
+                        self.pos -= 1;
+                        return self.error("a parameter");
+                    };
+                    self.symbol(":")?;
+                    let typ = self.typ()?;
+                    let index = function.block(block).params.len();
+                    let value = names.define_value(function, name, ValueData { typ, def: ValueDef::Param(block, index) }, loc)?;
+                    function.block_mut(block).params.push(value);
+                }
+            }
+            self.symbol(":")?;
+            while !self.terminator(function, names, block)? {
+                self.inst(function, names, block)?;
+            }
+        }
+        return Ok(());
+    }
+
+    fn operand(&mut self, function: &mut Function, names: &mut Names) -> Result<ValueId, IrError> {
+        let loc = self.span();
+        match self.next() {
+            Token::Value(name) => Ok(names.value(function, name, loc)),
+            _ => { self.pos -= 1; self.error("a value") }
+        }
+    }
+
+    /// An operand with the type written before it, which is checked once the body has been read.
+    fn typed_operand(&mut self, function: &mut Function, names: &mut Names, typ: Type) -> Result<ValueId, IrError> {
+        let loc = self.span();
+        let value = self.operand(function, names)?;
+        names.expected.push((value, typ, loc));
+        return Ok(value);
+    }
+
+    fn arguments(&mut self, function: &mut Function, names: &mut Names) -> Result<Vec<ValueId>, IrError> {
+        let mut arguments = Vec::new();
+        if self.eat("(") {
+            while !self.eat(")") {
+                if !arguments.is_empty() {
+                    self.symbol(",")?;
+                }
+                arguments.push(self.operand(function, names)?);
+            }
+        }
+        return Ok(arguments);
+    }
+
+    fn target(&mut self, function: &mut Function, names: &mut Names) -> Result<BlockTarget, IrError> {
+        let loc = self.span();
+        let Token::Block(name) = self.next() else {
+            self.pos -= 1;
+            return self.error("a block");
+        };
+        let block = names.block(function, name, loc);
+        return Ok(BlockTarget { block, args: self.arguments(function, names)? });
+    }
+
+    fn index(&mut self) -> Result<usize, IrError> {
+        match self.next() {
+            Token::Integer(n) if n >= 0 => Ok(n as usize),
+            _ => { self.pos -= 1; self.error("a field index") }
+        }
+    }
+
+    /// Reads the block's terminator, if it comes next.
+    fn terminator(&mut self, function: &mut Function, names: &mut Names, block: BlockId) -> Result<bool, IrError> {
+        let start = self.span();
+        let Token::Word(word) = self.peek().clone() else {
+            return Ok(false);
+        };
+        let terminator = match word.as_str() {
+            "jump" => {
+                self.next();
+                Terminator::Jump(self.target(function, names)?)
+            },
+            "br" => {
+                self.next();
+                let condition = self.operand(function, names)?;
+                self.symbol(",")?;
+                let then = self.target(function, names)?;
+                self.symbol(",")?;
+                Terminator::Branch(condition, then, self.target(function, names)?)
+            },
+            "ret" => {
+                self.next();
+                if matches!(self.peek(), Token::Value(_)) { Terminator::Return(Some(self.operand(function, names)?)) } else { Terminator::Return(None) }
+            },
+            "unreachable" => {
+                self.next();
+                Terminator::Unreachable
+            },
+            _ => return Ok(false)
+        };
+        function.set_terminator(block, terminator, start.combine(&self.previous_span()));
+        return Ok(true);
+    }
+
+    fn inst(&mut self, function: &mut Function, names: &mut Names, block: BlockId) -> Result<(), IrError> {
+        let start = self.span();
+        let result_name = match self.peek().clone() {
+            Token::Value(name) => {
+                self.next();
+                self.symbol("=")?;
+                Some(name)
+            },
+            _ => None
+        };
+        let op_loc = self.span();
+        let Token::Word(op) = self.next() else {
+            self.pos -= 1;
+            return self.error("an instruction");
+        };
+        let (kind, result) = match op.as_str() {
+            "const" => {
+                let typ = self.typ()?;
+                let loc = self.span();
+                let value = match (self.next(), typ.primitive()) {
+                    (Token::Integer(n), Some(p)) if p.is_integer() => ConstValue::Integer(n),
+                    (Token::Integer(n), Some(p)) if p.is_float() => ConstValue::Float(n as f64),
+                    (Token::Float(f), Some(p)) if p.is_float() => ConstValue::Float(f),
+                    (Token::Word(word), Some(p)) if p.is_float() && matches!(word.as_str(), "nan" | "inf" | "-inf") => {
+                        ConstValue::Float(match word.as_str() { "nan" => f64::NAN, "inf" => f64::INFINITY, _ => f64::NEG_INFINITY })
+                    },
+                    (Token::Word(word), Some(Primitive::Bool)) if word == "true" || word == "false" => ConstValue::Bool(word == "true"),
+                    (Token::Char(c), Some(Primitive::Char)) => ConstValue::Char(c),
+                    (Token::Str(s), Some(Primitive::Str)) => ConstValue::Str(s),
+                    _ => return Err(IrError::Syntax { message: String::from("Expected a constant of the type before it."), loc })
+                };
+                (InstKind::Const(value), Some(typ))
+            },
+            "cast" => {
+                let from = self.typ()?;
+                let operand = self.typed_operand(function, names, from)?;
+                self.word("to")?;
+                (InstKind::Cast(operand), Some(self.typ()?))
+            },
+            "call" => {
+                let callee = self.function_name()?;
+                let arguments = self.arguments(function, names)?;
+                let ret = self.returns[callee.0 as usize].clone();
+                (InstKind::Call(callee, arguments), Some(ret).filter(|ret| *ret != Type::UNIT))
+            },
+            "call_indirect" => {
+                let ret = self.typ()?;
+                let callee = self.operand(function, names)?;
+                let arguments = self.arguments(function, names)?;
+                (InstKind::CallIndirect(callee, arguments), Some(ret).filter(|ret| *ret != Type::UNIT))
+            },
+            "func" => (InstKind::Function(self.function_name()?), Some(Type::Ptr)),
+            "alloca" => (InstKind::Alloca(self.typ()?), Some(Type::Ptr)),
+            "load" => {
+                let typ = self.typ()?;
+                (InstKind::Load(self.operand(function, names)?), Some(typ))
+            },
+            "store" => {
+                let typ = self.typ()?;
+                let value = self.typed_operand(function, names, typ)?;
+                self.symbol(",")?;
+                (InstKind::Store { value, ptr: self.operand(function, names)? }, None)
+            },
+            "copy" => {
+                let typ = self.typ()?;
+                let dest = self.operand(function, names)?;
+                self.symbol(",")?;
+                (InstKind::Copy { typ, dest, src: self.operand(function, names)? }, None)
+            },
+            "field" => {
+                let typ = self.struct_name()?;
+                let ptr = self.operand(function, names)?;
+                self.symbol(",")?;
+                (InstKind::Field { typ, ptr, index: self.index()? }, Some(Type::Ptr))
+            },
+            "index" => {
+                let typ = self.typ()?;
+                let ptr = self.operand(function, names)?;
+                self.symbol(",")?;
+                (InstKind::Index { typ, ptr, index: self.operand(function, names)? }, Some(Type::Ptr))
+            },
+            "settag" | "is" | "downcast" => {
+                let target = self.struct_name()?;
+                let ptr = self.operand(function, names)?;
+                match op.as_str() {
+                    "settag" => (InstKind::SetTag(target, ptr), None),
+                    "is" => (InstKind::Is(ptr, target), Some(Type::BOOL)),
+                    _ => (InstKind::Downcast(ptr, target), Some(Type::Ptr))
+                }
+            },
+            _ => {
+                if let Some(op) = UnaryOp::ALL.iter().find(|unary| unary.name() == op) {
+                    let typ = self.typ()?;
+                    (InstKind::Unary(*op, self.typed_operand(function, names, typ.clone())?), Some(typ))
+                } else if let Some(op) = BinaryOp::ALL.iter().find(|binary| binary.name() == op) {
+                    let typ = self.typ()?;
+                    let left = self.typed_operand(function, names, typ.clone())?;
+                    self.symbol(",")?;
+                    let right = self.typed_operand(function, names, typ.clone())?;
+                    (InstKind::Binary(*op, left, right), Some(if op.is_comparison() { Type::BOOL } else { typ }))
+                } else {
+                    return Err(IrError::Syntax { message: format!("Unknown instruction '{}'.", op), loc: op_loc });
+                }
+            }
+        };
+        let loc = start.combine(&self.previous_span());
+        let inst = function.create_inst(kind, None, loc);
+        match (result_name, result) {
+            (Some(name), Some(typ)) => {
+                let value = names.define_value(function, name, ValueData { typ, def: ValueDef::Inst(inst) }, start)?;
+                function.inst_mut(inst).result = Some(value);
+            },
+            (None, Some(typ)) => {
+                let value = function.new_value(typ, ValueDef::Inst(inst));
+                function.inst_mut(inst).result = Some(value);
+            },
+            (Some(_), None) => return Err(IrError::Syntax { message: format!("'{}' has no result to name.", op), loc: start }),
+            (None, None) => { }
+        }
+        function.block_mut(block).insts.push(inst);
+        return Ok(());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::source::{Source, SourceMap, FileId};
+    use crate::ir::{Module, print_module, verify_module};
+    use super::parse_module;
+
+    fn read(text: &str) -> Module {
+        let Ok(module) = parse_module(text, FileId(0)) else {
+            panic!("the IR could not be read");
+        };
+        return module;
+    }
+
+    /// The messages of the errors in reading `text`.
+    fn syntax_errors(text: &str) -> Vec<String> {
+        let mut sources = SourceMap::new();
+        let file = sources.add(Source::from_string("test.ir", String::from(text)));
+        let Err(errors) = parse_module(text, file) else {
+            panic!("the IR was read without errors");
+        };
+        return errors.render(&sources, None).lines()
+            .filter_map(|line| line.strip_prefix("Error: "))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn printed_module_reads_back() {
+        let text = "\
+struct $Shape tagged { i64 }
+struct $Circle : $Shape tagged { i64, f64 }
+
+fn @area(ptr) -> f64 {
+^bb0(%0: ptr):
+    %1 = field $Circle %0, 1
+    %2 = load f64 %1
+    %3 = const f64 3.14
+    %4 = mul f64 %2, %3
+    ret %4
+}
+
+fn @apply(ptr, i64) -> i64 {
+^bb0(%0: ptr, %1: i64):
+    %2 = call_indirect i64 %0(%1)
+    ret %2
+}
+
+fn @main() -> i64 {
+^bb0:
+    %0 = alloca $Circle
+    %1 = alloca [i64; 3]
+    settag $Circle %0
+    %2 = field $Circle %0, 0
+    %3 = const i64 1
+    store i64 %3, %2
+    %4 = const u64 2
+    %5 = index [i64; 3] %1, %4
+    store i64 %3, %5
+    %6 = is $Shape %0
+    %7 = call @area(%0)
+    %8 = cast f64 %7 to i64
+    %9 = func @main.lambda0
+    %10 = call @apply(%9, %8)
+    %11 = const str \"hi\\n\"
+    %12 = eq str %11, %11
+    %13 = const bool false
+    br %12, ^bb1, ^bb2(%13)
+^bb1:
+    jump ^bb2(%6)
+^bb2(%14: bool):
+    br %14, ^bb3, ^bb4(%10)
+^bb3:
+    %15 = load i64 %5
+    %16 = add i64 %10, %15
+    jump ^bb4(%16)
+^bb4(%17: i64):
+    ret %17
+}
+
+fn @main.lambda0(i64) -> i64 {
+^bb0(%0: i64):
+    %1 = neg i64 %0
+    ret %1
+}
+
+entry @main
+";
+        let module = read(text);
+        assert!(verify_module(&module).is_ok());
+        assert_eq!(print_module(&module), text);
+    }
+
+    #[test]
+    fn names_are_renumbered_when_printed() {
+        let text = "fn @f(i64) -> i64 {\n^start(%x: i64):\n    jump ^next(%x)\n^next(%y: i64):\n    ret %y\n}\n";
+        let module = read(text);
+        assert_eq!(print_module(&module), "fn @f(i64) -> i64 {\n^bb0(%0: i64):\n    jump ^bb1(%0)\n^bb1(%1: i64):\n    ret %1\n}\n");
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert_eq!(syntax_errors("fn %0 -> i64 {\n"), ["Expected a function name, found '%0'."]);
+        assert_eq!(syntax_errors("struct { i64 }\n"), ["Expected a struct name, found '{'."]);
+        assert_eq!(syntax_errors("fn @f() -> i64 {\n^bb0:\n    %0 = const i64 0\n}\n"), ["Expected an instruction, found '}'."]);
+        assert_eq!(syntax_errors("fn @f() -> i64 {\n^bb0:\n    %0 = call @g()\n    ret %0\n}\n"), ["'@g' is not defined."]);
+        assert_eq!(syntax_errors("fn @f(i64, bool) -> i64 {\n^bb0(%0: i64, %1: bool):\n    %2 = add i64 %0, %1\n    ret %2\n}\n"),
+            ["The type written here is not the type of the value."]);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::Write;
+
+use crate::typecheck::ConstValue;
+use crate::ir::ir::{Module, Function, Type, InstKind, Terminator, BlockTarget, ValueId};
+
+
+/// Prints a module in the IR's text syntax, which `parse_module` reads back. Blocks and values are numbered
+/// in the order they are printed, so printing a parsed module gives the same text whatever names it used.
+pub fn print_module(module: &Module) -> String {
+    let mut out = String::new();
+    for def in &module.structs {
+        write!(out, "struct {}", global('$', &def.name)).unwrap();
+        if let Some(parent) = def.parent {
+            write!(out, " : {}", global('$', &module.struct_def(parent).name)).unwrap();
+        }
+        if def.tagged {
+            out.push_str(" tagged");
+        }
+        let fields: Vec<String> = def.fields.iter().map(|field| type_name(module, field)).collect();
+        writeln!(out, " {{ {} }}", fields.join(", ")).unwrap();
+    }
+    for function in &module.functions {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        print_function(module, function, &mut out);
+    }
+    if let Some(entry) = module.entry {
+        writeln!(out, "\nentry {}", global('@', &module.function(entry).name)).unwrap();
+    }
+    return out;
+}
+
+/// The numbers the values of a function are printed with, in the order of their definitions: the parameters
+/// of each block, then the results of its instructions.
+pub fn value_numbers(function: &Function) -> Vec<Option<u32>> {
+    let mut numbers = vec![None; function.values.len()];
+    let mut next = 0;
+    for block in &function.blocks {
+        let results = block.insts.iter().filter_map(|inst| function.inst(*inst).result);
+        for value in block.params.iter().copied().chain(results) {
+            numbers[value.0 as usize] = Some(next);
+            next += 1;
+        }
+    }
+    return numbers;
+}
+
+/// How a value is printed. A value that no block defines, which only invalid IR uses, is shown by its id.
+pub fn value_name(numbers: &[Option<u32>], value: ValueId) -> String {
+    match numbers.get(value.0 as usize).copied().flatten() {
+        Some(number) => format!("%{}", number),
+        None => format!("%<undefined {}>", value.0)
+    }
+}
+
+pub fn type_name(module: &Module, typ: &Type) -> String {
+    match typ {
+        Type::Primitive(p) => String::from(p.name()),
+        Type::Ptr => String::from("ptr"),
+        Type::Struct(id) => global('$', &module.struct_def(*id).name),
+        Type::Array(element, length) => format!("[{}; {}]", type_name(module, element), length)
+    }
+}
+
+/// The name of a function or struct with its sigil, quoted when it has characters other than letters, digits,
+/// underscores and dots, as the names of generic instances do.
+pub fn global(sigil: char, name: &str) -> String {
+    let bare = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+    return if bare { format!("{}{}", sigil, name) } else { format!("{}{:?}", sigil, name) };
+}
+
+pub fn constant(value: &ConstValue) -> String {
+    match value {
+        ConstValue::Integer(n) => n.to_string(),
+        ConstValue::Float(f) if f.is_nan() => String::from("nan"),
+        ConstValue::Float(f) if f.is_infinite() => String::from(if *f > 0.0 { "inf" } else { "-inf" }),
+        ConstValue::Float(f) => format!("{:?}", f),
+        ConstValue::Bool(b) => b.to_string(),
+        ConstValue::Char(c) => format!("{:?}", c),
+        ConstValue::Str(s) => format!("{:?}", s)
+    }
+}
+
+fn print_function(module: &Module, function: &Function, out: &mut String) {
+    let numbers = value_numbers(function);
+    let name = |value: &ValueId| value_name(&numbers, *value);
+    let params: Vec<String> = function.params.iter().map(|typ| type_name(module, typ)).collect();
+    writeln!(out, "fn {}({}) -> {} {{", global('@', &function.name), params.join(", "), type_name(module, &function.ret)).unwrap();
+    for (index, block) in function.blocks.iter().enumerate() {
+        write!(out, "^bb{}", index).unwrap();
+        if !block.params.is_empty() {
+            let params: Vec<String> = block.params.iter().map(|param| format!("{}: {}", name(param), type_name(module, function.typ(*param)))).collect();
+            write!(out, "({})", params.join(", ")).unwrap();
+        }
+        out.push_str(":\n");
+        for inst in &block.insts {
+            let inst = function.inst(*inst);
+            out.push_str("    ");
+            if let Some(result) = inst.result {
+                write!(out, "{} = ", name(&result)).unwrap();
+            }
+            let typ = |value: &ValueId| type_name(module, function.typ(*value));
+            let args = |args: &[ValueId]| args.iter().map(name).collect::<Vec<_>>().join(", ");
+            let result_type = || type_name(module, &inst.result.map_or(Type::UNIT, |result| function.typ(result).clone()));
+            match &inst.kind {
+                InstKind::Const(value) => write!(out, "const {} {}", result_type(), constant(value)),
+                InstKind::Unary(op, operand) => write!(out, "{} {} {}", op.name(), typ(operand), name(operand)),
+                InstKind::Binary(op, left, right) => write!(out, "{} {} {}, {}", op.name(), typ(left), name(left), name(right)),
+                InstKind::Cast(operand) => write!(out, "cast {} {} to {}", typ(operand), name(operand), result_type()),
+                InstKind::Call(callee, arguments) => write!(out, "call {}({})", global('@', &module.function(*callee).name), args(arguments)),
+                InstKind::CallIndirect(callee, arguments) => write!(out, "call_indirect {} {}({})", result_type(), name(callee), args(arguments)),
+                InstKind::Function(callee) => write!(out, "func {}", global('@', &module.function(*callee).name)),
+                InstKind::Alloca(alloc) => write!(out, "alloca {}", type_name(module, alloc)),
+                InstKind::Load(ptr) => write!(out, "load {} {}", result_type(), name(ptr)),
+                InstKind::Store { value, ptr } => write!(out, "store {} {}, {}", typ(value), name(value), name(ptr)),
+                InstKind::Copy { typ: copied, dest, src } => write!(out, "copy {} {}, {}", type_name(module, copied), name(dest), name(src)),
+                InstKind::Field { typ: owner, ptr, index } => write!(out, "field {} {}, {}", global('$', &module.struct_def(*owner).name), name(ptr), index),
+                InstKind::Index { typ: array, ptr, index } => write!(out, "index {} {}, {}", type_name(module, array), name(ptr), name(index)),
+                InstKind::SetTag(tag, ptr) => write!(out, "settag {} {}", global('$', &module.struct_def(*tag).name), name(ptr)),
+                InstKind::Is(ptr, target) => write!(out, "is {} {}", global('$', &module.struct_def(*target).name), name(ptr)),
+                InstKind::Downcast(ptr, target) => write!(out, "downcast {} {}", global('$', &module.struct_def(*target).name), name(ptr))
+            }.unwrap();
+            out.push('\n');
+        }
+        let target = |target: &BlockTarget| if target.args.is_empty() {
+            format!("^bb{}", target.block.0)
+        } else {
+            format!("^bb{}({})", target.block.0, target.args.iter().map(name).collect::<Vec<_>>().join(", "))
+        };
+        match &block.terminator {
+            Terminator::Jump(to) => writeln!(out, "    jump {}", target(to)),
+            Terminator::Branch(condition, then, otherwise) => writeln!(out, "    br {}, {}, {}", name(condition), target(then), target(otherwise)),
+            Terminator::Return(Some(value)) => writeln!(out, "    ret {}", name(value)),
+            Terminator::Return(None) => writeln!(out, "    ret"),
+            Terminator::Unreachable => writeln!(out, "    unreachable")
+        }.unwrap();
+    }
+    out.push_str("}\n");
+}
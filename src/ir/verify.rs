@@ -0,0 +1,434 @@
+use std::collections::HashSet;
+
+use crate::source::Span;
+use crate::error::ErrorSet;
+use crate::typecheck::{ConstValue, Primitive};
+use crate::ir::error::IrError;
+use crate::ir::dominators::Dominators;
+use crate::ir::printer::{value_numbers, value_name, type_name, global};
+use crate::ir::ir::{Module, Function, Type, InstKind, Terminator, BlockTarget, BlockId, InstId, ValueId, ValueDef, UnaryOp, BinaryOp, StructId};
+
+
+/// Checks that a module follows the rules of the IR: that every value is defined once and is used only where
+/// its definition dominates the use, that the operands of instructions and terminators have the types they
+/// need, and that every block ends in a terminator whose branches pass their targets the right arguments.
+pub fn verify_module(module: &Module) -> Result<(), ErrorSet<IrError>> {
+    let mut errors = ErrorSet::new();
+    verify_structs(module, &mut errors);
+    if let Some(entry) = module.entry {
+        let function = module.function(entry);
+        let returns_integer = function.ret.primitive().is_some_and(|p| p.is_integer() || p == Primitive::Unit);
+        if !function.params.is_empty() || !returns_integer {
+            errors.add_error(IrError::Invalid {
+                function: global('@', &function.name),
+                message: String::from("the entry function must take nothing and return an integer or unit."),
+                loc: function.loc
+            });
+        }
+    }
+    for function in &module.functions {
+        let numbers = value_numbers(function);
+        let mut verifier = Verifier { module, function, dominators: Dominators::new(function), numbers, errors: &mut errors };
+        verifier.function();
+    }
+    return if errors.is_empty() { Ok(()) } else { Err(errors) };
+}
+
+/// Checks that each struct's fields start with its parent's, that a struct is tagged exactly when it is part
+/// of a hierarchy, and that no struct contains itself.
+fn verify_structs(module: &Module, errors: &mut ErrorSet<IrError>) {
+    let loc = module.functions.first().map(|f| f.loc);
+    let mut invalid = |name: &str, message: &str| {
+        // Structs have no spans of their own, so errors about them point at the first function.
+        if let Some(loc) = loc {
+            errors.add_error(IrError::Invalid { function: global('$', name), message: String::from(message), loc });
+        }
+    };
+    for (index, def) in module.structs.iter().enumerate() {
+        let id = StructId(index as u32);
+        if let Some(parent) = def.parent {
+            let parent_def = module.struct_def(parent);
+            if !def.fields.starts_with(&parent_def.fields) {
+                invalid(&def.name, "the fields of a struct must start with those of its parent.");
+            }
+            if !def.tagged || !parent_def.tagged {
+                invalid(&def.name, "a struct with a parent, and its parent, must be tagged.");
+            }
+            if module.is_substruct(parent, id) {
+                invalid(&def.name, "the struct is its own ancestor.");
+                continue;
+            }
+        }
+        if contains(module, &Type::Struct(id), id, &mut HashSet::new()) {
+            invalid(&def.name, "the struct contains itself.");
+        }
+    }
+}
+
+/// Whether a value of type `typ` holds a struct of type `target` somewhere inside it.
+fn contains(module: &Module, typ: &Type, target: StructId, visited: &mut HashSet<StructId>) -> bool {
+    match typ {
+        Type::Struct(id) => {
+            if !visited.insert(*id) {
+                return false;
+            }
+            return module.struct_def(*id).fields.iter().any(|field| field == &Type::Struct(target) || contains(module, field, target, visited));
+        },
+        Type::Array(element, _) => **element == Type::Struct(target) || contains(module, element, target, visited),
+        Type::Primitive(_) | Type::Ptr => false
+    }
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    function: &'a Function,
+    dominators: Dominators,
+    numbers: Vec<Option<u32>>,
+    errors: &'a mut ErrorSet<IrError>
+}
+
+impl<'a> Verifier<'a> {
+    fn name(&self) -> String {
+        global('@', &self.function.name)
+    }
+
+    fn invalid(&mut self, message: String, loc: Span) {
+        self.errors.add_error(IrError::Invalid { function: self.name(), message, loc });
+    }
+
+    fn type_name(&self, typ: &Type) -> String {
+        type_name(self.module, typ)
+    }
+
+    fn expect(&mut self, value: ValueId, expected: &Type, loc: Span) {
+        let found = self.function.typ(value);
+        if found != expected {
+            self.errors.add_error(IrError::TypeMismatch {
+                function: self.name(), expected: self.type_name(expected), found: self.type_name(found), loc
+            });
+        }
+    }
+
+    /// Where each value is defined, as its block and its position there: 0 for a parameter, and one more than
+    /// the index of the instruction for a result.
+    fn definitions(&mut self) -> Vec<Option<(BlockId, usize)>> {
+        let function = self.function;
+        let mut sites = vec![None; function.values.len()];
+        let mut placed = HashSet::new();
+        for block in function.block_ids() {
+            for (index, param) in function.block(block).params.iter().enumerate() {
+                if function.value(*param).def != ValueDef::Param(block, index) || sites[param.0 as usize].is_some() {
+                    self.invalid(format!("the parameter {} of ^bb{} is defined elsewhere.", value_name(&self.numbers, *param), block.0), function.loc);
+                }
+                sites[param.0 as usize] = Some((block, 0));
+            }
+            for (position, inst) in function.block(block).insts.iter().enumerate() {
+                let data = function.inst(*inst);
+                if !placed.insert(*inst) {
+                    self.invalid(String::from("an instruction is placed more than once."), data.loc);
+                }
+                if let Some(result) = data.result {
+                    if function.value(result).def != ValueDef::Inst(*inst) {
+                        self.invalid(format!("the result {} is defined elsewhere.", value_name(&self.numbers, result)), data.loc);
+                    }
+                    sites[result.0 as usize] = Some((block, position + 1));
+                }
+            }
+        }
+        return sites;
+    }
+
+    fn function(&mut self) {
+        let function = self.function;
+        if function.blocks.is_empty() {
+            self.invalid(String::from("a function must have an entry block."), function.loc);
+            return;
+        }
+        let entry_types: Vec<&Type> = function.block(Function::ENTRY).params.iter().map(|param| function.typ(*param)).collect();
+        if entry_types != function.params.iter().collect::<Vec<_>>() {
+            self.invalid(String::from("the parameters of the entry block must be those of the function."), function.loc);
+        }
+        for typ in &function.params {
+            if !typ.is_scalar() {
+                self.invalid(format!("a parameter cannot have the type {}.", self.type_name(typ)), function.loc);
+            }
+        }
+        if !function.ret.is_scalar() && function.ret != Type::UNIT {
+            self.invalid(format!("a function cannot return the type {}.", self.type_name(&function.ret)), function.loc);
+        }
+
+        let sites = self.definitions();
+        for block in function.block_ids() {
+            for param in &function.block(block).params {
+                if !function.typ(*param).is_scalar() {
+                    let typ = self.type_name(function.typ(*param));
+                    self.invalid(format!("a block parameter cannot have the type {}.", typ), function.block(block).terminator_loc);
+                }
+            }
+            for (position, inst) in function.block(block).insts.iter().enumerate() {
+                let loc = function.inst(*inst).loc;
+                for operand in function.inst(*inst).kind.operands() {
+                    self.check_use(&sites, operand, block, position + 1, loc);
+                }
+                if matches!(function.inst(*inst).kind, InstKind::Alloca(_)) && block != Function::ENTRY {
+                    self.invalid(String::from("an alloca must be in the entry block."), loc);
+                }
+                self.inst(*inst);
+            }
+            let end = function.block(block).insts.len() + 1;
+            let loc = function.block(block).terminator_loc;
+            for operand in function.block(block).terminator.operands() {
+                self.check_use(&sites, operand, block, end, loc);
+            }
+            self.terminator(block);
+        }
+    }
+
+    /// Checks that a value is defined, and that its definition dominates a use at the given position of a
+    /// block. Uses in unreachable blocks can never run, so only need the value to be defined.
+    fn check_use(&mut self, sites: &[Option<(BlockId, usize)>], value: ValueId, block: BlockId, position: usize, loc: Span) {
+        let Some((def_block, def_position)) = sites.get(value.0 as usize).copied().flatten() else {
+            self.invalid(format!("{} is not defined by any block.", value_name(&self.numbers, value)), loc);
+            return;
+        };
+        if !self.dominators.is_reachable(block) {
+            return;
+        }
+        let dominated = if def_block == block { def_position < position } else { self.dominators.dominates(def_block, block) };
+        if !dominated {
+            self.errors.add_error(IrError::NotDominated { function: self.name(), value: value_name(&self.numbers, value), loc });
+        }
+    }
+
+    fn inst(&mut self, id: InstId) {
+        let function = self.function;
+        let inst = function.inst(id);
+        let loc = inst.loc;
+        let result = inst.result.map(|result| function.typ(result).clone());
+        let expected_result = match &inst.kind {
+            InstKind::Const(value) => {
+                let fits = match (value, result.as_ref().and_then(Type::primitive)) {
+                    (ConstValue::Integer(n), Some(p)) if p.is_integer() => {
+                        let (min, max) = p.integer_range().unwrap();
+                        min <= *n && *n <= max
+                    },
+                    (ConstValue::Float(_), Some(p)) => p.is_float(),
+                    (ConstValue::Bool(_), Some(p)) => p == Primitive::Bool,
+                    (ConstValue::Char(_), Some(p)) => p == Primitive::Char,
+                    (ConstValue::Str(_), Some(p)) => p == Primitive::Str,
+                    _ => false
+                };
+                if !fits {
+                    self.invalid(String::from("the constant does not have the type of its result."), loc);
+                }
+                return;
+            },
+            InstKind::Unary(op, operand) => {
+                let typ = function.typ(*operand).clone();
+                let allowed = match (op, typ.primitive()) {
+                    (UnaryOp::Neg, Some(p)) => p.is_numeric(),
+                    (UnaryOp::Not, Some(p)) => p == Primitive::Bool,
+                    _ => false
+                };
+                if !allowed {
+                    self.invalid(format!("'{}' cannot be applied to a value of type {}.", op.name(), self.type_name(&typ)), loc);
+                }
+                Some(typ)
+            },
+            InstKind::Binary(op, left, right) => {
+                let typ = function.typ(*left).clone();
+                self.expect(*right, &typ, loc);
+                let allowed = match (op, typ.primitive()) {
+                    (BinaryOp::Eq | BinaryOp::Ne, Some(p)) => p != Primitive::Unit,
+                    (BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge, Some(p)) => p.is_numeric() || p == Primitive::Char,
+                    (_, Some(p)) => p.is_numeric(),
+                    (_, None) => false
+                };
+                if !allowed {
+                    self.invalid(format!("'{}' cannot be applied to values of type {}.", op.name(), self.type_name(&typ)), loc);
+                }
+                Some(if op.is_comparison() { Type::BOOL } else { typ })
+            },
+            InstKind::Cast(operand) => {
+                let castable = |typ: Option<Primitive>| typ.is_some_and(|p| p.is_numeric() || p == Primitive::Bool || p == Primitive::Char);
+                let from = function.typ(*operand).primitive();
+                if !castable(from) || !castable(result.as_ref().and_then(Type::primitive)) || result == Some(Type::BOOL) {
+                    self.invalid(String::from("'cast' only converts between numbers, chars and bools, and not to bools."), loc);
+                }
+                return;
+            },
+            InstKind::Call(callee, arguments) => {
+                let callee = self.module.function(*callee);
+                self.arguments(&callee.params, arguments, loc);
+                if callee.ret == Type::UNIT { None } else { Some(callee.ret.clone()) }
+            },
+            InstKind::CallIndirect(callee, arguments) => {
+                self.expect(*callee, &Type::Ptr, loc);
+                for argument in arguments {
+                    if !function.typ(*argument).is_scalar() {
+                        self.invalid(String::from("an argument must be a scalar."), loc);
+                    }
+                }
+                if result.as_ref().is_some_and(|typ| !typ.is_scalar()) {
+                    self.invalid(String::from("a call's result must be a scalar."), loc);
+                }
+                return;
+            },
+            InstKind::Function(_) | InstKind::Alloca(_) => Some(Type::Ptr),
+            InstKind::Load(ptr) => {
+                self.expect(*ptr, &Type::Ptr, loc);
+                if !result.as_ref().is_some_and(Type::is_scalar) {
+                    self.invalid(String::from("'load' can only read a scalar."), loc);
+                }
+                return;
+            },
+            InstKind::Store { value, ptr } => {
+                self.expect(*ptr, &Type::Ptr, loc);
+                if !function.typ(*value).is_scalar() {
+                    self.invalid(String::from("'store' can only write a scalar."), loc);
+                }
+                None
+            },
+            InstKind::Copy { typ, dest, src } => {
+                self.expect(*dest, &Type::Ptr, loc);
+                self.expect(*src, &Type::Ptr, loc);
+                if !typ.is_aggregate() {
+                    self.invalid(String::from("'copy' only copies structs and arrays."), loc);
+                }
+                None
+            },
+            InstKind::Field { typ, ptr, index } => {
+                self.expect(*ptr, &Type::Ptr, loc);
+                if *index >= self.module.struct_def(*typ).fields.len() {
+                    self.invalid(format!("{} has no field {}.", global('$', &self.module.struct_def(*typ).name), index), loc);
+                }
+                Some(Type::Ptr)
+            },
+            InstKind::Index { typ, ptr, index } => {
+                self.expect(*ptr, &Type::Ptr, loc);
+                self.expect(*index, &Type::U64, loc);
+                if !matches!(typ, Type::Array(..)) {
+                    self.invalid(String::from("'index' needs an array type."), loc);
+                }
+                Some(Type::Ptr)
+            },
+            InstKind::SetTag(target, ptr) | InstKind::Is(ptr, target) | InstKind::Downcast(ptr, target) => {
+                self.expect(*ptr, &Type::Ptr, loc);
+                if !self.module.struct_def(*target).tagged {
+                    self.invalid(format!("{} is not tagged.", global('$', &self.module.struct_def(*target).name)), loc);
+                }
+                match inst.kind {
+                    InstKind::SetTag(..) => None,
+                    InstKind::Is(..) => Some(Type::BOOL),
+                    _ => Some(Type::Ptr)
+                }
+            }
+        };
+        if result != expected_result {
+            let describe = |typ: &Option<Type>| typ.as_ref().map_or(String::from("no result"), |typ| self.type_name(typ));
+            self.invalid(format!("the instruction should have {}, but has {}.", describe(&expected_result), describe(&result)), loc);
+        }
+    }
+
+    fn arguments(&mut self, params: &[Type], arguments: &[ValueId], loc: Span) {
+        if params.len() != arguments.len() {
+            self.invalid(format!("expected {} argument{}, found {}.", params.len(), if params.len() == 1 { "" } else { "s" }, arguments.len()), loc);
+            return;
+        }
+        for (param, argument) in params.iter().zip(arguments) {
+            self.expect(*argument, param, loc);
+        }
+    }
+
+    fn target(&mut self, target: &BlockTarget, loc: Span) {
+        if target.block == Function::ENTRY {
+            self.invalid(String::from("no block can branch to the entry block."), loc);
+        }
+        if target.block.0 as usize >= self.function.blocks.len() {
+            self.invalid(format!("^bb{} does not exist.", target.block.0), loc);
+            return;
+        }
+        let params: Vec<Type> = self.function.block(target.block).params.iter().map(|param| self.function.typ(*param).clone()).collect();
+        self.arguments(&params, &target.args, loc);
+    }
+
+    fn terminator(&mut self, block: BlockId) {
+        let function = self.function;
+        let loc = function.block(block).terminator_loc;
+        match &function.block(block).terminator {
+            Terminator::Jump(target) => self.target(target, loc),
+            Terminator::Branch(condition, then, otherwise) => {
+                self.expect(*condition, &Type::BOOL, loc);
+                self.target(then, loc);
+                self.target(otherwise, loc);
+            },
+            Terminator::Return(Some(value)) => self.expect(*value, &function.ret, loc),
+            Terminator::Return(None) => {
+                if function.ret != Type::UNIT {
+                    self.invalid(format!("the function must return a value of type {}.", self.type_name(&function.ret)), loc);
+                }
+            },
+            Terminator::Unreachable => { }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::source::{Source, SourceMap};
+    use crate::ir::parse_module;
+    use super::verify_module;
+
+    /// The messages of the errors the verifier finds in `text`, which must be read without errors.
+    fn errors(text: &str) -> Vec<String> {
+        let mut sources = SourceMap::new();
+        let file = sources.add(Source::from_string("test.ir", String::from(text)));
+        let Ok(module) = parse_module(text, file) else {
+            panic!("the IR could not be read");
+        };
+        let Err(errors) = verify_module(&module) else {
+            return Vec::new();
+        };
+        return errors.render(&sources, None).lines()
+            .filter_map(|line| line.strip_prefix("Error: "))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn accepts_valid_module() {
+        let text = "fn @f(bool) -> i64 {\n^bb0(%0: bool):\n    %1 = const i64 1\n    br %0, ^bb1, ^bb2(%1)\n^bb1:\n    jump ^bb2(%1)\n^bb2(%2: i64):\n    ret %2\n}\n";
+        assert!(errors(text).is_empty());
+    }
+
+    #[test]
+    fn rejects_use_not_dominated() {
+        let text = "fn @f(bool) -> i64 {\n^bb0(%0: bool):\n    br %0, ^bb1, ^bb2\n^bb1:\n    %1 = const i64 1\n    jump ^bb2\n^bb2:\n    ret %1\n}\n";
+        assert_eq!(errors(text), ["In @f: %1 is used where its definition does not always run first."]);
+    }
+
+    #[test]
+    fn rejects_mismatched_types() {
+        let text = "fn @f(i64) -> bool {\n^bb0(%0: i64):\n    br %0, ^bb1, ^bb1\n^bb1:\n    ret %0\n}\n";
+        assert_eq!(errors(text), ["In @f: expected a value of type bool, found i64.", "In @f: expected a value of type bool, found i64."]);
+    }
+
+    #[test]
+    fn rejects_wrong_block_arguments() {
+        let text = "fn @f(i64) -> i64 {\n^bb0(%0: i64):\n    jump ^bb1\n^bb1(%1: i64):\n    ret %1\n}\n";
+        assert_eq!(errors(text), ["In @f: expected 1 argument, found 0."]);
+    }
+
+    #[test]
+    fn rejects_entry_that_cannot_be_run() {
+        let text = "fn @main(i64) -> i64 {\n^bb0(%0: i64):\n    ret %0\n}\n\nentry @main\n";
+        assert_eq!(errors(text), ["In @main: the entry function must take nothing and return an integer or unit."]);
+    }
+
+    #[test]
+    fn rejects_struct_not_extending_parent() {
+        let text = "struct $A tagged { i64, bool }\nstruct $B : $A tagged { bool, i64 }\n\nfn @f() -> i64 {\n^bb0:\n    %0 = const i64 0\n    ret %0\n}\n";
+        assert_eq!(errors(text), ["In $B: the fields of a struct must start with those of its parent."]);
+    }
+}
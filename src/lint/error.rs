@@ -0,0 +1,49 @@
+use crate::source::Span;
+use crate::error::{CompilerError, ErrorDisplay, Severity, Fix};
+use crate::lint::registry::{Lint, LintLevel, LevelSource};
+
+
+pub struct LintError {
+    pub lint: &'static Lint,
+    pub level: LintLevel,
+    pub source: LevelSource,
+    pub message: String,
+    pub loc: Span,
+    pub fix: Option<Fix>
+}
+
+
+impl CompilerError for LintError {
+    fn render(&self, display: &mut ErrorDisplay) -> String {
+        let mut msg = display.error_with_location(self.severity(), &self.message, &self.loc);
+        msg.push_str(&display.note(&match &self.source {
+            LevelSource::Default => format!("`#[{}({})]` on by default", self.lint.default_level, self.lint.name),
+            LevelSource::CommandLine(level, name) if name == self.lint.name => {
+                format!("requested on the command line with `{} {}`", level.flag(), name)
+            },
+            LevelSource::CommandLine(level, name) => {
+                format!("`{} {}` implied by `{} {}`", level.flag(), self.lint.name, level.flag(), name)
+            },
+            LevelSource::Attribute(level) => format!("the lint level is set by `#[{}({})]`", level, self.lint.name)
+        }));
+        if let Some(fix) = &self.fix {
+            msg.push_str(&display.fix(fix));
+        }
+        return msg;
+    }
+
+    fn location(&self) -> Option<Span> {
+        Some(self.loc)
+    }
+
+    fn fix(&self) -> Option<&Fix> {
+        self.fix.as_ref()
+    }
+
+    fn severity(&self) -> Severity {
+        match self.level {
+            LintLevel::Deny => Severity::Error,
+            _ => Severity::Warning
+        }
+    }
+}
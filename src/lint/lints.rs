@@ -0,0 +1,394 @@
+use std::collections::HashSet;
+
+use crate::ast;
+use crate::source::{Span, SourceMap};
+use crate::error::{ErrorSet, Fix, Applicability};
+use crate::lint::error::LintError;
+use crate::lint::registry::*;
+use crate::flow::FlowInfo;
+use crate::visit::{Visitor, walk_file, walk_block, walk_stmt, walk_expr};
+
+
+pub fn check_program(store: &LintStore, sources: &SourceMap, program: &ast::Program, flow: &FlowInfo) -> ErrorSet<LintError> {
+    let mut context = LintContext {
+        store, sources, flow, file: None, used_names: HashSet::new(), attributes: Vec::new(), errors: ErrorSet::new()
+    };
+    context.visit_program(program);
+    return context.errors;
+}
+
+
+struct LintContext<'a> {
+    store: &'a LintStore,
+    sources: &'a SourceMap,
+    flow: &'a FlowInfo,
+    /// The file being checked, and the first segments of the names used in it outside of imports.
+    file: Option<&'a ast::File>,
+    used_names: HashSet<String>,
+    attributes: Vec<(&'static Lint, LintLevel)>,
+    errors: ErrorSet<LintError>
+}
+
+struct Local {
+    name: String,
+    loc: Span,
+    used: bool
+}
+
+impl<'a> LintContext<'a> {
+    fn emit(&mut self, lint: &'static Lint, loc: &Span, message: String, fix: Option<Fix>) {
+        let (level, source) = match self.attributes.iter().rev().find(|(l, _)| std::ptr::eq(*l, lint)) {
+            Some((_, level)) => (*level, LevelSource::Attribute(*level)),
+            None => self.store.level(lint)
+        };
+        let (level, source) = self.store.apply_warnings(level, source);
+        if level != LintLevel::Allow {
+            self.errors.add_error(LintError { lint, level, source, message, loc: *loc, fix });
+        }
+    }
+
+    fn with_attributes<F>(&mut self, attributes: &[ast::Attribute], func: F)
+        where F: FnOnce(&mut Self) {
+        let depth = self.attributes.len();
+        for attribute in attributes {
+            let level = match LintLevel::from_attribute(&attribute.name) {
+                Some(level) => level,
+                None => continue
+            };
+            for argument in &attribute.arguments {
+                match find_lint(&argument.name) {
+                    Some(lint) => self.attributes.push((lint, level)),
+                    None => self.emit(&UNKNOWN_LINTS, &argument.loc, format!("Unknown lint '{}'.", argument.name), None)
+                }
+            }
+        }
+        func(self);
+        self.attributes.truncate(depth);
+    }
+
+    fn check_import(&mut self, data: &ast::ImportData) {
+        let name = data.bound_name(self.file.unwrap());
+        if !self.used_names.contains(name) {
+            let fix = Fix::new("Remove the import.", whole_lines(self.sources, data.loc), "", Applicability::MachineApplicable);
+            self.emit(&UNUSED_IMPORTS, &data.loc, format!("Unused import '{}'.", name), Some(fix));
+        }
+    }
+
+    fn check_struct(&mut self, program: &ast::Program, id: ast::TopLevelId, data: &ast::StructData) {
+        let mut names = NameCollector { names: HashSet::new() };
+        names.visit_top_level(program, id);
+        self.check_generic_parameters(&data.generic_parameters, &names.names);
+        for (i, method) in data.methods.iter().enumerate() {
+            let item = ast::ItemId::Method(id, i as u32);
+            self.with_attributes(&method.attributes, |s| s.check_function(program, item, method, "Method"));
+        }
+    }
+
+    fn check_trait(&mut self, data: &ast::TraitData) {
+        for method in &data.methods {
+            self.check_snake_case(&method.name, method.name_loc, "Method");
+        }
+    }
+
+    /// Checks a function or method, where `kind` says which it is.
+    fn check_function(&mut self, program: &ast::Program, item: ast::ItemId, data: &ast::FunctionData, kind: &str) {
+        self.check_snake_case(&data.name, data.name_loc, kind);
+
+        let mut names = NameCollector { names: HashSet::new() };
+        names.visit_function(program, data);
+        self.check_generic_parameters(&data.generic_parameters, &names.names);
+
+        let mut locals = LocalCollector { scopes: Vec::new(), unused: Vec::new() };
+        locals.visit_block(program, &data.body);
+        self.report_unused_locals(locals.unused);
+        self.check_unreachable(program, item);
+    }
+
+    /// Checks the locals declared within a const's value, as for a function body.
+    fn check_const(&mut self, program: &ast::Program, data: &ast::ConstData) {
+        let mut locals = LocalCollector { scopes: vec![Vec::new()], unused: Vec::new() };
+        locals.visit_expr(program, data.value);
+        locals.end_scope();
+        self.report_unused_locals(locals.unused);
+    }
+
+    fn report_unused_locals(&mut self, locals: Vec<Local>) {
+        for local in locals {
+            let fix = Fix::new("If this is intentional, prefix it with an underscore.", local.loc.start(), "_", Applicability::MaybeIncorrect);
+            self.emit(&UNUSED_LOCALS, &local.loc, format!("Local variable '{}' is never used.", local.name), Some(fix));
+        }
+    }
+
+    fn check_snake_case(&mut self, name: &str, loc: Span, kind: &str) {
+        if !is_snake_case(name) {
+            let snake_name = to_snake_case(name);
+            self.emit(
+                &NON_SNAKE_CASE, &loc,
+                format!("{} '{}' should have a snake case name.", kind, name),
+                Some(Fix::new(&format!("Rename it to '{}'.", snake_name), loc, &snake_name, Applicability::MaybeIncorrect))
+            );
+        }
+    }
+
+    fn check_generic_parameters(&mut self, parameters: &[ast::GenericParameter], used_names: &HashSet<String>) {
+        for parameter in parameters {
+            if !used_names.contains(&parameter.name) {
+                self.emit(&UNUSED_GENERIC_PARAMETERS, &parameter.loc, format!("Generic parameter '{}' is never used.", parameter.name), None);
+            }
+        }
+    }
+
+    /// Reports the statements in a function's body and in its lambdas that follow a statement which always returns.
+    fn check_unreachable(&mut self, program: &ast::Program, item: ast::ItemId) {
+        let flow = match self.flow.functions.get(&item) {
+            Some(flow) => flow,
+            None => return
+        };
+        for graph in std::iter::once(&flow.body).chain(&flow.lambdas) {
+            let mut unreachable: Vec<Span> = graph.unreachable_statements().into_iter()
+                .map(|(first, last)| program.loc(first).combine(&program.loc(last)))
+                .collect();
+            unreachable.sort_by_key(|loc| loc.lo);
+            for loc in unreachable {
+                self.emit(&UNREACHABLE_CODE, &loc, String::from("Unreachable statement."), None);
+            }
+        }
+    }
+}
+
+
+/// Checks each file in turn. The checks of a declaration visit its nodes themselves, so the walk stops at
+/// the top levels.
+impl<'a> Visitor<'a> for LintContext<'a> {
+    fn visit_file(&mut self, program: &'a ast::Program, file: &'a ast::File) {
+        let mut names = NameCollector { names: HashSet::new() };
+        for id in file.top_level_ids() {
+            if !matches!(program[id], ast::TopLevelNode::Import(_)) {
+                names.visit_top_level(program, id);
+            }
+        }
+        self.file = Some(file);
+        self.used_names = names.names;
+        walk_file(self, program, file);
+    }
+
+    fn visit_top_level(&mut self, program: &'a ast::Program, id: ast::TopLevelId) {
+        match &program[id] {
+            ast::TopLevelNode::Import(data) => self.with_attributes(&data.attributes, |s| s.check_import(data)),
+            ast::TopLevelNode::Struct(data) => self.with_attributes(&data.attributes, |s| s.check_struct(program, id, data)),
+            ast::TopLevelNode::Trait(data) => self.with_attributes(&data.attributes, |s| s.check_trait(data)),
+            ast::TopLevelNode::Function(data) => self.with_attributes(&data.attributes, |s| s.check_function(program, ast::ItemId::TopLevel(id), data, "Function")),
+            ast::TopLevelNode::Const(data) => self.with_attributes(&data.attributes, |s| s.check_const(program, data))
+        }
+    }
+}
+
+/// Extends a span that is alone on its line to cover the whole line, including the line break,
+/// so that removing it does not leave a blank line behind.
+fn whole_lines(sources: &SourceMap, loc: Span) -> Span {
+    let source = sources.get(loc.file);
+    let line = source.line_of(loc.lo as usize);
+    let range = source.lines[line].clone();
+    if source.line_of(loc.hi as usize) != line || source.text[range.clone()].trim() != sources.text(loc) {
+        return loc;
+    }
+    return Span::new(loc.file, range.start, range.end);
+}
+
+fn qual_root(program: &ast::Program, name: ast::NameId) -> &str {
+    match &program[name] {
+        ast::QualifiedNameNode::Name(n) => &n.name,
+        ast::QualifiedNameNode::Namespace(n) => qual_root(program, n.source)
+    }
+}
+
+/// Collects the first segment of every name in the nodes it visits, to find the imports and generic
+/// parameters that are used.
+struct NameCollector {
+    names: HashSet<String>
+}
+
+impl<'a> Visitor<'a> for NameCollector {
+    fn visit_name(&mut self, program: &'a ast::Program, id: ast::NameId) {
+        self.names.insert(String::from(qual_root(program, id)));
+    }
+}
+
+/// Finds the locals of a function that are never read, following the scopes of its blocks. Each scope is
+/// checked when it ends, and the locals that were not used are kept in order.
+struct LocalCollector {
+    scopes: Vec<Vec<Local>>,
+    unused: Vec<Local>
+}
+
+impl LocalCollector {
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        self.unused.extend(scope.into_iter().filter(|local| !local.used && !local.name.starts_with('_')));
+    }
+}
+
+impl<'a> Visitor<'a> for LocalCollector {
+    fn visit_block(&mut self, program: &'a ast::Program, block: &'a ast::BlockData) {
+        self.scopes.push(Vec::new());
+        walk_block(self, program, block);
+        self.end_scope();
+    }
+
+    fn visit_stmt(&mut self, program: &'a ast::Program, id: ast::StmtId) {
+        match &program[id] {
+            ast::StmtNode::Let(data) => {
+                if let Some(value) = data.value {
+                    self.visit_expr(program, value);
+                }
+                self.scopes.last_mut().unwrap().push(Local { name: data.name.clone(), loc: data.name_loc, used: false });
+            },
+            ast::StmtNode::For(data) => {
+                match data.iterable {
+                    ast::ForIterable::Range(start, end) => {
+                        self.visit_expr(program, start);
+                        self.visit_expr(program, end);
+                    },
+                    ast::ForIterable::Array(array) => self.visit_expr(program, array)
+                }
+                self.scopes.push(vec![Local { name: data.name.clone(), loc: data.name_loc, used: false }]);
+                self.visit_block(program, &data.body);
+                self.end_scope();
+            },
+            _ => walk_stmt(self, program, id)
+        }
+    }
+
+    fn visit_expr(&mut self, program: &'a ast::Program, id: ast::ExprId) {
+        match &program[id] {
+            ast::ExprNode::Name(data) => {
+                if let ast::QualifiedNameNode::Name(name) = &program[data.name] {
+                    let local = self.scopes.iter_mut().rev()
+                        .flat_map(|scope| scope.iter_mut().rev())
+                        .find(|local| local.name == name.name);
+                    if let Some(local) = local {
+                        local.used = true;
+                    }
+                }
+            },
+            // Assigning to a variable is not a read of it, but a compound assignment, or an assignment to one
+            // of its fields or elements, is.
+            ast::ExprNode::Assign(data) if data.op.is_none() && matches!(program[data.target], ast::ExprNode::Name(_)) => {
+                self.visit_expr(program, data.value);
+            },
+            ast::ExprNode::Lambda(data) => {
+                // Parameters are not reported when unused, but they hide locals with the same names.
+                self.scopes.push(data.parameters.iter().map(|p| Local { name: p.name.clone(), loc: p.loc, used: true }).collect());
+                self.visit_expr(program, data.body);
+                self.scopes.pop();
+            },
+            _ => walk_expr(self, program, id)
+        }
+    }
+
+    /// Types cannot refer to locals.
+    fn visit_type(&mut self, _program: &'a ast::Program, _id: ast::TypeId) { }
+}
+
+
+fn is_snake_case(name: &str) -> bool {
+    return !name.chars().any(|c| c.is_uppercase()) && !name.trim_start_matches('_').contains("__");
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut prev_lower = false;
+    for chr in name.chars() {
+        if chr.is_uppercase() {
+            if prev_lower {
+                snake.push('_');
+            }
+            snake.extend(chr.to_lowercase());
+            prev_lower = false;
+        } else {
+            if chr == '_' && snake.ends_with('_') && !snake.trim_start_matches('_').is_empty() {
+                continue;
+            }
+            snake.push(chr);
+            prev_lower = chr.is_lowercase() || chr.is_ascii_digit();
+        }
+    }
+    return snake;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+
+    /// The messages of the warnings for a program read as if from standard input.
+    fn warnings(text: &str) -> Vec<String> {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        return result.report.lines()
+            .filter_map(|line| line.strip_prefix("Warning: "))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn unused_locals_follow_scopes() {
+        let text = "fn main() -> int {
+            let a = 1;
+            let b = 2;
+            let _c = 3;
+            for i in 0..3 { let inner = i; }
+            if a > 0 { let a = 5; }
+            let f = |b: int| b + 1;
+            let shadow = 1;
+            if true { let shadow = 2; let q = shadow; }
+            return f(1);
+        }";
+        assert_eq!(warnings(text), [
+            "Local variable 'b' is never used.",
+            "Local variable 'inner' is never used.",
+            "Local variable 'a' is never used.",
+            "Local variable 'shadow' is never used.",
+            "Local variable 'q' is never used."
+        ]);
+    }
+
+    #[test]
+    fn unused_generic_parameters() {
+        let text = "trait Show { fn show(self: Self&) -> int; }
+            struct Pair<T, U: Show> { first: T; }
+            fn id<T, U>(x: T) -> T { return x; }
+            fn bounded<T: Show>(x: T&) -> int { return x.show(); }
+            fn main() -> int { return 0; }";
+        assert_eq!(warnings(text), ["Generic parameter 'U' is never used.", "Generic parameter 'U' is never used."]);
+    }
+
+    #[test]
+    fn assigning_to_a_local_is_not_a_use() {
+        let text = "fn main() -> int {
+            let x = 0;
+            x = 1;
+            let y = 0;
+            y += 1;
+            let p = [1, 2];
+            p[0] = 3;
+            return 0;
+        }";
+        assert_eq!(warnings(text), ["Local variable 'x' is never used."]);
+    }
+
+    #[test]
+    fn const_attributes_are_checked() {
+        let text = "#[warn(no_such_lint)]
+            const LIMIT: int = 3;
+            fn main() -> int { return LIMIT; }";
+        assert_eq!(warnings(text), ["Unknown lint 'no_such_lint'."]);
+    }
+
+    #[test]
+    fn statements_after_a_return_are_unreachable() {
+        let text = "fn f(x: bool) -> int { if x { return 1; let _a = 2; } return 0; let _b = 3; }
+        fn g() -> int { while true { return 1; } return 2; }
+        fn h(x: bool) -> int { while x { return 1; } return 2; }";
+        assert_eq!(warnings(text), ["Unreachable statement.", "Unreachable statement.", "Unreachable statement."]);
+    }
+}
@@ -0,0 +1,6 @@
+mod registry;
+mod error;
+mod lints;
+
+pub use registry::{LintLevel, LintStore, describe_lints};
+pub use lints::check_program;
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny
+}
+
+impl LintLevel {
+    /// The level named by a lint attribute, such as `allow` in `#[allow(unused_locals)]`.
+    pub fn from_attribute(name: &str) -> Option<LintLevel> {
+        match name {
+            "allow" => Some(LintLevel::Allow),
+            "warn" => Some(LintLevel::Warn),
+            "deny" => Some(LintLevel::Deny),
+            _ => None
+        }
+    }
+
+    pub fn flag(&self) -> &'static str {
+        match self {
+            LintLevel::Allow => "-A",
+            LintLevel::Warn => "-W",
+            LintLevel::Deny => "-D"
+        }
+    }
+}
+
+impl Display for LintLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny"
+        })
+    }
+}
+
+
+pub struct Lint {
+    pub name: &'static str,
+    pub default_level: LintLevel,
+    pub description: &'static str
+}
+
+pub static UNUSED_LOCALS: Lint = Lint {
+    name: "unused_locals",
+    default_level: LintLevel::Warn,
+    description: "local variables that are declared but never read"
+};
+
+pub static UNUSED_IMPORTS: Lint = Lint {
+    name: "unused_imports",
+    default_level: LintLevel::Warn,
+    description: "imports whose names are never referenced"
+};
+
+pub static UNUSED_GENERIC_PARAMETERS: Lint = Lint {
+    name: "unused_generic_parameters",
+    default_level: LintLevel::Warn,
+    description: "generic parameters that no type in the item refers to"
+};
+
+pub static UNREACHABLE_CODE: Lint = Lint {
+    name: "unreachable_code",
+    default_level: LintLevel::Warn,
+    description: "statements that can never be executed because an earlier statement returns"
+};
+
+pub static NON_SNAKE_CASE: Lint = Lint {
+    name: "non_snake_case",
+    default_level: LintLevel::Warn,
+    description: "function names that are not in snake_case"
+};
+
+pub static UNKNOWN_LINTS: Lint = Lint {
+    name: "unknown_lints",
+    default_level: LintLevel::Warn,
+    description: "lint attributes that name a lint that does not exist"
+};
+
+pub static LINTS: &[&Lint] = &[
+    &UNUSED_LOCALS,
+    &UNUSED_IMPORTS,
+    &UNUSED_GENERIC_PARAMETERS,
+    &UNREACHABLE_CODE,
+    &NON_SNAKE_CASE,
+    &UNKNOWN_LINTS
+];
+
+/// The pseudo-lint that names every lint currently at the warn level, as in `-D warnings`.
+pub const WARNINGS: &str = "warnings";
+
+
+pub fn find_lint(name: &str) -> Option<&'static Lint> {
+    return LINTS.iter().find(|l| l.name == name).copied();
+}
+
+
+/// A table of every lint with its default level, as printed by `-W help`.
+pub fn describe_lints() -> String {
+    let width = LINTS.iter().map(|l| l.name.len()).max().unwrap_or(0);
+    let mut text = format!("{: <width$}  {: <7}  meaning\n", "name", "default");
+    for lint in LINTS {
+        text.push_str(&format!("{: <width$}  {: <7}  {}\n", lint.name, lint.default_level.to_string(), lint.description));
+    }
+    text.push_str(&format!("\n'{}' names every lint that is set to warn, as in '-D {}'.\n", WARNINGS, WARNINGS));
+    return text;
+}
+
+
+/// Where the level of a lint was decided, so that diagnostics can explain it.
+#[derive(Clone)]
+pub enum LevelSource {
+    Default,
+    CommandLine(LintLevel, String),
+    Attribute(LintLevel)
+}
+
+
+/// The lint levels requested on the command line, before any item-level attributes are applied.
+#[derive(Clone)]
+pub struct LintStore {
+    levels: HashMap<&'static str, (LintLevel, LevelSource)>,
+    warnings: Option<LintLevel>
+}
+
+impl LintStore {
+    pub fn new() -> LintStore {
+        LintStore { levels: HashMap::new(), warnings: None }
+    }
+
+    pub fn set_level(&mut self, name: &str, level: LintLevel) -> Result<(), String> {
+        if name == WARNINGS {
+            self.warnings = Some(level);
+            return Ok(());
+        }
+        match find_lint(name) {
+            Some(lint) => {
+                self.levels.insert(lint.name, (level, LevelSource::CommandLine(level, String::from(name))));
+                Ok(())
+            },
+            None => Err(format!("Unknown lint '{}'.", name))
+        }
+    }
+
+    pub fn level(&self, lint: &'static Lint) -> (LintLevel, LevelSource) {
+        match self.levels.get(lint.name) {
+            Some(level) => level.clone(),
+            None => (lint.default_level, LevelSource::Default)
+        }
+    }
+
+    /// Applies `-A warnings`, `-W warnings` or `-D warnings` to a lint that would otherwise warn.
+    pub fn apply_warnings(&self, level: LintLevel, source: LevelSource) -> (LintLevel, LevelSource) {
+        match (level, self.warnings) {
+            (LintLevel::Warn, Some(override_level)) => {
+                (override_level, LevelSource::CommandLine(override_level, String::from(WARNINGS)))
+            },
+            _ => (level, source)
+        }
+    }
+}
@@ -1,21 +1,355 @@
+
 mod source;
 mod ast;
+mod visit;
 mod parser;
+mod resolve;
+mod typecheck;
+mod flow;
+mod hir;
 mod compiler;
 mod error;
+mod lint;
+mod options;
+mod fix;
+mod vfs;
+mod dump;
+mod interp;
+mod bytecode;
+mod ir;
+mod opt;
+mod backend;
+
+use std::io::Read;
+use std::process::ExitCode;
+use std::time::Instant;
 
-use source::PathBuf;
 use compiler::CompilerState;
+use error::{Fix, ErrorSet};
+use options::{Options, Command, Emit, Backend, USAGE};
+use source::{PathBuf, Source, SourceMap};
+use vfs::{FileSystem, RealFileSystem, OverlayFileSystem};
+
+
+/// The number of times `adze fix` re-checks the program after applying fixes, since fixing one
+/// error can reveal another, such as lints that only run once the program parses.
+const MAX_FIX_ROUNDS: usize = 8;
+
+
+struct CheckResult {
+    state: CompilerState,
+    program: Option<ast::Program>,
+    /// The lowered program, when there were no errors.
+    hir: Option<hir::Program>,
+    report: String,
+    fixes: Vec<Fix>
+}
+
+/// The exit status of a program that stops with a run-time error, or cannot be run.
+const RUNTIME_ERROR_STATUS: u8 = 101;
+
+/// The extension of files written in the IR's text syntax rather than in the language.
+const IR_EXTENSION: &str = "ir";
+
+/// The executable `build` writes when the input file has no extension to remove, or is standard input.
+const DEFAULT_OUTPUT: &str = "a.out";
 
+/// The path that stands for standard input when it is given as the input file.
+const STDIN_PATH: &str = "-";
 
-fn main() {
-    let mut state = CompilerState::new();
-    let a = match parser::parse_program(&mut state, PathBuf::from("test.adze")) {
+/// The file system the compiler reads from. When the program is read from standard input, it is
+/// placed in an overlay as `<stdin>` in the working directory, so that its imports resolve from there.
+fn file_system(options: &Options, stdin: &Option<String>) -> (Box<dyn FileSystem>, PathBuf) {
+    match stdin {
+        Some(text) => {
+            let mut overlay = OverlayFileSystem::new(Box::new(RealFileSystem));
+            let path = overlay.add_file(&PathBuf::from("<stdin>"), text.clone());
+            (Box::new(overlay), path)
+        },
+        None => (Box::new(RealFileSystem), options.input.clone())
+    }
+}
+
+/// Runs a pass of the compiler, printing how long it took if `--time-passes` was given.
+fn timed<T, F>(options: &Options, name: &str, pass: F) -> T
+    where F: FnOnce() -> T {
+    let start = Instant::now();
+    let result = pass();
+    if options.time_passes {
+        eprintln!("{:>9}: {:9.3} ms", name, start.elapsed().as_secs_f64() * 1000.0);
+    }
+    return result;
+}
+
+/// Parses, resolves, type checks, analyses the control flow of and lints the program, then lowers it to HIR.
+/// The program is only returned when there were no hard errors.
+fn check(options: &Options, stdin: &Option<String>) -> CheckResult {
+    let (fs, input) = file_system(options, stdin);
+    let mut state = CompilerState::with_file_system(options.clone(), fs);
+    let program = match timed(options, "parse", || parser::parse_program(&mut state, input)) {
         Ok(a) => a,
         Err(errors) => {
-            print!("{}", errors); return;
+            let report = errors.render(&state.sources, options.max_errors);
+            return CheckResult { state, program: None, hir: None, report, fixes: errors.machine_applicable_fixes() };
+        }
+    };
+
+    let resolution = match timed(options, "resolve", || resolve::resolve_program(&program)) {
+        Ok(r) => r,
+        Err(errors) => {
+            let report = errors.render(&state.sources, options.max_errors);
+            return CheckResult { state, program: None, hir: None, report, fixes: errors.machine_applicable_fixes() };
+        }
+    };
+
+    let types = match timed(options, "typecheck", || typecheck::check_program(&program, &resolution)) {
+        Ok(t) => t,
+        Err(errors) => {
+            let report = errors.render(&state.sources, options.max_errors);
+            return CheckResult { state, program: None, hir: None, report, fixes: errors.machine_applicable_fixes() };
+        }
+    };
+
+    let flow = match timed(options, "flow", || flow::check_program(&program, &resolution, &types)) {
+        Ok(f) => f,
+        Err(errors) => {
+            let report = errors.render(&state.sources, options.max_errors);
+            return CheckResult { state, program: None, hir: None, report, fixes: errors.machine_applicable_fixes() };
         }
     };
 
-    println!("Hello, world! {}", state.sources.get(0).unwrap().name);
+    let lints = timed(options, "lint", || lint::check_program(&options.lints, &state.sources, &program, &flow));
+    let hir = if lints.has_errors() { None } else { Some(timed(options, "lower", || hir::lower_program(&program, &resolution, &types))) };
+    return CheckResult {
+        program: if lints.has_errors() { None } else { Some(program) },
+        hir,
+        report: lints.render(&state.sources, options.max_errors),
+        fixes: lints.machine_applicable_fixes(),
+        state
+    };
+}
+
+
+/// Prints the tokens of the input file or the parsed program, for `--dump-tokens` and `--dump-ast`.
+fn dump(options: &Options, stdin: &Option<String>) -> ExitCode {
+    let (fs, input) = file_system(options, stdin);
+    let mut state = CompilerState::with_file_system(options.clone(), fs);
+    let result = match (options.dump_tokens, options.dump_ast) {
+        (Some(format), _) => parser::lex_file(&mut state, input).map(|tokens| dump::dump_tokens(&tokens, format)),
+        (None, Some(format)) => parser::parse_program(&mut state, input).map(|program| dump::dump_program(&program, &state.sources, format)),
+        (None, None) => unreachable!()
+    };
+    match result {
+        Ok(text) => {
+            print!("{}", text);
+            return ExitCode::SUCCESS;
+        },
+        Err(errors) => {
+            print!("{}", errors.render(&state.sources, options.max_errors));
+            return ExitCode::FAILURE;
+        }
+    }
+}
+
+
+/// Runs a checked program, exiting with the low 8 bits of the integer `main` returns, as a process's exit status
+/// holds them. The program is compiled to bytecode and run on the virtual machine, unless `--interpret` was given.
+fn run(options: &Options, result: &CheckResult) -> ExitCode {
+    let hir = result.hir.as_ref().expect("a program without errors is lowered");
+    let status = interp::check_main(hir).and_then(|_| if options.interpret {
+        timed(options, "interpret", || interp::run_program(hir))
+    } else {
+        let module = timed(options, "bytecode", || bytecode::compile_program(hir));
+        timed(options, "execute", || bytecode::run_module(&module))
+    });
+    match status {
+        Ok(status) => ExitCode::from(status as u8),
+        Err(error) => {
+            print_runtime_error(result, error);
+            return ExitCode::from(RUNTIME_ERROR_STATUS);
+        }
+    }
+}
+
+/// Prints an error that stopped the program or kept it from starting, as the errors of a check are printed.
+fn print_runtime_error(result: &CheckResult, error: interp::RuntimeError) {
+    let mut errors = ErrorSet::new();
+    errors.add_error(error);
+    print!("{}", errors.render(&result.state.sources, None));
+}
+
+
+/// Lowers a checked program to the IR and optimizes it, then prints it in the form `--emit` asks for or builds it.
+fn compile(options: &Options, result: &CheckResult) -> ExitCode {
+    let hir = result.hir.as_ref().expect("a program without errors is lowered");
+    if options.command == Command::Build && options.emit.is_none() {
+        if let Err(error) = interp::check_main(hir) {
+            print_runtime_error(result, error);
+            return ExitCode::FAILURE;
+        }
+    }
+    let mut module = timed(options, "ir", || ir::lower_program(hir));
+    if let Err(errors) = timed(options, "verify", || ir::verify_module(&module)) {
+        panic!("The IR lowered from a checked program is invalid:\n{}\n{}", errors.render(&result.state.sources, None), ir::print_module(&module));
+    }
+    optimize(options, &result.state.sources, &mut module);
+    return emit(options, &result.state.sources, &module);
+}
+
+/// Prints an optimized module in the form `--emit` asks for, or builds an executable from it for `build`.
+fn emit(options: &Options, sources: &SourceMap, module: &ir::Module) -> ExitCode {
+    match options.emit {
+        Some(Emit::Ir) => print!("{}", ir::print_module(module)),
+        Some(Emit::C) => print!("{}", backend::emit_c(module, sources)),
+        Some(Emit::Asm) => print!("{}", backend::emit_asm(module, sources)),
+        None if options.command == Command::Build => return build(options, sources, module),
+        None => { }
+    }
+    return ExitCode::SUCCESS;
+}
+
+/// Compiles a module to an executable with the backend `--backend` asks for, written to the file `-o` names or
+/// to the input file without its extension.
+fn build(options: &Options, sources: &SourceMap, module: &ir::Module) -> ExitCode {
+    if module.entry.is_none() {
+        eprintln!("Error: The module has no entry function to build."); return ExitCode::FAILURE;
+    }
+    let output = options.output.clone().unwrap_or_else(|| match options.input.file_stem() {
+        Some(stem) if options.input.extension().is_some() => PathBuf::from(stem),
+        _ => PathBuf::from(DEFAULT_OUTPUT)
+    });
+    let result = match options.backend {
+        Backend::C => {
+            let code = timed(options, "emit", || backend::emit_c(module, sources));
+            timed(options, "cc", || backend::compile_c(&code, &output, options.opt_level))
+        },
+        Backend::Asm => {
+            let code = timed(options, "emit", || backend::emit_asm(module, sources));
+            timed(options, "link", || backend::assemble(&code, &output))
+        }
+    };
+    if let Err(message) = result {
+        eprintln!("Error: {}", message); return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
+}
+
+/// Optimizes a module at the level `-O` asks for, printing it after each pass if `--print-after-each` was given.
+/// In debug builds, the module is verified after each pass, so that a pass that breaks it is caught there.
+fn optimize(options: &Options, sources: &SourceMap, module: &mut ir::Module) {
+    timed(options, "optimize", || opt::optimize_module(module, options.opt_level, |pass, module, changed| {
+        if options.print_after_each {
+            if changed {
+                eprint!("// After {}:\n{}\n", pass.name, ir::print_module(module));
+            } else {
+                eprintln!("// After {}: unchanged\n", pass.name);
+            }
+        }
+        if cfg!(debug_assertions) && changed {
+            if let Err(errors) = ir::verify_module(module) {
+                panic!("The IR is invalid after {}:\n{}\n{}", pass.name, errors.render(sources, None), ir::print_module(module));
+            }
+        }
+    }));
+}
+
+/// Reads a file written in the IR's text syntax and verifies it, then prints or builds it as for a program, so
+/// that the IR and its backends can be tested without a program that lowers to it.
+fn check_ir(options: &Options) -> ExitCode {
+    let mut state = CompilerState::new(options.clone());
+    let source = match Source::from_file(&*state.fs, &options.input) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error: Could not read from file '{}': {}.", options.input.display(), e); return ExitCode::FAILURE;
+        }
+    };
+    let text = source.text.clone();
+    let file = state.sources.add(source);
+    let mut module = match timed(options, "parse", || ir::parse_module(&text, file)) {
+        Ok(module) => module,
+        Err(errors) => {
+            print!("{}", errors.render(&state.sources, options.max_errors));
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(errors) = timed(options, "verify", || ir::verify_module(&module)) {
+        print!("{}", errors.render(&state.sources, options.max_errors));
+        return ExitCode::FAILURE;
+    }
+    optimize(options, &state.sources, &mut module);
+    return emit(options, &state.sources, &module);
+}
+
+
+fn main() -> ExitCode {
+    let options = match Options::from_args(std::env::args().skip(1)) {
+        Ok(o) => o,
+        Err(msg) => {
+            eprintln!("Error: {}\n{}", msg, USAGE); return ExitCode::from(2);
+        }
+    };
+
+    if options.list_lints {
+        print!("{}", lint::describe_lints());
+        return ExitCode::SUCCESS;
+    }
+
+    let stdin = if options.input.as_os_str() == STDIN_PATH {
+        if options.command == Command::Fix {
+            eprintln!("Error: Cannot fix a program read from standard input."); return ExitCode::from(2);
+        }
+        let mut text = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut text) {
+            eprintln!("Error: Could not read from standard input: {}", e); return ExitCode::FAILURE;
+        }
+        Some(text)
+    } else {
+        None
+    };
+
+    if options.dump_tokens.is_some() || options.dump_ast.is_some() {
+        return dump(&options, &stdin);
+    }
+    if stdin.is_none() && options.input.extension().is_some_and(|extension| extension == IR_EXTENSION) {
+        if !matches!(options.command, Command::Check | Command::Build) {
+            eprintln!("Error: A file of IR can only be checked or built."); return ExitCode::from(2);
+        }
+        return check_ir(&options);
+    }
+
+    let mut result = check(&options, &stdin);
+    if options.command == Command::Fix {
+        let mut applied = 0;
+        for _ in 0..MAX_FIX_ROUNDS {
+            if result.fixes.is_empty() {
+                break;
+            }
+            match fix::apply_fixes(result.state.fs.as_ref(), &result.state.sources, &result.fixes) {
+                Ok(n) => applied += n,
+                Err(e) => {
+                    eprintln!("Error: Could not write fixes: {}", e); return ExitCode::FAILURE;
+                }
+            }
+            result = check(&options, &stdin);
+        }
+        println!("Applied {} fix{}.", applied, if applied == 1 { "" } else { "es" });
+    }
+
+    print!("{}", result.report);
+    if result.program.is_none() {
+        return ExitCode::FAILURE;
+    }
+    if options.emit.is_some() || options.command == Command::Build {
+        return compile(&options, &result);
+    }
+    match options.command {
+        Command::Run => return run(&options, &result),
+        Command::Disasm => {
+            let hir = result.hir.as_ref().expect("a program without errors is lowered");
+            print!("{}", bytecode::disassemble(&bytecode::compile_program(hir), &result.state.sources));
+            return ExitCode::SUCCESS;
+        },
+        Command::Check | Command::Fix | Command::Build => { }
+    }
+    return ExitCode::SUCCESS;
 }
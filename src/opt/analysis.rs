@@ -0,0 +1,140 @@
+use crate::ast;
+use crate::ir::{Function, InstKind, BlockId, ValueId, ValueDef, UnaryOp, BinaryOp, Type};
+use crate::typecheck::{ConstValue, fold_unary, fold_binary, fold_cast, truncate};
+
+
+/// The constant a value is defined as, if it is the result of a `const` instruction.
+pub fn constant(function: &Function, value: ValueId) -> Option<&ConstValue> {
+    match function.value(value).def {
+        ValueDef::Inst(inst) => match &function.inst(inst).kind {
+            InstKind::Const(constant) => Some(constant),
+            _ => None
+        },
+        ValueDef::Param(..) => None
+    }
+}
+
+/// Whether two constants are the same value. Floats are compared by their bits, so that a NaN is the same as
+/// itself and `0.0` is not the same as `-0.0`.
+pub fn same_constant(a: &ConstValue, b: &ConstValue) -> bool {
+    match (a, b) {
+        (ConstValue::Float(a), ConstValue::Float(b)) => a.to_bits() == b.to_bits(),
+        _ => a == b
+    }
+}
+
+/// The block each instruction is placed in, if it is placed in one.
+pub fn inst_blocks(function: &Function) -> Vec<Option<BlockId>> {
+    let mut blocks = vec![None; function.insts.len()];
+    for block in function.block_ids() {
+        for inst in &function.block(block).insts {
+            blocks[inst.0 as usize] = Some(block);
+        }
+    }
+    return blocks;
+}
+
+/// The block a value is defined in, given the blocks of the instructions.
+pub fn def_block(function: &Function, inst_blocks: &[Option<BlockId>], value: ValueId) -> Option<BlockId> {
+    match function.value(value).def {
+        ValueDef::Param(block, _) => Some(block),
+        ValueDef::Inst(inst) => inst_blocks[inst.0 as usize]
+    }
+}
+
+/// Whether an instruction can be removed when its result is unused, because it does not write to memory,
+/// call a function or stop the program.
+pub fn is_removable(function: &Function, kind: &InstKind) -> bool {
+    use InstKind::*;
+    match kind {
+        Const(_) | Unary(..) | Cast(_) | Function(_) | Alloca(_) | Load(_) | Field { .. } | Is(..) => true,
+        Binary(op, _, divisor) => !can_divide_by_zero(function, *op, *divisor),
+        Index { typ: Type::Array(_, length), index, .. } => matches!(constant(function, *index), Some(ConstValue::Integer(n)) if *n < *length as i128),
+        Index { .. } | Call(..) | CallIndirect(..) | Store { .. } | Copy { .. } | SetTag(..) | Downcast(..) => false
+    }
+}
+
+/// Whether an instruction always gives the same result for the same operands without reading memory, so
+/// that it can be replaced by an earlier copy of itself. It may still stop the program, as the earlier copy
+/// would have first.
+pub fn is_pure(kind: &InstKind) -> bool {
+    use InstKind::*;
+    match kind {
+        Const(_) | Unary(..) | Binary(..) | Cast(_) | Function(_) | Field { .. } | Index { .. } => true,
+        Alloca(_) | Load(_) | Is(..) | Downcast(..) | Call(..) | CallIndirect(..) | Store { .. } | Copy { .. } | SetTag(..) => false
+    }
+}
+
+/// Whether an integer division or remainder may be by zero, which stops the program.
+fn can_divide_by_zero(function: &Function, op: BinaryOp, divisor: ValueId) -> bool {
+    if !matches!(op, BinaryOp::Div | BinaryOp::Rem) || !function.typ(divisor).primitive().is_some_and(|p| p.is_integer()) {
+        return false;
+    }
+    return !matches!(constant(function, divisor), Some(ConstValue::Integer(n)) if *n != 0);
+}
+
+/// The constant an instruction computes when the operands it reads have the constant values `constant`
+/// gives, if it computes one: integer arithmetic wraps around as it does at run time, and division by zero
+/// has no value.
+pub fn evaluate<F>(function: &Function, kind: &InstKind, result: Option<ValueId>, constant: F) -> Option<ConstValue>
+    where F: Fn(ValueId) -> Option<ConstValue> {
+    match kind {
+        InstKind::Const(value) => Some(value.clone()),
+        InstKind::Unary(op, operand) => {
+            let typ = function.typ(*operand).primitive()?;
+            match (op, constant(*operand)?) {
+                (UnaryOp::Neg, ConstValue::Integer(n)) => Some(ConstValue::Integer(truncate(n.wrapping_neg(), typ))),
+                (UnaryOp::Neg, value) => fold_unary(ast::UnaryOp::Negate, &value, typ).ok(),
+                (UnaryOp::Not, value) => fold_unary(ast::UnaryOp::Not, &value, typ).ok()
+            }
+        },
+        InstKind::Binary(op, left, right) => {
+            let typ = function.typ(*left).primitive()?;
+            let (left, right) = (constant(*left)?, constant(*right)?);
+            if let (ConstValue::Integer(l), ConstValue::Integer(r), false) = (&left, &right, op.is_comparison()) {
+                let result = match op {
+                    BinaryOp::Add => l.wrapping_add(*r),
+                    BinaryOp::Sub => l.wrapping_sub(*r),
+                    BinaryOp::Mul => l.wrapping_mul(*r),
+                    BinaryOp::Div | BinaryOp::Rem if *r == 0 => return None,
+                    BinaryOp::Div => l / r,
+                    _ => l % r
+                };
+                return Some(ConstValue::Integer(truncate(result, typ)));
+            }
+            return fold_binary(ast_binary_op(*op), &left, &right, typ).ok();
+        },
+        InstKind::Cast(operand) => {
+            let from = function.typ(*operand).primitive()?;
+            let to = function.typ(result?).primitive()?;
+            return Some(fold_cast(&constant(*operand)?, from, to));
+        },
+        _ => None
+    }
+}
+
+fn ast_binary_op(op: BinaryOp) -> ast::BinaryOp {
+    match op {
+        BinaryOp::Add => ast::BinaryOp::Add,
+        BinaryOp::Sub => ast::BinaryOp::Subtract,
+        BinaryOp::Mul => ast::BinaryOp::Multiply,
+        BinaryOp::Div => ast::BinaryOp::Divide,
+        BinaryOp::Rem => ast::BinaryOp::Remainder,
+        BinaryOp::Eq => ast::BinaryOp::Equal,
+        BinaryOp::Ne => ast::BinaryOp::NotEqual,
+        BinaryOp::Lt => ast::BinaryOp::Less,
+        BinaryOp::Le => ast::BinaryOp::LessEqual,
+        BinaryOp::Gt => ast::BinaryOp::Greater,
+        BinaryOp::Ge => ast::BinaryOp::GreaterEqual
+    }
+}
+
+/// The number of instructions in a function, counting terminators, as a measure of its size.
+pub fn size(function: &Function) -> usize {
+    function.blocks.iter().map(|block| block.insts.len() + 1).sum()
+}
+
+/// Whether a value's type is an integer, which algebraic identities such as `x - x = 0` hold for.
+pub fn is_integer(function: &Function, value: ValueId) -> bool {
+    function.typ(value).primitive().is_some_and(|p| p.is_integer())
+}
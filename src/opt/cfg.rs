@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::ir::{Function, Block, Terminator, BlockId};
+use crate::typecheck::ConstValue;
+use crate::opt::Pass;
+use crate::opt::manager::each_function;
+use crate::opt::analysis::constant;
+
+
+/// Dead block elimination and control flow cleanup: branches on constants become jumps, jumps to empty blocks
+/// go straight to where those blocks lead, blocks are merged into their only predecessor when it jumps to
+/// them, and the blocks that can no longer be reached are removed.
+pub static SIMPLIFY_CFG: Pass = Pass { name: "simplify-cfg", run: |module| each_function(module, simplify) };
+
+
+fn simplify(function: &mut Function) -> bool {
+    let mut changed = fold_branches(function);
+    changed |= thread_jumps(function);
+    changed |= merge_blocks(function);
+    let blocks = function.blocks.len();
+    function.remove_unreachable_blocks();
+    changed |= function.blocks.len() != blocks;
+    changed |= function.remove_trivial_params();
+    return changed;
+}
+
+/// Turns branches on a constant, and branches that go the same way either way, into jumps.
+fn fold_branches(function: &mut Function) -> bool {
+    let mut changed = false;
+    for block in function.block_ids() {
+        let Terminator::Branch(condition, then, otherwise) = &function.block(block).terminator else {
+            continue;
+        };
+        let target = match constant(function, *condition) {
+            Some(ConstValue::Bool(true)) => then.clone(),
+            Some(ConstValue::Bool(false)) => otherwise.clone(),
+            _ if then.block == otherwise.block && then.args == otherwise.args => then.clone(),
+            _ => continue
+        };
+        let loc = function.block(block).terminator_loc;
+        function.set_terminator(block, Terminator::Jump(target), loc);
+        changed = true;
+    }
+    return changed;
+}
+
+/// Points branches to a block that only jumps somewhere else, without parameters or instructions, at where it
+/// jumps to instead. The arguments of that jump are defined before the empty block, so they are also defined
+/// before every branch to it.
+fn thread_jumps(function: &mut Function) -> bool {
+    let mut forwards = HashMap::new();
+    for block in function.block_ids().skip(1) {
+        let data = function.block(block);
+        if let (true, true, Terminator::Jump(target)) = (data.params.is_empty(), data.insts.is_empty(), &data.terminator) {
+            if target.block != block {
+                forwards.insert(block, target.clone());
+            }
+        }
+    }
+    let mut changed = false;
+    for block in function.block_ids() {
+        let mut terminator = function.block(block).terminator.clone();
+        for target in terminator.targets_mut() {
+            // A cycle of empty blocks is followed once around at most.
+            let mut steps = 0;
+            while let Some(forward) = forwards.get(&target.block).filter(|_| steps < forwards.len()) {
+                *target = forward.clone();
+                steps += 1;
+                changed = true;
+            }
+        }
+        function.block_mut(block).terminator = terminator;
+    }
+    return changed;
+}
+
+/// Merges each block into the block before it, when that is its only predecessor and jumps to it.
+fn merge_blocks(function: &mut Function) -> bool {
+    let mut changed = false;
+    let mut predecessors = function.predecessors();
+    for block in function.block_ids() {
+        while let Terminator::Jump(target) = &function.block(block).terminator {
+            let next = target.block;
+            if next == block || next == Function::ENTRY || predecessors[next.0 as usize] != [block] {
+                break;
+            }
+            let args = target.args.clone();
+            let replacements: HashMap<_, _> = function.block(next).params.iter().copied().zip(args).collect();
+            let empty = empty_block(function, next);
+            let merged = std::mem::replace(function.block_mut(next), empty);
+            function.block_mut(block).insts.extend(merged.insts);
+            function.set_terminator(block, merged.terminator, merged.terminator_loc);
+            for successor in function.block(block).terminator.successors() {
+                for predecessor in &mut predecessors[successor.0 as usize] {
+                    if *predecessor == next {
+                        *predecessor = block;
+                    }
+                }
+            }
+            predecessors[next.0 as usize].clear();
+            function.replace_uses(&replacements);
+            changed = true;
+        }
+    }
+    return changed;
+}
+
+/// A block with nothing in it, to leave in place of a block that was merged into another until it is removed.
+fn empty_block(function: &Function, block: BlockId) -> Block {
+    Block { params: Vec::new(), insts: Vec::new(), terminator: Terminator::Unreachable, terminator_loc: function.block(block).terminator_loc }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use crate::ir::{Function, InstKind, ValueId, BlockId, FuncId, StructId, UnaryOp, BinaryOp, Type, Dominators};
+use crate::typecheck::ConstValue;
+use crate::opt::Pass;
+use crate::opt::manager::each_function;
+use crate::opt::analysis::is_pure;
+
+
+/// Common subexpression elimination: a pure instruction that computes the same thing as one in a block that
+/// dominates it is replaced by that instruction's result. Blocks are visited down the dominator tree, keeping
+/// the instructions of the blocks above the current one.
+pub static CSE: Pass = Pass { name: "cse", run: |module| each_function(module, eliminate) };
+
+/// What a pure instruction computes, which two instructions are the same in when they have the same key.
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum Key {
+    Const(Type, ConstKey),
+    Unary(UnaryOp, ValueId),
+    Binary(BinaryOp, ValueId, ValueId),
+    Cast(ValueId, Type),
+    Function(FuncId),
+    Field(StructId, ValueId, usize),
+    Index(Type, ValueId, ValueId)
+}
+
+/// A constant, with floats held as their bits so that constants can be hashed.
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum ConstKey {
+    Integer(i128),
+    Float(u64),
+    Bool(bool),
+    Char(char),
+    Str(String)
+}
+
+
+fn eliminate(function: &mut Function) -> bool {
+    let dominators = Dominators::new(function);
+    let mut children: Vec<Vec<BlockId>> = vec![Vec::new(); function.blocks.len()];
+    for block in dominators.reverse_postorder() {
+        if let Some(parent) = dominators.idom(block) {
+            children[parent.0 as usize].push(block);
+        }
+    }
+
+    let mut available: HashMap<Key, ValueId> = HashMap::new();
+    let mut replacements: HashMap<ValueId, ValueId> = HashMap::new();
+    // Each block is pushed to be entered, then again under its keys, to be left once its children are done.
+    let mut stack = vec![(Function::ENTRY, None)];
+    while let Some((block, added)) = stack.pop() {
+        if let Some(added) = added {
+            for key in added {
+                available.remove(&key);
+            }
+            continue;
+        }
+        let mut added: Vec<Key> = Vec::new();
+        for inst in function.block(block).insts.clone() {
+            for operand in function.inst_mut(inst).kind.operands_mut() {
+                if let Some(replacement) = replacements.get(operand) {
+                    *operand = *replacement;
+                }
+            }
+            let (kind, Some(result)) = (&function.inst(inst).kind, function.inst(inst).result) else {
+                continue;
+            };
+            if !is_pure(kind) {
+                continue;
+            }
+            let key = key(kind, function.typ(result));
+            match available.get(&key) {
+                Some(existing) => { replacements.insert(result, *existing); },
+                None => {
+                    available.insert(key.clone(), result);
+                    added.push(key);
+                }
+            }
+        }
+        stack.push((block, Some(added)));
+        for child in children[block.0 as usize].iter().rev() {
+            stack.push((*child, None));
+        }
+    }
+    function.replace_uses(&replacements);
+    return !replacements.is_empty();
+}
+
+/// The key of a pure instruction with a result of the given type. The operands of commutative operators are
+/// put in order, so that `a + b` and `b + a` are the same.
+fn key(kind: &InstKind, typ: &Type) -> Key {
+    match kind {
+        InstKind::Const(value) => Key::Const(typ.clone(), match value {
+            ConstValue::Integer(n) => ConstKey::Integer(*n),
+            ConstValue::Float(f) => ConstKey::Float(f.to_bits()),
+            ConstValue::Bool(b) => ConstKey::Bool(*b),
+            ConstValue::Char(c) => ConstKey::Char(*c),
+            ConstValue::Str(s) => ConstKey::Str(s.clone())
+        }),
+        InstKind::Unary(op, operand) => Key::Unary(*op, *operand),
+        InstKind::Binary(op, left, right) => {
+            let commutative = matches!(op, BinaryOp::Add | BinaryOp::Mul | BinaryOp::Eq | BinaryOp::Ne);
+            let (left, right) = if commutative && right < left { (right, left) } else { (left, right) };
+            Key::Binary(*op, *left, *right)
+        },
+        InstKind::Cast(operand) => Key::Cast(*operand, typ.clone()),
+        InstKind::Function(function) => Key::Function(*function),
+        InstKind::Field { typ, ptr, index } => Key::Field(*typ, *ptr, *index),
+        InstKind::Index { typ, ptr, index } => Key::Index(typ.clone(), *ptr, *index),
+        _ => unreachable!("only pure instructions have keys")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::manager::run_pass;
+    use super::CSE;
+
+    #[test]
+    fn reuses_values_but_not_loads_across_stores() {
+        let before = "\
+fn @f(i64, i64, ptr) -> i64 {
+^bb0(%0: i64, %1: i64, %2: ptr):
+    %3 = add i64 %0, %1
+    %4 = add i64 %0, %1
+    %5 = load i64 %2
+    store i64 %3, %2
+    %6 = load i64 %2
+    %7 = mul i64 %3, %4
+    %8 = add i64 %7, %5
+    %9 = add i64 %8, %6
+    ret %9
+}
+";
+        let after = "\
+fn @f(i64, i64, ptr) -> i64 {
+^bb0(%0: i64, %1: i64, %2: ptr):
+    %3 = add i64 %0, %1
+    %4 = add i64 %0, %1
+    %5 = load i64 %2
+    store i64 %3, %2
+    %6 = load i64 %2
+    %7 = mul i64 %3, %3
+    %8 = add i64 %7, %5
+    %9 = add i64 %8, %6
+    ret %9
+}
+";
+        assert_eq!(run_pass(&CSE, before), after);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::ir::{Module, Function, InstKind, Terminator, FuncId, ValueDef};
+use crate::opt::Pass;
+use crate::opt::analysis::is_removable;
+
+
+/// Dead code elimination: removes the instructions and block parameters whose values are never needed, and
+/// the functions the entry function can never reach.
+pub static DCE: Pass = Pass { name: "dce", run: dce_module };
+
+
+fn dce_module(module: &mut Module) -> bool {
+    let mut changed = remove_unused_functions(module);
+    for function in &mut module.functions {
+        changed |= remove_dead_code(function);
+    }
+    return changed;
+}
+
+/// Removes the instructions that are removable and whose results are not needed, and the parameters of
+/// blocks other than the entry block that are only passed along to themselves or to other unneeded values.
+fn remove_dead_code(function: &mut Function) -> bool {
+    let mut live_values = vec![false; function.values.len()];
+    let mut live_insts = vec![false; function.insts.len()];
+    let mut worklist = Vec::new();
+    for block in function.block_ids() {
+        for inst in &function.block(block).insts {
+            if !is_removable(function, &function.inst(*inst).kind) {
+                live_insts[inst.0 as usize] = true;
+                worklist.extend(function.inst(*inst).kind.operands());
+            }
+        }
+        match &function.block(block).terminator {
+            Terminator::Branch(condition, ..) => worklist.push(*condition),
+            Terminator::Return(Some(value)) => worklist.push(*value),
+            _ => { }
+        }
+    }
+    while let Some(value) = worklist.pop() {
+        if live_values[value.0 as usize] {
+            continue;
+        }
+        live_values[value.0 as usize] = true;
+        match function.value(value).def {
+            ValueDef::Inst(inst) => {
+                live_insts[inst.0 as usize] = true;
+                worklist.extend(function.inst(inst).kind.operands());
+            },
+            ValueDef::Param(block, _) if block == Function::ENTRY => { },
+            ValueDef::Param(block, index) => {
+                for other in &function.blocks {
+                    for target in other.terminator.targets() {
+                        if target.block == block {
+                            worklist.push(target.args[index]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut changed = false;
+    for block in function.block_ids() {
+        let count = function.block(block).insts.len();
+        function.block_mut(block).insts.retain(|inst| live_insts[inst.0 as usize]);
+        changed |= function.block(block).insts.len() != count;
+        if block == Function::ENTRY {
+            continue;
+        }
+        for index in (0..function.block(block).params.len()).rev() {
+            if !live_values[function.block(block).params[index].0 as usize] {
+                function.remove_param(block, index);
+                changed = true;
+            }
+        }
+    }
+    return changed;
+}
+
+/// Removes the functions that the entry function never calls or takes a pointer to, directly or through other
+/// functions. A module without an entry function is a library, whose functions are all kept.
+fn remove_unused_functions(module: &mut Module) -> bool {
+    let Some(entry) = module.entry else {
+        return false;
+    };
+    let mut used = vec![false; module.functions.len()];
+    let mut worklist = vec![entry];
+    while let Some(id) = worklist.pop() {
+        if used[id.0 as usize] {
+            continue;
+        }
+        used[id.0 as usize] = true;
+        let function = module.function(id);
+        for block in &function.blocks {
+            for inst in &block.insts {
+                if let InstKind::Call(callee, _) | InstKind::Function(callee) = function.inst(*inst).kind {
+                    worklist.push(callee);
+                }
+            }
+        }
+    }
+    if used.iter().all(|used| *used) {
+        return false;
+    }
+
+    let mut numbers = vec![None; module.functions.len()];
+    let mut next = 0;
+    for (index, used) in used.iter().enumerate() {
+        if *used {
+            numbers[index] = Some(FuncId(next));
+            next += 1;
+        }
+    }
+    let mut index = 0;
+    module.functions.retain(|_| {
+        index += 1;
+        return used[index - 1];
+    });
+    for function in &mut module.functions {
+        for inst in &mut function.insts {
+            if let InstKind::Call(callee, _) | InstKind::Function(callee) = &mut inst.kind {
+                // Instructions that were removed from their blocks may still name removed functions.
+                *callee = numbers[callee.0 as usize].unwrap_or(FuncId(0));
+            }
+        }
+    }
+    module.entry = numbers[entry.0 as usize];
+    return true;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::manager::run_pass;
+    use super::DCE;
+
+    #[test]
+    fn removes_unused_functions() {
+        let before = "\
+fn @unused() -> i64 {
+^bb0:
+    %0 = const i64 1
+    ret %0
+}
+
+fn @main() -> i64 {
+^bb0:
+    %0 = const i64 6
+    %1 = const i64 2
+    %2 = mul i64 %0, %1
+    %3 = call @side()
+    ret %1
+}
+
+fn @side() -> i64 {
+^bb0:
+    %0 = const i64 0
+    ret %0
+}
+
+entry @main
+";
+        let after = "\
+fn @main() -> i64 {
+^bb0:
+    %0 = const i64 2
+    %1 = call @side()
+    ret %0
+}
+
+fn @side() -> i64 {
+^bb0:
+    %0 = const i64 0
+    ret %0
+}
+
+entry @main
+";
+        assert_eq!(run_pass(&DCE, before), after);
+    }
+
+    #[test]
+    fn keeps_division_that_may_trap() {
+        let before = "\
+fn @f(i64, i64) -> i64 {
+^bb0(%0: i64, %1: i64):
+    %2 = const i64 2
+    %3 = div i64 %0, %2
+    %4 = div i64 %0, %1
+    %5 = add i64 %0, %1
+    ret %5
+}
+";
+        let after = "\
+fn @f(i64, i64) -> i64 {
+^bb0(%0: i64, %1: i64):
+    %2 = div i64 %0, %1
+    %3 = add i64 %0, %1
+    ret %3
+}
+";
+        assert_eq!(run_pass(&DCE, before), after);
+    }
+}
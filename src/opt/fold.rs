@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use crate::ir::{Module, Function, InstKind, InstId, ValueId, ValueDef, UnaryOp, BinaryOp, Type};
+use crate::typecheck::ConstValue;
+use crate::opt::Pass;
+use crate::opt::manager::each_function;
+use crate::opt::analysis::{constant, evaluate, is_integer};
+
+
+/// Constant folding and propagation: instructions whose operands are constants become constants, which the
+/// instructions that use them may then be folded with in turn. Instructions that give back one of their
+/// operands, such as adding zero, are replaced by it, and calls of a known function pointer become direct.
+pub static FOLD: Pass = Pass { name: "fold", run: fold_module };
+
+/// What an instruction can be replaced with.
+enum Simplified {
+    Value(ValueId),
+    Constant(ConstValue)
+}
+
+
+fn fold_module(module: &mut Module) -> bool {
+    let signatures: Vec<(Vec<Type>, Type)> = module.functions.iter().map(|function| (function.params.clone(), function.ret.clone())).collect();
+    return each_function(module, |function| fold_function(function, &signatures));
+}
+
+fn fold_function(function: &mut Function, signatures: &[(Vec<Type>, Type)]) -> bool {
+    let mut replacements: HashMap<ValueId, ValueId> = HashMap::new();
+    let mut changed = false;
+    for block in function.reverse_postorder() {
+        for inst in function.block(block).insts.clone() {
+            for operand in function.inst_mut(inst).kind.operands_mut() {
+                while let Some(replacement) = replacements.get(operand) {
+                    *operand = *replacement;
+                }
+            }
+            let Some(result) = function.inst(inst).result else {
+                continue;
+            };
+            if matches!(function.inst(inst).kind, InstKind::Const(_)) {
+                continue;
+            }
+            let value = evaluate(function, &function.inst(inst).kind, Some(result), |value| constant(function, value).cloned());
+            let simplified = value.map(Simplified::Constant).or_else(|| simplify(function, inst));
+            match simplified {
+                Some(Simplified::Constant(value)) => function.inst_mut(inst).kind = InstKind::Const(value),
+                Some(Simplified::Value(value)) => { replacements.insert(result, value); },
+                None => {
+                    changed |= make_direct(function, inst, signatures);
+                    continue;
+                }
+            }
+            changed = true;
+        }
+    }
+    function.replace_uses(&replacements);
+    return changed;
+}
+
+/// Applies an algebraic identity to an instruction whose operands are not all constants.
+fn simplify(function: &Function, inst: InstId) -> Option<Simplified> {
+    let is = |value: ValueId, n: i128| matches!(constant(function, value), Some(ConstValue::Integer(m)) if *m == n);
+    let is_bool = |value: ValueId, b: bool| matches!(constant(function, value), Some(ConstValue::Bool(c)) if *c == b);
+    let result = function.inst(inst).result?;
+    match function.inst(inst).kind {
+        InstKind::Unary(UnaryOp::Not, operand) => match function.value(operand).def {
+            ValueDef::Inst(inner) => match function.inst(inner).kind {
+                InstKind::Unary(UnaryOp::Not, value) => Some(Simplified::Value(value)),
+                _ => None
+            },
+            ValueDef::Param(..) => None
+        },
+        InstKind::Cast(operand) if function.typ(operand) == function.typ(result) => Some(Simplified::Value(operand)),
+        InstKind::Binary(op, left, right) if is_integer(function, left) => {
+            use BinaryOp::*;
+            match op {
+                Add if is(right, 0) => Some(Simplified::Value(left)),
+                Add if is(left, 0) => Some(Simplified::Value(right)),
+                Sub if is(right, 0) => Some(Simplified::Value(left)),
+                Mul | Div if is(right, 1) => Some(Simplified::Value(left)),
+                Mul if is(left, 1) => Some(Simplified::Value(right)),
+                Mul if is(left, 0) || is(right, 0) => Some(Simplified::Constant(ConstValue::Integer(0))),
+                Rem if is(right, 1) => Some(Simplified::Constant(ConstValue::Integer(0))),
+                Sub if left == right => Some(Simplified::Constant(ConstValue::Integer(0))),
+                Eq | Le | Ge if left == right => Some(Simplified::Constant(ConstValue::Bool(true))),
+                Ne | Lt | Gt if left == right => Some(Simplified::Constant(ConstValue::Bool(false))),
+                _ => None
+            }
+        },
+        InstKind::Binary(op, left, right) if *function.typ(left) == Type::BOOL => match op {
+            BinaryOp::Eq if is_bool(right, true) => Some(Simplified::Value(left)),
+            BinaryOp::Eq if is_bool(left, true) => Some(Simplified::Value(right)),
+            BinaryOp::Ne if is_bool(right, false) => Some(Simplified::Value(left)),
+            BinaryOp::Ne if is_bool(left, false) => Some(Simplified::Value(right)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Turns a call of a function pointer that is known to point to a function of the right signature into a
+/// direct call of the function.
+fn make_direct(function: &mut Function, inst: InstId, signatures: &[(Vec<Type>, Type)]) -> bool {
+    let InstKind::CallIndirect(callee, args) = &function.inst(inst).kind else {
+        return false;
+    };
+    let target = match function.value(*callee).def {
+        ValueDef::Inst(def) => match function.inst(def).kind {
+            InstKind::Function(target) => target,
+            _ => return false
+        },
+        ValueDef::Param(..) => return false
+    };
+    let (params, ret) = &signatures[target.0 as usize];
+    let result = function.inst(inst).result.map(|value| function.typ(value));
+    let matches = params.len() == args.len()
+        && params.iter().zip(args).all(|(param, arg)| param == function.typ(*arg))
+        && (result == Some(ret) || result.is_none() && *ret == Type::UNIT);
+    if !matches {
+        return false;
+    }
+    let args = args.clone();
+    function.inst_mut(inst).kind = InstKind::Call(target, args);
+    return true;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::manager::run_pass;
+    use super::FOLD;
+
+    #[test]
+    fn folds_constants_and_identities() {
+        let before = "\
+fn @f(i64) -> i64 {
+^bb0(%0: i64):
+    %1 = const i64 2
+    %2 = const i64 3
+    %3 = mul i64 %1, %2
+    %4 = add i64 %0, %3
+    %5 = const i64 0
+    %6 = add i64 %4, %5
+    %7 = const i64 1
+    %8 = mul i64 %6, %7
+    ret %8
+}
+
+";
+        let after = "\
+fn @f(i64) -> i64 {
+^bb0(%0: i64):
+    %1 = const i64 2
+    %2 = const i64 3
+    %3 = const i64 6
+    %4 = add i64 %0, %3
+    %5 = const i64 0
+    %6 = add i64 %4, %5
+    %7 = const i64 1
+    %8 = mul i64 %4, %7
+    ret %4
+}
+";
+        assert_eq!(run_pass(&FOLD, before), after);
+    }
+
+    #[test]
+    fn wraps_integers() {
+        let before = "\
+fn @g() -> i8 {
+^bb0:
+    %0 = const i8 100
+    %1 = add i8 %0, %0
+    ret %1
+}
+";
+        let after = "\
+fn @g() -> i8 {
+^bb0:
+    %0 = const i8 100
+    %1 = const i8 -56
+    ret %1
+}
+";
+        assert_eq!(run_pass(&FOLD, before), after);
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::ir::{Module, Function, InstKind, Terminator, BlockTarget, BlockId, FuncId, InstId, ValueId};
+use crate::opt::Pass;
+use crate::opt::analysis::size;
+
+
+/// Inlining: calls of small functions are replaced by a copy of the function's body. The block of the call is
+/// split in two at the call, the part before it jumps to the copy of the entry block with the arguments, and
+/// each return of the copy jumps to the part after it with the returned value.
+pub static INLINE: Pass = Pass { name: "inline", run: inline_module };
+
+/// The largest function, counted by `analysis::size`, that is inlined.
+const MAX_CALLEE_SIZE: usize = 32;
+
+/// The size a function stops having calls inlined into it at, which also bounds how often functions that call
+/// each other can be inlined into one another.
+const MAX_CALLER_SIZE: usize = 2000;
+
+
+fn inline_module(module: &mut Module) -> bool {
+    let mut changed = false;
+    for caller in 0..module.functions.len() {
+        while size(&module.functions[caller]) < MAX_CALLER_SIZE {
+            let Some((block, index, callee)) = find_call_site(module, FuncId(caller as u32)) else {
+                break;
+            };
+            let callee = module.function(callee).clone();
+            inline_call(&mut module.functions[caller], block, index, &callee);
+            changed = true;
+        }
+    }
+    return changed;
+}
+
+/// The first call in a function of a function that can be inlined into it, as its block, its index in the
+/// block and the function it calls.
+fn find_call_site(module: &Module, caller: FuncId) -> Option<(BlockId, usize, FuncId)> {
+    let function = module.function(caller);
+    for block in function.block_ids() {
+        for (index, inst) in function.block(block).insts.iter().enumerate() {
+            if let InstKind::Call(callee, _) = function.inst(*inst).kind {
+                if callee != caller && can_inline(module, callee) {
+                    return Some((block, index, callee));
+                }
+            }
+        }
+    }
+    return None;
+}
+
+/// Whether a function is small enough to inline, does not call itself, and does not let the memory it allocates
+/// outlive it: once inlined into a loop, the same memory is used for every call rather than new memory for
+/// each, which only goes unnoticed when nothing keeps a pointer to it from one call to the next.
+fn can_inline(module: &Module, id: FuncId) -> bool {
+    let function = module.function(id);
+    if size(function) > MAX_CALLEE_SIZE {
+        return false;
+    }
+    let mut memory: Vec<ValueId> = Vec::new();
+    for block in &function.blocks {
+        for inst in &block.insts {
+            match &function.inst(*inst).kind {
+                InstKind::Call(callee, _) if *callee == id => return false,
+                InstKind::Alloca(_) => memory.extend(function.inst(*inst).result),
+                _ => { }
+            }
+        }
+    }
+    return !memory.iter().any(|ptr| function.escapes(*ptr));
+}
+
+/// Replaces the call at the given index of a block with the body of the function it calls.
+fn inline_call(caller: &mut Function, block: BlockId, index: usize, callee: &Function) {
+    let call = caller.block(block).insts[index];
+    let InstKind::Call(_, args) = caller.inst(call).kind.clone() else {
+        unreachable!("only calls are inlined")
+    };
+    let loc = caller.inst(call).loc;
+
+    // The instructions after the call, and the block's terminator, move to a new block that the copy of the
+    // callee returns to.
+    let after = caller.add_block(loc);
+    let rest = caller.block_mut(block).insts.split_off(index + 1);
+    caller.block_mut(block).insts.pop();
+    let terminator = std::mem::replace(&mut caller.block_mut(block).terminator, Terminator::Unreachable);
+    let terminator_loc = caller.block(block).terminator_loc;
+    caller.block_mut(after).insts = rest;
+    caller.set_terminator(after, terminator, terminator_loc);
+    if let Some(result) = caller.inst(call).result {
+        let typ = caller.typ(result).clone();
+        let param = caller.add_param(after, typ);
+        caller.replace_uses(&HashMap::from([(result, param)]));
+    }
+
+    let blocks: Vec<BlockId> = callee.blocks.iter().map(|block| caller.add_block(block.terminator_loc)).collect();
+    let mut values: Vec<Option<ValueId>> = vec![None; callee.values.len()];
+    for (from, to) in callee.block_ids().zip(&blocks) {
+        for param in &callee.block(from).params {
+            values[param.0 as usize] = Some(caller.add_param(*to, callee.typ(*param).clone()));
+        }
+    }
+    let mut copies: Vec<InstId> = Vec::new();
+    let mut allocas = 0;
+    for (from, to) in callee.block_ids().zip(&blocks) {
+        for inst in &callee.block(from).insts {
+            let inst = callee.inst(*inst);
+            let copy = caller.create_inst(inst.kind.clone(), inst.result.map(|value| callee.typ(value).clone()), inst.loc);
+            if let Some(result) = inst.result {
+                values[result.0 as usize] = caller.inst(copy).result;
+            }
+            // Memory is allocated in the entry block of the function it belongs to.
+            if matches!(inst.kind, InstKind::Alloca(_)) {
+                caller.block_mut(Function::ENTRY).insts.insert(allocas, copy);
+                allocas += 1;
+            } else {
+                caller.block_mut(*to).insts.push(copy);
+            }
+            copies.push(copy);
+        }
+    }
+    let value = |value: ValueId| values[value.0 as usize].expect("the callee defines the values it uses");
+    for copy in copies {
+        for operand in caller.inst_mut(copy).kind.operands_mut() {
+            *operand = value(*operand);
+        }
+    }
+    for (from, to) in callee.block_ids().zip(&blocks) {
+        let mut terminator = match &callee.block(from).terminator {
+            Terminator::Return(returned) => Terminator::Jump(BlockTarget { block: after, args: returned.iter().map(|v| value(*v)).collect() }),
+            terminator => terminator.clone()
+        };
+        if !matches!(callee.block(from).terminator, Terminator::Return(_)) {
+            for operand in terminator.operands_mut() {
+                *operand = value(*operand);
+            }
+            for target in terminator.targets_mut() {
+                target.block = blocks[target.block.0 as usize];
+            }
+        }
+        caller.set_terminator(*to, terminator, callee.block(from).terminator_loc);
+    }
+    let entry = BlockTarget { block: blocks[Function::ENTRY.0 as usize], args };
+    caller.set_terminator(block, Terminator::Jump(entry), loc);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::manager::run_pass;
+    use super::INLINE;
+
+    #[test]
+    fn inlines_small_functions() {
+        let before = "\
+fn @square(i64) -> i64 {
+^bb0(%0: i64):
+    %1 = mul i64 %0, %0
+    ret %1
+}
+
+fn @main() -> i64 {
+^bb0:
+    %0 = const i64 3
+    %1 = call @square(%0)
+    %2 = const i64 1
+    %3 = add i64 %1, %2
+    ret %3
+}
+
+entry @main
+";
+        let after = "\
+fn @square(i64) -> i64 {
+^bb0(%0: i64):
+    %1 = mul i64 %0, %0
+    ret %1
+}
+
+fn @main() -> i64 {
+^bb0:
+    %0 = const i64 3
+    jump ^bb2(%0)
+^bb1(%1: i64):
+    %2 = const i64 1
+    %3 = add i64 %1, %2
+    ret %3
+^bb2(%4: i64):
+    %5 = mul i64 %4, %4
+    jump ^bb1(%5)
+}
+
+entry @main
+";
+        assert_eq!(run_pass(&INLINE, before), after);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::ir::{Function, Terminator, BlockTarget, BlockId, InstId, Dominators};
+use crate::opt::Pass;
+use crate::opt::manager::each_function;
+use crate::opt::analysis::{is_pure, is_removable, inst_blocks, def_block};
+
+
+/// Loop-invariant code motion: pure instructions in a loop that cannot stop the program, and whose operands
+/// are all defined outside it, are moved to a block that runs once before the loop starts. Inner loops are
+/// done first, so that what is moved out of them can then be moved out of the loops around them.
+pub static LICM: Pass = Pass { name: "licm", run: |module| each_function(module, hoist) };
+
+/// A natural loop: the blocks that can reach one of the header's back edges without going through the header.
+struct Loop {
+    header: BlockId,
+    body: Vec<bool>
+}
+
+
+fn hoist(function: &mut Function) -> bool {
+    let mut loops = find_loops(function);
+    let mut blocks = inst_blocks(function);
+    let mut preheaders: Vec<(BlockId, BlockId)> = Vec::new();
+    let mut changed = false;
+    for index in 0..loops.len() {
+        let order = function.reverse_postorder();
+        let body = &loops[index].body;
+        let mut hoisted: Vec<InstId> = Vec::new();
+        for block in order.iter().filter(|block| body[block.0 as usize]) {
+            for inst in &function.block(*block).insts {
+                let kind = &function.inst(*inst).kind;
+                let invariant = kind.operands().iter().all(|operand| match def_block(function, &blocks, *operand) {
+                    Some(def) => !body[def.0 as usize],
+                    None => true
+                });
+                if is_pure(kind) && is_removable(function, kind) && invariant {
+                    // Marking the instruction as outside the loop makes the instructions that use it invariant too.
+                    blocks[inst.0 as usize] = None;
+                    hoisted.push(*inst);
+                }
+            }
+        }
+        if hoisted.is_empty() {
+            continue;
+        }
+
+        let header = loops[index].header;
+        let preheader = preheader(function, &loops[index]);
+        if function.blocks.len() > loops[index].body.len() {
+            preheaders.push((preheader, header));
+            for other in &mut loops {
+                let inside = other.body[header.0 as usize] && other.header != header;
+                other.body.push(inside);
+            }
+        }
+        for block in function.block_ids() {
+            function.block_mut(block).insts.retain(|inst| !hoisted.contains(inst));
+        }
+        for inst in &hoisted {
+            blocks[inst.0 as usize] = Some(preheader);
+        }
+        function.block_mut(preheader).insts.extend(hoisted);
+        changed = true;
+    }
+
+    if !preheaders.is_empty() {
+        // The new blocks are placed just before the headers of their loops, where they run.
+        let mut order = Vec::new();
+        for block in function.block_ids() {
+            if preheaders.iter().any(|(preheader, _)| *preheader == block) {
+                continue;
+            }
+            order.extend(preheaders.iter().filter(|(_, header)| *header == block).map(|(preheader, _)| *preheader));
+            order.push(block);
+        }
+        function.reorder_blocks(&order);
+    }
+    return changed;
+}
+
+/// The natural loops of a function, innermost first. The loops of back edges to the same header are merged.
+fn find_loops(function: &Function) -> Vec<Loop> {
+    let dominators = Dominators::new(function);
+    let predecessors = function.predecessors();
+    let mut loops: Vec<Loop> = Vec::new();
+    for block in dominators.reverse_postorder() {
+        for header in function.block(block).terminator.successors() {
+            if !dominators.dominates(header, block) {
+                continue;
+            }
+            let index = match loops.iter().position(|l| l.header == header) {
+                Some(index) => index,
+                None => {
+                    let mut body = vec![false; function.blocks.len()];
+                    body[header.0 as usize] = true;
+                    loops.push(Loop { header, body });
+                    loops.len() - 1
+                }
+            };
+            let body = &mut loops[index].body;
+            let mut worklist = vec![block];
+            while let Some(block) = worklist.pop() {
+                if body[block.0 as usize] || !dominators.is_reachable(block) {
+                    continue;
+                }
+                body[block.0 as usize] = true;
+                worklist.extend(&predecessors[block.0 as usize]);
+            }
+        }
+    }
+    loops.sort_by_key(|l| l.body.iter().filter(|inside| **inside).count());
+    return loops;
+}
+
+/// The block that runs just before a loop and only then, which is the one block that jumps to the header from
+/// outside the loop if there is one, and is otherwise a new block that every such branch is pointed at.
+fn preheader(function: &mut Function, l: &Loop) -> BlockId {
+    let outside: Vec<BlockId> = function.predecessors()[l.header.0 as usize].iter().copied().filter(|block| !l.body[block.0 as usize]).collect();
+    if let [only] = outside[..] {
+        if matches!(function.block(only).terminator, Terminator::Jump(_)) {
+            return only;
+        }
+    }
+    let loc = function.block(l.header).terminator_loc;
+    let preheader = function.add_block(loc);
+    let mut args = Vec::new();
+    for param in function.block(l.header).params.clone() {
+        let typ = function.typ(param).clone();
+        args.push(function.add_param(preheader, typ));
+    }
+    for block in outside {
+        for target in function.block_mut(block).terminator.targets_mut() {
+            if target.block == l.header {
+                target.block = preheader;
+            }
+        }
+    }
+    function.set_terminator(preheader, Terminator::Jump(BlockTarget { block: l.header, args }), loc);
+    return preheader;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::manager::run_pass;
+    use super::LICM;
+
+    #[test]
+    fn hoists_invariants_but_not_division() {
+        let before = "\
+fn @f(i64, i64, i64) -> i64 {
+^bb0(%0: i64, %1: i64, %2: i64):
+    %3 = const i64 0
+    jump ^bb1(%3, %3)
+^bb1(%4: i64, %5: i64):
+    %6 = lt i64 %4, %2
+    br %6, ^bb2, ^bb3
+^bb2:
+    %7 = mul i64 %0, %1
+    %8 = div i64 %0, %1
+    %9 = add i64 %7, %8
+    %10 = add i64 %5, %9
+    %11 = const i64 1
+    %12 = add i64 %4, %11
+    jump ^bb1(%12, %10)
+^bb3:
+    ret %5
+}
+";
+        let after = "\
+fn @f(i64, i64, i64) -> i64 {
+^bb0(%0: i64, %1: i64, %2: i64):
+    %3 = const i64 0
+    %4 = mul i64 %0, %1
+    %5 = const i64 1
+    jump ^bb1(%3, %3)
+^bb1(%6: i64, %7: i64):
+    %8 = lt i64 %6, %2
+    br %8, ^bb2, ^bb3
+^bb2:
+    %9 = div i64 %0, %1
+    %10 = add i64 %4, %9
+    %11 = add i64 %7, %10
+    %12 = add i64 %6, %5
+    jump ^bb1(%12, %11)
+^bb3:
+    ret %7
+}
+";
+        assert_eq!(run_pass(&LICM, before), after);
+    }
+}
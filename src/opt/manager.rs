@@ -0,0 +1,78 @@
+use crate::ir::{Module, Function};
+use crate::options::OptLevel;
+use crate::opt::{fold, sccp, dce, cfg, cse, licm, inline};
+
+
+/// An optimization pass over a module.
+pub struct Pass {
+    pub name: &'static str,
+    /// Runs the pass, returning whether it changed the module.
+    pub run: fn(&mut Module) -> bool
+}
+
+/// The passes `-O1` runs once, which clean up after lowering without moving code around.
+static O1_PASSES: &[&Pass] = &[&fold::FOLD, &cfg::SIMPLIFY_CFG, &cse::CSE, &dce::DCE];
+
+/// The passes `-O2` runs, in rounds until a round changes nothing, as each pass can give the others more to
+/// do: inlining exposes constant arguments, and folding branches away exposes blocks to merge.
+static O2_PASSES: &[&Pass] = &[&inline::INLINE, &sccp::SCCP, &fold::FOLD, &cfg::SIMPLIFY_CFG, &cse::CSE, &licm::LICM, &dce::DCE];
+
+/// The most rounds of `O2_PASSES` to run, since passes that keep changing the module back and forth would
+/// otherwise never stop.
+const MAX_ROUNDS: usize = 8;
+
+
+/// Optimizes a module, calling `after_pass` with each pass that was run, the module after it, and whether
+/// the pass changed it.
+pub fn optimize_module<F>(module: &mut Module, level: OptLevel, mut after_pass: F)
+    where F: FnMut(&Pass, &Module, bool) {
+    let mut run = |pass: &Pass, module: &mut Module| {
+        let changed = (pass.run)(module);
+        after_pass(pass, module, changed);
+        return changed;
+    };
+    match level {
+        OptLevel::O0 => { },
+        OptLevel::O1 => {
+            for pass in O1_PASSES {
+                run(pass, module);
+            }
+        },
+        OptLevel::O2 => {
+            for _ in 0..MAX_ROUNDS {
+                let mut changed = false;
+                for pass in O2_PASSES {
+                    changed |= run(pass, module);
+                }
+                if !changed {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Runs a pass that works on one function at a time over every function of a module.
+pub fn each_function<F>(module: &mut Module, mut pass: F) -> bool
+    where F: FnMut(&mut Function) -> bool {
+    let mut changed = false;
+    for function in &mut module.functions {
+        changed |= pass(function);
+    }
+    return changed;
+}
+
+/// Runs a pass over a module written in the IR's text syntax, checking that the module is valid before and
+/// after it, and prints the module the pass leaves.
+#[cfg(test)]
+pub fn run_pass(pass: &Pass, text: &str) -> String {
+    use crate::ir::{parse_module, print_module, verify_module};
+    let Ok(mut module) = parse_module(text, crate::source::FileId(0)) else {
+        panic!("the IR could not be read");
+    };
+    assert!(verify_module(&module).is_ok(), "the IR is invalid before {}", pass.name);
+    (pass.run)(&mut module);
+    let printed = print_module(&module);
+    assert!(verify_module(&module).is_ok(), "the IR is invalid after {}:\n{}", pass.name, printed);
+    return printed;
+}
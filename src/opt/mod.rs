@@ -0,0 +1,11 @@
+mod manager;
+mod analysis;
+mod fold;
+mod sccp;
+mod dce;
+mod cfg;
+mod cse;
+mod licm;
+mod inline;
+
+pub use manager::{Pass, optimize_module};
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use crate::ir::{Function, InstKind, Terminator, BlockTarget, ValueId};
+use crate::typecheck::ConstValue;
+use crate::opt::Pass;
+use crate::opt::manager::each_function;
+use crate::opt::analysis::{evaluate, same_constant};
+
+
+/// Sparse conditional constant propagation, after Wegman and Zadeck: values are assumed to be constant until
+/// shown otherwise, and blocks to never run until a branch that can be taken reaches them, so that constants
+/// are found through loops and branches that constant folding alone cannot see past.
+pub static SCCP: Pass = Pass { name: "sccp", run: |module| each_function(module, propagate) };
+
+/// What is known about a value: nothing yet, that it is always the same constant, or that it can vary.
+#[derive(Clone)]
+enum Lattice {
+    Unknown,
+    Constant(ConstValue),
+    Varying
+}
+
+impl Lattice {
+    /// What is known about a value that may be either of two values.
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Unknown, other) | (other, Lattice::Unknown) => other.clone(),
+            (Lattice::Constant(a), Lattice::Constant(b)) if same_constant(a, b) => self.clone(),
+            _ => Lattice::Varying
+        }
+    }
+
+    fn is_same(&self, other: &Lattice) -> bool {
+        match (self, other) {
+            (Lattice::Unknown, Lattice::Unknown) | (Lattice::Varying, Lattice::Varying) => true,
+            (Lattice::Constant(a), Lattice::Constant(b)) => same_constant(a, b),
+            _ => false
+        }
+    }
+}
+
+
+fn propagate(function: &mut Function) -> bool {
+    let mut values = vec![Lattice::Unknown; function.values.len()];
+    for param in &function.block(Function::ENTRY).params {
+        values[param.0 as usize] = Lattice::Varying;
+    }
+    let mut executable = vec![false; function.blocks.len()];
+    executable[Function::ENTRY.0 as usize] = true;
+    let order = function.reverse_postorder();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let lower = |values: &mut [Lattice], value: ValueId, new: &Lattice| {
+            let old = &values[value.0 as usize];
+            let met = old.meet(new);
+            if !met.is_same(old) {
+                values[value.0 as usize] = met;
+                return true;
+            }
+            return false;
+        };
+        for block in &order {
+            if !executable[block.0 as usize] {
+                continue;
+            }
+            for inst in &function.block(*block).insts {
+                let inst = function.inst(*inst);
+                let Some(result) = inst.result else {
+                    continue;
+                };
+                let new = evaluate_lattice(function, &inst.kind, result, &values);
+                changed |= lower(&mut values, result, &new);
+            }
+            let mut flow = |values: &mut [Lattice], target: &BlockTarget| {
+                let mut changed = !executable[target.block.0 as usize];
+                executable[target.block.0 as usize] = true;
+                for (param, arg) in function.block(target.block).params.iter().zip(&target.args) {
+                    let new = values[arg.0 as usize].clone();
+                    changed |= lower(values, *param, &new);
+                }
+                return changed;
+            };
+            match &function.block(*block).terminator {
+                Terminator::Jump(target) => changed |= flow(&mut values, target),
+                Terminator::Branch(condition, then, otherwise) => match &values[condition.0 as usize] {
+                    Lattice::Constant(ConstValue::Bool(true)) => changed |= flow(&mut values, then),
+                    Lattice::Constant(ConstValue::Bool(false)) => changed |= flow(&mut values, otherwise),
+                    Lattice::Unknown => { },
+                    _ => {
+                        changed |= flow(&mut values, then);
+                        changed |= flow(&mut values, otherwise);
+                    }
+                },
+                Terminator::Return(_) | Terminator::Unreachable => { }
+            }
+        }
+    }
+    return rewrite(function, &values, &executable);
+}
+
+/// What is known about the result of an instruction from what is known about its operands.
+fn evaluate_lattice(function: &Function, kind: &InstKind, result: ValueId, values: &[Lattice]) -> Lattice {
+    if !matches!(kind, InstKind::Const(_) | InstKind::Unary(..) | InstKind::Binary(..) | InstKind::Cast(_)) {
+        return Lattice::Varying;
+    }
+    let mut unknown = false;
+    for operand in kind.operands() {
+        match values[operand.0 as usize] {
+            Lattice::Varying => return Lattice::Varying,
+            Lattice::Unknown => unknown = true,
+            Lattice::Constant(_) => { }
+        }
+    }
+    if unknown {
+        return Lattice::Unknown;
+    }
+    let constant = |value: ValueId| match &values[value.0 as usize] {
+        Lattice::Constant(constant) => Some(constant.clone()),
+        _ => None
+    };
+    return match evaluate(function, kind, Some(result), constant) {
+        Some(constant) => Lattice::Constant(constant),
+        None => Lattice::Varying
+    };
+}
+
+/// Replaces the values found to be constant with constants, and the branches found to always go the same
+/// way with jumps, then removes the blocks that can no longer be reached.
+fn rewrite(function: &mut Function, values: &[Lattice], executable: &[bool]) -> bool {
+    let mut changed = false;
+    let mut replacements = HashMap::new();
+    for block in function.block_ids() {
+        if !executable[block.0 as usize] {
+            continue;
+        }
+        let mut constants = Vec::new();
+        for param in function.block(block).params.clone() {
+            if let Lattice::Constant(value) = &values[param.0 as usize] {
+                let typ = function.typ(param).clone();
+                let loc = function.block(block).terminator_loc;
+                let inst = function.create_inst(InstKind::Const(value.clone()), Some(typ), loc);
+                replacements.insert(param, function.inst(inst).result.unwrap());
+                constants.push(inst);
+            }
+        }
+        if !constants.is_empty() {
+            function.block_mut(block).insts.splice(0..0, constants);
+        }
+        for inst in function.block(block).insts.clone() {
+            let Some(result) = function.inst(inst).result else {
+                continue;
+            };
+            if matches!(function.inst(inst).kind, InstKind::Const(_)) {
+                continue;
+            }
+            if let Lattice::Constant(value) = &values[result.0 as usize] {
+                function.inst_mut(inst).kind = InstKind::Const(value.clone());
+                changed = true;
+            }
+        }
+        if let Terminator::Branch(condition, then, otherwise) = &function.block(block).terminator {
+            let target = match &values[condition.0 as usize] {
+                Lattice::Constant(ConstValue::Bool(true)) => then.clone(),
+                Lattice::Constant(ConstValue::Bool(false)) => otherwise.clone(),
+                _ => continue
+            };
+            let loc = function.block(block).terminator_loc;
+            function.set_terminator(block, Terminator::Jump(target), loc);
+            changed = true;
+        }
+    }
+    changed |= !replacements.is_empty();
+    function.replace_uses(&replacements);
+    let blocks = function.blocks.len();
+    function.remove_unreachable_blocks();
+    return changed || function.blocks.len() != blocks;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::manager::run_pass;
+    use super::SCCP;
+
+    #[test]
+    fn propagates_constants_around_loops() {
+        let before = "\
+fn @f(i64) -> i64 {
+^bb0(%0: i64):
+    %1 = const i64 1
+    jump ^bb1(%1)
+^bb1(%2: i64):
+    %3 = const i64 1
+    %4 = eq i64 %2, %3
+    br %4, ^bb2, ^bb3
+^bb2:
+    %5 = mul i64 %2, %2
+    jump ^bb1(%5)
+^bb3:
+    ret %0
+}
+";
+        let after = "\
+fn @f(i64) -> i64 {
+^bb0(%0: i64):
+    %1 = const i64 1
+    jump ^bb1(%1)
+^bb1(%2: i64):
+    %3 = const i64 1
+    %4 = const i64 1
+    %5 = const bool true
+    jump ^bb2
+^bb2:
+    %6 = const i64 1
+    jump ^bb1(%6)
+}
+";
+        assert_eq!(run_pass(&SCCP, before), after);
+    }
+}
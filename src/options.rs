@@ -0,0 +1,211 @@
+use crate::source::PathBuf;
+use crate::lint::{LintLevel, LintStore};
+use crate::dump::DumpFormat;
+
+
+pub const USAGE: &str = "Usage: adze [check|fix|run|disasm|build] [FILE|-] [-L DIR] [-W LINT] [-A LINT] [-D LINT] [-W help] [--max-errors=N] [--time-passes] [--interpret] [-O0|-O1|-O2] [--print-after-each] [--emit=ir|c|asm] [--backend=c|asm] [-o FILE] [--dump-tokens[=sexp|json]] [--dump-ast[=sexp|json]]";
+
+/// The environment variable holding library directories, searched after those given with `-L`.
+pub const LIBRARY_PATH_VARIABLE: &str = "ADZE_PATH";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Report diagnostics for the program.
+    Check,
+    /// Apply every machine-applicable fix to the files on disk, then report what is left.
+    Fix,
+    /// Check the program, then run its `main` function and exit with the integer it returns.
+    Run,
+    /// Check the program, then print the bytecode it compiles to.
+    Disasm,
+    /// Check the program, then compile it to an executable with the backend `--backend` asks for.
+    Build
+}
+
+/// What `--emit` prints instead of compiling the program further.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Emit {
+    /// The program lowered to the SSA intermediate representation, in its text syntax.
+    Ir,
+    /// The program as a C translation unit, as the C backend compiles it.
+    C,
+    /// The program as x86-64 assembly, as the assembly backend assembles it.
+    Asm
+}
+
+impl Emit {
+    pub fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "ir" => Some(Emit::Ir),
+            "c" => Some(Emit::C),
+            "asm" => Some(Emit::Asm),
+            _ => None
+        }
+    }
+}
+
+/// How `build` turns the IR into an executable.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Translate it to C and compile that with the system's C compiler.
+    C,
+    /// Translate it to x86-64 assembly and assemble and link that for Linux with the GNU binutils.
+    Asm
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "c" => Some(Backend::C),
+            "asm" => Some(Backend::Asm),
+            _ => None
+        }
+    }
+}
+
+/// How hard the IR is optimized before it is emitted.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum OptLevel {
+    /// No optimization, so that the IR follows the program as it was written.
+    O0,
+    /// Cheap passes that clean up what lowering leaves behind.
+    O1,
+    /// Every pass, including inlining, repeated while they keep finding more to do.
+    O2
+}
+
+impl OptLevel {
+    pub fn from_name(name: &str) -> Option<OptLevel> {
+        match name {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub command: Command,
+    pub input: PathBuf,
+    /// Directories searched for imports that cannot be found relative to the importing file.
+    pub library_paths: Vec<PathBuf>,
+    pub lints: LintStore,
+    pub list_lints: bool,
+    pub max_errors: Option<usize>,
+    /// Whether to print how long each pass of the compiler takes.
+    pub time_passes: bool,
+    /// Whether `run` walks the lowered program instead of compiling it to bytecode.
+    pub interpret: bool,
+    pub opt_level: OptLevel,
+    /// Whether to print the IR after every optimization pass that changes it.
+    pub print_after_each: bool,
+    /// Print the program in an intermediate or output form instead of compiling it further.
+    pub emit: Option<Emit>,
+    pub backend: Backend,
+    /// Where `build` writes the executable, which is the input file without its extension by default.
+    pub output: Option<PathBuf>,
+    /// Print the tokens of the input file instead of compiling it.
+    pub dump_tokens: Option<DumpFormat>,
+    /// Print the parsed program instead of compiling it.
+    pub dump_ast: Option<DumpFormat>
+}
+
+impl Options {
+    pub fn from_args<I>(args: I) -> Result<Options, String>
+        where I: IntoIterator<Item=String> {
+        let mut input = None;
+        let mut library_paths = Vec::new();
+        let mut lints = LintStore::new();
+        let mut list_lints = false;
+        let mut max_errors = None;
+        let mut time_passes = false;
+        let mut interpret = false;
+        let mut opt_level = OptLevel::O0;
+        let mut print_after_each = false;
+        let mut emit = None;
+        let mut backend = Backend::C;
+        let mut output = None;
+        let mut dump_tokens = None;
+        let mut dump_ast = None;
+
+        let mut args = args.into_iter().peekable();
+        let command = match args.peek().map(String::as_str) {
+            Some("check") => { args.next(); Command::Check },
+            Some("fix") => { args.next(); Command::Fix },
+            Some("run") => { args.next(); Command::Run },
+            Some("disasm") => { args.next(); Command::Disasm },
+            Some("build") => { args.next(); Command::Build },
+            _ => Command::Check
+        };
+        while let Some(arg) = args.next() {
+            let level = match arg.get(..2) {
+                Some("-W") => Some(LintLevel::Warn),
+                Some("-A") => Some(LintLevel::Allow),
+                Some("-D") => Some(LintLevel::Deny),
+                _ => None
+            };
+            if let Some(directory) = arg.strip_prefix("-L") {
+                let directory = if !directory.is_empty() {
+                    String::from(directory)
+                } else {
+                    args.next().ok_or_else(|| String::from("Expected a directory after '-L'."))?
+                };
+                library_paths.push(PathBuf::from(directory));
+            } else if let Some(level) = level {
+                let name = if arg.len() > 2 {
+                    String::from(&arg[2..])
+                } else {
+                    args.next().ok_or_else(|| format!("Expected a lint name after '{}'.", arg))?
+                };
+                if name == "help" {
+                    list_lints = true;
+                } else {
+                    lints.set_level(&name, level)?;
+                }
+            } else if let Some(value) = arg.strip_prefix("--max-errors=") {
+                max_errors = Some(value.parse::<usize>().map_err(|_| format!("Invalid error count '{}'.", value))?);
+            } else if arg == "--time-passes" {
+                time_passes = true;
+            } else if arg == "--interpret" {
+                interpret = true;
+            } else if let Some(level) = arg.strip_prefix("-O") {
+                opt_level = OptLevel::from_name(level).ok_or_else(|| format!("Unknown optimization level '{}', expected 0, 1 or 2.", level))?;
+            } else if arg == "--print-after-each" {
+                print_after_each = true;
+            } else if let Some(name) = arg.strip_prefix("--emit=") {
+                emit = Some(Emit::from_name(name).ok_or_else(|| format!("Unknown output '{}', expected 'ir', 'c' or 'asm'.", name))?);
+            } else if let Some(name) = arg.strip_prefix("--backend=") {
+                backend = Backend::from_name(name).ok_or_else(|| format!("Unknown backend '{}', expected 'c' or 'asm'.", name))?;
+            } else if arg == "-o" {
+                output = Some(PathBuf::from(args.next().ok_or_else(|| String::from("Expected a file after '-o'."))?));
+            } else if let Some(format) = arg.strip_prefix("--dump-tokens") {
+                dump_tokens = Some(dump_format(&arg, format)?);
+            } else if let Some(format) = arg.strip_prefix("--dump-ast") {
+                dump_ast = Some(dump_format(&arg, format)?);
+            } else if arg.starts_with('-') && arg != "-" {
+                return Err(format!("Unknown option '{}'.", arg));
+            } else if input.is_none() {
+                input = Some(PathBuf::from(arg));
+            } else {
+                return Err(format!("Unexpected argument '{}'.", arg));
+            }
+        }
+
+        if let Some(adze_path) = std::env::var_os(LIBRARY_PATH_VARIABLE) {
+            library_paths.extend(std::env::split_paths(&adze_path));
+        }
+
+        Ok(Options { command, input: input.unwrap_or_else(|| PathBuf::from("test.adze")), library_paths, lints, list_lints, max_errors, time_passes, interpret, opt_level, print_after_each, emit, backend, output, dump_tokens, dump_ast })
+    }
+}
+
+/// Parses the `=FORMAT` that may follow a dump option, which is S-expressions when it is left out.
+fn dump_format(arg: &str, suffix: &str) -> Result<DumpFormat, String> {
+    match suffix.strip_prefix('=') {
+        None if suffix.is_empty() => Ok(DumpFormat::Sexp),
+        Some(name) => DumpFormat::from_name(name).ok_or_else(|| format!("Unknown dump format '{}', expected 'sexp' or 'json'.", name)),
+        None => Err(format!("Unknown option '{}'.", arg))
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::Write;
+use std::time::Instant;
+
+use crate::compiler::CompilerState;
+use crate::options::Options;
+use crate::source::Path;
+use crate::vfs::OverlayFileSystem;
+use super::parse_program;
+
+
+/// The number of functions in the generated file, which makes it about 360k lines long.
+const FUNCTIONS: usize = 20000;
+
+/// The number of times the file is parsed. The median time is reported.
+const RUNS: usize = 7;
+
+/// Generates a program of `functions` functions, each using most kinds of statement and expression.
+fn generate(functions: usize) -> String {
+    let mut text = String::from("struct Point {\n    x: int;\n    y: int;\n\n    fn sum(self: Self&) -> int {\n        return self.x + self.y;\n    }\n}\n");
+    for i in 0..functions {
+        write!(text, "
+fn f{i}(a: int, b: int, p: Point&) -> int {{
+    let total = a * {i} + b - (a % 7) * 3;
+    let values = [a, b, {i}, a + b];
+    for k in 0..4 {{
+        total += values[k] * k;
+    }}
+    let q = Point {{ x: total, y: b }};
+    if total > {i} && b < 100 || a == 3 {{
+        total -= q.sum() + p.x;
+    }} else {{
+        while total > 10 {{
+            total = total / 2;
+        }}
+    }}
+    let f = |v: int| v + {i};
+    return f(total) + p.sum();
+}}
+").unwrap();
+    }
+    text.push_str("\nfn main() -> int {\n    let p = Point { x: 1, y: 2 };\n    return f0(1, 2, p) - f0(1, 2, p);\n}\n");
+    return text;
+}
+
+/// Times the lexer and parser on a large generated file. It is ignored by default, and is run with
+/// `cargo test --release parse_large_file -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn parse_large_file() {
+    let text = generate(FUNCTIONS);
+    let mut times = Vec::new();
+    for _ in 0..RUNS {
+        let mut fs = OverlayFileSystem::in_memory();
+        let path = fs.add_file(Path::new("/bench/main.adze"), text.clone());
+        let mut state = CompilerState::with_file_system(Options::from_args(Vec::new()).unwrap(), Box::new(fs));
+        let start = Instant::now();
+        let program = parse_program(&mut state, path);
+        times.push(start.elapsed().as_secs_f64() * 1000.0);
+        assert!(program.is_ok());
+    }
+    times.sort_by(f64::total_cmp);
+    println!("parsed {} lines ({} bytes) in {:.1} ms, the median of {} runs", text.lines().count(), text.len(), times[RUNS / 2], RUNS);
+}
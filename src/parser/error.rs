@@ -1,14 +1,17 @@
-use crate::source::{Location, PathBuf};
-use crate::error::{CompilerError, ErrorDisplay};
+use crate::source::{Span, PathBuf};
+use crate::error::{CompilerError, ErrorDisplay, Severity, Fix};
 use crate::parser::lexer::TokenType;
+use crate::vfs::LoadError;
 
 
 pub enum ParseError {
-    FileNotFound(PathBuf, Location),
-    UnexpectedCharacter(char, Location),
-    UnexpectedToken { expected: TokenType, got: TokenType, loc: Location },
-    CouldNotParseLiteral(TokenType, Location),
-    WithMessage(String, Location),
+    CouldNotLoad { path: PathBuf, error: LoadError, loc: Option<Span>, searched: Vec<PathBuf> },
+    ImportCycle { chain: Vec<(String, Span)>, loc: Span },
+    UnexpectedCharacter(char, Span),
+    UnexpectedToken { expected: TokenType, got: TokenType, loc: Span },
+    CouldNotParseLiteral(TokenType, Span),
+    WithMessage(String, Span),
+    WithFix(String, Span, Fix),
 }
 
 
@@ -16,21 +19,64 @@ impl CompilerError for ParseError {
     fn render(&self, display: &mut ErrorDisplay) -> String {
         use ParseError::*;
         return match self {
-            FileNotFound(file, loc) => {
-                display.error_with_location("Error", format!("Could not read from file '{}'.", file.display()).as_str(), loc)
+            CouldNotLoad { path, error, loc, searched } => {
+                let message = format!("Could not read from file '{}': {}.", path.display(), error);
+                let mut msg = match loc {
+                    Some(loc) => display.error_with_location(Severity::Error, &message, loc),
+                    None => display.error_without_location(Severity::Error, &message)
+                };
+                for other in searched {
+                    msg.push_str(&display.note(&format!("Also looked for '{}'.", other.display())));
+                }
+                msg
+            },
+            ImportCycle { chain, loc } => {
+                let names: Vec<&str> = chain.iter().map(|(name, _)| name.as_str()).collect();
+                let message = format!("Import cycle: {} -> {}.", names.join(" -> "), names[0]);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                msg.push_str(&display.with_indent(|display| {
+                    chain.iter().enumerate().map(|(i, (name, import))| {
+                        let imported = &chain[(i + 1) % chain.len()].0;
+                        display.error_with_location(Severity::Note, &format!("'{}' imports '{}' here.", name, imported), import)
+                    }).collect::<String>()
+                }));
+                msg
             },
             UnexpectedCharacter(chr, loc) => {
-                display.error_with_location("Error", format!("Unexpected character '{}'.", chr).as_str(), loc)
+                display.error_with_location(Severity::Error, format!("Unexpected character '{}'.", chr).as_str(), loc)
             },
             UnexpectedToken { expected, got, loc } => {
-                display.error_with_location("Error", format!("Unexpected token: Got {}, expected {}.", got, expected).as_str(), loc)
+                display.error_with_location(Severity::Error, format!("Unexpected token: Got {}, expected {}.", got, expected).as_str(), loc)
             },
             CouldNotParseLiteral(as_type, loc) => {
-                display.error_with_location("Error", format!("Could not parse literal as a {}.", as_type).as_str(), loc)
+                display.error_with_location(Severity::Error, format!("Could not parse literal as a {}.", as_type).as_str(), loc)
             }
             WithMessage(msg, loc) => {
-                display.error_with_location("Error", msg, loc)
+                display.error_with_location(Severity::Error, msg, loc)
+            }
+            WithFix(msg, loc, fix) => {
+                display.error_with_location(Severity::Error, msg, loc) + &display.fix(fix)
             }
         }
     }
-}
\ No newline at end of file
+
+    fn location(&self) -> Option<Span> {
+        use ParseError::*;
+        match self {
+            CouldNotLoad { loc, .. } => *loc,
+            ImportCycle { loc, .. } => Some(*loc),
+            UnexpectedCharacter(_, loc) => Some(*loc),
+            UnexpectedToken { loc, .. } => Some(*loc),
+            CouldNotParseLiteral(_, loc) => Some(*loc),
+            WithMessage(_, loc) => Some(*loc),
+            WithFix(_, loc, _) => Some(*loc)
+        }
+    }
+
+    fn fix(&self) -> Option<&Fix> {
+        match self {
+            ParseError::WithFix(_, _, fix) => Some(fix),
+            _ => None
+        }
+    }
+}
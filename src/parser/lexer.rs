@@ -1,26 +1,36 @@
 use std::fmt::{Display, Formatter};
-use std::rc::Rc;
 use crate::error::ErrorSet;
-use crate::source::{Source, Location, HasLoc};
+use crate::source::{Source, FileId, Span, HasLoc};
 use crate::parser::error::ParseError;
 
 
-#[derive(PartialEq, Copy, Clone)]
+// The names of the variants are printed by `--dump-tokens`, so `EOF` keeps its spelling.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum TokenType {
     Integer,
+    Float,
     Identifier,
     String,
+    Char,
 
     // Keywords
     Return,
     If,
+    Else,
     For,
+    In,
     Is,
     While,
     Struct,
     Import,
     Fn,
     Trait,
+    Let,
+    Const,
+    True,
+    False,
+    As,
 
     // Symbols
     LeftAngle, RightAngle,
@@ -33,6 +43,7 @@ pub enum TokenType {
     Ampersand, VerticalBar,
     Exclamation, Question,
     Period, Comma, Semicolon, Colon,
+    Hash,
 
     // Special
     Error, EOF
@@ -44,17 +55,26 @@ impl Display for TokenType {
 
         write!(f, "{}", match self {
             Integer => "an integer literal",
+            Float => "a float literal",
             Identifier => "an identifier",
             String => "a string literal",
+            Char => "a character literal",
             Return => "'return'",
             If => "'if'",
+            Else => "'else'",
             For => "'for'",
+            In => "'in'",
             Is => "'is'",
             While => "'while'",
             Struct => "'struct'",
             Import => "'import'",
             Fn => "'fn'",
             Trait => "'trait'",
+            Let => "'let'",
+            Const => "'const'",
+            True => "'true'",
+            False => "'false'",
+            As => "'as'",
             LeftAngle => "'<'",
             RightAngle => "'>'",
             LeftParenthesis => "'('",
@@ -78,6 +98,7 @@ impl Display for TokenType {
             Comma => "','",
             Semicolon => "';'",
             Colon => "':'",
+            Hash => "'#'",
             Error => "<error>",
             EOF => "<eof>"
         })
@@ -86,88 +107,116 @@ impl Display for TokenType {
 
 #[derive(Clone)]
 pub struct Token {
-    pub loc: Location,
+    pub loc: Span,
     pub token_type: TokenType,
     pub text: String,
     pub leading_ws: bool
 }
 
 impl Token {
-    pub fn new(text: &str, token_type: TokenType, loc: Location, leading_ws: bool) -> Token {
+    pub fn new(text: &str, token_type: TokenType, loc: Span, leading_ws: bool) -> Token {
         return Token { loc, token_type, text: String::from(text), leading_ws}
     }
 
-    pub fn loc_range(&self, other: &Token) -> Location {
+    pub fn loc_range(&self, other: &Token) -> Span {
         return self.loc.combine(&other.loc);
     }
 }
 
 impl HasLoc for Token {
-    fn get_loc(&self) -> &Location {
-        return &self.loc;
+    fn get_loc(&self) -> Span {
+        return self.loc;
     }
 }
 
 
-pub fn lex_source(source: Rc<Source>) -> Result<Vec<Token>, ErrorSet<ParseError>> {
+pub fn lex_source(file: FileId, source: &Source) -> Result<Vec<Token>, ErrorSet<ParseError>> {
     use TokenType::*;
 
     let mut tokens = Vec::new();
     let mut errors = ErrorSet::new();
 
-    let characters: Vec<char> = source.text.chars().collect();
+    let characters: Vec<(usize, char)> = source.text.char_indices().collect();
+    let position = |index: usize| characters.get(index).map_or(source.text.len(), |(pos, _)| *pos);
 
     let mut index = 0;
     let mut prev_is_ws = false;
-    let mut line = 0;
-    let mut line_start = 0;
     while index < characters.len() {
-        let mut chr = characters[index];
-        if chr == '\n' {
-            line_start = index+1;
-            line += 1;
-            index += 1;
-            prev_is_ws = true;
-        } else if chr.is_ascii_whitespace() {
+        let (start_pos, chr) = characters[index];
+        if chr.is_whitespace() {
             index += 1;
             prev_is_ws = true;
         } else if chr.is_ascii_alphabetic() || chr == '_' {
-            let start = index;
-            while index < characters.len() && (chr.is_ascii_alphanumeric() || chr == '_') {
+            while index < characters.len() && (characters[index].1.is_ascii_alphanumeric() || characters[index].1 == '_') {
                 index += 1;
-                chr = characters[index];
             }
-            let loc = Location::new(Rc::clone(&source), line, start - line_start, index - start);
-            let text = &source.text[start..index];
+            let loc = Span::new(file, start_pos, position(index));
+            let text = &source.text[start_pos..position(index)];
             let token_type = match text {
                 "while"  => While,
                 "if"     => If,
+                "else"   => Else,
                 "return" => Return,
                 "trait"  => Trait,
                 "fn"     => Fn,
                 "for"    => For,
+                "in"     => In,
                 "is"     => Is,
                 "import" => Import,
                 "struct" => Struct,
+                "let"    => Let,
+                "const"  => Const,
+                "true"   => True,
+                "false"  => False,
+                "as"     => As,
                 _        => Identifier
             };
             let token = Token::new(text, token_type, loc, prev_is_ws);
             tokens.push(token);
             prev_is_ws = false;
         } else if chr.is_ascii_digit() {
-            let start = index;
-            while index < characters.len() && chr.is_numeric() {
+            let is_digit = |index: usize| characters.get(index).is_some_and(|(_, c)| c.is_ascii_digit());
+            while is_digit(index) {
                 index += 1;
-                chr = characters[index];
             }
-            let loc = Location::new(Rc::clone(&source), line, start - line_start, index - start);
-            let text = &source.text[start..index];
-            let token = Token::new(text, Integer, loc, prev_is_ws);
+            // A '.' only continues the literal when a digit follows it, so that `0..10` and `1.max` still lex.
+            let token_type = if characters.get(index).is_some_and(|(_, c)| *c == '.') && is_digit(index + 1) {
+                index += 1;
+                while is_digit(index) {
+                    index += 1;
+                }
+                Float
+            } else {
+                Integer
+            };
+            let loc = Span::new(file, start_pos, position(index));
+            let text = &source.text[start_pos..position(index)];
+            let token = Token::new(text, token_type, loc, prev_is_ws);
+            tokens.push(token);
+            prev_is_ws = false;
+        } else if chr == '"' || chr == '\'' {
+            index += 1;
+            while index < characters.len() && characters[index].1 != chr && characters[index].1 != '\n' {
+                if characters[index].1 == '\\' {
+                    index += 1;
+                }
+                index += 1;
+            }
+            let (token_type, description) = if chr == '"' { (String, "string") } else { (Char, "character") };
+            if index < characters.len() && characters[index].1 == chr {
+                index += 1;
+            } else {
+                let loc = Span::new(file, start_pos, position(index));
+                errors.add_error(ParseError::WithMessage(format!("Unterminated {} literal.", description), loc));
+            }
+            let loc = Span::new(file, start_pos, position(index));
+            let text = &source.text[start_pos..position(index)];
+            let token = Token::new(text, token_type, loc, prev_is_ws);
             tokens.push(token);
             prev_is_ws = false;
         } else {
-            let loc = Location::new(Rc::clone(&source), line, index - line_start, 1);
-            let text = &source.text[index..index+1];
+            let loc = Span::new(file, start_pos, position(index + 1));
+            let text = &source.text[start_pos..position(index + 1)];
             let token_type = match chr {
                 '<' => LeftAngle,
                 '>' => RightAngle,
@@ -192,16 +241,17 @@ pub fn lex_source(source: Rc<Source>) -> Result<Vec<Token>, ErrorSet<ParseError>
                 ',' => Comma,
                 ';' => Semicolon,
                 ':' => Colon,
+                '#' => Hash,
                 c => {
-                    errors.add_error(ParseError::UnexpectedCharacter(c, loc.clone()));
+                    errors.add_error(ParseError::UnexpectedCharacter(c, loc));
                     Error
                 }
             };
-            let token = Token::new(text, token_type, loc.clone(), prev_is_ws);
+            let token = Token::new(text, token_type, loc, prev_is_ws);
             tokens.push(token);
             index += 1;
             prev_is_ws = false;
         }
     }
     return if errors.is_empty() { Ok(tokens) } else { Err(errors) }
-}
\ No newline at end of file
+}
@@ -1,5 +1,11 @@
 mod lexer;
 mod error;
+#[allow(clippy::module_inception)]
 mod parser;
+#[cfg(test)]
+mod bench;
 
-pub use parser::parse_program;
\ No newline at end of file
+pub use parser::{parse_program, lex_file};
+#[cfg(test)]
+pub use parser::parse_text;
+pub use lexer::Token;
@@ -1,36 +1,59 @@
-use std::collections::LinkedList;
-use std::rc::Rc;
+use std::collections::{HashMap, LinkedList};
 
 use crate::compiler::CompilerState;
-use crate::source::{Source, PathBuf, HasLoc, Location};
+use crate::source::{Source, SourceMap, FileId, PathBuf, Path, HasLoc, Span};
+use crate::vfs::LoadError;
 use crate::parser::error::ParseError;
-use crate::error::ErrorSet;
+use crate::error::{ErrorSet, Fix, TextEdit, Applicability};
 use crate::ast;
 use crate::parser::lexer::{Token, TokenType, lex_source};
 
 
+/// A file waiting to be loaded, along with the import that asked for it, if any.
+struct PendingFile {
+    /// The paths the file could be at, in the order they are tried.
+    candidates: Vec<PathBuf>,
+    import: Option<Span>
+}
+
+
 pub fn parse_program(state: &mut CompilerState, start: PathBuf) -> Result<ast::Program, ErrorSet<ParseError>> {
-    // TODO better location handling for missing file
-    let mut to_visit: LinkedList<PathBuf> = LinkedList::from([start]);
+    let mut to_visit: LinkedList<PendingFile> = LinkedList::from([PendingFile { candidates: vec![start], import: None }]);
+    let mut imported_files: HashMap<Span, FileId> = HashMap::new();
 
     let mut program = ast::Program { files: Vec::new() };
     let mut errors: ErrorSet<ParseError> = ErrorSet::new();
 
-    while !to_visit.is_empty() {
-        let next = to_visit.pop_front().unwrap();
-        if state.sources.iter().any(|s| s.path.as_ref().map_or(false, |p| p == &next)) {
+    while let Some(next) = to_visit.pop_front() {
+        let path = match resolve_path(state, &next.candidates) {
+            Ok(path) => path,
+            Err(error) => {
+                errors.add_error(ParseError::CouldNotLoad {
+                    path: next.candidates[0].clone(), error, loc: next.import, searched: next.candidates[1..].to_vec()
+                });
+                continue;
+            }
+        };
+        if let Some(file) = state.sources.find_path(&path) {
+            if let Some(import) = next.import {
+                imported_files.insert(import, file);
+            }
             continue
         }
 
-        state.sources.push(match Source::from_file(next.as_path()) {
-            Some(s) => Rc::from(s),
-            None => {
+        let file = match Source::from_file(state.fs.as_ref(), &path) {
+            Ok(s) => state.sources.add(s),
+            Err(error) => {
+                errors.add_error(ParseError::CouldNotLoad { path, error, loc: next.import, searched: Vec::new() });
                 continue;
             }
-        });
-        let source = state.sources.last().unwrap();
+        };
+        if let Some(import) = next.import {
+            imported_files.insert(import, file);
+        }
+        let source = state.sources.get(file);
 
-        let tokens = match lex_source(Rc::clone(source)) {
+        let tokens = match lex_source(file, source) {
             Ok(t) => t,
             Err(mut e) => {
                 errors.add_errors(&mut e);
@@ -38,12 +61,34 @@ pub fn parse_program(state: &mut CompilerState, start: PathBuf) -> Result<ast::P
             }
         };
 
-        match Parser::parse(Rc::clone(source), tokens) {
-            Ok(f) => program.files.push(f),
+        match Parser::parse(file, source, tokens) {
+            Ok(f) => {
+                let directory = source.path.as_ref().and_then(|p| p.parent()).map(Path::to_path_buf).unwrap_or_default();
+                for top_level in &f.top_levels {
+                    if let ast::TopLevelNode::Import(import) = top_level {
+                        let relative = import_path(&f, import.path);
+                        let mut candidates = vec![directory.join(&relative)];
+                        candidates.extend(state.options.library_paths.iter().map(|dir| dir.join(&relative)));
+                        to_visit.push_back(PendingFile { candidates, import: Some(import.loc) });
+                    }
+                }
+                program.files.push(f)
+            },
             Err(mut e) => errors.add_errors(&mut e)
         }
     }
 
+    for file in &mut program.files {
+        for top_level in &mut file.top_levels {
+            if let ast::TopLevelNode::Import(import) = top_level {
+                import.file = imported_files.get(&import.loc).copied();
+            }
+        }
+    }
+    for cycle in find_import_cycles(&program, &state.sources) {
+        errors.add_error(cycle);
+    }
+
     if !errors.is_empty() {
         Err(errors)
     } else {
@@ -51,13 +96,132 @@ pub fn parse_program(state: &mut CompilerState, start: PathBuf) -> Result<ast::P
     }
 }
 
+/// Parses a program whose only file is `text`, for tests that start from source code. It panics if the text
+/// cannot be parsed.
+#[cfg(test)]
+pub fn parse_text(text: &str) -> ast::Program {
+    use crate::options::Options;
+    use crate::vfs::OverlayFileSystem;
+
+    let mut fs = OverlayFileSystem::in_memory();
+    let path = fs.add_file(Path::new("/test/main.adze"), String::from(text));
+    let mut state = CompilerState::with_file_system(Options::from_args(Vec::new()).unwrap(), Box::new(fs));
+    let Ok(program) = parse_program(&mut state, path) else {
+        panic!("the program could not be parsed");
+    };
+    return program;
+}
+
+
+/// Loads and lexes a single file, without following its imports.
+pub fn lex_file(state: &mut CompilerState, path: PathBuf) -> Result<Vec<Token>, ErrorSet<ParseError>> {
+    let mut errors = ErrorSet::new();
+    let file = match Source::from_file(state.fs.as_ref(), &path) {
+        Ok(s) => state.sources.add(s),
+        Err(error) => {
+            errors.add_error(ParseError::CouldNotLoad { path, error, loc: None, searched: Vec::new() });
+            return Err(errors);
+        }
+    };
+    return lex_source(file, state.sources.get(file));
+}
+
+/// Finds the first candidate path that exists. If none do, the error is the one for the first
+/// candidate, unless a later candidate exists but could not be accessed.
+fn resolve_path(state: &CompilerState, candidates: &[PathBuf]) -> Result<PathBuf, LoadError> {
+    let mut first_error = None;
+    for candidate in candidates {
+        match state.fs.canonicalize(candidate) {
+            Ok(path) => return Ok(path),
+            Err(e) => {
+                let error = LoadError::from(e);
+                if error != LoadError::NotFound {
+                    return Err(error);
+                }
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    return Err(first_error.unwrap_or(LoadError::NotFound));
+}
+
+
+/// Maps an import path such as `a::b::c` to the relative file path `a/b/c.adze`.
+fn import_path(file: &ast::File, path: ast::NameId) -> PathBuf {
+    fn segments(file: &ast::File, path: ast::NameId) -> PathBuf {
+        match &file[path] {
+            ast::QualifiedNameNode::Name(n) => PathBuf::from(&n.name),
+            ast::QualifiedNameNode::Namespace(n) => segments(file, n.source).join(&n.attr)
+        }
+    }
+    let mut file = segments(file, path);
+    file.set_extension("adze");
+    return file;
+}
+
+
+/// Reports each cycle of imports once, at the import that closes it.
+fn find_import_cycles(program: &ast::Program, sources: &SourceMap) -> Vec<ParseError> {
+    let mut edges: HashMap<FileId, Vec<(Span, FileId)>> = HashMap::new();
+    for file in &program.files {
+        let file_edges = file.top_levels.iter().filter_map(|top_level| match top_level {
+            ast::TopLevelNode::Import(import) => Some((import.loc, import.file?)),
+            _ => None
+        });
+        edges.insert(file.file, file_edges.collect());
+    }
+
+    #[derive(PartialEq)]
+    enum Visit { InProgress, Done }
+
+    fn visit(file: FileId, edges: &HashMap<FileId, Vec<(Span, FileId)>>, state: &mut HashMap<FileId, Visit>,
+             stack: &mut Vec<(FileId, Span)>, sources: &SourceMap, cycles: &mut Vec<ParseError>) {
+        state.insert(file, Visit::InProgress);
+        for (import, target) in edges.get(&file).into_iter().flatten() {
+            match state.get(target) {
+                Some(Visit::InProgress) => {
+                    let start = stack.iter().position(|(f, _)| f == target).unwrap_or(stack.len());
+                    let mut chain: Vec<(String, Span)> = stack[start..].iter()
+                        .map(|(f, span)| (sources.get(*f).name.clone(), *span))
+                        .collect();
+                    chain.push((sources.get(file).name.clone(), *import));
+                    cycles.push(ParseError::ImportCycle { chain, loc: *import });
+                },
+                Some(Visit::Done) => { },
+                None => {
+                    stack.push((file, *import));
+                    visit(*target, edges, state, stack, sources, cycles);
+                    stack.pop();
+                }
+            }
+        }
+        state.insert(file, Visit::Done);
+    }
+
+    let mut state = HashMap::new();
+    let mut cycles = Vec::new();
+    for file in &program.files {
+        if !state.contains_key(&file.file) {
+            visit(file.file, &edges, &mut state, &mut Vec::new(), sources, &mut cycles);
+        }
+    }
+    return cycles;
+}
+
 
 struct Parser {
     errors: ErrorSet<ParseError>,
     handlers: Vec<(Vec<TokenType>, SyncFlag)>,
     tokens: Vec<Token>,
-    source: Rc<Source>,
-    curr_index: usize
+    file: FileId,
+    /// The file being parsed, whose arenas the parser allocates nodes in.
+    ast: ast::File,
+    eof: Span,
+    curr_index: usize,
+    last_error_index: Option<usize>,
+    /// Whether a name followed by '{' starts a struct literal. It does not in the condition of an `if` or
+    /// `while`, where the '{' starts the body instead.
+    struct_literals: bool
 }
 
 type SyncFlag = i64;
@@ -65,8 +229,9 @@ type ParseResult<T> = Result<T, SyncFlag>;
 
 
 impl Parser {
-    fn new(source: Rc<Source>, tokens: Vec<Token>) -> Parser {
-        return Parser { errors: ErrorSet::new(), handlers: vec![(Vec::new(), 0)], tokens, source, curr_index: 0 };
+    fn new(file: FileId, source: &Source, tokens: Vec<Token>) -> Parser {
+        let eof = Span::new(file, source.text.len(), source.text.len());
+        return Parser { errors: ErrorSet::new(), handlers: vec![(Vec::new(), 0)], tokens, file, ast: ast::File::new(file), eof, curr_index: 0, last_error_index: None, struct_literals: true };
     }
 
     fn is_done(&self) -> bool {
@@ -75,7 +240,7 @@ impl Parser {
 
     fn curr(&self) -> Token {
         if self.curr_index >= self.tokens.len() {
-            Token::new("\0", TokenType::EOF, Location::new_eof(Rc::clone(&self.source)), false)
+            Token::new("\0", TokenType::EOF, self.eof, false)
         } else {
             self.tokens[self.curr_index].clone()
         }
@@ -83,12 +248,20 @@ impl Parser {
 
     fn next(&self) -> Token {
         if self.curr_index + 1 >= self.tokens.len() {
-            Token::new("\0", TokenType::EOF, Location::new_eof(Rc::clone(&self.source)), false)
+            Token::new("\0", TokenType::EOF, self.eof, false)
         } else {
             self.tokens[self.curr_index + 1].clone()
         }
     }
 
+    fn previous(&self) -> Token {
+        if self.curr_index == 0 {
+            self.curr()
+        } else {
+            self.tokens[self.curr_index - 1].clone()
+        }
+    }
+
     fn expect(&self, expected: TokenType) -> bool {
         return self.curr().token_type == expected;
     }
@@ -116,6 +289,15 @@ impl Parser {
         return result;
     }
 
+    /// Records an error, unless the parser has not consumed a token since the last error. Such errors
+    /// are almost always follow-on errors caused by recovering from the first one.
+    fn report(&mut self, error: ParseError) {
+        if self.last_error_index != Some(self.curr_index) {
+            self.errors.add_error(error);
+            self.last_error_index = Some(self.curr_index);
+        }
+    }
+
     fn synchronize<T>(&mut self)  -> ParseResult<T> {
         while !self.is_done() {
             for (can_handle, flag) in &self.handlers {
@@ -132,7 +314,7 @@ impl Parser {
         return if self.expect(expected) {
             Ok(self.advance())
         } else {
-            self.errors.add_error(ParseError::UnexpectedToken {
+            self.report(ParseError::UnexpectedToken {
                 expected,
                 got: self.curr().token_type,
                 loc: self.curr().loc
@@ -145,52 +327,114 @@ impl Parser {
         if self.expect_symbol(first, second) {
             Ok((self.advance(), self.advance()))
         } else {
-            self.errors.add_error(ParseError::WithMessage(format!("Expected a {}.", name), self.curr().loc));
+            self.report(ParseError::WithMessage(format!("Expected a {}.", name), self.curr().loc));
             self.synchronize()
         }
     }
 
+    /// Consumes the ';' that ends a statement. A missing ';' is reported with a fix that inserts it after
+    /// the previous token, and parsing carries on as though it had been there.
+    fn consume_semicolon(&mut self) -> ParseResult<Token> {
+        if self.expect(TokenType::Semicolon) {
+            return Ok(self.advance());
+        }
+        let loc = self.previous().loc.end();
+        let applicability = if self.expect(TokenType::RightBrace) { Applicability::MachineApplicable } else { Applicability::MaybeIncorrect };
+        self.report(ParseError::WithFix(
+            format!("Unexpected token: Got {}, expected {}.", self.curr().token_type, TokenType::Semicolon),
+            self.curr().loc,
+            Fix::new("Insert a ';' at the end of the statement.", loc, ";", applicability)
+        ));
+        Ok(Token::new(";", TokenType::Semicolon, loc, false))
+    }
+
     fn consume_error(&mut self, expected: TokenType, error_msg: &str) -> ParseResult<Token> {
         return if self.expect(expected) {
             Ok(self.advance())
         } else {
-            self.errors.add_error(ParseError::WithMessage(String::from(error_msg), self.curr().loc));
+            self.report(ParseError::WithMessage(String::from(error_msg), self.curr().loc));
             self.synchronize()
         }
     }
 
-    fn parse(source: Rc<Source>, tokens: Vec<Token>) -> Result<ast::File, ErrorSet<ParseError>> {
-        let mut parser = Parser { errors: ErrorSet::new(), handlers: vec![(Vec::new(), 0)], tokens, source, curr_index: 0 };
+    fn parse(file: FileId, source: &Source, tokens: Vec<Token>) -> Result<ast::File, ErrorSet<ParseError>> {
+        let mut parser = Parser::new(file, source, tokens);
         match parser.parse_file() {
-            Ok(f) => Ok(f),
-            Err(_) => Err(parser.errors)
+            Ok(()) if parser.errors.is_empty() => {
+                let mut file = parser.ast;
+                file.link_parents();
+                Ok(file)
+            },
+            _ => Err(parser.errors)
         }
     }
 
-    fn parse_file(&mut self) -> ParseResult<ast::File> {
-        let mut top_levels = Vec::new();
+    fn parse_file(&mut self) -> ParseResult<()> {
         while !self.is_done() {
-            self.catch(&[TokenType::Struct, TokenType::Fn], |s| {
+            self.catch(&[TokenType::Struct, TokenType::Trait, TokenType::Fn, TokenType::Const, TokenType::Import, TokenType::Hash], |s| {
                 let top_level = s.parse_top_level()?;
-                top_levels.push(top_level);
+                s.ast.top_levels.push(top_level);
                 Ok(())
             })?;
         }
-        return Ok(ast::File { top_levels });
+        return Ok(());
     }
 
-    fn parse_top_level(&mut self) -> ParseResult<Box<ast::TopLevelNode>> {
-        if self.expect(TokenType::Struct) {
-            Ok(Box::from(ast::TopLevelNode::Struct(self.parse_struct()?)))
+    fn parse_top_level(&mut self) -> ParseResult<ast::TopLevelNode> {
+        let attributes = self.parse_attributes()?;
+        if self.expect(TokenType::Import) {
+            Ok(ast::TopLevelNode::Import(self.parse_import(attributes)?))
+        } else if self.expect(TokenType::Struct) {
+            Ok(ast::TopLevelNode::Struct(self.parse_struct(attributes)?))
+        } else if self.expect(TokenType::Trait) {
+            Ok(ast::TopLevelNode::Trait(self.parse_trait(attributes)?))
         } else if self.expect(TokenType::Fn) {
-            Ok(Box::from(ast::TopLevelNode::Function(self.parse_function()?)))
+            Ok(ast::TopLevelNode::Function(self.parse_function(attributes)?))
+        } else if self.expect(TokenType::Const) {
+            Ok(ast::TopLevelNode::Const(self.parse_const(attributes)?))
         } else {
-            self.errors.add_error(ParseError::WithMessage(String::from("Expected the start of a struct, function, or import."), self.curr().loc));
+            self.report(ParseError::WithMessage(String::from("Expected the start of a struct, trait, function, constant, or import."), self.curr().loc));
             self.synchronize()
         }
     }
 
-    fn parse_struct(&mut self) -> ParseResult<ast::StructData> {
+    fn parse_attributes(&mut self) -> ParseResult<Vec<ast::Attribute>> {
+        let mut attributes = Vec::new();
+        while self.expect(TokenType::Hash) {
+            let start = self.consume(TokenType::Hash)?;
+            self.consume(TokenType::LeftBracket)?;
+            let name = self.consume(TokenType::Identifier)?;
+            let mut arguments = Vec::new();
+            if self.expect(TokenType::LeftParenthesis) {
+                self.consume(TokenType::LeftParenthesis)?;
+                while !self.expect(TokenType::RightParenthesis) {
+                    let argument = self.consume(TokenType::Identifier)?;
+                    arguments.push(ast::QualNameData { loc: argument.get_loc(), name: argument.text });
+                    if !self.expect(TokenType::Comma) {
+                        break;
+                    } else {
+                        self.consume(TokenType::Comma)?;
+                    }
+                }
+                self.consume(TokenType::RightParenthesis)?;
+            }
+            let end = self.consume(TokenType::RightBracket)?;
+            if !matches!(name.text.as_str(), "allow" | "warn" | "deny") {
+                self.report(ParseError::WithMessage(format!("Unknown attribute '{}', expected 'allow', 'warn' or 'deny'.", name.text), name.loc));
+            }
+            attributes.push(ast::Attribute { loc: start.loc_range(&end), name: name.text, arguments });
+        }
+        Ok(attributes)
+    }
+
+    fn parse_import(&mut self, attributes: Vec<ast::Attribute>) -> ParseResult<ast::ImportData> {
+        let start = self.consume(TokenType::Import)?;
+        let path = self.parse_qual_name()?;
+        let end = self.consume(TokenType::Semicolon)?;
+        Ok(ast::ImportData { loc: start.loc_range(&end), attributes, path, file: None })
+    }
+
+    fn parse_struct(&mut self, attributes: Vec<ast::Attribute>) -> ParseResult<ast::StructData> {
         let start = self.curr();
         self.consume_error(TokenType::Struct, "Struct definitions must begin with 'struct'")?;
         let name = self.consume(TokenType::Identifier)?;
@@ -211,42 +455,115 @@ impl Parser {
 
         let superstruct = if self.expect(TokenType::LeftParenthesis) {
             self.consume(TokenType::LeftParenthesis)?;
-            Some(self.parse_qual_name()?)
+            let superstruct = self.parse_qual_name()?;
+            self.consume(TokenType::RightParenthesis)?;
+            Some(superstruct)
         } else {
             None
         };
-        let interfaces = Vec::new();
+
+        let mut interfaces = Vec::new();
+        if self.expect(TokenType::Colon) {
+            self.consume(TokenType::Colon)?;
+            loop {
+                interfaces.push(self.parse_qual_name()?);
+                if !self.expect(TokenType::Comma) {
+                    break;
+                } else {
+                    self.consume(TokenType::Comma)?;
+                }
+            }
+        }
 
         let mut fields = Vec::new();
+        let mut methods = Vec::new();
         self.consume(TokenType::LeftBrace)?;
         while !self.expect(TokenType::RightBrace) {
-            fields.push(self.parse_struct_field()?);
+            let member_attributes = self.parse_attributes()?;
+            if self.expect(TokenType::Fn) {
+                methods.push(self.parse_function(member_attributes)?);
+            } else {
+                if let Some(attribute) = member_attributes.first() {
+                    self.report(ParseError::WithMessage(String::from("Attributes can only be applied to methods, not fields."), attribute.loc));
+                }
+                fields.push(self.parse_struct_field()?);
+            }
         }
-        self.consume(TokenType::RightBrace)?;
+        let end = self.consume(TokenType::RightBrace)?;
+
+        let loc = start.loc_range(&end);
 
-        let loc = self.curr().loc_range(&start);
+        Ok(ast::StructData { loc, attributes, name: String::from(&name.text), name_loc: name.loc, generic_parameters, superstruct, interfaces, fields, methods })
+    }
 
-        Ok(ast::StructData { loc, name: String::from(&name.text), generic_parameters, superstruct, interfaces, fields})
+    fn parse_generic_parameter(&mut self) -> ParseResult<ast::GenericParameter> {
+        let name = self.consume(TokenType::Identifier)?;
+        let mut bounds = Vec::new();
+        if self.expect(TokenType::Colon) {
+            self.consume(TokenType::Colon)?;
+            loop {
+                bounds.push(self.parse_qual_name()?);
+                if !self.expect(TokenType::Plus) {
+                    break;
+                } else {
+                    self.consume(TokenType::Plus)?;
+                }
+            }
+        }
+        Ok(ast::GenericParameter { loc: name.get_loc(), name: name.text, bounds })
     }
 
-    fn parse_generic_parameter(&mut self) -> ParseResult<Box<ast::GenericParameter>> {
+    fn parse_trait(&mut self, attributes: Vec<ast::Attribute>) -> ParseResult<ast::TraitData> {
+        let start = self.consume(TokenType::Trait)?;
         let name = self.consume(TokenType::Identifier)?;
-        Ok(Box::from(ast::GenericParameter { loc: name.get_loc().clone(), name: name.text, bound: None }))
+        let mut methods = Vec::new();
+        self.consume(TokenType::LeftBrace)?;
+        while !self.expect(TokenType::RightBrace) {
+            methods.push(self.parse_trait_method()?);
+        }
+        let end = self.consume(TokenType::RightBrace)?;
+        Ok(ast::TraitData { loc: start.loc_range(&end), attributes, name: name.text, name_loc: name.loc, methods })
+    }
+
+    fn parse_trait_method(&mut self) -> ParseResult<ast::TraitMethod> {
+        let start = self.consume(TokenType::Fn)?;
+        let name = self.consume(TokenType::Identifier)?;
+        if self.expect(TokenType::LeftAngle) {
+            self.report(ParseError::WithMessage(String::from("Trait methods cannot have generic parameters."), self.curr().loc));
+            return self.synchronize();
+        }
+
+        let mut parameters = Vec::new();
+        self.consume(TokenType::LeftParenthesis)?;
+        while !self.expect(TokenType::RightParenthesis) {
+            parameters.push(self.parse_function_parameter()?);
+            if !self.expect(TokenType::Comma) {
+                break;
+            } else {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+        self.consume(TokenType::RightParenthesis)?;
+
+        self.consume_return_arrow()?;
+        let ret = self.parse_type()?;
+        let end = self.consume_semicolon()?;
+        Ok(ast::TraitMethod { loc: start.loc_range(&end), name: name.text, name_loc: name.loc, parameters, ret })
     }
 
-    fn parse_struct_field(&mut self) -> ParseResult<Box<ast::StructField>> {
+    fn parse_struct_field(&mut self) -> ParseResult<ast::StructField> {
         let name = self.consume(TokenType::Identifier)?;
         self.consume(TokenType::Colon)?;
         let typ = self.parse_type()?;
         self.consume(TokenType::Semicolon)?;
-        Ok(Box::from(ast::StructField {
-            loc: name.get_loc().combine(typ.get_loc()),
+        Ok(ast::StructField {
+            loc: name.get_loc().combine(&self.ast.loc(typ)),
             name: name.text,
             typ
-        }))
+        })
     }
 
-    fn parse_function(&mut self) -> ParseResult<ast::FunctionData> {
+    fn parse_function(&mut self, attributes: Vec<ast::Attribute>) -> ParseResult<ast::FunctionData> {
         let start = self.consume(TokenType::Fn)?;
         let name = self.consume(TokenType::Identifier)?;
 
@@ -277,13 +594,15 @@ impl Parser {
         }
         self.consume(TokenType::RightParenthesis)?;
 
-        self.consume_symbol(TokenType::Minus, TokenType::RightAngle, "'->'")?;
+        self.consume_return_arrow()?;
         let ret = self.parse_type()?;
 
         let body = self.parse_block()?;
 
         Ok(ast::FunctionData {
             loc: start.get_loc().combine(&body.loc),
+            attributes,
+            name_loc: name.loc,
             name: name.text,
             generic_parameters,
             parameters,
@@ -292,110 +611,562 @@ impl Parser {
         })
     }
 
-    fn parse_function_parameter(&mut self) -> ParseResult<Box<ast::FunctionParameter>> {
+    /// Consumes the '->' before a function's return type. When the return type is there but the arrow is
+    /// missing or written as ':', the error carries a fix that supplies the arrow.
+    fn consume_return_arrow(&mut self) -> ParseResult<()> {
+        if self.expect_symbol(TokenType::Minus, TokenType::RightAngle) {
+            self.advance(); self.advance();
+        } else if self.expect(TokenType::Colon) {
+            let colon = self.advance();
+            self.report(ParseError::WithFix(
+                String::from("Expected a '->' before the return type."),
+                colon.loc,
+                Fix::new("Replace ':' with '->'.", colon.loc, if colon.leading_ws { "->" } else { " ->" }, Applicability::MachineApplicable)
+            ));
+        } else if self.expect(TokenType::Identifier) || self.expect(TokenType::LeftParenthesis) {
+            let loc = self.curr().loc;
+            self.report(ParseError::WithFix(
+                String::from("Expected a '->' before the return type."),
+                loc,
+                Fix::new("Insert '->' before the return type.", loc.start(), "-> ", Applicability::MachineApplicable)
+            ));
+        } else {
+            self.consume_symbol(TokenType::Minus, TokenType::RightAngle, "'->'")?;
+        }
+        Ok(())
+    }
+
+    fn parse_const(&mut self, attributes: Vec<ast::Attribute>) -> ParseResult<ast::ConstData> {
+        let start = self.consume(TokenType::Const)?;
+        let name = self.consume(TokenType::Identifier)?;
+        self.consume(TokenType::Colon)?;
+        let typ = self.parse_type()?;
+        self.consume(TokenType::Equal)?;
+        let value = self.parse_expr()?;
+        let end = self.consume_semicolon()?;
+        Ok(ast::ConstData { loc: start.loc_range(&end), attributes, name: name.text, name_loc: name.loc, typ, value })
+    }
+
+    fn parse_function_parameter(&mut self) -> ParseResult<ast::FunctionParameter> {
         let name = self.consume(TokenType::Identifier)?;
         self.consume(TokenType::Colon)?;
         let typ = self.parse_type()?;
-        return Ok(Box::from(ast::FunctionParameter { loc: name.get_loc().combine(typ.get_loc()), name: name.text, typ}))
+        return Ok(ast::FunctionParameter { loc: name.get_loc().combine(&self.ast.loc(typ)), name: name.text, typ})
     }
 
-    fn parse_stmt(&mut self) -> ParseResult<Box<ast::StmtNode>> {
-        if self.expect(TokenType::Return) {
-            Ok(Box::from(ast::StmtNode::Return(self.parse_return()?)))
+    fn parse_stmt(&mut self) -> ParseResult<ast::StmtId> {
+        let stmt = if self.expect(TokenType::Return) {
+            ast::StmtNode::Return(self.parse_return()?)
+        } else if self.expect(TokenType::Let) {
+            ast::StmtNode::Let(self.parse_let()?)
+        } else if self.expect(TokenType::If) {
+            ast::StmtNode::If(self.parse_if()?)
+        } else if self.expect(TokenType::While) {
+            ast::StmtNode::While(self.parse_while()?)
+        } else if self.expect(TokenType::For) {
+            ast::StmtNode::For(self.parse_for()?)
         } else {
-            Ok(Box::from(ast::StmtNode::Expr(self.parse_expr_stmt()?)))
-        }
+            ast::StmtNode::Expr(self.parse_expr_stmt()?)
+        };
+        Ok(self.ast.alloc_stmt(stmt))
     }
 
     fn parse_return(&mut self) -> ParseResult<ast::StmtReturnData> {
         let start = self.consume(TokenType::Return)?;
         let expr = self.parse_expr()?;
-        let end = self.consume(TokenType::Semicolon)?;
-        Ok(ast::StmtReturnData { loc: start.get_loc().combine(end.get_loc()), expr })
+        let end = self.consume_semicolon()?;
+        Ok(ast::StmtReturnData { loc: start.get_loc().combine(&end.get_loc()), expr })
+    }
+
+    fn parse_let(&mut self) -> ParseResult<ast::StmtLetData> {
+        let start = self.consume(TokenType::Let)?;
+        let name = self.consume(TokenType::Identifier)?;
+        let typ = if self.expect(TokenType::Colon) {
+            self.consume(TokenType::Colon)?;
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let value = if self.expect(TokenType::Equal) {
+            self.consume(TokenType::Equal)?;
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let end = self.consume_semicolon()?;
+        Ok(ast::StmtLetData { loc: start.loc_range(&end), name: name.text, name_loc: name.loc, typ, value })
+    }
+
+    fn parse_if(&mut self) -> ParseResult<ast::StmtIfData> {
+        let start = self.consume(TokenType::If)?;
+        let condition = self.parse_condition()?;
+        let body = self.parse_block()?;
+        let else_body = if self.expect(TokenType::Else) {
+            self.consume(TokenType::Else)?;
+            if self.expect(TokenType::If) {
+                let nested = self.parse_if()?;
+                Some(ast::BlockData { loc: nested.loc, stmts: vec![self.ast.alloc_stmt(ast::StmtNode::If(nested))] })
+            } else {
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
+        };
+        let end = else_body.as_ref().map_or(body.loc, |b| b.loc);
+        Ok(ast::StmtIfData { loc: start.loc.combine(&end), condition, body, else_body })
+    }
+
+    fn parse_while(&mut self) -> ParseResult<ast::StmtWhileData> {
+        let start = self.consume(TokenType::While)?;
+        let condition = self.parse_condition()?;
+        let body = self.parse_block()?;
+        Ok(ast::StmtWhileData { loc: start.loc.combine(&body.loc), condition, body })
+    }
+
+    fn parse_for(&mut self) -> ParseResult<ast::StmtForData> {
+        let start = self.consume(TokenType::For)?;
+        let name = self.consume(TokenType::Identifier)?;
+        self.consume(TokenType::In)?;
+        let first = self.parse_condition()?;
+        let iterable = if self.expect_symbol(TokenType::Period, TokenType::Period) {
+            self.advance(); self.advance();
+            ast::ForIterable::Range(first, self.parse_condition()?)
+        } else {
+            ast::ForIterable::Array(first)
+        };
+        let body = self.parse_block()?;
+        Ok(ast::StmtForData { loc: start.loc.combine(&body.loc), name: name.text, name_loc: name.loc, iterable, body })
+    }
+
+    /// Parses the condition of an `if` or `while`, or the iterable of a `for`, in which a struct literal would be
+    /// mistaken for the start of the body unless it is in parentheses.
+    fn parse_condition(&mut self) -> ParseResult<ast::ExprId> {
+        let outer = std::mem::replace(&mut self.struct_literals, false);
+        let condition = self.parse_expr();
+        self.struct_literals = outer;
+        return condition;
     }
 
     fn parse_expr_stmt(&mut self) -> ParseResult<ast::StmtExprData> {
         let expr = self.parse_expr()?;
-        let end = self.consume(TokenType::Semicolon)?;
-        Ok(ast::StmtExprData { loc: expr.get_loc().combine(end.get_loc()), expr })
+        let end = self.consume_semicolon()?;
+        Ok(ast::StmtExprData { loc: self.ast.loc(expr).combine(&end.get_loc()), expr })
+    }
+
+    fn parse_expr(&mut self) -> ParseResult<ast::ExprId> {
+        self.parse_expr_assign()
+    }
+
+    fn parse_expr_assign(&mut self) -> ParseResult<ast::ExprId> {
+        use TokenType::*;
+        let target = self.parse_expr_or()?;
+        let compound = [(Plus, ast::BinaryOp::Add), (Minus, ast::BinaryOp::Subtract), (Star, ast::BinaryOp::Multiply),
+                        (Slash, ast::BinaryOp::Divide), (Percent, ast::BinaryOp::Remainder)];
+        let op = compound.iter().find(|(token, _)| self.expect_symbol(*token, Equal)).map(|(_, op)| *op);
+        if op.is_some() {
+            self.advance();
+        }
+        if self.expect(Equal) {
+            self.consume(Equal)?;
+            let value = self.parse_expr_assign()?;
+            let loc = self.ast.loc(target).combine(&self.ast.loc(value));
+            Ok(self.ast.alloc_expr(ast::ExprNode::Assign(ast::AssignData { loc, op, target, value })))
+        } else {
+            Ok(target)
+        }
+    }
+
+    /// Parses a left-associative chain of binary operators at one level of precedence, where `operators`
+    /// pairs the tokens that spell each operator with the operator.
+    fn parse_binary_level<F>(&mut self, operators: &[(&[TokenType], ast::BinaryOp)], operand: F) -> ParseResult<ast::ExprId>
+        where F: Fn(&mut Self) -> ParseResult<ast::ExprId> {
+        let mut left = operand(self)?;
+        'outer: loop {
+            for (tokens, op) in operators {
+                if self.expect_operator(tokens) {
+                    for _ in tokens.iter() {
+                        self.advance();
+                    }
+                    let right = operand(self)?;
+                    let loc = self.ast.loc(left).combine(&self.ast.loc(right));
+                    left = self.ast.alloc_expr(ast::ExprNode::Binary(ast::BinaryData { loc, op: *op, left, right }));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    /// Whether the next tokens spell an operator. A one-character operator followed directly by '=' is
+    /// not matched, since that is a different operator such as `<=`.
+    fn expect_operator(&self, tokens: &[TokenType]) -> bool {
+        match tokens {
+            [first] => self.expect(*first) && !self.expect_symbol(*first, TokenType::Equal),
+            [first, second] => self.expect_symbol(*first, *second),
+            _ => false
+        }
+    }
+
+    fn parse_expr_or(&mut self) -> ParseResult<ast::ExprId> {
+        use TokenType::*;
+        self.parse_binary_level(&[(&[VerticalBar, VerticalBar], ast::BinaryOp::Or)], Self::parse_expr_and)
+    }
+
+    fn parse_expr_and(&mut self) -> ParseResult<ast::ExprId> {
+        use TokenType::*;
+        self.parse_binary_level(&[(&[Ampersand, Ampersand], ast::BinaryOp::And)], Self::parse_expr_comparison)
     }
 
-    fn parse_expr(&mut self) -> ParseResult<Box<ast::ExprNode>> {
-        self.parse_expr_block()
+    fn parse_expr_comparison(&mut self) -> ParseResult<ast::ExprId> {
+        use TokenType::*;
+        self.parse_binary_level(&[
+            (&[Equal, Equal], ast::BinaryOp::Equal),
+            (&[Exclamation, Equal], ast::BinaryOp::NotEqual),
+            (&[LeftAngle, Equal], ast::BinaryOp::LessEqual),
+            (&[RightAngle, Equal], ast::BinaryOp::GreaterEqual),
+            (&[LeftAngle], ast::BinaryOp::Less),
+            (&[RightAngle], ast::BinaryOp::Greater)
+        ], Self::parse_expr_additive)
     }
 
-    fn parse_expr_block(&mut self) -> ParseResult<Box<ast::ExprNode>> {
+    fn parse_expr_additive(&mut self) -> ParseResult<ast::ExprId> {
+        use TokenType::*;
+        self.parse_binary_level(&[
+            (&[Plus], ast::BinaryOp::Add),
+            (&[Minus], ast::BinaryOp::Subtract)
+        ], Self::parse_expr_multiplicative)
+    }
+
+    fn parse_expr_multiplicative(&mut self) -> ParseResult<ast::ExprId> {
+        use TokenType::*;
+        self.parse_binary_level(&[
+            (&[Star], ast::BinaryOp::Multiply),
+            (&[Slash], ast::BinaryOp::Divide),
+            (&[Percent], ast::BinaryOp::Remainder)
+        ], Self::parse_expr_cast)
+    }
+
+    fn parse_expr_cast(&mut self) -> ParseResult<ast::ExprId> {
+        let mut expr = self.parse_expr_unary()?;
+        loop {
+            if self.expect(TokenType::As) {
+                self.consume(TokenType::As)?;
+                let typ = self.parse_type()?;
+                let loc = self.ast.loc(expr).combine(&self.ast.loc(typ));
+                expr = self.ast.alloc_expr(ast::ExprNode::Cast(ast::CastData { loc, expr, typ }));
+            } else if self.expect(TokenType::Is) {
+                self.consume(TokenType::Is)?;
+                let typ = self.parse_type()?;
+                let loc = self.ast.loc(expr).combine(&self.ast.loc(typ));
+                expr = self.ast.alloc_expr(ast::ExprNode::Is(ast::IsData { loc, expr, typ }));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_expr_unary(&mut self) -> ParseResult<ast::ExprId> {
+        let op = if self.expect(TokenType::Minus) {
+            ast::UnaryOp::Negate
+        } else if self.expect(TokenType::Exclamation) {
+            ast::UnaryOp::Not
+        } else {
+            return self.parse_expr_postfix();
+        };
+        let start = self.advance();
+        let operand = self.parse_expr_unary()?;
+        let loc = start.loc.combine(&self.ast.loc(operand));
+        Ok(self.ast.alloc_expr(ast::ExprNode::Unary(ast::UnaryData { loc, op, operand })))
+    }
+
+    fn parse_expr_postfix(&mut self) -> ParseResult<ast::ExprId> {
+        let mut expr = self.parse_expr_block()?;
+        loop {
+            if self.expect(TokenType::LeftParenthesis) {
+                self.consume(TokenType::LeftParenthesis)?;
+                let mut arguments = Vec::new();
+                while !self.expect(TokenType::RightParenthesis) {
+                    arguments.push(self.parse_expr()?);
+                    if !self.expect(TokenType::Comma) {
+                        break;
+                    } else {
+                        self.consume(TokenType::Comma)?;
+                    }
+                }
+                let end = self.consume(TokenType::RightParenthesis)?;
+                let loc = self.ast.loc(expr).combine(&end.loc);
+                expr = self.ast.alloc_expr(ast::ExprNode::Call(ast::CallData { loc, callee: expr, arguments }));
+            } else if self.expect(TokenType::Period) && !self.expect_symbol(TokenType::Period, TokenType::Period) {
+                self.consume(TokenType::Period)?;
+                let field = self.consume(TokenType::Identifier)?;
+                let loc = self.ast.loc(expr).combine(&field.loc);
+                expr = self.ast.alloc_expr(ast::ExprNode::Field(ast::FieldData { loc, object: expr, field: field.text, field_loc: field.loc }));
+            } else if self.expect(TokenType::LeftBracket) {
+                self.consume(TokenType::LeftBracket)?;
+                let index = self.parse_nested_expr()?;
+                let end = self.consume(TokenType::RightBracket)?;
+                let loc = self.ast.loc(expr).combine(&end.loc);
+                expr = self.ast.alloc_expr(ast::ExprNode::Index(ast::IndexData { loc, object: expr, index }));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_expr_block(&mut self) -> ParseResult<ast::ExprId> {
         if self.expect(TokenType::LeftBrace) {
-            Ok(Box::from(ast::ExprNode::Block(self.parse_block()?)))
+            let block = self.parse_block()?;
+            Ok(self.ast.alloc_expr(ast::ExprNode::Block(block)))
         } else {
             self.parse_expr_terminal()
         }
     }
 
     fn parse_block(&mut self) -> ParseResult<ast::BlockData> {
+        let outer = std::mem::replace(&mut self.struct_literals, true);
+        let block = self.parse_block_statements();
+        self.struct_literals = outer;
+        return block;
+    }
+
+    fn parse_block_statements(&mut self) -> ParseResult<ast::BlockData> {
         let start = self.consume(TokenType::LeftBrace)?;
         let mut stmts = Vec::new();
-        while !self.expect(TokenType::RightBrace) {
-            stmts.push(self.parse_stmt()?);
+        while !self.expect(TokenType::RightBrace) && !self.is_done() {
+            let mut parsed = false;
+            self.catch(&[TokenType::Semicolon, TokenType::RightBrace, TokenType::Let, TokenType::Return, TokenType::If, TokenType::While, TokenType::For], |s| {
+                let stmt = s.parse_stmt()?;
+                stmts.push(stmt);
+                parsed = true;
+                Ok(())
+            })?;
+            if !parsed && self.expect(TokenType::Semicolon) {
+                self.advance();
+            }
         }
         let end = self.consume(TokenType::RightBrace)?;
-        Ok(ast::BlockData { loc: start.get_loc().combine(end.get_loc()), stmts })
+        Ok(ast::BlockData { loc: start.get_loc().combine(&end.get_loc()), stmts })
     }
 
-    fn parse_expr_terminal(&mut self) -> ParseResult<Box<ast::ExprNode>> {
+    fn parse_expr_terminal(&mut self) -> ParseResult<ast::ExprId> {
         if self.expect(TokenType::Integer) {
-            Ok(Box::from(ast::ExprNode::Integer(self.parse_integer()?)))
+            let integer = self.parse_integer()?;
+            Ok(self.ast.alloc_expr(ast::ExprNode::Integer(integer)))
+        } else if self.expect(TokenType::Float) {
+            let token = self.advance();
+            match token.text.parse::<f64>() {
+                Ok(float) => Ok(self.ast.alloc_expr(ast::ExprNode::Float(ast::FloatData { loc: token.loc, float }))),
+                Err(_) => {
+                    self.report(ParseError::CouldNotParseLiteral(TokenType::Float, token.loc));
+                    self.synchronize()
+                }
+            }
+        } else if self.expect(TokenType::True) || self.expect(TokenType::False) {
+            let token = self.advance();
+            Ok(self.ast.alloc_expr(ast::ExprNode::Bool(ast::BoolData { loc: token.loc, value: token.token_type == TokenType::True })))
+        } else if self.expect(TokenType::String) {
+            let token = self.advance();
+            let value = self.unescape(&token)?;
+            Ok(self.ast.alloc_expr(ast::ExprNode::String(ast::StringData { loc: token.loc, value })))
+        } else if self.expect(TokenType::Char) {
+            let token = self.advance();
+            let value = self.unescape(&token)?;
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(value), None) => Ok(self.ast.alloc_expr(ast::ExprNode::Char(ast::CharData { loc: token.loc, value }))),
+                _ => {
+                    self.report(ParseError::WithMessage(String::from("Character literals must contain exactly one character."), token.loc));
+                    self.synchronize()
+                }
+            }
+        } else if self.expect(TokenType::Identifier) {
+            let name = self.parse_qual_name()?;
+            if self.expect(TokenType::LeftBrace) && !self.struct_literals && self.starts_struct_literal_field() {
+                // Most likely a struct literal in a condition, which is parsed anyway so that the error can say so.
+                let literal = self.parse_struct_literal(name)?;
+                let loc = literal.loc;
+                self.report(ParseError::WithFix(
+                    String::from("Struct literals are not allowed before the body of an 'if', 'while' or 'for' unless they are in parentheses."),
+                    loc,
+                    Fix {
+                        message: String::from("Wrap it in parentheses."),
+                        edits: vec![TextEdit { loc: loc.start(), replacement: String::from("(") }, TextEdit { loc: loc.end(), replacement: String::from(")") }],
+                        applicability: Applicability::MachineApplicable
+                    }
+                ));
+                Ok(self.ast.alloc_expr(ast::ExprNode::StructLiteral(literal)))
+            } else if self.expect(TokenType::LeftBrace) && self.struct_literals {
+                let literal = self.parse_struct_literal(name)?;
+                Ok(self.ast.alloc_expr(ast::ExprNode::StructLiteral(literal)))
+            } else {
+                let loc = self.ast.loc(name);
+                Ok(self.ast.alloc_expr(ast::ExprNode::Name(ast::NameData { loc, name })))
+            }
         } else if self.expect(TokenType::LeftParenthesis) {
             self.consume(TokenType::LeftParenthesis)?;
-            let expr = self.parse_expr()?;
+            let expr = self.parse_nested_expr()?;
             self.consume(TokenType::RightParenthesis)?;
             Ok(expr)
+        } else if self.expect(TokenType::VerticalBar) {
+            let lambda = self.parse_lambda()?;
+            Ok(self.ast.alloc_expr(ast::ExprNode::Lambda(lambda)))
+        } else if self.expect(TokenType::LeftBracket) {
+            let array = self.parse_array()?;
+            Ok(self.ast.alloc_expr(ast::ExprNode::Array(array)))
         } else {
-            self.errors.add_error(ParseError::WithMessage(String::from("Expected an expression."), self.curr().loc));
+            self.report(ParseError::WithMessage(String::from("Expected an expression."), self.curr().loc));
             self.synchronize()
         }
     }
 
+    fn parse_lambda(&mut self) -> ParseResult<ast::LambdaData> {
+        let start = self.consume(TokenType::VerticalBar)?;
+        let mut parameters = Vec::new();
+        while !self.expect(TokenType::VerticalBar) {
+            let name = self.consume(TokenType::Identifier)?;
+            let typ = if self.expect(TokenType::Colon) {
+                self.consume(TokenType::Colon)?;
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+            let loc = typ.map_or(name.loc, |t| name.loc.combine(&self.ast.loc(t)));
+            parameters.push(ast::LambdaParameter { loc, name: name.text, typ });
+            if !self.expect(TokenType::Comma) {
+                break;
+            } else {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+        self.consume(TokenType::VerticalBar)?;
+        let body = self.parse_expr()?;
+        Ok(ast::LambdaData { loc: start.loc.combine(&self.ast.loc(body)), parameters, body })
+    }
+
+    fn parse_array(&mut self) -> ParseResult<ast::ArrayData> {
+        let start = self.consume(TokenType::LeftBracket)?;
+        let mut elements = Vec::new();
+        while !self.expect(TokenType::RightBracket) {
+            elements.push(self.parse_nested_expr()?);
+            if !self.expect(TokenType::Comma) {
+                break;
+            } else {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+        let end = self.consume(TokenType::RightBracket)?;
+        Ok(ast::ArrayData { loc: start.loc_range(&end), elements })
+    }
+
+    /// Parses an expression in parentheses or brackets, where struct literals are allowed even in a condition.
+    fn parse_nested_expr(&mut self) -> ParseResult<ast::ExprId> {
+        let outer = std::mem::replace(&mut self.struct_literals, true);
+        let expr = self.parse_expr();
+        self.struct_literals = outer;
+        return expr;
+    }
+
+    /// Whether the '{' at the current token is followed by `name:`, as the first field of a struct literal is.
+    fn starts_struct_literal_field(&self) -> bool {
+        let token_type = |offset: usize| self.tokens.get(self.curr_index + offset).map(|t| t.token_type);
+        return token_type(1) == Some(TokenType::Identifier) && token_type(2) == Some(TokenType::Colon);
+    }
+
+    fn parse_struct_literal(&mut self, name: ast::NameId) -> ParseResult<ast::StructLiteralData> {
+        self.consume(TokenType::LeftBrace)?;
+        let mut fields = Vec::new();
+        while !self.expect(TokenType::RightBrace) {
+            let field = self.consume(TokenType::Identifier)?;
+            self.consume(TokenType::Colon)?;
+            let value = self.parse_expr()?;
+            fields.push(ast::StructLiteralField { loc: field.loc.combine(&self.ast.loc(value)), name: field.text, name_loc: field.loc, value });
+            if !self.expect(TokenType::Comma) {
+                break;
+            } else {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+        let end = self.consume(TokenType::RightBrace)?;
+        Ok(ast::StructLiteralData { loc: self.ast.loc(name).combine(&end.loc), name, fields })
+    }
+
+    /// The contents of a string or character literal token, with its quotes removed and escape
+    /// sequences replaced.
+    fn unescape(&mut self, token: &Token) -> ParseResult<String> {
+        let mut value = String::new();
+        let inner = &token.text[1..token.text.len() - 1];
+        let mut chars = inner.char_indices();
+        while let Some((i, chr)) = chars.next() {
+            if chr != '\\' {
+                value.push(chr);
+                continue;
+            }
+            let escaped = chars.next().map(|(_, c)| c);
+            value.push(match escaped {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '\'' | '"')) => c,
+                _ => {
+                    let sequence = &inner[i..i + 1 + escaped.map_or(0, char::len_utf8)];
+                    let start = token.loc.lo as usize + 1 + i;
+                    let loc = Span::new(self.file, start, start + sequence.len());
+                    self.report(ParseError::WithMessage(format!("Unknown escape sequence '{}'.", sequence), loc));
+                    return self.synchronize();
+                }
+            });
+        }
+        Ok(value)
+    }
+
     fn parse_integer(&mut self) -> ParseResult<ast::IntegerData> {
         let num_str = self.consume(TokenType::Integer)?;
         let num = match num_str.text.parse::<u64>() {
             Ok(n) => n,
             Err(_) => {
-                self.errors.add_error(ParseError::CouldNotParseLiteral(TokenType::Integer, num_str.get_loc().clone()));
+                self.report(ParseError::CouldNotParseLiteral(TokenType::Integer, num_str.get_loc()));
                 self.synchronize()?
             }
         };
         Ok(ast::IntegerData {
-            loc: num_str.get_loc().clone(),
+            loc: num_str.get_loc(),
             integer: num
         })
     }
 
-    fn parse_qual_name(&mut self) -> ParseResult<Box<ast::QualifiedNameNode>> {
+    fn parse_qual_name(&mut self) -> ParseResult<ast::NameId> {
         let name = self.consume(TokenType::Identifier)?;
-        let mut left = Box::from(ast::QualifiedNameNode::Name(ast::QualNameData { loc: name.get_loc().clone(), name: name.text }));
+        let mut left = self.ast.alloc_name(ast::QualifiedNameNode::Name(ast::QualNameData { loc: name.get_loc(), name: name.text }));
         loop {
             if self.expect_symbol(TokenType::Colon, TokenType::Colon) {
                 self.advance(); self.advance();
-                let attr_name = self.consume(TokenType::Identifier)?;
-                left = Box::from(ast::QualifiedNameNode::Namespace(ast::QualNamespaceData {
-                    loc: left.get_loc().combine(attr_name.get_loc()),
-                    source: left,
-                    attr: attr_name.text
-                }))
+            } else if self.expect_symbol(TokenType::Colon, TokenType::Identifier) {
+                let colon = self.advance();
+                self.report(ParseError::WithFix(
+                    String::from("Expected a '::' between the parts of a qualified name."),
+                    colon.loc,
+                    Fix::new("Replace ':' with '::'.", colon.loc, "::", Applicability::MachineApplicable)
+                ));
             } else {
                 break;
             }
+            let attr_name = self.consume(TokenType::Identifier)?;
+            left = self.ast.alloc_name(ast::QualifiedNameNode::Namespace(ast::QualNamespaceData {
+                loc: self.ast.loc(left).combine(&attr_name.get_loc()),
+                source: left,
+                attr: attr_name.text
+            }))
         }
         Ok(left)
     }
 
-    fn parse_type(&mut self) -> ParseResult<Box<ast::TypeNode>> {
+    fn parse_type(&mut self) -> ParseResult<ast::TypeId> {
         let typ = self.parse_type_terminal()?;
         if self.expect(TokenType::Ampersand) {
             let tok = self.consume(TokenType::Ampersand)?;
-            Ok(Box::from(ast::TypeNode::Reference(ast::TypeReferenceData {
-                loc: typ.get_loc().combine(tok.get_loc()),
+            Ok(self.ast.alloc_type(ast::TypeNode::Reference(ast::TypeReferenceData {
+                loc: self.ast.loc(typ).combine(&tok.get_loc()),
                 typ
             })))
         } else {
@@ -403,21 +1174,47 @@ impl Parser {
         }
     }
 
-    fn parse_type_terminal(&mut self) -> ParseResult<Box<ast::TypeNode>> {
-        if self.expect(TokenType::LeftParenthesis) {
-            Ok(Box::from(ast::TypeNode::Function(self.parse_function_type()?)))
+    fn parse_type_terminal(&mut self) -> ParseResult<ast::TypeId> {
+        let typ = if self.expect(TokenType::LeftParenthesis) {
+            ast::TypeNode::Function(self.parse_function_type()?)
+        } else if self.expect(TokenType::LeftBracket) {
+            ast::TypeNode::Array(self.parse_array_type()?)
         } else {
-            Ok(Box::from(ast::TypeNode::Name(self.parse_name_type()?)))
-        }
+            ast::TypeNode::Name(self.parse_name_type()?)
+        };
+        Ok(self.ast.alloc_type(typ))
     }
 
     fn parse_name_type(&mut self) -> ParseResult<ast::TypeNameData> {
         let name = self.parse_qual_name()?;
-        Ok(ast::TypeNameData {
-            loc: name.get_loc().clone(),
-            name,
-            generic_arguments: None
-        })
+        let mut loc = self.ast.loc(name);
+        let generic_arguments = if self.expect(TokenType::LeftAngle) {
+            self.consume(TokenType::LeftAngle)?;
+            let mut arguments = Vec::new();
+            while !self.expect(TokenType::RightAngle) {
+                arguments.push(self.parse_type()?);
+                if !self.expect(TokenType::Comma) {
+                    break;
+                } else {
+                    self.consume(TokenType::Comma)?;
+                }
+            }
+            let end = self.consume(TokenType::RightAngle)?;
+            loc = loc.combine(&end.loc);
+            Some(arguments)
+        } else {
+            None
+        };
+        Ok(ast::TypeNameData { loc, name, generic_arguments })
+    }
+
+    fn parse_array_type(&mut self) -> ParseResult<ast::TypeArrayData> {
+        let start = self.consume(TokenType::LeftBracket)?;
+        let element = self.parse_type()?;
+        self.consume(TokenType::Semicolon)?;
+        let length = self.parse_nested_expr()?;
+        let end = self.consume(TokenType::RightBracket)?;
+        Ok(ast::TypeArrayData { loc: start.loc_range(&end), element, length })
     }
 
     fn parse_function_type(&mut self) -> ParseResult<ast::TypeFunctionData> {
@@ -438,9 +1235,63 @@ impl Parser {
         let output = self.parse_type()?;
 
         return Ok(ast::TypeFunctionData {
-            loc : start.get_loc().combine(output.get_loc()),
+            loc : start.get_loc().combine(&self.ast.loc(output)),
             arguments: inputs,
             ret: output
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::CompilerState;
+    use crate::options::Options;
+    use crate::source::{Path, PathBuf};
+    use crate::vfs::OverlayFileSystem;
+    use super::parse_program;
+
+    /// Parses the program starting at `/project/main.adze` among the given files, and returns the first line of
+    /// each error and note in the report, or nothing if it parses.
+    fn load(files: &[(&str, &str)], args: &[&str]) -> Vec<String> {
+        let mut fs = OverlayFileSystem::in_memory();
+        for (path, text) in files {
+            fs.add_file(Path::new(path), String::from(*text));
+        }
+        let options = Options::from_args(args.iter().map(|arg| String::from(*arg))).unwrap();
+        let mut state = CompilerState::with_file_system(options, Box::new(fs));
+        let Err(errors) = parse_program(&mut state, PathBuf::from("/project/main.adze")) else {
+            return Vec::new();
+        };
+        return errors.render(&state.sources, None).lines()
+            .map(|line| line.trim_start_matches([' ', '|']))
+            .filter(|line| line.starts_with("Error: ") || line.starts_with("Note: ") || line.starts_with("= Note: "))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn files_that_cannot_be_loaded_are_reported() {
+        assert_eq!(load(&[], &[]), ["Error: Could not read from file '/project/main.adze': the file does not exist."]);
+        assert_eq!(load(&[("/project/main.adze", "import util;")], &["-L", "/lib"]), [
+            "Error: Could not read from file '/project/util.adze': the file does not exist.",
+            "= Note: Also looked for '/lib/util.adze'."
+        ]);
+    }
+
+    #[test]
+    fn imports_are_found_in_library_paths() {
+        let files = [("/project/main.adze", "import util;"), ("/lib/util.adze", "fn one() -> int { return 1; }")];
+        assert_eq!(load(&files, &["-L", "/lib"]), Vec::<String>::new());
+        assert_eq!(load(&files, &["-L/other", "-L/lib"]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn import_cycles_are_reported() {
+        let files = [("/project/main.adze", "import a;"), ("/project/a.adze", "import b;"), ("/project/b.adze", "import a;")];
+        assert_eq!(load(&files, &[]), [
+            "Error: Import cycle: /project/a.adze -> /project/b.adze -> /project/a.adze.",
+            "Note: '/project/a.adze' imports '/project/b.adze' here.",
+            "Note: '/project/b.adze' imports '/project/a.adze' here."
+        ]);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::source::Span;
+use crate::error::{CompilerError, ErrorDisplay, Severity, Fix};
+
+
+pub enum ResolveError {
+    /// A name that is not declared in any enclosing scope or, when `module` is given, in that module.
+    /// The fix replaces it with a similar name that is declared there, if there is one.
+    Undefined { name: String, module: Option<String>, loc: Span, suggestion: Option<Fix> },
+    Duplicate { name: String, loc: Span, previous: Option<Span> },
+    NotAModule { name: String, loc: Span },
+    /// A local variable or parameter of an enclosing function used in a lambda, which cannot capture them.
+    CapturedLocal { name: String, loc: Span, declared: Option<Span> }
+}
+
+
+impl CompilerError for ResolveError {
+    fn render(&self, display: &mut ErrorDisplay) -> String {
+        use ResolveError::*;
+        return match self {
+            Undefined { name, module, loc, suggestion } => {
+                let message = match module {
+                    Some(module) => format!("Module '{}' has no item named '{}'.", module, name),
+                    None => format!("Undefined name '{}'.", name)
+                };
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(fix) = suggestion {
+                    msg.push_str(&display.fix(fix));
+                }
+                msg
+            },
+            Duplicate { name, loc, previous } => {
+                let mut msg = display.error_with_location(Severity::Error, &format!("'{}' is already declared in this scope.", name), loc);
+                if let Some(previous) = previous {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, "The previous declaration is here.", previous)
+                    }));
+                }
+                msg
+            },
+            NotAModule { name, loc } => {
+                display.error_with_location(Severity::Error, &format!("'{}' is not an imported module.", name), loc)
+            },
+            CapturedLocal { name, loc, declared } => {
+                let message = format!("Lambdas cannot use '{}', which is a local variable of the enclosing function.", name);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, &format!("'{}' is declared here.", name), declared)
+                    }));
+                }
+                msg.push_str(&display.help("Pass it to the lambda as a parameter instead."));
+                msg
+            }
+        }
+    }
+
+    fn location(&self) -> Option<Span> {
+        use ResolveError::*;
+        match self {
+            Undefined { loc, .. } => Some(*loc),
+            Duplicate { loc, .. } => Some(*loc),
+            NotAModule { loc, .. } => Some(*loc),
+            CapturedLocal { loc, .. } => Some(*loc)
+        }
+    }
+
+    fn fix(&self) -> Option<&Fix> {
+        match self {
+            ResolveError::Undefined { suggestion, .. } => suggestion.as_ref(),
+            _ => None
+        }
+    }
+}
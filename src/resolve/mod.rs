@@ -0,0 +1,4 @@
+mod error;
+mod resolver;
+
+pub use resolver::{resolve_program, Resolution, DeclKind, DeclId, Site};
\ No newline at end of file
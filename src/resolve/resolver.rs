@@ -0,0 +1,634 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::source::{Span, FileId, HasLoc};
+use crate::error::{ErrorSet, Fix, Applicability};
+use crate::resolve::error::ResolveError;
+
+
+/// The names of the types that every file can use without importing them.
+pub const PRIMITIVE_TYPES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "int", "u8", "u16", "u32", "u64", "f32", "f64", "bool", "char", "str", "unit"
+];
+
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct DeclId(pub u32);
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DeclKind {
+    Primitive,
+    Import(FileId),
+    Struct,
+    Trait,
+    Function,
+    Const,
+    /// A function declared in the body of a struct or trait, which is found through the type of the
+    /// value it is called on rather than by name.
+    Method,
+    GenericParameter,
+    /// The name `Self` in the body of the given struct or trait.
+    SelfType(DeclId),
+    Parameter,
+    Local
+}
+
+/// Where in the program a declaration is made, by the node that declares it.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Site {
+    /// A top-level declaration or a method.
+    Item(ast::ItemId),
+    /// A generic parameter of a struct, function or method, by its position.
+    GenericParameter(ast::ItemId, u32),
+    /// A parameter of a function or method, by its position.
+    Parameter(ast::ItemId, u32),
+    /// A parameter of a lambda, by its position.
+    LambdaParameter(ast::ExprId, u32),
+    /// The variable declared by a `let` statement or a `for` loop.
+    Local(ast::StmtId)
+}
+
+pub struct Declaration {
+    pub name: String,
+    pub kind: DeclKind,
+    /// The span of the name where it is declared, or nothing for built-in declarations.
+    pub loc: Option<Span>
+}
+
+
+/// The result of name resolution: every declaration in the program, and the declaration each name
+/// refers to.
+pub struct Resolution {
+    pub declarations: Vec<Declaration>,
+    /// Maps each name that refers to a declaration to the declaration. A qualified name such as `a.b` refers
+    /// to the declaration of `b`, and its part `a` to the import.
+    pub uses: HashMap<ast::NameId, DeclId>,
+    /// Maps the site of each declaration in the program to the declaration.
+    pub definitions: HashMap<Site, DeclId>,
+    /// The top-level declarations of each file, by name.
+    pub modules: HashMap<FileId, HashMap<String, DeclId>>,
+    pub primitives: HashMap<String, DeclId>
+}
+
+impl Resolution {
+    pub fn declaration(&self, id: DeclId) -> &Declaration {
+        &self.declarations[id.0 as usize]
+    }
+
+    /// The declaration a name refers to.
+    pub fn use_of(&self, name: ast::NameId) -> Option<DeclId> {
+        self.uses.get(&name).copied()
+    }
+
+    /// The declaration made at a site.
+    pub fn definition(&self, site: Site) -> Option<DeclId> {
+        self.definitions.get(&site).copied()
+    }
+
+    /// The declaration of a top-level declaration or method.
+    pub fn item(&self, id: ast::ItemId) -> Option<DeclId> {
+        self.definition(Site::Item(id))
+    }
+}
+
+
+pub fn resolve_program(program: &ast::Program) -> Result<Resolution, ErrorSet<ResolveError>> {
+    let mut resolver = Resolver {
+        program,
+        resolution: Resolution {
+            declarations: Vec::new(),
+            uses: HashMap::new(),
+            definitions: HashMap::new(),
+            modules: HashMap::new(),
+            primitives: HashMap::new()
+        },
+        scopes: Vec::new(),
+        lambda_scopes: Vec::new(),
+        current_file: None,
+        errors: ErrorSet::new()
+    };
+
+    for name in PRIMITIVE_TYPES {
+        let id = resolver.add_declaration(name, DeclKind::Primitive, None);
+        resolver.resolution.primitives.insert(String::from(*name), id);
+    }
+    for file in &program.files {
+        resolver.declare_top_levels(file);
+    }
+    for file in &program.files {
+        resolver.resolve_file(file);
+    }
+
+    if resolver.errors.is_empty() {
+        Ok(resolver.resolution)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+
+struct Resolver<'a> {
+    program: &'a ast::Program,
+    resolution: Resolution,
+    /// The scopes enclosing the current position, innermost last. Top-level items are not included.
+    scopes: Vec<HashMap<String, DeclId>>,
+    /// The index in `scopes` of the outermost scope of each lambda enclosing the current position.
+    lambda_scopes: Vec<usize>,
+    current_file: Option<FileId>,
+    errors: ErrorSet<ResolveError>
+}
+
+impl<'a> Resolver<'a> {
+    /// Adds a declaration, which is made at a site in the program whose name is at a span, unless it is built in.
+    fn add_declaration(&mut self, name: &str, kind: DeclKind, site: Option<(Site, Span)>) -> DeclId {
+        let id = DeclId(self.resolution.declarations.len() as u32);
+        self.resolution.declarations.push(Declaration { name: String::from(name), kind, loc: site.map(|(_, loc)| loc) });
+        if let Some((site, _)) = site {
+            self.resolution.definitions.insert(site, id);
+        }
+        return id;
+    }
+
+    /// Declares a name in the given scope, reporting it if the scope already has that name.
+    fn declare_in(&mut self, scope: &mut HashMap<String, DeclId>, name: &str, kind: DeclKind, site: Site, loc: Span) -> DeclId {
+        let id = self.add_declaration(name, kind, Some((site, loc)));
+        if let Some(previous) = scope.insert(String::from(name), id) {
+            let previous = self.resolution.declaration(previous).loc;
+            self.errors.add_error(ResolveError::Duplicate { name: String::from(name), loc, previous });
+        }
+        return id;
+    }
+
+    /// Declares a name in the innermost scope. Later declarations of the same name shadow earlier ones
+    /// unless `unique` is set, in which case they are reported.
+    fn declare_local(&mut self, name: &str, kind: DeclKind, site: Site, loc: Span, unique: bool) -> DeclId {
+        let mut scope = self.scopes.pop().unwrap();
+        let id = if unique {
+            self.declare_in(&mut scope, name, kind, site, loc)
+        } else {
+            let id = self.add_declaration(name, kind, Some((site, loc)));
+            scope.insert(String::from(name), id);
+            id
+        };
+        self.scopes.push(scope);
+        return id;
+    }
+
+    fn with_scope<F>(&mut self, func: F)
+        where F: FnOnce(&mut Self) {
+        self.scopes.push(HashMap::new());
+        func(self);
+        self.scopes.pop();
+    }
+
+    fn declare_top_levels(&mut self, file: &ast::File) {
+        let mut module = HashMap::new();
+        for id in file.top_level_ids() {
+            let site = Site::Item(ast::ItemId::TopLevel(id));
+            match &file[id] {
+                ast::TopLevelNode::Import(data) => {
+                    if let Some(imported) = data.file {
+                        let loc = file[data.path].get_loc();
+                        self.declare_in(&mut module, data.bound_name(file), DeclKind::Import(imported), site, loc);
+                    }
+                },
+                ast::TopLevelNode::Struct(data) => { self.declare_in(&mut module, &data.name, DeclKind::Struct, site, data.name_loc); },
+                ast::TopLevelNode::Trait(data) => { self.declare_in(&mut module, &data.name, DeclKind::Trait, site, data.name_loc); },
+                ast::TopLevelNode::Function(data) => { self.declare_in(&mut module, &data.name, DeclKind::Function, site, data.name_loc); },
+                ast::TopLevelNode::Const(data) => { self.declare_in(&mut module, &data.name, DeclKind::Const, site, data.name_loc); }
+            }
+        }
+        self.resolution.modules.insert(file.file, module);
+    }
+
+    fn resolve_file(&mut self, file: &'a ast::File) {
+        self.current_file = Some(file.file);
+        for id in file.top_level_ids() {
+            match &file[id] {
+                ast::TopLevelNode::Import(_) => { },
+                ast::TopLevelNode::Struct(data) => self.resolve_struct(id, data),
+                ast::TopLevelNode::Trait(data) => self.resolve_trait(id, data),
+                ast::TopLevelNode::Function(data) => self.resolve_function(ast::ItemId::TopLevel(id), data),
+                ast::TopLevelNode::Const(data) => {
+                    self.resolve_type(data.typ);
+                    self.resolve_expr(data.value);
+                }
+            }
+        }
+        self.current_file = None;
+    }
+
+    fn resolve_generic_parameters(&mut self, owner: ast::ItemId, parameters: &[ast::GenericParameter]) {
+        for (i, parameter) in parameters.iter().enumerate() {
+            self.declare_local(&parameter.name, DeclKind::GenericParameter, Site::GenericParameter(owner, i as u32), parameter.loc, true);
+        }
+        for parameter in parameters {
+            for bound in &parameter.bounds {
+                self.resolve_qual_name(*bound);
+            }
+        }
+    }
+
+    /// Declares `Self` in the innermost scope as the struct or trait declared at a top level.
+    fn declare_self_type(&mut self, id: ast::TopLevelId) {
+        if let Some(owner) = self.resolution.definition(Site::Item(ast::ItemId::TopLevel(id))) {
+            let id = self.add_declaration("Self", DeclKind::SelfType(owner), None);
+            self.scopes.last_mut().unwrap().insert(String::from("Self"), id);
+        }
+    }
+
+    fn resolve_struct(&mut self, id: ast::TopLevelId, data: &'a ast::StructData) {
+        self.with_scope(|s| {
+            s.declare_self_type(id);
+            s.resolve_generic_parameters(ast::ItemId::TopLevel(id), &data.generic_parameters);
+            if let Some(superstruct) = data.superstruct {
+                s.resolve_qual_name(superstruct);
+            }
+            for interface in &data.interfaces {
+                s.resolve_qual_name(*interface);
+            }
+            for field in &data.fields {
+                s.resolve_type(field.typ);
+            }
+            for (i, method) in data.methods.iter().enumerate() {
+                let item = ast::ItemId::Method(id, i as u32);
+                s.add_declaration(&method.name, DeclKind::Method, Some((Site::Item(item), method.name_loc)));
+                s.resolve_function(item, method);
+            }
+        });
+    }
+
+    fn resolve_trait(&mut self, id: ast::TopLevelId, data: &'a ast::TraitData) {
+        self.with_scope(|s| {
+            s.declare_self_type(id);
+            for (i, method) in data.methods.iter().enumerate() {
+                let item = ast::ItemId::Method(id, i as u32);
+                s.add_declaration(&method.name, DeclKind::Method, Some((Site::Item(item), method.name_loc)));
+                s.with_scope(|s| {
+                    for (j, parameter) in method.parameters.iter().enumerate() {
+                        s.resolve_type(parameter.typ);
+                        s.declare_local(&parameter.name, DeclKind::Parameter, Site::Parameter(item, j as u32), parameter.loc, true);
+                    }
+                    s.resolve_type(method.ret);
+                });
+            }
+        });
+    }
+
+    fn resolve_function(&mut self, id: ast::ItemId, data: &'a ast::FunctionData) {
+        self.with_scope(|s| {
+            s.resolve_generic_parameters(id, &data.generic_parameters);
+            s.with_scope(|s| {
+                for (i, parameter) in data.parameters.iter().enumerate() {
+                    s.resolve_type(parameter.typ);
+                    s.declare_local(&parameter.name, DeclKind::Parameter, Site::Parameter(id, i as u32), parameter.loc, true);
+                }
+                s.resolve_type(data.ret);
+                s.resolve_block(&data.body);
+            });
+        });
+    }
+
+    fn resolve_block(&mut self, block: &'a ast::BlockData) {
+        self.with_scope(|s| {
+            for stmt in &block.stmts {
+                s.resolve_stmt(*stmt);
+            }
+        });
+    }
+
+    fn resolve_stmt(&mut self, stmt: ast::StmtId) {
+        let program = self.program;
+        match &program[stmt] {
+            ast::StmtNode::Expr(data) => self.resolve_expr(data.expr),
+            ast::StmtNode::Return(data) => self.resolve_expr(data.expr),
+            ast::StmtNode::Let(data) => {
+                if let Some(typ) = data.typ {
+                    self.resolve_type(typ);
+                }
+                if let Some(value) = data.value {
+                    self.resolve_expr(value);
+                }
+                self.declare_local(&data.name, DeclKind::Local, Site::Local(stmt), data.name_loc, false);
+            },
+            ast::StmtNode::If(data) => {
+                self.resolve_expr(data.condition);
+                self.resolve_block(&data.body);
+                if let Some(else_body) = &data.else_body {
+                    self.resolve_block(else_body);
+                }
+            },
+            ast::StmtNode::While(data) => {
+                self.resolve_expr(data.condition);
+                self.resolve_block(&data.body);
+            },
+            ast::StmtNode::For(data) => {
+                match data.iterable {
+                    ast::ForIterable::Range(start, end) => {
+                        self.resolve_expr(start);
+                        self.resolve_expr(end);
+                    },
+                    ast::ForIterable::Array(array) => self.resolve_expr(array)
+                }
+                self.with_scope(|s| {
+                    s.declare_local(&data.name, DeclKind::Local, Site::Local(stmt), data.name_loc, false);
+                    s.resolve_block(&data.body);
+                });
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: ast::ExprId) {
+        let program = self.program;
+        match &program[expr] {
+            ast::ExprNode::Name(data) => { self.resolve_qual_name(data.name); },
+            ast::ExprNode::Integer(_) | ast::ExprNode::Float(_) | ast::ExprNode::Bool(_)
+            | ast::ExprNode::Char(_) | ast::ExprNode::String(_) => { },
+            ast::ExprNode::Block(data) => self.resolve_block(data),
+            ast::ExprNode::Call(data) => {
+                self.resolve_expr(data.callee);
+                for argument in &data.arguments {
+                    self.resolve_expr(*argument);
+                }
+            },
+            ast::ExprNode::Field(data) => self.resolve_expr(data.object),
+            ast::ExprNode::Assign(data) => {
+                self.resolve_expr(data.target);
+                self.resolve_expr(data.value);
+            },
+            ast::ExprNode::StructLiteral(data) => {
+                self.resolve_qual_name(data.name);
+                for field in &data.fields {
+                    self.resolve_expr(field.value);
+                }
+            },
+            ast::ExprNode::Unary(data) => self.resolve_expr(data.operand),
+            ast::ExprNode::Binary(data) => {
+                self.resolve_expr(data.left);
+                self.resolve_expr(data.right);
+            },
+            ast::ExprNode::Cast(data) => {
+                self.resolve_expr(data.expr);
+                self.resolve_type(data.typ);
+            },
+            ast::ExprNode::Is(data) => {
+                self.resolve_expr(data.expr);
+                self.resolve_type(data.typ);
+            },
+            ast::ExprNode::Lambda(data) => {
+                self.lambda_scopes.push(self.scopes.len());
+                self.with_scope(|s| {
+                    for (i, parameter) in data.parameters.iter().enumerate() {
+                        if let Some(typ) = parameter.typ {
+                            s.resolve_type(typ);
+                        }
+                        s.declare_local(&parameter.name, DeclKind::Parameter, Site::LambdaParameter(expr, i as u32), parameter.loc, true);
+                    }
+                    s.resolve_expr(data.body);
+                });
+                self.lambda_scopes.pop();
+            },
+            ast::ExprNode::Array(data) => {
+                for element in &data.elements {
+                    self.resolve_expr(*element);
+                }
+            },
+            ast::ExprNode::Index(data) => {
+                self.resolve_expr(data.object);
+                self.resolve_expr(data.index);
+            }
+        }
+    }
+
+    fn resolve_type(&mut self, typ: ast::TypeId) {
+        let program = self.program;
+        match &program[typ] {
+            ast::TypeNode::Name(data) => {
+                self.resolve_qual_name(data.name);
+                for argument in data.generic_arguments.iter().flatten() {
+                    self.resolve_type(*argument);
+                }
+            },
+            ast::TypeNode::Function(data) => {
+                for argument in &data.arguments {
+                    self.resolve_type(*argument);
+                }
+                self.resolve_type(data.ret);
+            },
+            ast::TypeNode::Reference(data) => self.resolve_type(data.typ),
+            ast::TypeNode::Array(data) => {
+                self.resolve_type(data.element);
+                self.resolve_expr(data.length);
+            }
+        }
+    }
+
+    fn resolve_qual_name(&mut self, name: ast::NameId) -> Option<DeclId> {
+        let program = self.program;
+        let id = match &program[name] {
+            ast::QualifiedNameNode::Name(data) => self.lookup(&data.name, data.loc)?,
+            ast::QualifiedNameNode::Namespace(data) => {
+                let source = self.resolve_qual_name(data.source)?;
+                let declaration = self.resolution.declaration(source);
+                let file = match declaration.kind {
+                    DeclKind::Import(file) => file,
+                    _ => {
+                        let name = declaration.name.clone();
+                        self.errors.add_error(ResolveError::NotAModule { name, loc: program[data.source].get_loc() });
+                        return None;
+                    }
+                };
+                let module_name = declaration.name.clone();
+                let module = &self.resolution.modules[&file];
+                match module.get(&data.attr) {
+                    Some(id) => *id,
+                    None => {
+                        let attr_loc = Span { file: data.loc.file, lo: data.loc.hi - data.attr.len() as u32, hi: data.loc.hi };
+                        let suggestion = suggest(&data.attr, module.keys(), attr_loc);
+                        self.errors.add_error(ResolveError::Undefined { name: data.attr.clone(), module: Some(module_name), loc: data.loc, suggestion });
+                        return None;
+                    }
+                }
+            }
+        };
+        self.resolution.uses.insert(name, id);
+        return Some(id);
+    }
+
+    /// Looks up an unqualified name in the enclosing scopes, then the current file, then the primitives.
+    fn lookup(&mut self, name: &str, loc: Span) -> Option<DeclId> {
+        let local = self.scopes.iter().enumerate().rev().find_map(|(i, scope)| Some((i, *scope.get(name)?)));
+        if let (Some((depth, id)), Some(boundary)) = (local, self.lambda_scopes.last()) {
+            let declaration = self.resolution.declaration(id);
+            if depth < *boundary && matches!(declaration.kind, DeclKind::Local | DeclKind::Parameter) {
+                self.errors.add_error(ResolveError::CapturedLocal { name: String::from(name), loc, declared: declaration.loc });
+            }
+        }
+        let module = &self.resolution.modules[&self.current_file.unwrap()];
+        let found = local.map(|(_, id)| id)
+            .or_else(|| module.get(name).copied())
+            .or_else(|| self.resolution.primitives.get(name).copied());
+        if found.is_some() {
+            return found;
+        }
+
+        let candidates = self.scopes.iter().flat_map(|scope| scope.keys())
+            .chain(module.keys())
+            .chain(self.resolution.primitives.keys());
+        let suggestion = suggest(name, candidates, loc);
+        self.errors.add_error(ResolveError::Undefined { name: String::from(name), module: None, loc, suggestion });
+        return None;
+    }
+}
+
+
+/// A fix that replaces a misspelled name with the most similar candidate, if any is close enough.
+fn suggest<'a, I>(name: &str, candidates: I, loc: Span) -> Option<Fix>
+    where I: Iterator<Item=&'a String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let best = candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()?;
+    return Some(Fix::new(&format!("Did you mean '{}'?", best.1), loc, best.1, Applicability::MaybeIncorrect));
+}
+
+/// The edit distance between two strings, counted in characters, where swapping two adjacent
+/// characters counts as a single edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i-1] == b[j-1] { 0 } else { 1 };
+            let mut distance = (distances[i-1][j] + 1).min(distances[i][j-1] + 1).min(distances[i-1][j-1] + cost);
+            if i > 1 && j > 1 && a[i-1] == b[j-2] && a[i-2] == b[j-1] {
+                distance = distance.min(distances[i-2][j-2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    return distances[a.len()][b.len()];
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{ItemId, ExprNode, StmtNode, StmtLetData, TopLevelNode};
+    use crate::options::Options;
+    use crate::parser::parse_text;
+    use super::*;
+
+    /// The messages of the errors for a program read as if from standard input, each followed by its help.
+    fn errors(text: &str) -> Vec<String> {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        return result.report.lines()
+            .filter_map(|line| line.strip_prefix("Error: ").or_else(|| line.trim_start().strip_prefix("= Help: ")))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn undefined_names_are_reported_with_suggestions() {
+        assert_eq!(errors("fn main() -> int { let count = 1; return cuont; }"), ["Undefined name 'cuont'.", "Did you mean 'count'?"]);
+        assert_eq!(errors("fn main() -> Point { return 0; }"), ["Undefined name 'Point'."]);
+        assert_eq!(errors("fn main() -> int { return util::x; }"), ["Undefined name 'util'."]);
+        // A local is not in scope after the block it is declared in.
+        assert_eq!(errors("fn main() -> int { if true { let inner = 1; } return inner; }"), ["Undefined name 'inner'."]);
+    }
+
+    #[test]
+    fn duplicate_declarations_are_reported() {
+        assert_eq!(errors("fn f() -> int { return 1; } fn f() -> int { return 2; }"), ["'f' is already declared in this scope."]);
+        assert_eq!(errors("fn f(a: int, a: int) -> int { return a; }"), ["'a' is already declared in this scope."]);
+        assert_eq!(errors("struct S { x: int; x: int; }"), ["Field 'x' is already declared in this struct."]);
+    }
+
+    #[test]
+    fn lambdas_cannot_use_enclosing_locals() {
+        assert_eq!(errors("fn main() -> int { let a = 1; let f = || a; return f(); }"), [
+            "Lambdas cannot use 'a', which is a local variable of the enclosing function.",
+            "Pass it to the lambda as a parameter instead."
+        ]);
+    }
+
+    #[test]
+    fn locals_shadow_earlier_declarations() {
+        let program = parse_text("fn main() -> int {
+            let a = 1;
+            let a = a + 1;
+            if true { let a = 3; return a; }
+            return a;
+        }");
+        let Ok(resolution) = resolve_program(&program) else {
+            panic!("the program could not be resolved");
+        };
+        let main = program.top_level_ids().next().unwrap();
+        let TopLevelNode::Function(data) = &program[main] else {
+            panic!("expected a function");
+        };
+        let stmts = &data.body.stmts;
+        let use_in = |stmt: ast::StmtId| {
+            let StmtNode::Return(data) = &program[stmt] else {
+                panic!("expected a return statement");
+            };
+            let ExprNode::Name(name) = &program[data.expr] else {
+                panic!("expected a name");
+            };
+            return resolution.use_of(name.name);
+        };
+        let StmtNode::If(branch) = &program[stmts[2]] else {
+            panic!("expected an if statement");
+        };
+        assert_eq!(use_in(branch.body.stmts[1]), resolution.definition(Site::Local(branch.body.stmts[0])));
+        assert_eq!(use_in(stmts[3]), resolution.definition(Site::Local(stmts[1])));
+        assert_ne!(resolution.definition(Site::Local(stmts[0])), resolution.definition(Site::Local(stmts[1])));
+    }
+
+    #[test]
+    fn declarations_are_found_by_site() {
+        let program = parse_text("
+            struct Cell<T> { value: T; fn get(self: Self&) -> T { return self.value; } }
+            fn main() -> int {
+                let f = |x: int| x;
+                return f(1);
+            }");
+        let Ok(resolution) = resolve_program(&program) else {
+            panic!("the program could not be resolved");
+        };
+        let name_at = |site| resolution.definition(site).map(|id| resolution.declaration(id).name.as_str());
+        let ids: Vec<ast::TopLevelId> = program.top_level_ids().collect();
+        let (cell, main) = (ItemId::TopLevel(ids[0]), ids[1]);
+        let get = ItemId::Method(ids[0], 0);
+        assert_eq!(name_at(Site::Item(cell)), Some("Cell"));
+        assert_eq!(name_at(Site::GenericParameter(cell, 0)), Some("T"));
+        assert_eq!(name_at(Site::Item(get)), Some("get"));
+        assert_eq!(name_at(Site::Parameter(get, 0)), Some("self"));
+
+        let TopLevelNode::Function(data) = &program[main] else {
+            panic!("expected a function");
+        };
+        let (declare, ret) = (data.body.stmts[0], data.body.stmts[1]);
+        assert_eq!(name_at(Site::Local(declare)), Some("f"));
+        let StmtNode::Let(StmtLetData { value: Some(lambda), .. }) = &program[declare] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(name_at(Site::LambdaParameter(*lambda, 0)), Some("x"));
+
+        let StmtNode::Return(data) = &program[ret] else {
+            panic!("expected a return statement");
+        };
+        let ExprNode::Call(call) = &program[data.expr] else {
+            panic!("expected a call");
+        };
+        let ExprNode::Name(callee) = &program[call.callee] else {
+            panic!("expected a name");
+        };
+        assert_eq!(resolution.use_of(callee.name), resolution.definition(Site::Local(declare)));
+    }
+}
@@ -1,19 +1,17 @@
-extern crate dunce;
-
 use std::fmt::Debug;
-use std::fs::File;
-use std::io::Read;
 use std::ops::Range;
 pub use std::path::{PathBuf, Path};
-use std::rc::Rc;
 use std::cmp::{min, max};
 
+use crate::vfs::{FileSystem, LoadError};
+
 
 #[derive(Debug)]
 pub struct Source {
     pub path: Option<PathBuf>,
     pub name: String,
     pub text: String,
+    /// The byte range of each line, including its line break.
     pub lines: Vec<Range<usize>>
 }
 
@@ -25,69 +23,160 @@ impl PartialEq<Self> for Source {
 
 
 impl Source {
-    pub fn from_file(path: &Path) -> Option<Source> {
-        let abs_path = dunce::canonicalize(path).ok()?;
+    pub fn from_file(fs: &dyn FileSystem, path: &Path) -> Result<Source, LoadError> {
+        let abs_path = fs.canonicalize(path)?;
+        let text = fs.read_to_string(&abs_path)?;
 
-        let mut file = File::open(&abs_path).ok()?;
-        let name = abs_path.display().to_string();
-        let mut text = String::new();
-        file.read_to_string(&mut text).ok()?;
+        let mut source = Source::from_string(&abs_path.display().to_string(), text);
+        source.path = Some(abs_path);
+        return Ok(source);
+    }
+
+    /// A source that does not correspond to a file, such as a string in a test.
+    pub fn from_string(name: &str, text: String) -> Source {
+        let lines = Source::find_lines(&text);
+        return Source { path: None, name: String::from(name), text, lines };
+    }
 
+    fn find_lines(text: &str) -> Vec<Range<usize>> {
         let mut lines = Vec::new();
         let mut line_start = 0;
-        for (i, chr) in text.chars().enumerate() {
+        for (i, chr) in text.char_indices() {
             if chr == '\n' {
                 lines.push(line_start..i+1);
                 line_start = i+1;
             }
         }
         lines.push(line_start..text.len());
+        return lines;
+    }
+
+    pub fn get_line(&self, index: usize) -> &str {
+        &self.text[self.lines[index].clone()]
+    }
 
-        return Some(Source { path: Some(abs_path), name, text, lines });
+    /// The index of the line containing the byte offset `pos`.
+    pub fn line_of(&self, pos: usize) -> usize {
+        return self.lines.partition_point(|line| line.end <= pos).min(self.lines.len() - 1);
     }
 
-    pub fn get_line(&self, index: usize) -> String {
-        self.text[self.lines[index].clone()].to_owned()
+    /// The zero-based line and column of the byte offset `pos`, where the column counts characters.
+    pub fn line_col(&self, pos: usize) -> (usize, usize) {
+        let line = self.line_of(pos);
+        let line_start = self.lines[line].start;
+        let column = self.text[line_start..pos.min(self.text.len())].chars().count();
+        return (line, column);
     }
 }
 
 
-#[derive(Clone, Debug)]
-pub struct Location {
-    pub source: Rc<Source>,
-    pub line: usize,
-    pub offset: usize,
-    pub length: usize,
-    pub multiline: bool
+/// Identifies a source file within a `SourceMap`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct FileId(pub u32);
+
+
+/// Owns every source file in a compilation, and hands out the `FileId`s that spans refer to.
+pub struct SourceMap {
+    sources: Vec<Source>
 }
 
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap { sources: Vec::new() }
+    }
 
-impl Location {
-    pub fn new(source: Rc<Source>, line: usize, offset: usize, length: usize) -> Location {
-        Location { source, line, offset, length, multiline: false }
+    pub fn add(&mut self, source: Source) -> FileId {
+        self.sources.push(source);
+        return FileId(self.sources.len() as u32 - 1);
     }
 
-    pub fn new_multiline(source: Rc<Source>, line: usize, offset: usize, length: usize) -> Location {
-        Location { source, line, offset, length, multiline: true}
+    pub fn get(&self, file: FileId) -> &Source {
+        &self.sources[file.0 as usize]
     }
 
-    pub fn new_eof(source: Rc<Source>) -> Location {
-        Location { line: source.lines.len()-1, offset: source.lines.last().unwrap().end, source, length: 1, multiline: false }
+    pub fn find_path(&self, path: &Path) -> Option<FileId> {
+        let index = self.sources.iter().position(|s| s.path.as_deref() == Some(path))?;
+        return Some(FileId(index as u32));
     }
 
-    pub fn combine(&self, other: &Location) -> Location {
-        if self.source != other.source { panic!("Sources must be the same") }
+    pub fn files(&self) -> impl Iterator<Item=(FileId, &Source)> {
+        self.sources.iter().enumerate().map(|(i, s)| (FileId(i as u32), s))
+    }
 
-        return Location {
-            source: Rc::clone(&self.source),
-            line: min(self.line, other.line),
-            offset: if (self.line, self.offset) <= (other.line, other.offset) { self.offset } else { other.offset },
-            length: max(self.offset + self.length, other.offset + self.length) - min(self.offset, other.offset),
-            multiline: self.line != other.line
-        }
+    /// The text covered by a span.
+    pub fn text(&self, span: Span) -> &str {
+        &self.get(span.file).text[span.lo as usize..span.hi as usize]
+    }
+}
+
+
+/// A range of bytes `lo..hi` in a source file. Line and column information is looked up from the
+/// `SourceMap` only when it is needed, such as when a diagnostic is rendered.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct Span {
+    pub file: FileId,
+    pub lo: u32,
+    pub hi: u32
+}
+
+
+impl Span {
+    pub fn new(file: FileId, lo: usize, hi: usize) -> Span {
+        Span { file, lo: lo as u32, hi: hi as u32 }
+    }
+
+    /// An empty span just before the first character of this one, for inserting text.
+    pub fn start(&self) -> Span {
+        Span { file: self.file, lo: self.lo, hi: self.lo }
+    }
+
+    /// An empty span just after the last character of this one, for inserting text.
+    pub fn end(&self) -> Span {
+        Span { file: self.file, lo: self.hi, hi: self.hi }
+    }
+
+    pub fn combine(&self, other: &Span) -> Span {
+        if self.file != other.file { panic!("Sources must be the same") }
+
+        return Span { file: self.file, lo: min(self.lo, other.lo), hi: max(self.hi, other.hi) }
     }
 }
 
 pub trait HasLoc {
-    fn get_loc(&self) -> &Location;
-}
\ No newline at end of file
+    fn get_loc(&self) -> Span;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Source, SourceMap, Span};
+
+    #[test]
+    fn lines_and_columns_are_found_from_offsets() {
+        let source = Source::from_string("test.adze", String::from("ab\nçd\n\nlast"));
+        assert_eq!(source.line_col(0), (0, 0));
+        assert_eq!(source.line_col(2), (0, 2));
+        assert_eq!(source.line_col(3), (1, 0));
+        // 'ç' is two bytes, but one column.
+        assert_eq!(source.line_col(5), (1, 1));
+        assert_eq!(source.line_col(7), (2, 0));
+        assert_eq!(source.line_col(11), (3, 3));
+        assert_eq!(source.line_col(12), (3, 4));
+        assert_eq!(source.get_line(1), "çd\n");
+    }
+
+    #[test]
+    fn spans_combine_to_cover_both() {
+        let mut sources = SourceMap::new();
+        let first = sources.add(Source::from_string("a.adze", String::from("let value = 1 + 2;")));
+        let second = sources.add(Source::from_string("b.adze", String::from("")));
+        assert_ne!(first, second);
+
+        let left = Span::new(first, 12, 13);
+        let right = Span::new(first, 16, 17);
+        assert_eq!(sources.text(left.combine(&right)), "1 + 2");
+        assert_eq!(right.combine(&left), left.combine(&right));
+        assert_eq!(sources.text(Span::new(first, 4, 9).combine(&Span::new(first, 6, 7))), "value");
+        assert_eq!((left.start().lo, left.start().hi, left.end().lo, left.end().hi), (12, 12, 13, 13));
+    }
+}
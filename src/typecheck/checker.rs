@@ -0,0 +1,2227 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::ast;
+use crate::source::Span;
+use crate::error::{ErrorSet, Fix, TextEdit, Applicability};
+use crate::resolve::{Resolution, DeclId, DeclKind, Site};
+use crate::typecheck::error::TypeError;
+use crate::typecheck::types::*;
+use crate::typecheck::constant::{ConstValue, FoldError, fold_unary, fold_binary, fold_cast};
+
+
+/// How deeply instances of generic functions may request further instances. This stops functions that
+/// instantiate themselves with ever larger types.
+const MAX_INSTANTIATION_DEPTH: usize = 64;
+
+
+/// Checks every function without generic parameters, and every instance of a generic function they
+/// use, directly or through other instances. The body of a generic function is checked once for each
+/// list of type arguments it is used with, with the arguments in place of its generic parameters, and
+/// is not checked at all if it is never used.
+///
+/// Within a body, the types of variables without annotations, of lambda parameters and of numeric literals
+/// are inferred from how they are used, as are the type arguments of generic functions and structs. Each
+/// unknown type starts out as an inference variable, which is decided by unifying it with the types it
+/// meets. Whatever is still undecided once the body has been checked is reported, except for numeric
+/// literals, which default to `int` and `f64`.
+pub fn check_program<'a>(program: &'a ast::Program, resolution: &'a Resolution) -> Result<TypeInfo, ErrorSet<TypeError>> {
+    let mut checker = Checker {
+        program,
+        resolution,
+        info: TypeInfo {
+            structs: HashMap::new(),
+            traits: HashMap::new(),
+            functions: HashMap::new(),
+            bounds: HashMap::new(),
+            constants: HashMap::new(),
+            instances: Vec::new(),
+            instance_ids: HashMap::new(),
+            struct_instances: Vec::new()
+        },
+        bodies: HashMap::new(),
+        constant_bodies: HashMap::new(),
+        evaluating: Vec::new(),
+        invalid_constants: HashSet::new(),
+        origins: Vec::new(),
+        queue: VecDeque::new(),
+        instance: None,
+        bindings: HashMap::new(),
+        annotations: HashMap::new(),
+        ret: None,
+        substitution: Vec::new(),
+        variable_origins: HashMap::new(),
+        pending_literals: Vec::new(),
+        pending_negations: Vec::new(),
+        deferred_uses: Vec::new(),
+        errors: ErrorSet::new()
+    };
+
+    let structs = program.top_level_ids().filter_map(|id| match &program[id] {
+        ast::TopLevelNode::Struct(data) => Some((id, data)),
+        _ => None
+    });
+    let traits = program.top_level_ids().filter_map(|id| match &program[id] {
+        ast::TopLevelNode::Trait(data) => Some((id, data)),
+        _ => None
+    });
+    let functions = program.top_level_ids().filter_map(|id| match &program[id] {
+        ast::TopLevelNode::Function(data) => Some((id, data)),
+        _ => None
+    });
+    let constants = program.top_level_ids().filter_map(|id| match &program[id] {
+        ast::TopLevelNode::Const(data) => Some((id, data)),
+        _ => None
+    });
+
+    // Constants are evaluated when they are first needed, which may be by the length of an array in a field.
+    for (id, data) in constants.clone() {
+        if let Some(id) = resolution.item(ast::ItemId::TopLevel(id)) {
+            checker.constant_bodies.insert(id, data);
+        }
+    }
+
+    // Every struct's generic parameters are needed to check the generic arguments of the types of fields.
+    for (id, data) in structs.clone() {
+        checker.declare_struct(id, data);
+    }
+    for (id, data) in structs.clone() {
+        checker.declare_superstruct(id, data);
+    }
+    checker.check_inheritance(structs.clone());
+    for (id, data) in traits {
+        checker.declare_trait(id, data);
+    }
+    // Superstructs come first, so that their fields are known when they are inherited.
+    let mut by_depth: Vec<(ast::TopLevelId, &ast::StructData)> = structs.clone().collect();
+    by_depth.sort_by_key(|(id, _)| checker.inheritance_depth(*id));
+    for (id, data) in by_depth {
+        checker.declare_fields(id, data);
+    }
+    for (id, _) in constants {
+        if let Some(id) = resolution.item(ast::ItemId::TopLevel(id)) {
+            checker.constant(id);
+        }
+    }
+    for (id, data) in functions.clone() {
+        checker.declare_function(id, data);
+    }
+    for (id, data) in structs.clone() {
+        checker.declare_methods(id, data);
+    }
+    for (id, data) in structs.clone() {
+        checker.check_implementations(id, data);
+    }
+    let methods = structs.flat_map(|(id, data)| (0..data.methods.len() as u32).map(move |i| ast::ItemId::Method(id, i)));
+    for item in functions.map(|(id, _)| ast::ItemId::TopLevel(id)).chain(methods) {
+        if let Some(id) = resolution.item(item) {
+            if checker.info.functions.get(&id).is_some_and(|s| s.generic_parameters.is_empty()) {
+                checker.instantiate(id, Vec::new(), None);
+            }
+        }
+    }
+    while let Some(id) = checker.queue.pop_front() {
+        checker.check_instance(id);
+    }
+    checker.collect_struct_instances();
+
+    if checker.errors.is_empty() {
+        Ok(checker.info)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+
+struct Checker<'a> {
+    program: &'a ast::Program,
+    resolution: &'a Resolution,
+    info: TypeInfo,
+    /// The declaration and body of each function and method.
+    bodies: HashMap<DeclId, (ast::ItemId, &'a ast::FunctionData)>,
+    constant_bodies: HashMap<DeclId, &'a ast::ConstData>,
+    /// The constants being evaluated, innermost last, for finding constants whose values depend on themselves.
+    evaluating: Vec<DeclId>,
+    /// The constants whose values could not be evaluated, which have been reported.
+    invalid_constants: HashSet<DeclId>,
+    /// For each instance, the span of the use that first requested it and the instance containing that
+    /// use. Instances of functions without generic parameters are not requested by anything.
+    origins: Vec<Option<(Span, InstanceId)>>,
+    /// Instances that have been requested but not checked yet.
+    queue: VecDeque<InstanceId>,
+    /// The instance being checked, and the types its function's generic parameters are bound to.
+    instance: Option<InstanceId>,
+    bindings: HashMap<DeclId, Type>,
+    /// The spans of the type annotations of variables, for pointing at the type a value must have.
+    annotations: HashMap<DeclId, Span>,
+    /// The return type of the function or lambda being checked, and the span of its annotation if it has one.
+    ret: Option<(Type, Option<Span>)>,
+    /// What each inference variable of the body being checked has been unified with, indexed by the
+    /// variable's id. Variables that have not been decided yet have no type.
+    substitution: Vec<(VariableKind, Option<Type>)>,
+    /// The variables and lambda parameters whose types are inference variables, with the spans of their names,
+    /// for suggesting where an annotation would decide the variable.
+    variable_origins: HashMap<u32, (String, Span)>,
+    /// Numeric literals whose types were not known when they were checked, with their values. They are
+    /// checked against the ranges of their types once the body has been checked.
+    pending_literals: Vec<(i128, Span, Type)>,
+    /// Negations of values whose types were not known when they were checked, which must turn out to be signed.
+    pending_negations: Vec<(Type, Span)>,
+    /// Uses of generic functions and structs, whose type arguments may still be undecided. They are
+    /// instantiated once the body has been checked.
+    deferred_uses: Vec<DeferredUse>,
+    errors: ErrorSet<TypeError>
+}
+
+/// A use of a generic function or struct, with the type arguments inferred for it. A use of a function is made
+/// by an expression, which is given the instance it refers to.
+struct DeferredUse {
+    declaration: DeclId,
+    arguments: Vec<Type>,
+    loc: Span,
+    expr: Option<ast::ExprId>
+}
+
+impl<'a> Checker<'a> {
+    fn display(&self, typ: &Type) -> String {
+        typ.display(self.resolution)
+    }
+
+    fn declaration_of(&self, name: ast::NameId) -> Option<DeclId> {
+        self.resolution.use_of(name)
+    }
+
+    /// Reports an error. Errors found while checking an instance of a generic function say which instance,
+    /// and how it came to be instantiated.
+    fn error(&mut self, error: TypeError) {
+        let chain = self.instantiation_chain();
+        if chain.is_empty() {
+            self.errors.add_error(error);
+        } else {
+            self.errors.add_error(TypeError::InInstantiation { error: Box::new(error), chain });
+        }
+    }
+
+    /// The instances of generic functions that led to the current one, innermost first, each with the
+    /// span of the use that requested it.
+    fn instantiation_chain(&self) -> Vec<(String, Span)> {
+        let mut chain = Vec::new();
+        let mut current = self.instance;
+        while let Some(id) = current {
+            match self.origins[id.0 as usize] {
+                Some((loc, parent)) => {
+                    chain.push((self.display_instance(id), loc));
+                    current = Some(parent);
+                },
+                None => break
+            }
+        }
+        return chain;
+    }
+
+    fn display_instance(&self, id: InstanceId) -> String {
+        let instance = self.info.instance(id);
+        self.display_generic(instance.function, &instance.arguments)
+    }
+
+    /// A function or struct as it would be written with explicit type arguments, such as `max<i8>`.
+    fn display_generic(&self, declaration: DeclId, arguments: &[Type]) -> String {
+        let name = &self.resolution.declaration(declaration).name;
+        if arguments.is_empty() {
+            return name.clone();
+        }
+        let arguments: Vec<String> = arguments.iter().map(|a| self.display(a)).collect();
+        return format!("{}<{}>", name, arguments.join(", "));
+    }
+
+    fn current(&mut self) -> &mut Instance {
+        &mut self.info.instances[self.instance.unwrap().0 as usize]
+    }
+
+    fn type_of(&self, expr: ast::ExprId) -> Option<Type> {
+        self.info.instance(self.instance?).expressions.get(&expr).map(|typ| self.resolve(typ))
+    }
+
+    /// Finds or creates the instance of a function with the given type arguments. New instances are
+    /// checked once the current one is finished.
+    fn instantiate(&mut self, function: DeclId, arguments: Vec<Type>, requested_at: Option<Span>) -> Option<InstanceId> {
+        if let Some(id) = self.info.find_instance(function, &arguments) {
+            return Some(id);
+        }
+        if let Some(loc) = requested_at {
+            if self.instantiation_chain().len() >= MAX_INSTANTIATION_DEPTH {
+                let instance = self.display_generic(function, &arguments);
+                self.error(TypeError::InstantiationDepth { instance, limit: MAX_INSTANTIATION_DEPTH, loc });
+                return None;
+            }
+        }
+        let id = InstanceId(self.info.instances.len() as u32);
+        self.info.instances.push(Instance {
+            function, arguments: arguments.clone(), variables: HashMap::new(), expressions: HashMap::new(), functions: HashMap::new()
+        });
+        self.origins.push(requested_at.zip(self.instance));
+        self.info.instance_ids.insert((function, arguments), id);
+        self.queue.push_back(id);
+        return Some(id);
+    }
+
+    fn lower_type(&mut self, typ: ast::TypeId) -> Type {
+        match &self.program[typ] {
+            ast::TypeNode::Name(data) => {
+                let id = match self.declaration_of(data.name) {
+                    Some(id) => id,
+                    None => return Type::Error
+                };
+                let declaration = self.resolution.declaration(id);
+                let arguments: Vec<Type> = data.generic_arguments.iter().flatten().map(|a| self.lower_type(*a)).collect();
+                let expected = match declaration.kind {
+                    DeclKind::Struct => self.info.structs.get(&id).map_or(0, |s| s.generic_parameters.len()),
+                    _ => 0
+                };
+                let takes_arguments = matches!(declaration.kind, DeclKind::Primitive | DeclKind::Struct | DeclKind::GenericParameter | DeclKind::SelfType(_));
+                if takes_arguments && arguments.len() != expected {
+                    self.error(TypeError::GenericArgumentCount { name: declaration.name.clone(), expected, found: arguments.len(), loc: data.loc });
+                    return Type::Error;
+                }
+                match declaration.kind {
+                    DeclKind::Primitive => match Primitive::from_name(&declaration.name) {
+                        Some(primitive) => Type::Primitive(primitive),
+                        None => Type::Error
+                    },
+                    DeclKind::Struct => {
+                        let parameters = self.info.structs.get(&id).map(|s| s.generic_parameters.clone()).unwrap_or_default();
+                        if !self.check_bounds(id, &parameters, &arguments, data.loc) {
+                            return Type::Error;
+                        }
+                        Type::Struct(id, arguments)
+                    },
+                    DeclKind::GenericParameter => self.bindings.get(&id).cloned().unwrap_or(Type::GenericParameter(id)),
+                    // In a struct, `Self` is the struct with its own generic parameters as arguments. In a trait, it
+                    // stands for whichever struct implements the trait.
+                    DeclKind::SelfType(owner) => match self.info.structs.get(&owner) {
+                        Some(struct_type) => {
+                            let arguments = struct_type.generic_parameters.iter().map(|p| Type::GenericParameter(*p)).collect();
+                            Type::Struct(owner, arguments).substitute(&self.bindings)
+                        },
+                        None => Type::GenericParameter(id)
+                    },
+                    _ => {
+                        self.error(TypeError::NotAType { name: declaration.name.clone(), loc: data.loc });
+                        Type::Error
+                    }
+                }
+            },
+            ast::TypeNode::Function(data) => {
+                let parameters = data.arguments.iter().map(|a| self.lower_type(*a)).collect();
+                Type::Function(parameters, Box::new(self.lower_type(data.ret)))
+            },
+            ast::TypeNode::Reference(data) => Type::Reference(Box::new(self.lower_type(data.typ))),
+            ast::TypeNode::Array(data) => {
+                let element = self.lower_type(data.element);
+                match self.array_length(data.length) {
+                    Some(length) => Type::Array(Box::new(element), length),
+                    None => Type::Error
+                }
+            }
+        }
+    }
+
+    /// The declarations of the generic parameters of a struct, function or method, whose bounds are recorded so
+    /// that their arguments can be checked.
+    fn declare_generic_parameters(&mut self, owner: ast::ItemId, parameters: &[ast::GenericParameter]) -> Vec<DeclId> {
+        let mut ids = Vec::new();
+        for (i, parameter) in parameters.iter().enumerate() {
+            let id = match self.resolution.definition(Site::GenericParameter(owner, i as u32)) {
+                Some(id) => id,
+                None => continue
+            };
+            let bounds = parameter.bounds.iter().filter_map(|bound| self.trait_of(*bound)).collect();
+            self.info.bounds.insert(id, bounds);
+            ids.push(id);
+        }
+        return ids;
+    }
+
+    /// The trait a name refers to. Names of anything other than a trait are reported.
+    fn trait_of(&mut self, name: ast::NameId) -> Option<DeclId> {
+        let id = self.declaration_of(name)?;
+        let declaration = self.resolution.declaration(id);
+        if declaration.kind != DeclKind::Trait {
+            self.error(TypeError::NotATrait { name: declaration.name.clone(), loc: self.program.loc(name) });
+            return None;
+        }
+        return Some(id);
+    }
+
+    /// Whether a type implements a trait. A generic parameter implements the traits it is bounded by, and
+    /// the `Self` of a trait implements that trait.
+    fn implements(&self, typ: &Type, trait_id: DeclId) -> bool {
+        match typ {
+            Type::Error => true,
+            Type::Struct(id, _) => self.implementor_of(*id, trait_id).is_some(),
+            Type::GenericParameter(id) => {
+                self.info.bounds.get(id).is_some_and(|bounds| bounds.contains(&trait_id))
+                    || self.resolution.declaration(*id).kind == DeclKind::SelfType(trait_id)
+            },
+            _ => false
+        }
+    }
+
+    /// Checks that the arguments for the generic parameters of a function or struct implement the traits the
+    /// parameters are bounded by, reporting those that do not.
+    fn check_bounds(&mut self, owner: DeclId, parameters: &[DeclId], arguments: &[Type], loc: Span) -> bool {
+        let mut satisfied = true;
+        for (parameter, argument) in parameters.iter().zip(arguments) {
+            let bounds = self.info.bounds.get(parameter).cloned().unwrap_or_default();
+            let missing: Vec<String> = bounds.into_iter()
+                .filter(|bound| !self.implements(argument, *bound))
+                .map(|bound| self.resolution.declaration(bound).name.clone())
+                .collect();
+            if !missing.is_empty() {
+                let declaration = self.resolution.declaration(*parameter);
+                self.error(TypeError::UnsatisfiedBounds {
+                    typ: self.display(argument),
+                    missing,
+                    parameter: declaration.name.clone(),
+                    owner: self.resolution.declaration(owner).name.clone(),
+                    loc,
+                    declared: declaration.loc
+                });
+                satisfied = false;
+            }
+        }
+        return satisfied;
+    }
+
+    fn declare_struct(&mut self, item: ast::TopLevelId, data: &ast::StructData) {
+        if let Some(id) = self.resolution.item(ast::ItemId::TopLevel(item)) {
+            let generic_parameters = self.declare_generic_parameters(ast::ItemId::TopLevel(item), &data.generic_parameters);
+            let interfaces = data.interfaces.iter().filter_map(|interface| self.trait_of(*interface)).collect();
+            self.info.structs.insert(id, StructType {
+                generic_parameters, fields: Vec::new(), superstruct: None, tagged: false, interfaces, methods: HashMap::new()
+            });
+        }
+    }
+
+    fn declare_superstruct(&mut self, item: ast::TopLevelId, data: &ast::StructData) {
+        let (id, name) = match (self.resolution.item(ast::ItemId::TopLevel(item)), data.superstruct) {
+            (Some(id), Some(name)) => (id, name),
+            _ => return
+        };
+        let superstruct = match self.declaration_of(name) {
+            Some(superstruct) => superstruct,
+            None => return
+        };
+        let declaration = self.resolution.declaration(superstruct);
+        match self.info.structs.get(&superstruct) {
+            None => self.error(TypeError::NotAStruct { typ: declaration.name.clone(), loc: self.program.loc(name) }),
+            Some(s) if !s.generic_parameters.is_empty() => {
+                self.error(TypeError::GenericSuperstruct { name: declaration.name.clone(), loc: self.program.loc(name) })
+            },
+            Some(_) => self.info.structs.get_mut(&id).unwrap().superstruct = Some(superstruct)
+        }
+    }
+
+    /// Reports structs that inherit from themselves and removes their superstructs, so that later passes can
+    /// follow superstructs without going around in circles. Then tags the structs that are part of a hierarchy.
+    fn check_inheritance<I>(&mut self, structs: I)
+        where I: Iterator<Item=(ast::TopLevelId, &'a ast::StructData)> {
+        let mut in_cycles = Vec::new();
+        for (item, data) in structs {
+            let id = match self.resolution.item(ast::ItemId::TopLevel(item)) {
+                Some(id) => id,
+                None => continue
+            };
+            let mut chain = vec![id];
+            let mut current = self.info.structs[&id].superstruct;
+            while let Some(next) = current {
+                if next == id || chain.len() > self.info.structs.len() {
+                    break;
+                }
+                chain.push(next);
+                current = self.info.structs[&next].superstruct;
+            }
+            if current != Some(id) {
+                continue;
+            }
+            in_cycles.push(id);
+            // Each cycle is reported once, at the struct in it that is declared first.
+            if chain.iter().min() == Some(&id) {
+                let mut names: Vec<String> = chain.iter().map(|s| self.resolution.declaration(*s).name.clone()).collect();
+                names.push(names[0].clone());
+                self.error(TypeError::InheritanceCycle { chain: names, loc: self.program.loc(data.superstruct.unwrap()) });
+            }
+        }
+        for id in in_cycles {
+            self.info.structs.get_mut(&id).unwrap().superstruct = None;
+        }
+
+        let superstructs: Vec<(DeclId, DeclId)> = self.info.structs.iter().filter_map(|(id, s)| Some((*id, s.superstruct?))).collect();
+        for (id, superstruct) in superstructs {
+            self.info.structs.get_mut(&id).unwrap().tagged = true;
+            self.info.structs.get_mut(&superstruct).unwrap().tagged = true;
+        }
+    }
+
+    /// The number of superstructs a struct has, directly or through other superstructs.
+    fn inheritance_depth(&self, item: ast::TopLevelId) -> usize {
+        let mut depth = 0;
+        let mut current = self.resolution.item(ast::ItemId::TopLevel(item)).and_then(|id| self.info.structs.get(&id)?.superstruct);
+        while let Some(id) = current {
+            depth += 1;
+            current = self.info.structs[&id].superstruct;
+        }
+        return depth;
+    }
+
+    /// The method with the given name of a struct or, if it has none, of its closest superstruct that has one.
+    fn method_of(&self, struct_id: DeclId, name: &str) -> Option<DeclId> {
+        let mut current = Some(struct_id);
+        while let Some(id) = current {
+            let struct_type = self.info.structs.get(&id)?;
+            if let Some(method) = struct_type.methods.get(name) {
+                return Some(*method);
+            }
+            current = struct_type.superstruct;
+        }
+        return None;
+    }
+
+    /// The struct that declares a method.
+    fn owner_of(&self, method: DeclId) -> Option<DeclId> {
+        self.info.structs.iter().find(|(_, s)| s.methods.values().any(|m| *m == method)).map(|(id, _)| *id)
+    }
+
+    /// The struct or superstruct that claims a trait, if any.
+    fn implementor_of(&self, struct_id: DeclId, trait_id: DeclId) -> Option<DeclId> {
+        let mut current = Some(struct_id);
+        while let Some(id) = current {
+            let struct_type = self.info.structs.get(&id)?;
+            if struct_type.interfaces.contains(&trait_id) {
+                return Some(id);
+            }
+            current = struct_type.superstruct;
+        }
+        return None;
+    }
+
+    fn declare_trait(&mut self, item: ast::TopLevelId, data: &ast::TraitData) {
+        let id = match self.resolution.item(ast::ItemId::TopLevel(item)) {
+            Some(id) => id,
+            None => return
+        };
+        let self_type = match self.resolution.declarations.iter().position(|d| d.kind == DeclKind::SelfType(id)) {
+            Some(index) => DeclId(index as u32),
+            None => return
+        };
+        let mut methods: Vec<TraitMethod> = Vec::new();
+        for (i, method) in data.methods.iter().enumerate() {
+            let method_id = match self.resolution.item(ast::ItemId::Method(item, i as u32)) {
+                Some(id) => id,
+                None => continue
+            };
+            if let Some(previous) = methods.iter().find(|m| m.name == method.name) {
+                let previous = self.resolution.declaration(previous.id).loc.unwrap();
+                self.error(TypeError::DuplicateMethod { name: method.name.clone(), loc: method.name_loc, previous });
+                continue;
+            }
+            let parameters: Vec<Type> = method.parameters.iter().map(|p| self.lower_type(p.typ)).collect();
+            self.check_receiver(&parameters, Type::GenericParameter(self_type), method.name_loc);
+            let signature = Signature {
+                generic_parameters: Vec::new(),
+                parameters,
+                parameter_locs: method.parameters.iter().map(|p| self.program.loc(p.typ)).collect(),
+                ret: self.lower_type(method.ret),
+                ret_loc: self.program.loc(method.ret)
+            };
+            methods.push(TraitMethod { id: method_id, name: method.name.clone(), signature });
+        }
+        self.info.traits.insert(id, TraitType { self_type, methods });
+    }
+
+    /// Checks that the first parameter of a method is the value it is called on.
+    fn check_receiver(&mut self, parameters: &[Type], self_type: Type, loc: Span) {
+        match parameters.first().map(Type::dereferenced) {
+            Some(typ) if *typ == self_type || typ.is_error() => { },
+            _ => self.error(TypeError::MethodReceiver { loc })
+        }
+    }
+
+    fn declare_fields(&mut self, item: ast::TopLevelId, data: &ast::StructData) {
+        let id = match self.resolution.item(ast::ItemId::TopLevel(item)) {
+            Some(id) => id,
+            None => return
+        };
+        let superstruct = self.info.structs[&id].superstruct;
+        let mut fields: Vec<StructField> = superstruct.map(|s| self.info.structs[&s].fields.clone()).unwrap_or_default();
+        let inherited_count = fields.len();
+        let mut field_locs: HashMap<&str, Span> = HashMap::new();
+        for field in &data.fields {
+            if let Some(inherited) = fields[..inherited_count].iter().find(|f| f.name == field.name) {
+                self.error(TypeError::InheritedField {
+                    name: field.name.clone(),
+                    superstruct: self.resolution.declaration(superstruct.unwrap()).name.clone(),
+                    loc: field.loc,
+                    previous: inherited.loc
+                });
+                continue;
+            }
+            if let Some(previous) = field_locs.insert(&field.name, field.loc) {
+                self.error(TypeError::DuplicateFieldDeclaration { name: field.name.clone(), loc: field.loc, previous });
+                continue;
+            }
+            let typ = self.lower_type(field.typ);
+            fields.push(StructField { name: field.name.clone(), typ, loc: field.loc, type_loc: self.program.loc(field.typ) });
+        }
+        self.info.structs.get_mut(&id).unwrap().fields = fields;
+    }
+
+    fn declare_function(&mut self, item: ast::TopLevelId, data: &'a ast::FunctionData) {
+        let item = ast::ItemId::TopLevel(item);
+        if let Some(id) = self.resolution.item(item) {
+            let generic_parameters = self.declare_generic_parameters(item, &data.generic_parameters);
+            self.declare_signature(id, item, data, generic_parameters);
+        }
+    }
+
+    /// Declares the methods of a struct. Their generic parameters are those of the struct, followed by their own.
+    fn declare_methods(&mut self, item: ast::TopLevelId, data: &'a ast::StructData) {
+        let id = match self.resolution.item(ast::ItemId::TopLevel(item)) {
+            Some(id) => id,
+            None => return
+        };
+        let mut declared: HashMap<String, Span> = self.info.structs[&id].fields.iter().map(|f| (f.name.clone(), f.loc)).collect();
+        for (i, method) in data.methods.iter().enumerate() {
+            let method_item = ast::ItemId::Method(item, i as u32);
+            let method_id = match self.resolution.item(method_item) {
+                Some(id) => id,
+                None => continue
+            };
+            if let Some(previous) = declared.insert(method.name.clone(), method.name_loc) {
+                self.error(TypeError::DuplicateMethod { name: method.name.clone(), loc: method.name_loc, previous });
+                continue;
+            }
+            let mut generic_parameters = self.info.structs[&id].generic_parameters.clone();
+            generic_parameters.extend(self.declare_generic_parameters(method_item, &method.generic_parameters));
+            self.declare_signature(method_id, method_item, method, generic_parameters);
+
+            let arguments = self.info.structs[&id].generic_parameters.iter().map(|p| Type::GenericParameter(*p)).collect();
+            let parameters = self.info.functions[&method_id].parameters.clone();
+            self.check_receiver(&parameters, Type::Struct(id, arguments), method.name_loc);
+            self.info.structs.get_mut(&id).unwrap().methods.insert(method.name.clone(), method_id);
+        }
+    }
+
+    /// Checks that a struct has the methods of the traits it claims to implement, with the types they require,
+    /// and that it claims each trait once and no two of its traits require different methods with the same name.
+    fn check_implementations(&mut self, item: ast::TopLevelId, data: &ast::StructData) {
+        let id = match self.resolution.item(ast::ItemId::TopLevel(item)) {
+            Some(id) => id,
+            None => return
+        };
+        let struct_type = &self.info.structs[&id];
+        let typ = Type::Struct(id, struct_type.generic_parameters.iter().map(|p| Type::GenericParameter(*p)).collect());
+
+        let mut claimed: Vec<(DeclId, Span)> = Vec::new();
+        // The traits that require each method name, with the span where the trait is claimed and the type the method must have.
+        let mut required: HashMap<String, (DeclId, Span, Type)> = HashMap::new();
+        for interface in &data.interfaces {
+            let trait_id = match self.declaration_of(*interface) {
+                Some(trait_id) if self.info.traits.contains_key(&trait_id) => trait_id,
+                _ => continue
+            };
+            let loc = self.program.loc(*interface);
+            let trait_name = self.resolution.declaration(trait_id).name.clone();
+            if let Some((_, previous)) = claimed.iter().find(|(t, _)| *t == trait_id) {
+                self.error(TypeError::DuplicateImplementation { typ: self.display(&typ), trait_name, loc, previous: *previous });
+                continue;
+            }
+            claimed.push((trait_id, loc));
+            if let Some(superstruct) = self.info.structs[&id].superstruct.and_then(|s| self.implementor_of(s, trait_id)) {
+                let superstruct = self.resolution.declaration(superstruct);
+                self.error(TypeError::InheritedImplementation {
+                    typ: self.display(&typ), trait_name, superstruct: superstruct.name.clone(), loc, previous: superstruct.loc
+                });
+                continue;
+            }
+
+            let trait_type = &self.info.traits[&trait_id];
+            let self_type = trait_type.self_type;
+            let bindings = HashMap::from([(self_type, typ.clone())]);
+            let trait_methods: Vec<(String, DeclId, Signature)> = trait_type.methods.iter()
+                .map(|m| (m.name.clone(), m.id, m.signature.clone()))
+                .collect();
+            let mut missing = Vec::new();
+            for (name, method_id, signature) in trait_methods {
+                let expected = signature.function_type().substitute(&bindings);
+                match required.get(&name) {
+                    Some((other, other_loc, other_type)) if *other_type != expected => {
+                        self.error(TypeError::ConflictingRequirements {
+                            typ: self.display(&typ),
+                            method: name.clone(),
+                            first: self.resolution.declaration(*other).name.clone(),
+                            second: trait_name.clone(),
+                            loc,
+                            previous: *other_loc
+                        });
+                        continue;
+                    },
+                    Some(_) => { },
+                    None => { required.insert(name.clone(), (trait_id, loc, expected.clone())); }
+                }
+                let required_at = self.resolution.declaration(method_id).loc;
+                let implementation = match self.method_of(id, &name) {
+                    Some(implementation) => implementation,
+                    None => {
+                        missing.push((name, required_at));
+                        continue;
+                    }
+                };
+                // An inherited method is compared with `Self` as the superstruct that declares it.
+                let owner = self.owner_of(implementation);
+                let expected = match owner {
+                    Some(owner) if owner != id => signature.function_type().substitute(&HashMap::from([(self_type, Type::Struct(owner, Vec::new()))])),
+                    _ => expected
+                };
+                let generic_count = owner.and_then(|o| self.info.structs.get(&o)).map_or(0, |s| s.generic_parameters.len());
+                let signature = &self.info.functions[&implementation];
+                let found = signature.function_type();
+                let is_generic = signature.generic_parameters.len() > generic_count;
+                if (found != expected || is_generic) && !found.is_error() && !expected.is_error() {
+                    self.error(TypeError::MethodSignature {
+                        method: name,
+                        trait_name: trait_name.clone(),
+                        expected: self.display(&expected),
+                        found: self.display(&found),
+                        loc: self.resolution.declaration(implementation).loc.unwrap_or(loc),
+                        required: required_at
+                    });
+                }
+            }
+            if !missing.is_empty() {
+                self.error(TypeError::MissingMethods { typ: self.display(&typ), trait_name, methods: missing, loc });
+            }
+        }
+    }
+
+    fn declare_signature(&mut self, id: DeclId, item: ast::ItemId, data: &'a ast::FunctionData, generic_parameters: Vec<DeclId>) {
+        let mut parameters = Vec::new();
+        let mut parameter_locs = Vec::new();
+        for (i, parameter) in data.parameters.iter().enumerate() {
+            parameters.push(self.lower_type(parameter.typ));
+            parameter_locs.push(self.program.loc(parameter.typ));
+            if let Some(param_id) = self.resolution.definition(Site::Parameter(item, i as u32)) {
+                self.annotations.insert(param_id, self.program.loc(parameter.typ));
+            }
+        }
+        let ret = self.lower_type(data.ret);
+        self.info.functions.insert(id, Signature { generic_parameters, parameters, parameter_locs, ret, ret_loc: self.program.loc(data.ret) });
+        self.bodies.insert(id, (item, data));
+    }
+
+    /// The type and value of a constant, which is evaluated the first time it is needed. Constants whose values
+    /// cannot be evaluated have none, and are reported once.
+    fn constant(&mut self, id: DeclId) -> Option<Constant> {
+        if let Some(constant) = self.info.constants.get(&id) {
+            return Some(constant.clone());
+        }
+        if self.invalid_constants.contains(&id) {
+            return None;
+        }
+        let data = self.constant_bodies[&id];
+        if let Some(i) = self.evaluating.iter().position(|c| *c == id) {
+            let chain = self.evaluating[i..].iter().chain([&id]).map(|c| self.resolution.declaration(*c).name.clone()).collect();
+            self.error(TypeError::ConstantCycle { chain, loc: data.name_loc });
+            return None;
+        }
+
+        self.evaluating.push(id);
+        let constant = self.evaluate_constant(data);
+        self.evaluating.pop();
+        match &constant {
+            Some(constant) => { self.info.constants.insert(id, constant.clone()); },
+            None => { self.invalid_constants.insert(id); }
+        }
+        return constant;
+    }
+
+    fn evaluate_constant(&mut self, data: &ast::ConstData) -> Option<Constant> {
+        let typ = match self.lower_type(data.typ) {
+            Type::Primitive(primitive) if primitive != Primitive::Unit => primitive,
+            Type::Error => return None,
+            typ => {
+                self.error(TypeError::ConstantType { typ: self.display(&typ), loc: self.program.loc(data.typ) });
+                return None;
+            }
+        };
+        let (found, value) = self.evaluate(data.value, Some(typ))?;
+        if found.widens_to(typ) {
+            return Some(Constant { typ, value });
+        }
+        if found.is_numeric() && typ.is_numeric() {
+            self.error(TypeError::Narrowing {
+                from: String::from(found.name()), to: String::from(typ.name()), loc: self.program.loc(data.value),
+                declared: Some(self.program.loc(data.typ)), fix: cast_fix(self.program, data.value, typ)
+            });
+        } else {
+            self.error(TypeError::Mismatch {
+                expected: String::from(typ.name()), found: String::from(found.name()), loc: self.program.loc(data.value), declared: Some(self.program.loc(data.typ))
+            });
+        }
+        return None;
+    }
+
+    /// The length of an array type, which must be a constant integer that is not negative.
+    fn array_length(&mut self, expr: ast::ExprId) -> Option<u64> {
+        match self.evaluate(expr, None)? {
+            (_, ConstValue::Integer(length)) if length >= 0 => Some(length as u64),
+            (_, ConstValue::Integer(length)) => {
+                self.error(TypeError::NegativeArrayLength { length, loc: self.program.loc(expr) });
+                None
+            },
+            (typ, _) => {
+                self.error(TypeError::ArrayLengthType { typ: String::from(typ.name()), loc: self.program.loc(expr) });
+                None
+            }
+        }
+    }
+
+    /// Evaluates a constant expression, giving its type and value. The types of literals are decided by `hint`
+    /// and by the other operands, as they are in function bodies, and values are converted implicitly in the
+    /// same cases.
+    fn evaluate(&mut self, expr: ast::ExprId, hint: Option<Primitive>) -> Option<(Primitive, ConstValue)> {
+        match &self.program[expr] {
+            ast::ExprNode::Integer(data) => self.evaluate_integer(data.integer as i128, data.loc, hint),
+            ast::ExprNode::Float(data) => {
+                let typ = hint.filter(Primitive::is_float).unwrap_or(Primitive::F64);
+                Some((typ, fold_cast(&ConstValue::Float(data.float), Primitive::F64, typ)))
+            },
+            ast::ExprNode::Bool(data) => Some((Primitive::Bool, ConstValue::Bool(data.value))),
+            ast::ExprNode::Char(data) => Some((Primitive::Char, ConstValue::Char(data.value))),
+            ast::ExprNode::String(data) => Some((Primitive::Str, ConstValue::Str(data.value.clone()))),
+            ast::ExprNode::Name(data) => {
+                let id = self.declaration_of(data.name)?;
+                if self.resolution.declaration(id).kind != DeclKind::Const {
+                    self.error(TypeError::NotConstant { loc: data.loc });
+                    return None;
+                }
+                let constant = self.constant(id)?;
+                Some((constant.typ, constant.value))
+            },
+            ast::ExprNode::Unary(data) => {
+                // A negated literal is checked as a negative number, so that `-128` fits in an `i8`.
+                if let (ast::UnaryOp::Negate, ast::ExprNode::Integer(literal)) = (data.op, &self.program[data.operand]) {
+                    return self.evaluate_integer(-(literal.integer as i128), data.loc, hint);
+                }
+                let (typ, value) = self.evaluate(data.operand, hint)?;
+                let valid = match data.op {
+                    ast::UnaryOp::Negate => typ.is_signed() || typ.is_float(),
+                    ast::UnaryOp::Not => typ == Primitive::Bool
+                };
+                if !valid {
+                    self.error(TypeError::InvalidOperand { op: data.op.symbol(), typ: String::from(typ.name()), loc: data.loc });
+                    return None;
+                }
+                let value = self.fold(fold_unary(data.op, &value, typ), data.op.symbol(), typ, data.loc)?;
+                Some((typ, value))
+            },
+            ast::ExprNode::Binary(data) => self.evaluate_binary(data, hint),
+            ast::ExprNode::Cast(data) => {
+                let (from, value) = self.evaluate(data.expr, None)?;
+                let to = self.lower_type(data.typ);
+                match to {
+                    Type::Error => None,
+                    Type::Primitive(to) if can_cast(&Type::Primitive(from), &Type::Primitive(to)) => Some((to, fold_cast(&value, from, to))),
+                    to => {
+                        self.error(TypeError::InvalidCast { from: String::from(from.name()), to: self.display(&to), loc: data.loc });
+                        None
+                    }
+                }
+            },
+            _ => {
+                self.error(TypeError::NotConstant { loc: self.program.loc(expr) });
+                None
+            }
+        }
+    }
+
+    /// An integer literal in a constant expression, whose type is the integer type it is expected to have, or
+    /// `int` if it is not expected to have one.
+    fn evaluate_integer(&mut self, value: i128, loc: Span, hint: Option<Primitive>) -> Option<(Primitive, ConstValue)> {
+        let typ = hint.filter(Primitive::is_integer).unwrap_or(Primitive::I64);
+        if !self.check_literal_range(value, typ, loc) {
+            return None;
+        }
+        return Some((typ, ConstValue::Integer(value)));
+    }
+
+    fn evaluate_binary(&mut self, data: &ast::BinaryData, hint: Option<Primitive>) -> Option<(Primitive, ConstValue)> {
+        use ast::BinaryOp::*;
+        let hint = if matches!(data.op, Add | Subtract | Multiply | Divide | Remainder) { hint } else { None };
+        let ((left, left_value), (right, right_value)) = if is_literal(self.program, data.left) && !is_literal(self.program, data.right) {
+            let right = self.evaluate(data.right, hint)?;
+            (self.evaluate(data.left, Some(right.0))?, right)
+        } else {
+            let left = self.evaluate(data.left, hint)?;
+            let right = self.evaluate(data.right, Some(left.0))?;
+            (left, right)
+        };
+
+        let common = if left.widens_to(right) { Some(right) } else if right.widens_to(left) { Some(left) } else { None };
+        let result = match (data.op, common) {
+            (Add | Subtract | Multiply | Divide | Remainder, Some(p)) if p.is_numeric() => Some(p),
+            (Less | LessEqual | Greater | GreaterEqual, Some(p)) if p.is_numeric() || p == Primitive::Char => Some(Primitive::Bool),
+            (Equal | NotEqual, Some(p)) if p != Primitive::Unit => Some(Primitive::Bool),
+            (And | Or, Some(Primitive::Bool)) => Some(Primitive::Bool),
+            _ => None
+        };
+        let (Some(common), Some(result)) = (common, result) else {
+            self.error(TypeError::InvalidOperands {
+                op: data.op.symbol(), left: String::from(left.name()), right: String::from(right.name()), loc: data.loc
+            });
+            return None;
+        };
+        let value = self.fold(fold_binary(data.op, &left_value, &right_value, common), data.op.symbol(), common, data.loc)?;
+        return Some((result, value));
+    }
+
+    /// The result of folding an operation, reporting it if it has none.
+    fn fold(&mut self, result: Result<ConstValue, FoldError>, op: &'static str, typ: Primitive, loc: Span) -> Option<ConstValue> {
+        match result {
+            Ok(value) => Some(value),
+            Err(FoldError::Overflow) => {
+                let (min, max) = typ.integer_range().unwrap();
+                self.error(TypeError::ConstantOverflow { op, typ: String::from(typ.name()), min, max, loc });
+                None
+            },
+            Err(FoldError::DivisionByZero) => {
+                self.error(TypeError::ConstantDivisionByZero { loc });
+                None
+            }
+        }
+    }
+
+    fn check_instance(&mut self, id: InstanceId) {
+        let function = self.info.instance(id).function;
+        let (item, data) = self.bodies[&function];
+        let signature = &self.info.functions[&function];
+        let bindings = bind(&signature.generic_parameters, &self.info.instance(id).arguments);
+        let parameters: Vec<Type> = signature.parameters.iter().map(|p| p.substitute(&bindings)).collect();
+        self.ret = Some((signature.ret.substitute(&bindings), Some(signature.ret_loc)));
+        self.bindings = bindings;
+        self.instance = Some(id);
+
+        for (i, typ) in parameters.into_iter().enumerate() {
+            if let Some(param_id) = self.resolution.definition(Site::Parameter(item, i as u32)) {
+                self.current().variables.insert(param_id, typ);
+            }
+        }
+        self.check_block(&data.body);
+        self.finish_inference();
+
+        self.instance = None;
+        self.bindings = HashMap::new();
+        self.ret = None;
+    }
+
+    fn fresh_variable(&mut self, kind: VariableKind) -> Type {
+        let id = self.substitution.len() as u32;
+        self.substitution.push((kind, None));
+        return Type::Variable(TypeVariable { id, kind });
+    }
+
+    /// The type with every inference variable that has been decided replaced by its type.
+    fn resolve(&self, typ: &Type) -> Type {
+        match typ {
+            Type::Variable(variable) => match &self.substitution[variable.id as usize].1 {
+                Some(decided) => self.resolve(decided),
+                None => typ.clone()
+            },
+            Type::Struct(id, arguments) => Type::Struct(*id, arguments.iter().map(|a| self.resolve(a)).collect()),
+            Type::Function(parameters, ret) => {
+                Type::Function(parameters.iter().map(|p| self.resolve(p)).collect(), Box::new(self.resolve(ret)))
+            },
+            Type::Reference(inner) => Type::Reference(Box::new(self.resolve(inner))),
+            Type::Array(element, length) => Type::Array(Box::new(self.resolve(element)), *length),
+            typ => typ.clone()
+        }
+    }
+
+    /// Makes two types equal by deciding the inference variables in them, if that is possible. If it is not,
+    /// no variable is decided.
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        let saved = self.substitution.clone();
+        let unified = self.unify_parts(a, b);
+        if !unified {
+            self.substitution = saved;
+        }
+        return unified;
+    }
+
+    fn unify_parts(&mut self, a: &Type, b: &Type) -> bool {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        match (&a, &b) {
+            (Type::Error, _) | (_, Type::Error) => true,
+            (Type::Variable(x), Type::Variable(y)) if x.id == y.id => true,
+            (Type::Variable(x), Type::Variable(y)) => {
+                // The variable that can stand for more types is the one that is decided.
+                let (variable, typ) = match (x.kind, y.kind) {
+                    (VariableKind::General, _) => (x, &b),
+                    (_, VariableKind::General) => (y, &a),
+                    (x_kind, y_kind) if x_kind == y_kind => (x, &b),
+                    _ => return false
+                };
+                self.substitution[variable.id as usize].1 = Some(typ.clone());
+                true
+            },
+            (Type::Variable(variable), typ) | (typ, Type::Variable(variable)) => {
+                let admitted = match variable.kind {
+                    VariableKind::General => !typ.variables().iter().any(|v| v.id == variable.id),
+                    VariableKind::Integer => typ.primitive().is_some_and(|p| p.is_integer()),
+                    VariableKind::Float => typ.primitive().is_some_and(|p| p.is_float())
+                };
+                if admitted {
+                    self.substitution[variable.id as usize].1 = Some(typ.clone());
+                }
+                admitted
+            },
+            (Type::Struct(a_id, a_arguments), Type::Struct(b_id, b_arguments)) => {
+                a_id == b_id && a_arguments.len() == b_arguments.len()
+                    && a_arguments.iter().zip(b_arguments).all(|(a, b)| self.unify_parts(a, b))
+            },
+            (Type::Function(a_parameters, a_ret), Type::Function(b_parameters, b_ret)) => {
+                a_parameters.len() == b_parameters.len()
+                    && a_parameters.iter().zip(b_parameters).all(|(a, b)| self.unify_parts(a, b))
+                    && self.unify_parts(a_ret, b_ret)
+            },
+            (Type::Reference(a_inner), Type::Reference(b_inner)) => self.unify_parts(a_inner, b_inner),
+            (Type::Array(a_element, a_length), Type::Array(b_element, b_length)) => {
+                a_length == b_length && self.unify_parts(a_element, b_element)
+            },
+            _ => a == b
+        }
+    }
+
+    /// The type with the inference variables decided so far replaced. A value whose type could still be anything
+    /// cannot be used where its type matters, so the variable is reported and becomes an error.
+    fn known(&mut self, typ: &Type, loc: Span) -> Type {
+        let typ = self.resolve(typ);
+        if let Type::Variable(variable) = typ.dereferenced() {
+            if variable.kind == VariableKind::General {
+                let annotation = self.origin_of(*variable);
+                self.error(TypeError::UnknownType { loc, annotation });
+                self.substitution[variable.id as usize].1 = Some(Type::Error);
+                return Type::Error;
+            }
+        }
+        return typ;
+    }
+
+    /// Decides the undecided variables in a type that is involved in an error as errors, so that they are not
+    /// reported again as ambiguous.
+    fn poison(&mut self, typ: &Type) {
+        for variable in self.resolve(typ).variables() {
+            if variable.kind == VariableKind::General {
+                self.substitution[variable.id as usize].1 = Some(Type::Error);
+            }
+        }
+    }
+
+    /// Gives a numeric literal whose type is still undecided its default type, for uses that need to know it.
+    fn default_numeric(&mut self, typ: &Type) -> Type {
+        if let Type::Variable(variable) = self.resolve(typ).dereferenced() {
+            let default = match variable.kind {
+                VariableKind::Integer => Type::int(),
+                VariableKind::Float => Type::Primitive(Primitive::F64),
+                VariableKind::General => return self.resolve(typ)
+            };
+            self.substitution[variable.id as usize].1 = Some(default);
+        }
+        return self.resolve(typ);
+    }
+
+    /// Records the variable whose type a type is, so that an undecided variable in it can point at where an
+    /// annotation would decide it. Variables that already came from somewhere keep their first origin.
+    fn record_origin(&mut self, typ: &Type, name: &str, loc: Span) {
+        for variable in self.resolve(typ).variables() {
+            if variable.kind == VariableKind::General {
+                self.variable_origins.entry(variable.id).or_insert_with(|| (String::from(name), loc));
+            }
+        }
+    }
+
+    /// The first variable, in source order, whose type mentions an undecided inference variable.
+    fn origin_of(&self, variable: TypeVariable) -> Option<(String, Span)> {
+        let mut origins: Vec<&(String, Span)> = self.variable_origins.iter()
+            .filter(|(id, _)| self.resolve(&Type::Variable(TypeVariable { id: **id, kind: VariableKind::General })).variables().contains(&variable))
+            .map(|(_, origin)| origin)
+            .collect();
+        origins.sort_by_key(|(_, loc)| (loc.file, loc.lo));
+        return origins.first().map(|origin| (*origin).clone());
+    }
+
+    /// Decides the inference variables that are left once a body has been checked, instantiates the generic
+    /// functions it uses, and reports what could not be inferred. Every variable in the types recorded for the
+    /// body is then replaced by its type.
+    fn finish_inference(&mut self) {
+        // Numeric literals that nothing else decided take their default types.
+        for i in 0..self.substitution.len() {
+            let default = match self.substitution[i] {
+                (VariableKind::Integer, None) => Type::int(),
+                (VariableKind::Float, None) => Type::Primitive(Primitive::F64),
+                _ => continue
+            };
+            self.substitution[i].1 = Some(default);
+        }
+        for (value, loc, typ) in std::mem::take(&mut self.pending_literals) {
+            if let Type::Primitive(primitive) = self.resolve(&typ) {
+                self.check_literal_range(value, primitive, loc);
+            }
+        }
+        for (typ, loc) in std::mem::take(&mut self.pending_negations) {
+            let typ = self.resolve(&typ);
+            if typ.primitive().is_some_and(|p| !p.is_signed() && !p.is_float()) {
+                self.error(TypeError::InvalidOperand { op: ast::UnaryOp::Negate.symbol(), typ: self.display(&typ), loc });
+            }
+        }
+
+        for deferred in std::mem::take(&mut self.deferred_uses) {
+            let arguments: Vec<Type> = deferred.arguments.iter().map(|a| self.resolve(a)).collect();
+            let (parameters, is_function) = match self.info.functions.get(&deferred.declaration) {
+                Some(signature) => (signature.generic_parameters.clone(), true),
+                None => (self.info.structs[&deferred.declaration].generic_parameters.clone(), false)
+            };
+            if let Some(i) = arguments.iter().position(|a| !a.variables().is_empty()) {
+                let annotation = self.origin_of(arguments[i].variables()[0]);
+                self.error(TypeError::CannotInfer {
+                    parameter: self.resolution.declaration(parameters[i]).name.clone(),
+                    function: self.resolution.declaration(deferred.declaration).name.clone(),
+                    loc: deferred.loc,
+                    annotation
+                });
+                for argument in &arguments {
+                    self.poison(argument);
+                }
+                continue;
+            }
+            if arguments.iter().any(Type::is_error) || !self.check_bounds(deferred.declaration, &parameters, &arguments, deferred.loc) {
+                continue;
+            }
+            if is_function {
+                if let (Some(instance), Some(expr)) = (self.instantiate(deferred.declaration, arguments, Some(deferred.loc)), deferred.expr) {
+                    self.current().functions.insert(expr, instance);
+                }
+            }
+        }
+
+        let mut origins: Vec<(u32, (String, Span))> = self.variable_origins.drain().collect();
+        origins.sort_by_key(|(_, (_, loc))| (loc.file, loc.lo));
+        for (id, (name, loc)) in origins {
+            if let Type::Variable(variable) = self.resolve(&Type::Variable(TypeVariable { id, kind: VariableKind::General })) {
+                self.error(TypeError::AmbiguousType { name, loc });
+                self.substitution[variable.id as usize].1 = Some(Type::Error);
+            }
+        }
+        for decided in &mut self.substitution {
+            decided.1.get_or_insert(Type::Error);
+        }
+
+        let instance = self.instance.unwrap().0 as usize;
+        let mut variables = std::mem::take(&mut self.info.instances[instance].variables);
+        let mut expressions = std::mem::take(&mut self.info.instances[instance].expressions);
+        for typ in variables.values_mut().chain(expressions.values_mut()) {
+            *typ = self.resolve(typ);
+        }
+        self.info.instances[instance].variables = variables;
+        self.info.instances[instance].expressions = expressions;
+        self.substitution.clear();
+    }
+
+    fn check_block(&mut self, block: &ast::BlockData) {
+        for stmt in &block.stmts {
+            self.check_stmt(*stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: ast::StmtId) {
+        match &self.program[stmt] {
+            ast::StmtNode::Expr(data) => { self.infer_expr(data.expr); },
+            ast::StmtNode::Return(data) => {
+                let (ret, ret_loc) = self.ret.clone().unwrap();
+                self.check_expr(data.expr, &ret, ret_loc);
+            },
+            ast::StmtNode::Let(data) => {
+                // A variable declared without a value or an annotation takes its type from its assignments.
+                let typ = match (data.typ, data.value) {
+                    (Some(annotation), value) => {
+                        let typ = self.lower_type(annotation);
+                        if let Some(value) = value {
+                            self.check_expr(value, &typ, Some(self.program.loc(annotation)));
+                        }
+                        typ
+                    },
+                    (None, value) => {
+                        let typ = match value {
+                            Some(value) => self.infer_expr(value),
+                            None => self.fresh_variable(VariableKind::General)
+                        };
+                        self.record_origin(&typ, &data.name, data.name_loc);
+                        typ
+                    }
+                };
+                if let Some(id) = self.resolution.definition(Site::Local(stmt)) {
+                    self.current().variables.insert(id, typ);
+                    if let Some(annotation) = data.typ {
+                        self.annotations.insert(id, self.program.loc(annotation));
+                    }
+                }
+            },
+            ast::StmtNode::If(data) => {
+                self.check_expr(data.condition, &Type::bool(), None);
+                self.check_block(&data.body);
+                if let Some(else_body) = &data.else_body {
+                    self.check_block(else_body);
+                }
+            },
+            ast::StmtNode::While(data) => {
+                self.check_expr(data.condition, &Type::bool(), None);
+                self.check_block(&data.body);
+            },
+            ast::StmtNode::For(data) => {
+                let element = self.infer_iterable(&data.iterable);
+                if let Some(id) = self.resolution.definition(Site::Local(stmt)) {
+                    self.current().variables.insert(id, element);
+                }
+                self.check_block(&data.body);
+            }
+        }
+    }
+
+    /// The type of the values a `for` loop iterates over.
+    fn infer_iterable(&mut self, iterable: &ast::ForIterable) -> Type {
+        match *iterable {
+            ast::ForIterable::Range(start, end) => {
+                let start_type = self.infer_expr(start);
+                let end_type = self.infer_expr_with(end, Some(&start_type));
+                let (start_type, end_type) = (self.resolve(&start_type).dereferenced().clone(), self.resolve(&end_type).dereferenced().clone());
+                if start_type.is_error() || end_type.is_error() {
+                    return Type::Error;
+                }
+                self.unify(&start_type, &end_type);
+                let start_type = self.known(&start_type, self.program.loc(start));
+                let end_type = self.known(&end_type, self.program.loc(end));
+                if start_type.is_error() || end_type.is_error() {
+                    return Type::Error;
+                }
+                // Integer literals whose types are still undecided are decided by how the loop variable is used.
+                if let (Type::Variable(s), Type::Variable(e)) = (&start_type, &end_type) {
+                    if s == e && s.kind == VariableKind::Integer {
+                        return start_type;
+                    }
+                }
+                let (start_type, end_type) = (self.default_numeric(&start_type), self.default_numeric(&end_type));
+                let common = match (start_type.primitive(), end_type.primitive()) {
+                    (Some(s), Some(e)) if s.widens_to(e) => Some(e),
+                    (Some(s), Some(e)) if e.widens_to(s) => Some(s),
+                    _ => None
+                };
+                match common {
+                    Some(primitive) if primitive.is_integer() => Type::Primitive(primitive),
+                    _ => {
+                        let start_is_integer = start_type.primitive().is_some_and(|p| p.is_integer());
+                        let (typ, loc) = if start_is_integer { (&end_type, self.program.loc(end)) } else { (&start_type, self.program.loc(start)) };
+                        self.error(TypeError::RangeType { typ: self.display(typ), loc });
+                        Type::Error
+                    }
+                }
+            },
+            ast::ForIterable::Array(array) => {
+                let typ = self.infer_expr(array);
+                let typ = self.known(&typ, self.program.loc(array));
+                let typ = self.default_numeric(&typ);
+                match typ.dereferenced() {
+                    Type::Array(element, _) => element.as_ref().clone(),
+                    Type::Error => Type::Error,
+                    other => {
+                        self.error(TypeError::NotIterable { typ: self.display(other), loc: self.program.loc(array) });
+                        Type::Error
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks that an expression can be used where a value of type `expected` is required. A reference
+    /// can be read where its referent is expected, a variable or field can be used where a reference
+    /// to it is expected, and numbers are widened to types that can hold all of their values.
+    fn check_expr(&mut self, expr: ast::ExprId, expected: &Type, declared: Option<Span>) {
+        let found = self.infer_expr_with(expr, Some(expected));
+        self.coerce(expr, &found, expected, declared);
+    }
+
+    /// Checks that an expression whose type has already been inferred as `found` can be used where a
+    /// value of type `expected` is required, deciding the inference variables in either type if needed.
+    fn coerce(&mut self, expr: ast::ExprId, found: &Type, expected: &Type, declared: Option<Span>) {
+        let (found, expected) = (self.resolve(found), self.resolve(expected));
+        if found.is_error() || expected.is_error() {
+            self.poison(&found);
+            self.poison(&expected);
+            return;
+        }
+        // A reference used where the type is still being inferred is read, as where its referent is expected.
+        if let (Type::Reference(inner), Type::Variable(variable)) = (&found, &expected) {
+            if variable.kind == VariableKind::General {
+                self.unify(inner, &expected);
+                return;
+            }
+        }
+        if self.unify(&found, &expected) {
+            return;
+        }
+        if let Type::Reference(inner) = &expected {
+            // A reference to a struct can be used as a reference to any of its superstructs.
+            let upcast = self.is_upcast(found.dereferenced(), inner);
+            let is_reference = matches!(found, Type::Reference(_));
+            if is_reference && upcast {
+                return;
+            }
+            if upcast || (!is_reference && self.unify(&found, inner)) {
+                if !self.is_place(expr) {
+                    self.error(TypeError::TemporaryReference { typ: self.display(&expected), loc: self.program.loc(expr), declared });
+                }
+                return;
+            }
+        }
+        if self.unify(found.dereferenced(), &expected) {
+            return;
+        }
+        let value = self.default_numeric(found.dereferenced());
+        match (value.primitive(), expected.primitive()) {
+            (Some(from), Some(to)) if from.widens_to(to) => { },
+            (Some(from), Some(to)) if from.is_numeric() && to.is_numeric() => {
+                self.error(TypeError::Narrowing {
+                    from: String::from(from.name()), to: String::from(to.name()), loc: self.program.loc(expr), declared, fix: cast_fix(self.program, expr, to)
+                });
+            },
+            _ => {
+                self.error(TypeError::Mismatch {
+                    expected: self.display(&expected), found: self.display(&found), loc: self.program.loc(expr), declared
+                });
+            }
+        }
+    }
+
+    /// Whether `from` is a struct that inherits from the struct `to`.
+    fn is_upcast(&self, from: &Type, to: &Type) -> bool {
+        match (from, to) {
+            (Type::Struct(from, _), Type::Struct(to, _)) => from != to && self.info.is_substruct(*from, *to),
+            _ => false
+        }
+    }
+
+    /// Checks that one of two struct types inherits from the other, so that a value of the first could have been
+    /// created as the second.
+    fn check_related(&mut self, from: &Type, to: &Type, loc: Span) -> bool {
+        let related = match (from, to) {
+            (Type::Struct(from_id, _), Type::Struct(to_id, _)) => {
+                self.info.is_substruct(*from_id, *to_id) || self.info.is_substruct(*to_id, *from_id)
+            },
+            _ => false
+        };
+        if !related {
+            self.error(TypeError::UnrelatedStructs { from: self.display(from), to: self.display(to), loc });
+        }
+        return related;
+    }
+
+    /// Whether an expression refers to a variable or field, rather than a temporary value.
+    fn is_place(&self, expr: ast::ExprId) -> bool {
+        match &self.program[expr] {
+            ast::ExprNode::Name(data) => match self.declaration_of(data.name) {
+                Some(id) => matches!(self.resolution.declaration(id).kind, DeclKind::Local | DeclKind::Parameter),
+                None => false
+            },
+            ast::ExprNode::Field(data) => {
+                self.is_place(data.object) || matches!(self.type_of(data.object), Some(Type::Reference(_)))
+            },
+            ast::ExprNode::Index(data) => {
+                self.is_place(data.object) || matches!(self.type_of(data.object), Some(Type::Reference(_)))
+            },
+            _ => false
+        }
+    }
+
+    /// The annotation that gives a place its type, if there is one.
+    fn place_annotation(&self, expr: ast::ExprId) -> Option<Span> {
+        match &self.program[expr] {
+            ast::ExprNode::Name(data) => self.annotations.get(&self.declaration_of(data.name)?).copied(),
+            ast::ExprNode::Field(data) => match self.type_of(data.object)?.dereferenced() {
+                Type::Struct(id, _) => Some(self.info.structs.get(id)?.field(&data.field)?.type_loc),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    fn infer_expr(&mut self, expr: ast::ExprId) -> Type {
+        self.infer_expr_with(expr, None)
+    }
+
+    /// Infers the type of an expression. `hint` is the type the expression is expected to have, if
+    /// known, which decides the types of numeric literals.
+    fn infer_expr_with(&mut self, expr: ast::ExprId, hint: Option<&Type>) -> Type {
+        let typ = match &self.program[expr] {
+            ast::ExprNode::Integer(data) => self.integer_literal(data, false, data.loc, hint),
+            ast::ExprNode::Float(_) => match hint.map(|h| self.resolve(h)).as_ref().map(Type::dereferenced) {
+                Some(Type::Primitive(primitive)) if primitive.is_float() => Type::Primitive(*primitive),
+                _ => self.fresh_variable(VariableKind::Float)
+            },
+            ast::ExprNode::Bool(_) => Type::bool(),
+            ast::ExprNode::Char(_) => Type::Primitive(Primitive::Char),
+            ast::ExprNode::String(_) => Type::Primitive(Primitive::Str),
+            ast::ExprNode::Name(data) => self.infer_name(expr, data),
+            ast::ExprNode::Block(data) => {
+                self.check_block(data);
+                Type::unit()
+            },
+            ast::ExprNode::Call(data) => self.infer_call(data, hint),
+            ast::ExprNode::Field(data) => {
+                let object = self.infer_expr(data.object);
+                let object = self.known(&object, self.program.loc(data.object));
+                self.infer_field(data, &object)
+            },
+            ast::ExprNode::Assign(data) => {
+                let target = self.infer_expr(data.target);
+                if self.is_place(data.target) {
+                    let declared = self.place_annotation(data.target);
+                    match data.op {
+                        None => self.check_expr(data.value, target.dereferenced(), declared),
+                        Some(op) => {
+                            // `x += y` is checked as `x = x + y`.
+                            let value = self.infer_expr_with(data.value, Some(target.dereferenced()));
+                            let result = self.binary_type(op, &target, &value, self.program.loc(data.target), self.program.loc(data.value), data.loc);
+                            self.coerce(data.value, &result, target.dereferenced(), declared);
+                        }
+                    }
+                } else {
+                    self.error(TypeError::NotAssignable { loc: self.program.loc(data.target) });
+                    self.infer_expr(data.value);
+                }
+                Type::unit()
+            },
+            ast::ExprNode::StructLiteral(data) => self.infer_struct_literal(data),
+            ast::ExprNode::Unary(data) => self.infer_unary(data, hint),
+            ast::ExprNode::Binary(data) => self.infer_binary(data, hint),
+            ast::ExprNode::Cast(data) => {
+                let from = self.infer_expr(data.expr);
+                let from = self.known(&from, self.program.loc(data.expr));
+                let from = self.default_numeric(&from);
+                let to = self.lower_type(data.typ);
+                match (from.dereferenced(), &to) {
+                    _ if from.is_error() || to.is_error() => { },
+                    // Casting between references to structs in the same hierarchy is checked at run time.
+                    (Type::Struct(..), Type::Reference(target)) if matches!(target.as_ref(), Type::Struct(..)) => {
+                        let related = self.check_related(from.dereferenced(), target, data.loc);
+                        if related && !matches!(from, Type::Reference(_)) && !self.is_place(data.expr) {
+                            self.error(TypeError::TemporaryReference { typ: self.display(&to), loc: self.program.loc(data.expr), declared: Some(self.program.loc(data.typ)) });
+                        }
+                    },
+                    (from_value, _) if !can_cast(from_value, &to) => {
+                        self.error(TypeError::InvalidCast { from: self.display(&from), to: self.display(&to), loc: data.loc });
+                    },
+                    _ => { }
+                }
+                to
+            },
+            ast::ExprNode::Is(data) => {
+                let from = self.infer_expr(data.expr);
+                let from = self.known(&from, self.program.loc(data.expr));
+                let to = self.lower_type(data.typ);
+                match (from.dereferenced(), &to) {
+                    _ if from.is_error() || to.is_error() => { },
+                    (Type::Struct(..), Type::Struct(..)) => { self.check_related(from.dereferenced(), &to, data.loc); },
+                    (Type::Struct(..), _) => self.error(TypeError::NotAStruct { typ: self.display(&to), loc: self.program.loc(data.typ) }),
+                    _ => self.error(TypeError::NotAStruct { typ: self.display(&from), loc: self.program.loc(data.expr) })
+                }
+                Type::bool()
+            },
+            ast::ExprNode::Lambda(data) => self.infer_lambda(expr, data, hint),
+            ast::ExprNode::Array(data) => {
+                // The elements take their type from the array the literal is expected to be, or from the first element.
+                let element = match hint.map(|h| self.resolve(h)).as_ref().map(Type::dereferenced) {
+                    Some(Type::Array(element, _)) => element.as_ref().clone(),
+                    _ => self.fresh_variable(VariableKind::General)
+                };
+                for value in &data.elements {
+                    self.check_expr(*value, &element, None);
+                }
+                Type::Array(Box::new(element), data.elements.len() as u64)
+            },
+            ast::ExprNode::Index(data) => self.infer_index(data)
+        };
+        let typ = self.resolve(&typ);
+        self.current().expressions.insert(expr, typ.clone());
+        return typ;
+    }
+
+    /// The type of a lambda. Parameters without annotations take their types from the function type the lambda
+    /// is expected to have, or are inferred from how the body uses them.
+    fn infer_lambda(&mut self, expr: ast::ExprId, data: &ast::LambdaData, hint: Option<&Type>) -> Type {
+        let expected = match hint.map(|h| self.resolve(h)) {
+            Some(Type::Function(parameters, ret)) if parameters.len() == data.parameters.len() => Some((parameters, *ret)),
+            _ => None
+        };
+        let mut parameters = Vec::new();
+        for (i, parameter) in data.parameters.iter().enumerate() {
+            let id = self.resolution.definition(Site::LambdaParameter(expr, i as u32));
+            let typ = match (parameter.typ, &expected) {
+                (Some(annotation), _) => {
+                    if let Some(id) = id {
+                        self.annotations.insert(id, self.program.loc(annotation));
+                    }
+                    self.lower_type(annotation)
+                },
+                (None, Some((expected, _))) => expected[i].clone(),
+                (None, None) => {
+                    let typ = self.fresh_variable(VariableKind::General);
+                    self.record_origin(&typ, &parameter.name, parameter.loc);
+                    typ
+                }
+            };
+            if let Some(id) = id {
+                self.current().variables.insert(id, typ.clone());
+            }
+            parameters.push(typ);
+        }
+
+        let ret = match expected {
+            Some((_, ret)) => ret,
+            None => self.fresh_variable(VariableKind::General)
+        };
+        match &self.program[data.body] {
+            ast::ExprNode::Block(block) => {
+                let outer = self.ret.replace((ret.clone(), None));
+                self.check_block(block);
+                self.ret = outer;
+                // A block without `return` statements returns nothing.
+                if matches!(self.resolve(&ret), Type::Variable(v) if v.kind == VariableKind::General) {
+                    self.unify(&ret, &Type::unit());
+                }
+            },
+            _ => self.check_expr(data.body, &ret, None)
+        }
+        return Type::Function(parameters, Box::new(ret));
+    }
+
+    /// The type of a field of a value of type `object`.
+    fn infer_field(&mut self, data: &ast::FieldData, object: &Type) -> Type {
+        match object.dereferenced() {
+            Type::Error => Type::Error,
+            Type::Struct(id, arguments) if self.info.structs.contains_key(id) => {
+                let struct_type = &self.info.structs[id];
+                match struct_type.field_type(&data.field, arguments) {
+                    Some(typ) => typ,
+                    None if self.method_of(*id, &data.field).is_some() => {
+                        self.error(TypeError::MethodNotCalled { typ: self.display(object.dereferenced()), method: data.field.clone(), loc: data.field_loc });
+                        Type::Error
+                    },
+                    None => {
+                        self.error(TypeError::NoField { typ: self.display(object), field: data.field.clone(), loc: data.field_loc });
+                        Type::Error
+                    }
+                }
+            },
+            _ => {
+                self.error(TypeError::NoField { typ: self.display(object), field: data.field.clone(), loc: data.field_loc });
+                Type::Error
+            }
+        }
+    }
+
+    fn infer_index(&mut self, data: &ast::IndexData) -> Type {
+        let object = self.infer_expr(data.object);
+        let object = self.known(&object, self.program.loc(data.object));
+        let index = self.infer_expr(data.index);
+        let index = self.known(&index, self.program.loc(data.index));
+        let is_integer = match index.dereferenced() {
+            Type::Primitive(primitive) => primitive.is_integer(),
+            Type::Variable(variable) => variable.kind == VariableKind::Integer,
+            _ => index.is_error()
+        };
+        if !is_integer {
+            let index = self.default_numeric(&index);
+            self.error(TypeError::IndexType { typ: self.display(&index), loc: self.program.loc(data.index) });
+        }
+        match object.dereferenced() {
+            Type::Error => Type::Error,
+            Type::Array(element, length) => {
+                if let ast::ExprNode::Integer(literal) = &self.program[data.index] {
+                    if literal.integer >= *length {
+                        self.error(TypeError::IndexOutOfBounds { index: literal.integer, length: *length, loc: self.program.loc(data.index) });
+                    }
+                }
+                element.as_ref().clone()
+            },
+            _ => {
+                let object = self.default_numeric(&object);
+                self.error(TypeError::NotIndexable { typ: self.display(&object), loc: self.program.loc(data.object) });
+                Type::Error
+            }
+        }
+    }
+
+    /// The method named by a field access on a value of type `object`, if the value's struct has a method and
+    /// no field with that name.
+    fn find_method(&self, object: &Type, name: &str) -> Option<DeclId> {
+        match object.dereferenced() {
+            Type::Struct(id, _) => {
+                if self.info.structs.get(id)?.field(name).is_some() {
+                    return None;
+                }
+                self.method_of(*id, name)
+            },
+            _ => None
+        }
+    }
+
+    /// The type of an integer literal, which is the integer type it is expected to have. If that is not known
+    /// yet, it is an inference variable, and the literal is checked against the range of its type once the
+    /// type has been decided.
+    fn integer_literal(&mut self, data: &ast::IntegerData, negated: bool, loc: Span, hint: Option<&Type>) -> Type {
+        let value = if negated { -(data.integer as i128) } else { data.integer as i128 };
+        if let Some(primitive) = hint.and_then(|h| self.resolve(h).dereferenced().primitive()) {
+            if primitive.is_integer() {
+                self.check_literal_range(value, primitive, loc);
+                return Type::Primitive(primitive);
+            }
+        }
+        let typ = self.fresh_variable(VariableKind::Integer);
+        self.pending_literals.push((value, loc, typ.clone()));
+        return typ;
+    }
+
+    /// Reports an integer literal that does not fit in its type, returning whether it fits.
+    fn check_literal_range(&mut self, value: i128, primitive: Primitive, loc: Span) -> bool {
+        let (min, max) = primitive.integer_range().unwrap();
+        if value < min || value > max {
+            self.error(TypeError::LiteralOutOfRange {
+                literal: value.to_string(), typ: String::from(primitive.name()), min, max, loc
+            });
+            return false;
+        }
+        return true;
+    }
+
+    fn infer_unary(&mut self, data: &ast::UnaryData, hint: Option<&Type>) -> Type {
+        // A negated literal is checked as a negative number, so that `-128` fits in an `i8`.
+        if let (ast::UnaryOp::Negate, ast::ExprNode::Integer(literal)) = (data.op, &self.program[data.operand]) {
+            let typ = self.integer_literal(literal, true, data.loc, hint);
+            self.current().expressions.insert(data.operand, typ.clone());
+            return typ;
+        }
+        let operand = self.infer_expr_with(data.operand, hint);
+        if data.op == ast::UnaryOp::Not {
+            self.unify(&operand, &Type::bool());
+        }
+        let operand = self.known(&operand, self.program.loc(data.operand));
+        let valid = match (data.op, operand.dereferenced()) {
+            (_, Type::Error) => return Type::Error,
+            (ast::UnaryOp::Negate, Type::Primitive(p)) => p.is_signed() || p.is_float(),
+            // Whether an integer is signed may only be decided later.
+            (ast::UnaryOp::Negate, Type::Variable(variable)) => {
+                if variable.kind == VariableKind::Integer {
+                    self.pending_negations.push((operand.dereferenced().clone(), data.loc));
+                }
+                true
+            },
+            (ast::UnaryOp::Not, Type::Primitive(p)) => *p == Primitive::Bool,
+            _ => false
+        };
+        if !valid {
+            self.error(TypeError::InvalidOperand { op: data.op.symbol(), typ: self.display(&operand), loc: data.loc });
+            return Type::Error;
+        }
+        return operand.dereferenced().clone();
+    }
+
+    fn infer_binary(&mut self, data: &ast::BinaryData, hint: Option<&Type>) -> Type {
+        use ast::BinaryOp::*;
+        // The operands of a comparison are not expected to have the type of its result.
+        let hint = if matches!(data.op, Add | Subtract | Multiply | Divide | Remainder) { hint } else { None };
+        // A literal operand takes the type of the other operand, so that `1 + x` works for any type of `x`.
+        let (left, right) = if is_literal(self.program, data.left) && !is_literal(self.program, data.right) {
+            let right = self.infer_expr_with(data.right, hint);
+            (self.infer_expr_with(data.left, Some(&right)), right)
+        } else {
+            let left = self.infer_expr_with(data.left, hint);
+            (left.clone(), self.infer_expr_with(data.right, Some(&left)))
+        };
+        return self.binary_type(data.op, &left, &right, self.program.loc(data.left), self.program.loc(data.right), data.loc);
+    }
+
+    /// The type of the result of a binary operator applied to operands of the given types.
+    fn binary_type(&mut self, op: ast::BinaryOp, left: &Type, right: &Type, left_loc: Span, right_loc: Span, loc: Span) -> Type {
+        use ast::BinaryOp::*;
+        let (left, right) = (self.resolve(left).dereferenced().clone(), self.resolve(right).dereferenced().clone());
+        if left.is_error() || right.is_error() {
+            return Type::Error;
+        }
+        // Operands whose types are still being inferred take the type of the other operand, or `bool` for the
+        // logical operators.
+        if matches!(op, And | Or) {
+            self.unify(&left, &Type::bool());
+            self.unify(&right, &Type::bool());
+        } else {
+            self.unify(&left, &right);
+        }
+        let left = self.known(&left, left_loc);
+        let right = self.known(&right, right_loc);
+        if left.is_error() || right.is_error() {
+            return Type::Error;
+        }
+        // Numbers whose types are still undecided can be used with any operator their eventual type supports.
+        if let (Type::Variable(l), Type::Variable(r)) = (&left, &right) {
+            match op {
+                Add | Subtract | Multiply | Divide | Remainder if l == r => return left.clone(),
+                Less | LessEqual | Greater | GreaterEqual | Equal | NotEqual if l == r => return Type::bool(),
+                _ => { }
+            }
+        }
+        let (left, right) = (self.default_numeric(&left), self.default_numeric(&right));
+
+        let common = match (left.primitive(), right.primitive()) {
+            (Some(l), Some(r)) if l.widens_to(r) => Some(r),
+            (Some(l), Some(r)) if r.widens_to(l) => Some(l),
+            _ => None
+        };
+        let result = match (op, common) {
+            (Add | Subtract | Multiply | Divide | Remainder, Some(p)) if p.is_numeric() => Some(Type::Primitive(p)),
+            (Less | LessEqual | Greater | GreaterEqual, Some(p)) if p.is_numeric() || p == Primitive::Char => Some(Type::bool()),
+            (Equal | NotEqual, Some(p)) if p != Primitive::Unit => Some(Type::bool()),
+            (And | Or, Some(Primitive::Bool)) => Some(Type::bool()),
+            _ => None
+        };
+        return match result {
+            Some(typ) => typ,
+            None => {
+                self.error(TypeError::InvalidOperands {
+                    op: op.symbol(), left: self.display(&left), right: self.display(&right), loc
+                });
+                Type::Error
+            }
+        };
+    }
+
+    fn infer_name(&mut self, expr: ast::ExprId, data: &ast::NameData) -> Type {
+        let id = match self.declaration_of(data.name) {
+            Some(id) => id,
+            None => return Type::Error
+        };
+        let declaration = self.resolution.declaration(id);
+        match declaration.kind {
+            DeclKind::Local | DeclKind::Parameter => self.current().variables.get(&id).cloned().unwrap_or(Type::Error),
+            DeclKind::Const => self.info.constants.get(&id).map_or(Type::Error, |constant| Type::Primitive(constant.typ)),
+            DeclKind::Function => {
+                // A generic function that is not called takes its type arguments from the type it is expected to have.
+                let generic_parameters = self.info.functions[&id].generic_parameters.clone();
+                let arguments: Vec<Type> = generic_parameters.iter().map(|_| self.fresh_variable(VariableKind::General)).collect();
+                let function_type = self.info.functions[&id].function_type().substitute(&bind(&generic_parameters, &arguments));
+                self.defer_use(id, arguments, data.loc, Some(expr));
+                function_type
+            },
+            _ => {
+                self.error(TypeError::NotAValue { name: declaration.name.clone(), loc: data.loc });
+                Type::Error
+            }
+        }
+    }
+
+    /// Records a use of a function or generic struct with the given type arguments. They may mention inference
+    /// variables, so the bounds of the generic parameters are checked, and the function instantiated, once the
+    /// body has been checked.
+    fn defer_use(&mut self, declaration: DeclId, arguments: Vec<Type>, loc: Span, expr: Option<ast::ExprId>) {
+        self.deferred_uses.push(DeferredUse { declaration, arguments, loc, expr });
+    }
+
+    fn infer_call(&mut self, data: &ast::CallData, hint: Option<&Type>) -> Type {
+        // Calls of generic functions by name take their type arguments from the arguments of the call.
+        if let ast::ExprNode::Name(name) = &self.program[data.callee] {
+            if let Some(id) = self.declaration_of(name.name) {
+                if self.info.functions.get(&id).is_some_and(|s| !s.generic_parameters.is_empty()) {
+                    return self.infer_generic_call(data, name, id, hint);
+                }
+            }
+        }
+
+        let callee = match &self.program[data.callee] {
+            ast::ExprNode::Field(field) => {
+                let object = self.infer_expr(field.object);
+                let object = self.known(&object, self.program.loc(field.object));
+                if let Some(method) = self.find_method(&object, &field.field) {
+                    return self.infer_method_call(data, field, &object, method, hint);
+                }
+                let typ = self.infer_field(field, &object);
+                self.current().expressions.insert(data.callee, typ.clone());
+                typ
+            },
+            _ => self.infer_expr(data.callee)
+        };
+        // A value whose type is still being inferred is a function taking the arguments it is called with.
+        if matches!(&callee, Type::Variable(v) if v.kind == VariableKind::General) {
+            let parameters = data.arguments.iter().map(|_| self.fresh_variable(VariableKind::General)).collect();
+            let function_type = Type::Function(parameters, Box::new(self.fresh_variable(VariableKind::General)));
+            self.unify(&callee, &function_type);
+        }
+        let (parameters, ret) = match self.resolve(&callee).dereferenced() {
+            Type::Function(parameters, ret) => (parameters.clone(), ret.as_ref().clone()),
+            typ => {
+                if !typ.is_error() {
+                    self.error(TypeError::NotCallable { typ: self.display(typ), loc: self.program.loc(data.callee) });
+                }
+                for argument in &data.arguments {
+                    self.infer_expr(*argument);
+                }
+                return Type::Error;
+            }
+        };
+
+        // When calling a function by name, mismatched arguments can point at the parameters' annotations.
+        let function = match &self.program[data.callee] {
+            ast::ExprNode::Name(name) => self.declaration_of(name.name).filter(|id| self.info.functions.contains_key(id)),
+            _ => None
+        };
+        self.check_argument_count(data, parameters.len(), function);
+        let parameter_locs = function.map(|id| self.info.functions[&id].parameter_locs.clone());
+        self.check_arguments(&data.arguments, &parameters, parameter_locs.as_deref(), &ret, hint);
+        return ret;
+    }
+
+    fn check_argument_count(&mut self, data: &ast::CallData, expected: usize, function: Option<DeclId>) {
+        if expected != data.arguments.len() {
+            self.error(TypeError::ArgumentCount {
+                expected,
+                found: data.arguments.len(),
+                loc: data.loc,
+                declared: function.and_then(|id| self.resolution.declaration(id).loc)
+            });
+        }
+    }
+
+    /// Checks the arguments of a call against the types of the parameters, which may mention inference
+    /// variables standing for generic arguments. Literals are checked last, after the return type has been
+    /// unified with the type the call is expected to have, so that they take their types from the other
+    /// arguments or from where the call is used.
+    fn check_arguments(&mut self, arguments: &[ast::ExprId], parameters: &[Type], parameter_locs: Option<&[Span]>,
+                       ret: &Type, hint: Option<&Type>) {
+        for literals in [false, true] {
+            if literals {
+                if let Some(hint) = hint {
+                    // The result of a call is a temporary, so only a reference result can match a reference.
+                    let hint = if matches!(ret, Type::Reference(_)) { hint.clone() } else { self.resolve(hint).dereferenced().clone() };
+                    self.unify(ret, &hint);
+                }
+            }
+            for (i, &argument) in arguments.iter().enumerate() {
+                if is_literal(self.program, argument) != literals {
+                    continue;
+                }
+                match parameters.get(i) {
+                    Some(parameter) => self.check_expr(argument, parameter, parameter_locs.map(|locs| locs[i])),
+                    None => { self.infer_expr(argument); }
+                }
+            }
+        }
+    }
+
+    fn infer_generic_call(&mut self, data: &ast::CallData, callee: &ast::NameData, function: DeclId, hint: Option<&Type>) -> Type {
+        let signature = &self.info.functions[&function];
+        let (generic_parameters, parameter_count) = (signature.generic_parameters.clone(), signature.parameters.len());
+        self.check_argument_count(data, parameter_count, Some(function));
+
+        let arguments: Vec<Type> = generic_parameters.iter().map(|_| self.fresh_variable(VariableKind::General)).collect();
+        let signature = &self.info.functions[&function];
+        let function_type = signature.function_type().substitute(&bind(&generic_parameters, &arguments));
+        let parameter_locs = signature.parameter_locs.clone();
+        self.defer_use(function, arguments, callee.loc, Some(data.callee));
+        self.current().expressions.insert(data.callee, function_type.clone());
+
+        let (parameters, ret) = match function_type {
+            Type::Function(parameters, ret) => (parameters, *ret),
+            _ => unreachable!()
+        };
+        self.check_arguments(&data.arguments, &parameters, Some(&parameter_locs), &ret, hint);
+        return ret;
+    }
+
+    /// Checks a call of a method on `object`, which is passed as the method's first parameter. The generic
+    /// parameters of the struct are bound to the object's generic arguments, and the method's own are inferred.
+    fn infer_method_call(&mut self, data: &ast::CallData, field: &ast::FieldData, object: &Type, method: DeclId, hint: Option<&Type>) -> Type {
+        let signature = &self.info.functions[&method];
+        let (generic_parameters, parameter_count) = (signature.generic_parameters.clone(), signature.parameters.len());
+        if parameter_count == 0 {
+            for argument in &data.arguments {
+                self.infer_expr(*argument);
+            }
+            return Type::Error;
+        }
+        if parameter_count - 1 != data.arguments.len() {
+            self.error(TypeError::ArgumentCount {
+                expected: parameter_count - 1,
+                found: data.arguments.len(),
+                loc: data.loc,
+                declared: self.resolution.declaration(method).loc
+            });
+        }
+
+        let struct_bindings = match object.dereferenced() {
+            Type::Struct(id, arguments) => bind(&self.info.structs[id].generic_parameters, arguments),
+            _ => HashMap::new()
+        };
+        let arguments: Vec<Type> = generic_parameters.iter()
+            .map(|p| struct_bindings.get(p).cloned().unwrap_or_else(|| self.fresh_variable(VariableKind::General)))
+            .collect();
+        let signature = &self.info.functions[&method];
+        let function_type = signature.function_type().substitute(&bind(&generic_parameters, &arguments));
+        let parameter_locs = signature.parameter_locs.clone();
+        self.defer_use(method, arguments, field.field_loc, Some(data.callee));
+        self.current().expressions.insert(data.callee, function_type.clone());
+
+        let (parameters, ret) = match function_type {
+            Type::Function(parameters, ret) => (parameters, *ret),
+            _ => unreachable!()
+        };
+        self.coerce(field.object, object, &parameters[0], Some(parameter_locs[0]));
+        self.check_arguments(&data.arguments, &parameters[1..], Some(&parameter_locs[1..]), &ret, hint);
+        return ret;
+    }
+
+    fn infer_struct_literal(&mut self, data: &ast::StructLiteralData) -> Type {
+        let id = match self.declaration_of(data.name) {
+            Some(id) if self.info.structs.contains_key(&id) => id,
+            Some(id) => {
+                let name = self.resolution.declaration(id).name.clone();
+                self.error(TypeError::NotAStruct { typ: name, loc: self.program.loc(data.name) });
+                for field in &data.fields {
+                    self.infer_expr(field.value);
+                }
+                return Type::Error;
+            },
+            None => return Type::Error
+        };
+
+        // The generic arguments of the struct are inferred from the values of its fields.
+        let generic_parameters = self.info.structs[&id].generic_parameters.clone();
+        let arguments: Vec<Type> = generic_parameters.iter().map(|_| self.fresh_variable(VariableKind::General)).collect();
+        let typ = Type::Struct(id, arguments.clone());
+        let mut given: HashMap<&str, Span> = HashMap::new();
+        for field in &data.fields {
+            if let Some(previous) = given.insert(&field.name, field.name_loc) {
+                self.error(TypeError::DuplicateField { name: field.name.clone(), loc: field.name_loc, previous });
+            }
+            let declared = self.info.structs[&id].field(&field.name).map(|f| f.type_loc);
+            match (self.info.structs[&id].field_type(&field.name, &arguments), declared) {
+                (Some(field_type), Some(type_loc)) => self.check_expr(field.value, &field_type, Some(type_loc)),
+                _ => {
+                    self.infer_expr(field.value);
+                    self.error(TypeError::NoField { typ: self.display(&typ), field: field.name.clone(), loc: field.name_loc });
+                }
+            }
+        }
+        if !generic_parameters.is_empty() {
+            self.defer_use(id, arguments, self.program.loc(data.name), None);
+        }
+
+        let missing: Vec<String> = self.info.structs[&id].fields.iter()
+            .filter(|f| !given.contains_key(f.name.as_str()))
+            .map(|f| f.name.clone())
+            .collect();
+        if !missing.is_empty() {
+            self.error(TypeError::MissingFields { typ: self.display(&typ), fields: missing, loc: data.loc });
+        }
+        return typ;
+    }
+
+    /// Records every struct type that the instances use, along with the struct types of their fields.
+    fn collect_struct_instances(&mut self) {
+        let mut pending = Vec::new();
+        for instance in &self.info.instances {
+            let mut expressions: Vec<(&ast::ExprId, &Type)> = instance.expressions.iter().collect();
+            expressions.sort_by_key(|(expr, _)| {
+                let loc = self.program.loc(**expr);
+                (loc.file, loc.lo, loc.hi, expr.index)
+            });
+            let mut variables: Vec<(&DeclId, &Type)> = instance.variables.iter().collect();
+            variables.sort_by_key(|(id, _)| **id);
+            pending.extend(variables.into_iter().map(|(_, t)| t.clone()));
+            pending.extend(expressions.into_iter().map(|(_, t)| t.clone()));
+        }
+        pending.reverse();
+
+        while let Some(typ) = pending.pop() {
+            match typ {
+                Type::Struct(id, arguments) => {
+                    let typ = Type::Struct(id, arguments.clone());
+                    if typ.is_error() || !typ.is_concrete() || self.info.struct_instances.contains(&typ) {
+                        continue;
+                    }
+                    let struct_type = &self.info.structs[&id];
+                    let bindings = bind(&struct_type.generic_parameters, &arguments);
+                    pending.extend(struct_type.fields.iter().rev().map(|f| f.typ.substitute(&bindings)));
+                    pending.extend(arguments);
+                    self.info.struct_instances.push(typ);
+                },
+                Type::Function(parameters, ret) => {
+                    pending.push(*ret);
+                    pending.extend(parameters);
+                },
+                Type::Reference(inner) | Type::Array(inner, _) => pending.push(*inner),
+                _ => { }
+            }
+        }
+    }
+}
+
+
+/// Whether an expression is a numeric literal, whose type depends on where it is used.
+fn is_literal(program: &ast::Program, expr: ast::ExprId) -> bool {
+    match &program[expr] {
+        ast::ExprNode::Integer(_) | ast::ExprNode::Float(_) => true,
+        ast::ExprNode::Unary(data) => data.op == ast::UnaryOp::Negate && is_literal(program, data.operand),
+        _ => false
+    }
+}
+
+/// Whether an `as` cast can convert a value of type `from` to `to`.
+fn can_cast(from: &Type, to: &Type) -> bool {
+    if from == to {
+        return true;
+    }
+    return match (from.primitive(), to.primitive()) {
+        (Some(from), Some(to)) => {
+            (from.is_numeric() && to.is_numeric())
+                || ((from == Primitive::Bool || from == Primitive::Char) && to.is_integer())
+                || (from == Primitive::U8 && to == Primitive::Char)
+        },
+        _ => false
+    };
+}
+
+/// A fix that makes a narrowing conversion explicit by casting the expression to `to`.
+fn cast_fix(program: &ast::Program, expr: ast::ExprId, to: Primitive) -> Fix {
+    let loc = program.loc(expr);
+    let cast = format!(" as {}", to.name());
+    let edits = if matches!(program[expr], ast::ExprNode::Binary(_) | ast::ExprNode::Assign(_)) {
+        vec![TextEdit { loc: loc.start(), replacement: String::from("(") }, TextEdit { loc: loc.end(), replacement: format!("){}", cast) }]
+    } else {
+        vec![TextEdit { loc: loc.end(), replacement: cast }]
+    };
+    return Fix { message: format!("Convert it explicitly with `as {}`.", to.name()), edits, applicability: Applicability::MaybeIncorrect };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use crate::typecheck::{Type, Primitive};
+
+    /// The messages of the errors for a program read as if from standard input.
+    fn errors(text: &str) -> Vec<String> {
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        return result.report.lines()
+            .filter_map(|line| line.strip_prefix("Error: "))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn mismatches_name_both_types() {
+        assert_eq!(errors("fn f() -> int { return true; }"), ["Mismatched types: expected 'i64', found 'bool'."]);
+        assert_eq!(errors("fn f(x: int) -> int { return x; } fn g() -> int { return f(true); }"), ["Mismatched types: expected 'i64', found 'bool'."]);
+        assert_eq!(errors("fn g() -> int { let x = 1; x = true; return x; }"), ["Mismatched types: expected '{integer}', found 'bool'."]);
+        assert_eq!(errors("struct P { x: int; } fn g(p: P&) -> bool { return p.x; }"), ["Mismatched types: expected 'bool', found 'i64'."]);
+    }
+
+    #[test]
+    fn calls_fields_and_assignments_are_checked() {
+        assert_eq!(errors("fn f(x: int) -> int { return x; } fn g() -> int { return f(); }"), ["Expected 1 argument, found 0."]);
+        assert_eq!(errors("fn g() -> int { let x: int = 1; return x(); }"), ["A value of type 'i64' cannot be called."]);
+        assert_eq!(errors("struct P { x: int; } fn g(p: P&) -> int { return p.y; }"), ["Type 'P&' has no field named 'y'."]);
+        assert_eq!(errors("fn g() -> int { 1 = 2; return 0; }"), ["Only variables and fields can be assigned to."]);
+    }
+
+    #[test]
+    fn references_and_function_types_are_accepted() {
+        let text = "struct P { x: int; }
+        fn apply(f: (int) -> bool, x: int) -> bool { return f(x); }
+        fn positive(x: int) -> bool { return x > 0; }
+        fn main() -> int {
+            let p = P { x: 1 };
+            let r: P& = p;
+            r.x = 2;
+            if apply(positive, r.x) { return p.x; }
+            return 0;
+        }";
+        assert_eq!(errors(text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn narrowing_needs_an_explicit_cast() {
+        assert_eq!(errors("fn f() -> i8 { let x: i64 = 1; return x; }"), [
+            "Expected 'i8', found 'i64', which cannot be converted implicitly because it may not fit."
+        ]);
+        assert_eq!(errors("fn f() -> u8 { let x: i8 = 1 as i8; return x; }"), [
+            "Expected 'u8', found 'i8', which cannot be converted implicitly because it may not fit."
+        ]);
+        assert_eq!(errors("fn f() -> i64 { let x: i8 = 1 as i8; let y: f64 = 1.0 as f32; return x; }"), Vec::<String>::new());
+        assert_eq!(errors("fn f() -> i8 { let x: i64 = 300; return x as i8; }"), Vec::<String>::new());
+        assert_eq!(errors("fn f() -> bool { return 1 as bool; }"), ["Cannot cast a value of type 'i64' to 'bool'."]);
+    }
+
+    #[test]
+    fn generic_functions_get_one_instance_per_type_argument() {
+        let text = "fn id<T>(x: T) -> T { return x; }
+        struct Pair<A, B> { a: A; b: B; }
+        fn main() -> int {
+            let _a: i8 = id(1 as i8);
+            let _b = id(id(true));
+            let p = Pair { a: id(2), b: false };
+            return p.a;
+        }";
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        let hir = result.hir.as_ref().expect("the program has errors");
+        let mut names: Vec<&str> = hir.functions.iter().map(|(_, function)| function.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["id<bool>", "id<i64>", "id<i8>", "main"]);
+        assert_eq!(crate::interp::run_program(hir).ok(), Some(2));
+    }
+
+    #[test]
+    fn errors_in_an_instance_say_which_instance() {
+        let text = "fn add<T>(x: T, y: T) -> T { return x + y; }
+        fn main() -> int { let _s = add(\"a\", \"b\"); return add(1, 2); }";
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        assert_eq!(errors(text), ["Cannot apply '+' to values of types 'str' and 'str'."]);
+        assert!(result.report.contains("Note: In instantiation of `add<str>` requested here."));
+    }
+
+    #[test]
+    fn type_arguments_must_be_inferred_consistently() {
+        assert_eq!(errors("fn make<T>() -> int { return 0; } fn main() -> int { return make(); }"), [
+            "Cannot infer the type of the generic parameter 'T' of 'make'."
+        ]);
+        assert_eq!(errors("fn first<T>(x: T, y: T) -> T { return x; } fn main() -> int { return first(1, true); }"), [
+            "Mismatched types: expected 'i64', found 'bool'.",
+            "Mismatched types: expected 'bool', found '{integer}'."
+        ]);
+    }
+
+    #[test]
+    fn implementations_must_provide_every_method() {
+        assert_eq!(errors("trait Show { fn show(self: Self&) -> int; fn size(self: Self&) -> int; } struct P: Show { x: int; }"), [
+            "'P' does not implement trait 'Show': it is missing the methods 'show' and 'size'."
+        ]);
+        assert_eq!(errors("trait Show { fn show(self: Self&) -> int; } struct P: Show { fn show(self: Self&) -> bool { return true; } }"), [
+            "Method 'show' has type '(P&) -> bool', but trait 'Show' requires it to have type '(P&) -> i64'."
+        ]);
+        assert_eq!(errors("trait Show { fn show(self: Self&) -> int; } struct P: Show, Show { fn show(self: Self&) -> int { return 1; } }"), [
+            "'P' already implements trait 'Show'."
+        ]);
+        assert_eq!(errors("trait A { fn m(self: Self&) -> int; } trait B { fn m(self: Self&) -> bool; } struct P: A, B { fn m(self: Self&) -> int { return 0; } }"), [
+            "'P' cannot implement both 'A' and 'B', which require methods named 'm' with different types."
+        ]);
+    }
+
+    #[test]
+    fn type_arguments_must_satisfy_bounds() {
+        let show = "trait Show { fn show(self: Self&) -> int; }
+        struct P: Show { x: int; fn show(self: Self&) -> int { return self.x; } }
+        fn f<T: Show>(x: T) -> int { return x.show(); }";
+        assert_eq!(errors(&format!("{} fn main() -> int {{ return f(P {{ x: 4 }}); }}", show)), Vec::<String>::new());
+        assert_eq!(errors(&format!("{} fn main() -> int {{ return f(1); }}", show)), [
+            "The type 'i64' does not implement 'Show', which is required by the generic parameter 'T' of 'f'."
+        ]);
+        assert_eq!(errors("struct Q { } fn f<T: Q>(x: T) -> int { return 0; }"), ["'Q' is not a trait."]);
+    }
+
+    #[test]
+    fn superstructs_are_checked() {
+        assert_eq!(errors("struct A(B) { x: int; } struct B(A) { y: int; }"), ["'A' inherits from itself: A -> B -> A."]);
+        assert_eq!(errors("struct A { x: int; } struct B(A) { x: int; }"), ["Field 'x' is already declared in the superstruct 'A'."]);
+        let text = "struct A { x: int; }
+        struct B(A) { y: int; }
+        fn f(a: A&) -> int { return a.x; }
+        fn main() -> int { let b = B { x: 1, y: 2 }; let a: A& = b; return f(b) + a.x; }";
+        assert_eq!(errors(text), Vec::<String>::new());
+        assert_eq!(errors("struct A { x: int; } struct B(A) { y: int; } fn f(b: B&) -> int { return b.y; }
+        fn main() -> int { let a = A { x: 1 }; return f(a); }"), ["Mismatched types: expected 'B&', found 'A'."]);
+    }
+
+    #[test]
+    fn locals_take_the_type_their_uses_need() {
+        // The first use of `small` decides its type, which later uses may widen.
+        let text = "fn main() -> int {
+            let small = 1;
+            let later;
+            later = 3 as i16;
+            let add = |a| a + 1;
+            let _x: i8 = small;
+            let big: i32 = small;
+            return add(2) + big as int + later as int;
+        }";
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        let hir = result.hir.as_ref().expect("the program has errors");
+        let main = hir.functions.iter().map(|(_, function)| function).find(|function| function.name == "main").unwrap();
+        let type_of = |name: &str| main.locals.iter().find(|(_, local)| local.name == name).map(|(_, local)| local.typ.clone());
+        assert_eq!(type_of("small"), Some(Type::Primitive(Primitive::I8)));
+        assert_eq!(type_of("later"), Some(Type::Primitive(Primitive::I16)));
+        let int = Type::Primitive(Primitive::I64);
+        assert_eq!(type_of("add"), Some(Type::Function(vec![int.clone()], Box::new(int))));
+    }
+
+    #[test]
+    fn types_that_cannot_be_inferred_are_reported() {
+        assert_eq!(errors("fn main() -> int { let x; return 0; }"), ["Cannot infer the type of 'x'."]);
+        assert_eq!(errors("fn main() -> int { let f = |a| a; return 0; }"), ["Cannot infer the type of 'f'."]);
+        assert_eq!(errors("fn main() -> int { let x; x.foo; return 0; }"), ["The type of this value must be known here."]);
+    }
+
+    #[test]
+    fn constants_are_evaluated_when_checked() {
+        let text = "const N: int = 2 * 3;
+        const BIG: bool = N > 5 && N as u8 == 6 as u8;
+        fn main() -> int { let a: [int; N] = [1, 2, 3, 4, 5, 6]; if BIG { return a[N - 1]; } return 0; }";
+        let result = crate::check(&Options::from_args(Vec::new()).unwrap(), &Some(String::from(text)));
+        let hir = result.hir.as_ref().expect("the program has errors");
+        assert_eq!(crate::interp::run_program(hir).ok(), Some(6));
+    }
+
+    #[test]
+    fn constants_that_have_no_value_are_reported() {
+        assert_eq!(errors("const A: int = B + 1; const B: int = A;"), ["The value of constant 'A' depends on itself: A -> B -> A."]);
+        assert_eq!(errors("const A: i8 = 100 + 100;"), ["The result of '+' does not fit in 'i8', whose values are from -128 to 127."]);
+        assert_eq!(errors("const A: int = 1 / 0;"), ["Division by zero in a constant expression."]);
+        assert_eq!(errors("fn f() -> int { return 1; } const A: int = f();"), [
+            "This cannot be evaluated at compile time. Constant expressions may only use literals, constants, arithmetic, comparisons and casts."
+        ]);
+    }
+
+    #[test]
+    fn literals_must_fit_their_type() {
+        assert_eq!(errors("fn f() -> int { let _x: i8 = 300; return 0; }"), ["The literal '300' does not fit in 'i8', whose values are from -128 to 127."]);
+        assert_eq!(errors("fn f() -> u8 { return -1; }"), ["The literal '-1' does not fit in 'u8', whose values are from 0 to 255."]);
+        assert_eq!(errors("fn f() -> i8 { return -128; }"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unknown_types_are_shown_as_underscores() {
+        let text = "fn g<T>(x: T&) -> T& { return x; }
+        fn main() -> int { let h = g; let b: bool = h; return 0; }";
+        assert_eq!(errors(text), vec![
+            "Cannot infer the type of the generic parameter 'T' of 'g'.",
+            "Mismatched types: expected 'bool', found '(_) -> _'."
+        ]);
+        assert_eq!(errors("fn main() -> int { let a = []; let b: bool = a; return 0; }"), vec![
+            "Cannot infer the type of 'a'.",
+            "Mismatched types: expected 'bool', found '[_; 0]'."
+        ]);
+    }
+}
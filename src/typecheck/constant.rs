@@ -0,0 +1,188 @@
+use std::cmp::Ordering;
+
+use crate::ast::{UnaryOp, BinaryOp};
+use crate::typecheck::types::Primitive;
+
+
+/// The value of a constant expression. Integers of every integer type are held as `i128`, which can hold
+/// any of them, and `f32` values are held as the `f64` with the same value.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConstValue {
+    Integer(i128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String)
+}
+
+/// Why an operation in a constant expression has no value.
+pub enum FoldError {
+    /// The result of integer arithmetic does not fit in the type of its operands.
+    Overflow,
+    DivisionByZero
+}
+
+
+/// Applies a unary operator to a value of type `typ`, which the operator has been checked to apply to.
+pub fn fold_unary(op: UnaryOp, value: &ConstValue, typ: Primitive) -> Result<ConstValue, FoldError> {
+    match (op, value) {
+        (UnaryOp::Negate, ConstValue::Integer(n)) => fit(-n, typ),
+        (UnaryOp::Negate, ConstValue::Float(f)) => Ok(ConstValue::Float(round(-f, typ))),
+        (UnaryOp::Not, ConstValue::Bool(b)) => Ok(ConstValue::Bool(!b)),
+        _ => unreachable!()
+    }
+}
+
+/// Applies a binary operator to two values of type `typ`, which the operator has been checked to apply to.
+/// Unlike at run time, integer arithmetic that overflows is an error rather than wrapping around.
+pub fn fold_binary(op: BinaryOp, left: &ConstValue, right: &ConstValue, typ: Primitive) -> Result<ConstValue, FoldError> {
+    use BinaryOp::*;
+    if let Some(ordering) = compare(left, right) {
+        let result = match op {
+            Less => Some(ordering == Some(Ordering::Less)),
+            LessEqual => Some(matches!(ordering, Some(Ordering::Less | Ordering::Equal))),
+            Greater => Some(ordering == Some(Ordering::Greater)),
+            GreaterEqual => Some(matches!(ordering, Some(Ordering::Greater | Ordering::Equal))),
+            Equal => Some(ordering == Some(Ordering::Equal)),
+            NotEqual => Some(ordering != Some(Ordering::Equal)),
+            _ => None
+        };
+        if let Some(result) = result {
+            return Ok(ConstValue::Bool(result));
+        }
+    }
+    match (left, right) {
+        (ConstValue::Integer(l), ConstValue::Integer(r)) => {
+            if matches!(op, Divide | Remainder) && *r == 0 {
+                return Err(FoldError::DivisionByZero);
+            }
+            let result = match op {
+                Add => l.checked_add(*r),
+                Subtract => l.checked_sub(*r),
+                Multiply => l.checked_mul(*r),
+                Divide => l.checked_div(*r),
+                Remainder => l.checked_rem(*r),
+                _ => unreachable!()
+            };
+            return result.ok_or(FoldError::Overflow).and_then(|n| fit(n, typ));
+        },
+        (ConstValue::Float(l), ConstValue::Float(r)) => {
+            let result = match op {
+                Add => l + r,
+                Subtract => l - r,
+                Multiply => l * r,
+                Divide => l / r,
+                Remainder => l % r,
+                _ => unreachable!()
+            };
+            return Ok(ConstValue::Float(round(result, typ)));
+        },
+        (ConstValue::Bool(l), ConstValue::Bool(r)) => {
+            return Ok(ConstValue::Bool(match op {
+                And => *l && *r,
+                Or => *l || *r,
+                _ => unreachable!()
+            }));
+        },
+        _ => unreachable!()
+    }
+}
+
+/// Converts a value of type `from` to `to` as an `as` cast does, for a cast that has been checked to be allowed.
+pub fn fold_cast(value: &ConstValue, from: Primitive, to: Primitive) -> ConstValue {
+    if from == to {
+        return value.clone();
+    }
+    let integer = match value {
+        ConstValue::Integer(n) => *n,
+        ConstValue::Bool(b) => *b as i128,
+        ConstValue::Char(c) => *c as i128,
+        ConstValue::Float(f) if to.is_integer() => {
+            // Floats round towards zero and saturate at the bounds of the integer type.
+            let (min, max) = to.integer_range().unwrap();
+            return ConstValue::Integer(if f.is_nan() {
+                0
+            } else if *f <= min as f64 {
+                min
+            } else if *f >= max as f64 {
+                max
+            } else {
+                f.trunc() as i128
+            });
+        },
+        ConstValue::Float(f) => return ConstValue::Float(round(*f, to)),
+        ConstValue::Str(_) => unreachable!()
+    };
+    if to.is_float() {
+        return ConstValue::Float(if to == Primitive::F32 { integer as f32 as f64 } else { integer as f64 });
+    }
+    if to == Primitive::Char {
+        return ConstValue::Char(char::from(integer as u8));
+    }
+    return ConstValue::Integer(truncate(integer, to));
+}
+
+
+/// How two values of the same type compare, if they can be compared. `Some(None)` means that they are
+/// unordered, as NaN is with every float.
+fn compare(left: &ConstValue, right: &ConstValue) -> Option<Option<Ordering>> {
+    match (left, right) {
+        (ConstValue::Integer(l), ConstValue::Integer(r)) => Some(Some(l.cmp(r))),
+        (ConstValue::Float(l), ConstValue::Float(r)) => Some(l.partial_cmp(r)),
+        (ConstValue::Char(l), ConstValue::Char(r)) => Some(Some(l.cmp(r))),
+        (ConstValue::Bool(l), ConstValue::Bool(r)) => Some(Some(l.cmp(r))),
+        (ConstValue::Str(l), ConstValue::Str(r)) => Some(Some(l.cmp(r))),
+        _ => None
+    }
+}
+
+/// An integer result, if it is in the range of its type.
+fn fit(value: i128, typ: Primitive) -> Result<ConstValue, FoldError> {
+    let (min, max) = typ.integer_range().unwrap();
+    if value < min || value > max {
+        return Err(FoldError::Overflow);
+    }
+    return Ok(ConstValue::Integer(value));
+}
+
+/// Keeps the low bits of an integer that fit in `typ`, reading them as two's complement if it is signed.
+pub fn truncate(value: i128, typ: Primitive) -> i128 {
+    let bits = typ.size() as u32 * 8;
+    let low = value & ((1i128 << bits) - 1);
+    return if typ.is_signed() && low >> (bits - 1) == 1 { low - (1i128 << bits) } else { low };
+}
+
+/// Rounds a float result to the precision of its type.
+pub fn round(value: f64, typ: Primitive) -> f64 {
+    if typ == Primitive::F32 { value as f32 as f64 } else { value }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{UnaryOp, BinaryOp};
+    use crate::typecheck::types::Primitive;
+    use super::*;
+
+    #[test]
+    fn integer_arithmetic_is_checked_against_its_type() {
+        let int = |n| ConstValue::Integer(n);
+        assert_eq!(fold_binary(BinaryOp::Add, &int(100), &int(27), Primitive::I8).ok(), Some(int(127)));
+        assert!(matches!(fold_binary(BinaryOp::Add, &int(100), &int(28), Primitive::I8), Err(FoldError::Overflow)));
+        assert!(matches!(fold_binary(BinaryOp::Subtract, &int(0), &int(1), Primitive::U64), Err(FoldError::Overflow)));
+        assert!(matches!(fold_unary(UnaryOp::Negate, &int(-128), Primitive::I8), Err(FoldError::Overflow)));
+        assert!(matches!(fold_binary(BinaryOp::Remainder, &int(1), &int(0), Primitive::I64), Err(FoldError::DivisionByZero)));
+        assert_eq!(fold_binary(BinaryOp::Divide, &int(-7), &int(2), Primitive::I64).ok(), Some(int(-3)));
+        assert_eq!(fold_binary(BinaryOp::Less, &int(1), &int(2), Primitive::I64).ok(), Some(ConstValue::Bool(true)));
+    }
+
+    #[test]
+    fn casts_fold_as_they_run() {
+        assert_eq!(fold_cast(&ConstValue::Integer(300), Primitive::I64, Primitive::U8), ConstValue::Integer(44));
+        assert_eq!(fold_cast(&ConstValue::Integer(-1), Primitive::I64, Primitive::U64), ConstValue::Integer(u64::MAX as i128));
+        assert_eq!(fold_cast(&ConstValue::Float(-2.7), Primitive::F64, Primitive::I32), ConstValue::Integer(-2));
+        assert_eq!(fold_cast(&ConstValue::Float(1e10), Primitive::F64, Primitive::I8), ConstValue::Integer(127));
+        assert_eq!(fold_cast(&ConstValue::Float(f64::NAN), Primitive::F64, Primitive::I32), ConstValue::Integer(0));
+        assert_eq!(fold_cast(&ConstValue::Float(0.1), Primitive::F64, Primitive::F32), ConstValue::Float(0.1f32 as f64));
+    }
+}
@@ -0,0 +1,467 @@
+use crate::source::Span;
+use crate::error::{CompilerError, ErrorDisplay, Severity, Fix};
+
+
+/// The number of instantiations shown for an error inside nested instantiations. The ones in the middle of
+/// longer chains are left out.
+const MAX_CHAIN_NOTES: usize = 8;
+
+
+pub enum TypeError {
+    /// An expression whose type does not match the type it is required to have. `declared` is the
+    /// annotation the required type comes from, if it was written in the source.
+    Mismatch { expected: String, found: String, loc: Span, declared: Option<Span> },
+    NotAValue { name: String, loc: Span },
+    NotAType { name: String, loc: Span },
+    NotCallable { typ: String, loc: Span },
+    ArgumentCount { expected: usize, found: usize, loc: Span, declared: Option<Span> },
+    NotAStruct { typ: String, loc: Span },
+    NoField { typ: String, field: String, loc: Span },
+    MissingFields { typ: String, fields: Vec<String>, loc: Span },
+    DuplicateField { name: String, loc: Span, previous: Span },
+    DuplicateFieldDeclaration { name: String, loc: Span, previous: Span },
+    NotAssignable { loc: Span },
+    /// A numeric value used where a type that cannot hold all of its values is expected. The fix adds a cast.
+    Narrowing { from: String, to: String, loc: Span, declared: Option<Span>, fix: Fix },
+    LiteralOutOfRange { literal: String, typ: String, min: i128, max: i128, loc: Span },
+    InvalidOperand { op: &'static str, typ: String, loc: Span },
+    InvalidOperands { op: &'static str, left: String, right: String, loc: Span },
+    InvalidCast { from: String, to: String, loc: Span },
+    GenericArgumentCount { name: String, expected: usize, found: usize, loc: Span },
+    /// A generic parameter whose argument could not be inferred from a use. `annotation` names the variable whose
+    /// type annotation would decide it, and the span of the variable's name.
+    CannotInfer { parameter: String, function: String, loc: Span, annotation: Option<(String, Span)> },
+    /// A value whose type has not been inferred yet, used in a way that depends on its type.
+    UnknownType { loc: Span, annotation: Option<(String, Span)> },
+    /// A variable or lambda parameter whose type could not be inferred from anything in the function.
+    AmbiguousType { name: String, loc: Span },
+    InstantiationDepth { instance: String, limit: usize, loc: Span },
+    /// An error found while checking an instance of a generic function. `chain` lists the instance and
+    /// the ones that led to it, innermost first, each with the span of the use that requested it.
+    InInstantiation { error: Box<TypeError>, chain: Vec<(String, Span)> },
+    /// A value that is not stored in a variable or field, used where a reference is expected.
+    TemporaryReference { typ: String, loc: Span, declared: Option<Span> },
+    NotATrait { name: String, loc: Span },
+    /// A type argument that does not implement some of the traits that bound its generic parameter, which
+    /// is `parameter` of `owner` and declared at `declared`.
+    UnsatisfiedBounds { typ: String, missing: Vec<String>, parameter: String, owner: String, loc: Span, declared: Option<Span> },
+    /// A struct that claims a trait but lacks some of its methods, each with the span of its declaration in the trait.
+    MissingMethods { typ: String, trait_name: String, methods: Vec<(String, Option<Span>)>, loc: Span },
+    MethodSignature { method: String, trait_name: String, expected: String, found: String, loc: Span, required: Option<Span> },
+    /// A struct that claims the same trait more than once.
+    DuplicateImplementation { typ: String, trait_name: String, loc: Span, previous: Span },
+    /// A struct that claims two traits requiring methods with the same name but different signatures,
+    /// which no single method can satisfy.
+    ConflictingRequirements { typ: String, method: String, first: String, second: String, loc: Span, previous: Span },
+    DuplicateMethod { name: String, loc: Span, previous: Span },
+    /// A method whose first parameter is not the value it is called on.
+    MethodReceiver { loc: Span },
+    MethodNotCalled { typ: String, method: String, loc: Span },
+    GenericSuperstruct { name: String, loc: Span },
+    /// Structs that inherit from themselves, listed in the order they inherit from each other.
+    InheritanceCycle { chain: Vec<String>, loc: Span },
+    /// A field with the same name as one inherited from `superstruct`, which is declared at `previous`.
+    InheritedField { name: String, superstruct: String, loc: Span, previous: Span },
+    /// A struct that claims a trait one of its superstructs already implements.
+    InheritedImplementation { typ: String, trait_name: String, superstruct: String, loc: Span, previous: Option<Span> },
+    /// An `is` check or cast between structs where neither inherits from the other, so it could never succeed.
+    UnrelatedStructs { from: String, to: String, loc: Span },
+    /// An expression in a constant or array length that cannot be evaluated while compiling.
+    NotConstant { loc: Span },
+    /// Integer arithmetic in a constant expression whose result does not fit in the type of its operands.
+    ConstantOverflow { op: &'static str, typ: String, min: i128, max: i128, loc: Span },
+    ConstantDivisionByZero { loc: Span },
+    /// Constants whose values depend on themselves, listed in the order they use each other.
+    ConstantCycle { chain: Vec<String>, loc: Span },
+    /// A constant whose type is not a primitive type that has values.
+    ConstantType { typ: String, loc: Span },
+    ArrayLengthType { typ: String, loc: Span },
+    NegativeArrayLength { length: i128, loc: Span },
+    NotIndexable { typ: String, loc: Span },
+    IndexType { typ: String, loc: Span },
+    /// A literal index that is past the end of the array it indexes.
+    IndexOutOfBounds { index: u64, length: u64, loc: Span },
+    /// A bound of a `for` loop's range that is not an integer.
+    RangeType { typ: String, loc: Span },
+    NotIterable { typ: String, loc: Span }
+}
+
+
+impl CompilerError for TypeError {
+    fn render(&self, display: &mut ErrorDisplay) -> String {
+        use TypeError::*;
+        return match self {
+            Mismatch { expected, found, loc, declared } => {
+                let message = format!("Mismatched types: expected '{}', found '{}'.", expected, found);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, &format!("The type '{}' is declared here.", expected), declared)
+                    }));
+                }
+                msg
+            },
+            Narrowing { from, to, loc, declared, fix } => {
+                let message = format!("Expected '{}', found '{}', which cannot be converted implicitly because it may not fit.", to, from);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, &format!("The type '{}' is declared here.", to), declared)
+                    }));
+                }
+                msg.push_str(&display.fix(fix));
+                msg
+            },
+            LiteralOutOfRange { literal, typ, min, max, loc } => {
+                let message = format!("The literal '{}' does not fit in '{}', whose values are from {} to {}.", literal, typ, min, max);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            InvalidOperand { op, typ, loc } => {
+                display.error_with_location(Severity::Error, &format!("Cannot apply '{}' to a value of type '{}'.", op, typ), loc)
+            },
+            InvalidOperands { op, left, right, loc } => {
+                display.error_with_location(Severity::Error, &format!("Cannot apply '{}' to values of types '{}' and '{}'.", op, left, right), loc)
+            },
+            InvalidCast { from, to, loc } => {
+                display.error_with_location(Severity::Error, &format!("Cannot cast a value of type '{}' to '{}'.", from, to), loc)
+            },
+            GenericArgumentCount { name, expected, found, loc } => {
+                let message = format!(
+                    "'{}' takes {} generic argument{}, but {} {} given.",
+                    name, expected, if *expected == 1 { "" } else { "s" }, found, if *found == 1 { "was" } else { "were" }
+                );
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            CannotInfer { parameter, function, loc, annotation } => {
+                let message = format!("Cannot infer the type of the generic parameter '{}' of '{}'.", parameter, function);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(annotation) = annotation {
+                    msg.push_str(&annotation_note(display, annotation));
+                }
+                msg
+            },
+            UnknownType { loc, annotation } => {
+                let mut msg = display.error_with_location(Severity::Error, "The type of this value must be known here.", loc);
+                if let Some(annotation) = annotation {
+                    msg.push_str(&annotation_note(display, annotation));
+                }
+                msg
+            },
+            AmbiguousType { name, loc } => {
+                let mut msg = display.error_with_location(Severity::Error, &format!("Cannot infer the type of '{}'.", name), loc);
+                msg.push_str(&annotation_note(display, &(name.clone(), *loc)));
+                msg
+            },
+            InstantiationDepth { instance, limit, loc } => {
+                let message = format!("Instantiating `{}` exceeds the limit of {} nested instantiations.", instance, limit);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            InInstantiation { error, chain } => {
+                let mut msg = error.render(display);
+                msg.push_str(&display.with_indent(|display| {
+                    let mut notes = String::new();
+                    for (i, (instance, loc)) in chain.iter().enumerate() {
+                        if chain.len() > MAX_CHAIN_NOTES && i == MAX_CHAIN_NOTES / 2 {
+                            let hidden = chain.len() - MAX_CHAIN_NOTES;
+                            notes.push_str(&display.error_without_location(Severity::Note, &format!("{} more instantiations are not shown.", hidden)));
+                        }
+                        if chain.len() > MAX_CHAIN_NOTES && (MAX_CHAIN_NOTES / 2..chain.len() - MAX_CHAIN_NOTES / 2).contains(&i) {
+                            continue;
+                        }
+                        notes.push_str(&display.error_with_location(Severity::Note, &format!("In instantiation of `{}` requested here.", instance), loc));
+                    }
+                    notes
+                }));
+                msg
+            },
+            NotAValue { name, loc } => {
+                display.error_with_location(Severity::Error, &format!("'{}' is not a value.", name), loc)
+            },
+            NotAType { name, loc } => {
+                display.error_with_location(Severity::Error, &format!("'{}' is not a type.", name), loc)
+            },
+            NotCallable { typ, loc } => {
+                display.error_with_location(Severity::Error, &format!("A value of type '{}' cannot be called.", typ), loc)
+            },
+            ArgumentCount { expected, found, loc, declared } => {
+                let message = format!(
+                    "Expected {} argument{}, found {}.", expected, if *expected == 1 { "" } else { "s" }, found
+                );
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, "The function is declared here.", declared)
+                    }));
+                }
+                msg
+            },
+            NotAStruct { typ, loc } => {
+                display.error_with_location(Severity::Error, &format!("'{}' is not a struct.", typ), loc)
+            },
+            NoField { typ, field, loc } => {
+                display.error_with_location(Severity::Error, &format!("Type '{}' has no field named '{}'.", typ, field), loc)
+            },
+            MissingFields { typ, fields, loc } => {
+                let fields: Vec<String> = fields.iter().map(|f| format!("'{}'", f)).collect();
+                let message = format!("Missing field{} {} in '{}'.", if fields.len() == 1 { "" } else { "s" }, fields.join(", "), typ);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            DuplicateField { name, loc, previous } => {
+                let mut msg = display.error_with_location(Severity::Error, &format!("Field '{}' is given more than once.", name), loc);
+                msg.push_str(&display.with_indent(|display| {
+                    display.error_with_location(Severity::Note, "It is first given here.", previous)
+                }));
+                msg
+            },
+            DuplicateFieldDeclaration { name, loc, previous } => {
+                let mut msg = display.error_with_location(Severity::Error, &format!("Field '{}' is already declared in this struct.", name), loc);
+                msg.push_str(&display.with_indent(|display| {
+                    display.error_with_location(Severity::Note, "The previous declaration is here.", previous)
+                }));
+                msg
+            },
+            NotAssignable { loc } => {
+                display.error_with_location(Severity::Error, "Only variables and fields can be assigned to.", loc)
+            },
+            TemporaryReference { typ, loc, declared } => {
+                let message = format!("Expected a reference of type '{}', but this value is not stored in a variable or field.", typ);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, &format!("The type '{}' is declared here.", typ), declared)
+                    }));
+                }
+                msg
+            },
+            NotATrait { name, loc } => {
+                display.error_with_location(Severity::Error, &format!("'{}' is not a trait.", name), loc)
+            },
+            UnsatisfiedBounds { typ, missing, parameter, owner, loc, declared } => {
+                let message = format!(
+                    "The type '{}' does not implement {}, which {} required by the generic parameter '{}' of '{}'.",
+                    typ, list(missing), if missing.len() == 1 { "is" } else { "are" }, parameter, owner
+                );
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(declared) = declared {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, &format!("'{}' is declared here.", parameter), declared)
+                    }));
+                }
+                msg
+            },
+            MissingMethods { typ, trait_name, methods, loc } => {
+                let names: Vec<String> = methods.iter().map(|(name, _)| name.clone()).collect();
+                let message = format!(
+                    "'{}' does not implement trait '{}': it is missing the method{} {}.",
+                    typ, trait_name, if names.len() == 1 { "" } else { "s" }, list(&names)
+                );
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                msg.push_str(&display.with_indent(|display| {
+                    let mut notes = String::new();
+                    for (name, declared) in methods {
+                        if let Some(declared) = declared {
+                            notes.push_str(&display.error_with_location(Severity::Note, &format!("'{}' is required here.", name), declared));
+                        }
+                    }
+                    notes
+                }));
+                msg
+            },
+            MethodSignature { method, trait_name, expected, found, loc, required } => {
+                let message = format!(
+                    "Method '{}' has type '{}', but trait '{}' requires it to have type '{}'.", method, found, trait_name, expected
+                );
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(required) = required {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, "The method is required here.", required)
+                    }));
+                }
+                msg
+            },
+            DuplicateImplementation { typ, trait_name, loc, previous } => {
+                let message = format!("'{}' already implements trait '{}'.", typ, trait_name);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                msg.push_str(&display.with_indent(|display| {
+                    display.error_with_location(Severity::Note, "It is first implemented here.", previous)
+                }));
+                msg
+            },
+            ConflictingRequirements { typ, method, first, second, loc, previous } => {
+                let message = format!(
+                    "'{}' cannot implement both '{}' and '{}', which require methods named '{}' with different types.", typ, first, second, method
+                );
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                msg.push_str(&display.with_indent(|display| {
+                    display.error_with_location(Severity::Note, &format!("'{}' is implemented here.", first), previous)
+                }));
+                msg
+            },
+            DuplicateMethod { name, loc, previous } => {
+                let mut msg = display.error_with_location(Severity::Error, &format!("'{}' is already declared in this struct.", name), loc);
+                msg.push_str(&display.with_indent(|display| {
+                    display.error_with_location(Severity::Note, "The previous declaration is here.", previous)
+                }));
+                msg
+            },
+            MethodReceiver { loc } => {
+                let message = "The first parameter of a method must be the value it is called on, with type 'Self' or 'Self&'.";
+                display.error_with_location(Severity::Error, message, loc)
+            },
+            MethodNotCalled { typ, method, loc } => {
+                display.error_with_location(Severity::Error, &format!("'{}' is a method of '{}', so it can only be called.", method, typ), loc)
+            },
+            GenericSuperstruct { name, loc } => {
+                display.error_with_location(Severity::Error, &format!("'{}' has generic parameters, so it cannot be a superstruct.", name), loc)
+            },
+            InheritanceCycle { chain, loc } => {
+                let message = format!("'{}' inherits from itself: {}.", chain[0], chain.join(" -> "));
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            InheritedField { name, superstruct, loc, previous } => {
+                let message = format!("Field '{}' is already declared in the superstruct '{}'.", name, superstruct);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                msg.push_str(&display.with_indent(|display| {
+                    display.error_with_location(Severity::Note, "The inherited field is declared here.", previous)
+                }));
+                msg
+            },
+            InheritedImplementation { typ, trait_name, superstruct, loc, previous } => {
+                let message = format!("'{}' already implements trait '{}' through its superstruct '{}'.", typ, trait_name, superstruct);
+                let mut msg = display.error_with_location(Severity::Error, &message, loc);
+                if let Some(previous) = previous {
+                    msg.push_str(&display.with_indent(|display| {
+                        display.error_with_location(Severity::Note, &format!("'{}' is declared here.", superstruct), previous)
+                    }));
+                }
+                msg
+            },
+            UnrelatedStructs { from, to, loc } => {
+                let message = format!("A value of type '{}' can never be a '{}', since neither inherits from the other.", from, to);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            NotConstant { loc } => {
+                let message = "This cannot be evaluated at compile time. Constant expressions may only use literals, constants, arithmetic, comparisons and casts.";
+                display.error_with_location(Severity::Error, message, loc)
+            },
+            ConstantOverflow { op, typ, min, max, loc } => {
+                let message = format!("The result of '{}' does not fit in '{}', whose values are from {} to {}.", op, typ, min, max);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            ConstantDivisionByZero { loc } => {
+                display.error_with_location(Severity::Error, "Division by zero in a constant expression.", loc)
+            },
+            ConstantCycle { chain, loc } => {
+                let message = format!("The value of constant '{}' depends on itself: {}.", chain[0], chain.join(" -> "));
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            ConstantType { typ, loc } => {
+                let message = format!("Constants must have an integer, float, 'bool', 'char' or 'str' type, not '{}'.", typ);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            ArrayLengthType { typ, loc } => {
+                display.error_with_location(Severity::Error, &format!("Array lengths must be integers, found '{}'.", typ), loc)
+            },
+            NegativeArrayLength { length, loc } => {
+                display.error_with_location(Severity::Error, &format!("Array lengths cannot be negative, found {}.", length), loc)
+            },
+            NotIndexable { typ, loc } => {
+                display.error_with_location(Severity::Error, &format!("Cannot index a value of type '{}', only an array.", typ), loc)
+            },
+            IndexType { typ, loc } => {
+                display.error_with_location(Severity::Error, &format!("Array indices must be integers, found '{}'.", typ), loc)
+            },
+            IndexOutOfBounds { index, length, loc } => {
+                let message = format!("Index {} is out of bounds for an array of length {}.", index, length);
+                display.error_with_location(Severity::Error, &message, loc)
+            },
+            RangeType { typ, loc } => {
+                display.error_with_location(Severity::Error, &format!("Ranges must be of integers, found '{}'.", typ), loc)
+            },
+            NotIterable { typ, loc } => {
+                let message = format!("Cannot iterate over a value of type '{}', only a range such as '0..n' or an array.", typ);
+                display.error_with_location(Severity::Error, &message, loc)
+            }
+        }
+    }
+
+    fn location(&self) -> Option<Span> {
+        use TypeError::*;
+        match self {
+            Mismatch { loc, .. } => Some(*loc),
+            Narrowing { loc, .. } => Some(*loc),
+            LiteralOutOfRange { loc, .. } => Some(*loc),
+            InvalidOperand { loc, .. } => Some(*loc),
+            InvalidOperands { loc, .. } => Some(*loc),
+            InvalidCast { loc, .. } => Some(*loc),
+            GenericArgumentCount { loc, .. } => Some(*loc),
+            CannotInfer { loc, .. } => Some(*loc),
+            UnknownType { loc, .. } => Some(*loc),
+            AmbiguousType { loc, .. } => Some(*loc),
+            InstantiationDepth { loc, .. } => Some(*loc),
+            InInstantiation { error, .. } => error.location(),
+            NotAValue { loc, .. } => Some(*loc),
+            NotAType { loc, .. } => Some(*loc),
+            NotCallable { loc, .. } => Some(*loc),
+            ArgumentCount { loc, .. } => Some(*loc),
+            NotAStruct { loc, .. } => Some(*loc),
+            NoField { loc, .. } => Some(*loc),
+            MissingFields { loc, .. } => Some(*loc),
+            DuplicateField { loc, .. } => Some(*loc),
+            DuplicateFieldDeclaration { loc, .. } => Some(*loc),
+            NotAssignable { loc } => Some(*loc),
+            TemporaryReference { loc, .. } => Some(*loc),
+            NotATrait { loc, .. } => Some(*loc),
+            UnsatisfiedBounds { loc, .. } => Some(*loc),
+            MissingMethods { loc, .. } => Some(*loc),
+            MethodSignature { loc, .. } => Some(*loc),
+            DuplicateImplementation { loc, .. } => Some(*loc),
+            ConflictingRequirements { loc, .. } => Some(*loc),
+            DuplicateMethod { loc, .. } => Some(*loc),
+            MethodReceiver { loc } => Some(*loc),
+            MethodNotCalled { loc, .. } => Some(*loc),
+            GenericSuperstruct { loc, .. } => Some(*loc),
+            InheritanceCycle { loc, .. } => Some(*loc),
+            InheritedField { loc, .. } => Some(*loc),
+            InheritedImplementation { loc, .. } => Some(*loc),
+            UnrelatedStructs { loc, .. } => Some(*loc),
+            NotConstant { loc } => Some(*loc),
+            ConstantOverflow { loc, .. } => Some(*loc),
+            ConstantDivisionByZero { loc } => Some(*loc),
+            ConstantCycle { loc, .. } => Some(*loc),
+            ConstantType { loc, .. } => Some(*loc),
+            ArrayLengthType { loc, .. } => Some(*loc),
+            NegativeArrayLength { loc, .. } => Some(*loc),
+            NotIndexable { loc, .. } => Some(*loc),
+            IndexType { loc, .. } => Some(*loc),
+            IndexOutOfBounds { loc, .. } => Some(*loc),
+            RangeType { loc, .. } => Some(*loc),
+            NotIterable { loc, .. } => Some(*loc)
+        }
+    }
+
+    fn fix(&self) -> Option<&Fix> {
+        match self {
+            TypeError::Narrowing { fix, .. } => Some(fix),
+            TypeError::InInstantiation { error, .. } => error.fix(),
+            _ => None
+        }
+    }
+}
+
+
+/// A note suggesting a type annotation for the variable with the given name, pointing at the name.
+fn annotation_note(display: &mut ErrorDisplay, (name, loc): &(String, Span)) -> String {
+    return display.with_indent(|display| {
+        display.error_with_location(Severity::Note, &format!("Consider annotating the type of '{}', as in `{}: Type`.", name, name), loc)
+    });
+}
+
+/// Quotes names and joins them into a list such as `'a', 'b' and 'c'`.
+fn list(names: &[String]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+    return match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => quoted.join("")
+    };
+}
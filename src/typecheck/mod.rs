@@ -0,0 +1,8 @@
+mod types;
+mod constant;
+mod error;
+mod checker;
+
+pub use types::{TypeInfo, Type, Primitive, Instance, InstanceId, bind};
+pub use constant::{ConstValue, fold_unary, fold_binary, fold_cast, truncate, round};
+pub use checker::check_program;
@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::source::Span;
+use crate::resolve::{Resolution, DeclId};
+use crate::typecheck::constant::ConstValue;
+
+
+/// The built-in types.
+///
+/// Integer arithmetic wraps around on overflow, using two's complement for the signed types, so
+/// `127 as i8 + 1` is `-128`. Integer division truncates towards zero, and dividing by zero or taking
+/// the remainder of a division by zero stops the program. Floats follow IEEE 754.
+///
+/// Numeric values are never converted implicitly, except to a type that can hold every value of the
+/// original: a wider integer of the same signedness, an unsigned integer to a wider signed one, and
+/// `f32` to `f64`. Every other conversion needs an `as` cast. Casting between integers truncates or
+/// extends the value, casting a float to an integer rounds towards zero and saturates at the bounds
+/// of the integer type, with NaN becoming 0, and casting an integer to a float rounds to the nearest
+/// representable value.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Primitive {
+    /// An 8-bit signed integer, from -128 to 127.
+    I8,
+    /// A 16-bit signed integer, from -32768 to 32767.
+    I16,
+    /// A 32-bit signed integer, from -2^31 to 2^31 - 1.
+    I32,
+    /// A 64-bit signed integer, from -2^63 to 2^63 - 1. `int` is another name for it, and it is
+    /// the type of integer literals that are not required to have some other type.
+    I64,
+    /// An 8-bit unsigned integer, from 0 to 255.
+    U8,
+    /// A 16-bit unsigned integer, from 0 to 65535.
+    U16,
+    /// A 32-bit unsigned integer, from 0 to 2^32 - 1.
+    U32,
+    /// A 64-bit unsigned integer, from 0 to 2^64 - 1.
+    U64,
+    /// A 32-bit IEEE 754 float.
+    F32,
+    /// A 64-bit IEEE 754 float, and the type of float literals that are not required to be `f32`.
+    F64,
+    /// `true` or `false`, stored in 1 byte.
+    Bool,
+    /// A Unicode scalar value, stored in 4 bytes.
+    Char,
+    /// A UTF-8 string, stored as a pointer to its bytes and its length in bytes, 16 bytes in total.
+    Str,
+    /// The type of expressions that do not produce a value, such as blocks and assignments. It takes
+    /// no space.
+    Unit
+}
+
+impl Primitive {
+    /// The primitive type a built-in type name refers to.
+    pub fn from_name(name: &str) -> Option<Primitive> {
+        use Primitive::*;
+        match name {
+            "i8" => Some(I8),
+            "i16" => Some(I16),
+            "i32" => Some(I32),
+            "i64" | "int" => Some(I64),
+            "u8" => Some(U8),
+            "u16" => Some(U16),
+            "u32" => Some(U32),
+            "u64" => Some(U64),
+            "f32" => Some(F32),
+            "f64" => Some(F64),
+            "bool" => Some(Bool),
+            "char" => Some(Char),
+            "str" => Some(Str),
+            "unit" => Some(Unit),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        use Primitive::*;
+        match self {
+            I8 => "i8",
+            I16 => "i16",
+            I32 => "i32",
+            I64 => "i64",
+            U8 => "u8",
+            U16 => "u16",
+            U32 => "u32",
+            U64 => "u64",
+            F32 => "f32",
+            F64 => "f64",
+            Bool => "bool",
+            Char => "char",
+            Str => "str",
+            Unit => "unit"
+        }
+    }
+
+    /// The size of a value of this type, in bytes.
+    pub fn size(&self) -> usize {
+        use Primitive::*;
+        match self {
+            I8 | U8 | Bool => 1,
+            I16 | U16 => 2,
+            I32 | U32 | F32 | Char => 4,
+            I64 | U64 | F64 => 8,
+            Str => 16,
+            Unit => 0
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        use Primitive::*;
+        matches!(self, I8 | I16 | I32 | I64 | U8 | U16 | U32 | U64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        use Primitive::*;
+        matches!(self, I8 | I16 | I32 | I64)
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Primitive::F32 | Primitive::F64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// The smallest and largest values of an integer type.
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        if !self.is_integer() {
+            return None;
+        }
+        let bits = self.size() as u32 * 8;
+        return Some(if self.is_signed() {
+            (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+        } else {
+            (0, (1i128 << bits) - 1)
+        });
+    }
+
+    /// Whether every value of this type can be implicitly converted to `other` without losing information.
+    pub fn widens_to(&self, other: Primitive) -> bool {
+        if self.is_integer() && other.is_integer() {
+            let (min, max) = self.integer_range().unwrap();
+            let (other_min, other_max) = other.integer_range().unwrap();
+            return other_min <= min && max <= other_max;
+        }
+        return *self == other || (*self == Primitive::F32 && other == Primitive::F64);
+    }
+}
+
+/// What an inference variable may stand for.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum VariableKind {
+    /// Any type.
+    General,
+    /// An integer type, which is `int` if nothing decides otherwise. Integer literals start out with one.
+    Integer,
+    /// A float type, which is `f64` if nothing decides otherwise.
+    Float
+}
+
+/// A type that is not known yet while a function body is checked, and is decided by how the values that
+/// have it are used. Once the body is checked, every variable has been replaced.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct TypeVariable {
+    pub id: u32,
+    pub kind: VariableKind
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Type {
+    Primitive(Primitive),
+    /// A struct, identified by its declaration, with its generic arguments.
+    Struct(DeclId, Vec<Type>),
+    GenericParameter(DeclId),
+    Function(Vec<Type>, Box<Type>),
+    Reference(Box<Type>),
+    /// A fixed number of values of the same type, stored one after another. Reading or writing an element
+    /// outside the array stops the program.
+    Array(Box<Type>, u64),
+    Variable(TypeVariable),
+    /// The type of an expression that has already been reported as an error. It is compatible
+    /// with every other type, so that one mistake is not reported again by every use.
+    Error
+}
+
+impl Type {
+    pub fn int() -> Type {
+        Type::Primitive(Primitive::I64)
+    }
+
+    pub fn bool() -> Type {
+        Type::Primitive(Primitive::Bool)
+    }
+
+    pub fn unit() -> Type {
+        Type::Primitive(Primitive::Unit)
+    }
+
+    /// The type a reference refers to, or the type itself if it is not a reference.
+    pub fn dereferenced(&self) -> &Type {
+        match self {
+            Type::Reference(inner) => inner,
+            typ => typ
+        }
+    }
+
+    pub fn primitive(&self) -> Option<Primitive> {
+        match self {
+            Type::Primitive(primitive) => Some(*primitive),
+            _ => None
+        }
+    }
+
+    /// Replaces the generic parameters in this type that have bindings with the types they are bound to.
+    pub fn substitute(&self, bindings: &HashMap<DeclId, Type>) -> Type {
+        match self {
+            Type::GenericParameter(id) => bindings.get(id).cloned().unwrap_or(Type::GenericParameter(*id)),
+            Type::Struct(id, arguments) => Type::Struct(*id, arguments.iter().map(|a| a.substitute(bindings)).collect()),
+            Type::Function(parameters, ret) => {
+                Type::Function(parameters.iter().map(|p| p.substitute(bindings)).collect(), Box::new(ret.substitute(bindings)))
+            },
+            Type::Reference(inner) => Type::Reference(Box::new(inner.substitute(bindings))),
+            Type::Array(element, length) => Type::Array(Box::new(element.substitute(bindings)), *length),
+            typ => typ.clone()
+        }
+    }
+
+    /// Whether the type mentions no generic parameters.
+    pub fn is_concrete(&self) -> bool {
+        match self {
+            Type::GenericParameter(_) => false,
+            Type::Struct(_, arguments) => arguments.iter().all(Type::is_concrete),
+            Type::Function(parameters, ret) => parameters.iter().all(Type::is_concrete) && ret.is_concrete(),
+            Type::Reference(inner) | Type::Array(inner, _) => inner.is_concrete(),
+            _ => true
+        }
+    }
+
+    /// The inference variables the type mentions.
+    pub fn variables(&self) -> Vec<TypeVariable> {
+        match self {
+            Type::Variable(variable) => vec![*variable],
+            Type::Struct(_, arguments) => arguments.iter().flat_map(Type::variables).collect(),
+            Type::Function(parameters, ret) => parameters.iter().chain([ret.as_ref()]).flat_map(Type::variables).collect(),
+            Type::Reference(inner) | Type::Array(inner, _) => inner.variables(),
+            _ => Vec::new()
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        match self {
+            Type::Error => true,
+            Type::Struct(_, arguments) => arguments.iter().any(Type::is_error),
+            Type::Function(parameters, ret) => parameters.iter().any(Type::is_error) || ret.is_error(),
+            Type::Reference(inner) | Type::Array(inner, _) => inner.is_error(),
+            _ => false
+        }
+    }
+
+    /// The type as it would be written in the source, such as `(int, Point&) -> int`.
+    pub fn display(&self, resolution: &Resolution) -> String {
+        match self {
+            Type::Primitive(primitive) => String::from(primitive.name()),
+            Type::Struct(id, arguments) => {
+                let name = &resolution.declaration(*id).name;
+                if arguments.is_empty() {
+                    name.clone()
+                } else {
+                    let arguments: Vec<String> = arguments.iter().map(|a| a.display(resolution)).collect();
+                    format!("{}<{}>", name, arguments.join(", "))
+                }
+            },
+            Type::GenericParameter(id) => resolution.declaration(*id).name.clone(),
+            Type::Function(parameters, ret) => {
+                let parameters: Vec<String> = parameters.iter().map(|p| p.display(resolution)).collect();
+                format!("({}) -> {}", parameters.join(", "), ret.display(resolution))
+            },
+            // A reference to a type that is still unknown is shown as unknown too, rather than as `_&`.
+            Type::Reference(inner) if matches!(**inner, Type::Variable(v) if v.kind == VariableKind::General) => String::from("_"),
+            Type::Reference(inner) => format!("{}&", inner.display(resolution)),
+            Type::Array(element, length) => format!("[{}; {}]", element.display(resolution), length),
+            Type::Variable(variable) => String::from(match variable.kind {
+                VariableKind::General => "_",
+                VariableKind::Integer => "{integer}",
+                VariableKind::Float => "{float}"
+            }),
+            Type::Error => String::from("{error}")
+        }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct StructField {
+    pub name: String,
+    pub typ: Type,
+    /// The span of the field's declaration.
+    pub loc: Span,
+    /// The span of the field's type annotation.
+    pub type_loc: Span
+}
+
+/// A struct's type. A struct with a superstruct starts with the fields of its superstruct, in the same order,
+/// followed by its own, so a reference to it can be used as a reference to the superstruct.
+///
+/// Structs that have a superstruct or substructs are tagged: their values are preceded by a hidden 4-byte
+/// type tag holding the struct's `DeclId`, which `is` checks and casts from a reference to a superstruct to a
+/// reference to a substruct read to find the struct the value was created as. A cast to a struct the
+/// value is not stops the program.
+pub struct StructType {
+    pub generic_parameters: Vec<DeclId>,
+    /// The struct's fields, including the inherited ones.
+    pub fields: Vec<StructField>,
+    pub superstruct: Option<DeclId>,
+    pub tagged: bool,
+    /// The traits the struct claims to implement.
+    pub interfaces: Vec<DeclId>,
+    /// The methods declared in the struct's body, by name. Their signatures are with the other functions.
+    pub methods: HashMap<String, DeclId>
+}
+
+impl StructType {
+    pub fn field(&self, name: &str) -> Option<&StructField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// The type of a field in the instance of this struct with the given generic arguments.
+    pub fn field_type(&self, name: &str, arguments: &[Type]) -> Option<Type> {
+        let field = self.field(name)?;
+        return Some(field.typ.substitute(&bind(&self.generic_parameters, arguments)));
+    }
+}
+
+/// A method that a trait requires. Its signature uses the trait's `Self` type as a generic parameter,
+/// which is bound to the implementing struct when comparing it to the struct's method.
+pub struct TraitMethod {
+    pub id: DeclId,
+    pub name: String,
+    pub signature: Signature
+}
+
+pub struct TraitType {
+    /// The declaration of `Self` in the trait's body.
+    pub self_type: DeclId,
+    pub methods: Vec<TraitMethod>
+}
+
+/// Pairs generic parameters with the arguments given for them.
+pub fn bind(parameters: &[DeclId], arguments: &[Type]) -> HashMap<DeclId, Type> {
+    parameters.iter().copied().zip(arguments.iter().cloned()).collect()
+}
+
+#[derive(Clone)]
+pub struct Signature {
+    pub generic_parameters: Vec<DeclId>,
+    pub parameters: Vec<Type>,
+    /// The spans of the parameters' type annotations.
+    pub parameter_locs: Vec<Span>,
+    pub ret: Type,
+    pub ret_loc: Span
+}
+
+impl Signature {
+    pub fn function_type(&self) -> Type {
+        Type::Function(self.parameters.clone(), Box::new(self.ret.clone()))
+    }
+}
+
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct InstanceId(pub u32);
+
+/// A function body checked with concrete types in place of the function's generic parameters. A
+/// function without generic parameters has a single instance, and a generic function has one for
+/// each distinct list of type arguments it is used with.
+pub struct Instance {
+    pub function: DeclId,
+    pub arguments: Vec<Type>,
+    /// The types of parameters and local variables.
+    pub variables: HashMap<DeclId, Type>,
+    /// The type of each expression.
+    pub expressions: HashMap<ast::ExprId, Type>,
+    /// The instance that each name expression referring to a function refers to. For method calls, the key is
+    /// the field expression that names the method.
+    pub functions: HashMap<ast::ExprId, InstanceId>
+}
+
+
+/// The type and value of a constant.
+#[derive(Clone)]
+pub struct Constant {
+    pub typ: Primitive,
+    pub value: ConstValue
+}
+
+
+/// The result of type checking: the types of every struct, trait and function, the values of every
+/// constant, and the instances of every function that is used.
+pub struct TypeInfo {
+    pub structs: HashMap<DeclId, StructType>,
+    pub traits: HashMap<DeclId, TraitType>,
+    /// The signatures of functions and of the methods of structs.
+    pub functions: HashMap<DeclId, Signature>,
+    /// The traits that the arguments for each generic parameter must implement.
+    pub bounds: HashMap<DeclId, Vec<DeclId>>,
+    pub constants: HashMap<DeclId, Constant>,
+    pub instances: Vec<Instance>,
+    pub instance_ids: HashMap<(DeclId, Vec<Type>), InstanceId>,
+    /// Every struct type with concrete generic arguments that the instances use, directly or through the
+    /// fields of another struct, in the order they were found.
+    pub struct_instances: Vec<Type>
+}
+
+impl TypeInfo {
+    pub fn instance(&self, id: InstanceId) -> &Instance {
+        &self.instances[id.0 as usize]
+    }
+
+    /// Whether `sub` is `sup` or inherits from it, directly or through other superstructs.
+    pub fn is_substruct(&self, sub: DeclId, sup: DeclId) -> bool {
+        let mut current = Some(sub);
+        while let Some(id) = current {
+            if id == sup {
+                return true;
+            }
+            current = self.structs.get(&id).and_then(|s| s.superstruct);
+        }
+        return false;
+    }
+
+    /// The instance of a function with the given type arguments, if it is used.
+    pub fn find_instance(&self, function: DeclId, arguments: &[Type]) -> Option<InstanceId> {
+        self.instance_ids.get(&(function, arguments.to_vec())).copied()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Primitive;
+    use super::Primitive::*;
+
+    #[test]
+    fn integer_types_have_their_documented_ranges() {
+        assert_eq!(Primitive::from_name("int"), Some(I64));
+        assert_eq!(I8.integer_range(), Some((-128, 127)));
+        assert_eq!(U16.integer_range(), Some((0, 65535)));
+        assert_eq!(I64.integer_range(), Some((i64::MIN as i128, i64::MAX as i128)));
+        assert_eq!(U64.integer_range(), Some((0, u64::MAX as i128)));
+        assert_eq!(F32.integer_range(), None);
+        assert_eq!([Bool, Char, Str, Unit].map(|p| p.size()), [1, 4, 16, 0]);
+    }
+
+    #[test]
+    fn only_conversions_that_keep_every_value_widen() {
+        assert!(I8.widens_to(I16) && U8.widens_to(I16) && U32.widens_to(I64) && F32.widens_to(F64));
+        assert!(!I16.widens_to(I8) && !I8.widens_to(U8) && !U64.widens_to(I64) && !F64.widens_to(F32));
+        assert!(!I32.widens_to(F64) && !Bool.widens_to(I8) && !Char.widens_to(U32));
+    }
+}
@@ -0,0 +1,170 @@
+extern crate dunce;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Component;
+
+use crate::source::{Path, PathBuf};
+
+
+/// Why a source file could not be loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    NotFound,
+    PermissionDenied,
+    IsADirectory,
+    InvalidUtf8,
+    Other(String)
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        match error.kind() {
+            io::ErrorKind::NotFound => LoadError::NotFound,
+            io::ErrorKind::PermissionDenied => LoadError::PermissionDenied,
+            io::ErrorKind::IsADirectory => LoadError::IsADirectory,
+            io::ErrorKind::InvalidData => LoadError::InvalidUtf8,
+            _ => LoadError::Other(error.to_string())
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::NotFound => write!(f, "the file does not exist"),
+            LoadError::PermissionDenied => write!(f, "permission denied"),
+            LoadError::IsADirectory => write!(f, "it is a directory"),
+            LoadError::InvalidUtf8 => write!(f, "the file is not valid UTF-8"),
+            LoadError::Other(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+
+/// The operations the compiler needs to find, read and rewrite source files. Implementations other than the
+/// real disk let tools supply file contents directly, such as unsaved editor buffers.
+pub trait FileSystem {
+    /// Resolves a path to the absolute form used to identify the file, failing if it does not exist.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+
+    /// Replaces the contents of a file, as `fix` does.
+    fn write(&self, path: &Path, text: &str) -> io::Result<()>;
+}
+
+
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        dunce::canonicalize(path)
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &Path, text: &str) -> io::Result<()> {
+        fs::write(path, text)
+    }
+}
+
+
+/// Files held in memory, layered over another file system. A file in the overlay hides a file at
+/// the same path in the file system below it, and writes to it stay in the overlay.
+pub struct OverlayFileSystem {
+    base: Option<Box<dyn FileSystem>>,
+    working_directory: PathBuf,
+    files: RefCell<HashMap<PathBuf, String>>
+}
+
+impl OverlayFileSystem {
+    pub fn new(base: Box<dyn FileSystem>) -> OverlayFileSystem {
+        OverlayFileSystem { base: Some(base), working_directory: current_directory(), files: RefCell::new(HashMap::new()) }
+    }
+
+    /// An overlay with nothing below it, so that only the files added to it exist.
+    #[cfg(test)]
+    pub fn in_memory() -> OverlayFileSystem {
+        OverlayFileSystem { base: None, working_directory: current_directory(), files: RefCell::new(HashMap::new()) }
+    }
+
+    /// Adds or replaces a file. Relative paths are taken relative to the working directory.
+    pub fn add_file(&mut self, path: &Path, text: String) -> PathBuf {
+        let path = self.normalize(path);
+        self.files.get_mut().insert(path.clone(), text);
+        return path;
+    }
+
+    /// Makes a path absolute and removes `.` and `..` components without consulting the disk.
+    fn normalize(&self, path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        for component in self.working_directory.join(path).components() {
+            match component {
+                Component::CurDir => { },
+                Component::ParentDir => { normalized.pop(); },
+                other => normalized.push(other)
+            }
+        }
+        return normalized;
+    }
+
+    fn base(&self) -> io::Result<&dyn FileSystem> {
+        self.base.as_deref().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+impl FileSystem for OverlayFileSystem {
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let normalized = self.normalize(path);
+        if self.files.borrow().contains_key(&normalized) {
+            Ok(normalized)
+        } else {
+            self.base()?.canonicalize(path)
+        }
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        match self.files.borrow().get(&self.normalize(path)) {
+            Some(text) => Ok(text.clone()),
+            None => self.base()?.read_to_string(path)
+        }
+    }
+
+    fn write(&self, path: &Path, text: &str) -> io::Result<()> {
+        let normalized = self.normalize(path);
+        let mut files = self.files.borrow_mut();
+        match files.get_mut(&normalized) {
+            Some(file) => *file = String::from(text),
+            None if self.base.is_none() => { files.insert(normalized, String::from(text)); },
+            None => return self.base()?.write(path, text)
+        }
+        return Ok(());
+    }
+}
+
+
+fn current_directory() -> PathBuf {
+    std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::LoadError;
+
+    #[test]
+    fn io_errors_are_reported_by_kind() {
+        let load_error = |kind| LoadError::from(io::Error::from(kind)).to_string();
+        assert_eq!(load_error(io::ErrorKind::NotFound), "the file does not exist");
+        assert_eq!(load_error(io::ErrorKind::PermissionDenied), "permission denied");
+        assert_eq!(load_error(io::ErrorKind::IsADirectory), "it is a directory");
+        assert_eq!(load_error(io::ErrorKind::InvalidData), "the file is not valid UTF-8");
+    }
+}
@@ -0,0 +1,724 @@
+use crate::ast::*;
+
+
+/// Visits the nodes of a program in the order they appear in the source. Each method by default calls the
+/// matching `walk_` function, which visits the node's children; an implementation that overrides a method
+/// calls the `walk_` function itself to keep visiting below that node.
+pub trait Visitor<'a>: Sized {
+    fn visit_program(&mut self, program: &'a Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_file(&mut self, program: &'a Program, file: &'a File) {
+        walk_file(self, program, file);
+    }
+
+    fn visit_top_level(&mut self, program: &'a Program, id: TopLevelId) {
+        walk_top_level(self, program, id);
+    }
+
+    /// Visits a function or a method of a struct.
+    fn visit_function(&mut self, program: &'a Program, function: &'a FunctionData) {
+        walk_function(self, program, function);
+    }
+
+    fn visit_block(&mut self, program: &'a Program, block: &'a BlockData) {
+        walk_block(self, program, block);
+    }
+
+    fn visit_stmt(&mut self, program: &'a Program, id: StmtId) {
+        walk_stmt(self, program, id);
+    }
+
+    fn visit_expr(&mut self, program: &'a Program, id: ExprId) {
+        walk_expr(self, program, id);
+    }
+
+    fn visit_type(&mut self, program: &'a Program, id: TypeId) {
+        walk_type(self, program, id);
+    }
+
+    fn visit_name(&mut self, program: &'a Program, id: NameId) {
+        walk_name(self, program, id);
+    }
+}
+
+pub fn walk_program<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program) {
+    for file in &program.files {
+        visitor.visit_file(program, file);
+    }
+}
+
+pub fn walk_file<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, file: &'a File) {
+    for id in file.top_level_ids() {
+        visitor.visit_top_level(program, id);
+    }
+}
+
+pub fn walk_top_level<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, id: TopLevelId) {
+    match &program[id] {
+        TopLevelNode::Import(data) => visitor.visit_name(program, data.path),
+        TopLevelNode::Struct(data) => {
+            walk_generic_parameters(visitor, program, &data.generic_parameters);
+            if let Some(superstruct) = data.superstruct {
+                visitor.visit_name(program, superstruct);
+            }
+            for interface in &data.interfaces {
+                visitor.visit_name(program, *interface);
+            }
+            for field in &data.fields {
+                visitor.visit_type(program, field.typ);
+            }
+            for method in &data.methods {
+                visitor.visit_function(program, method);
+            }
+        },
+        TopLevelNode::Trait(data) => {
+            for method in &data.methods {
+                for parameter in &method.parameters {
+                    visitor.visit_type(program, parameter.typ);
+                }
+                visitor.visit_type(program, method.ret);
+            }
+        },
+        TopLevelNode::Function(data) => visitor.visit_function(program, data),
+        TopLevelNode::Const(data) => {
+            visitor.visit_type(program, data.typ);
+            visitor.visit_expr(program, data.value);
+        }
+    }
+}
+
+fn walk_generic_parameters<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, parameters: &'a [GenericParameter]) {
+    for parameter in parameters {
+        for bound in &parameter.bounds {
+            visitor.visit_name(program, *bound);
+        }
+    }
+}
+
+pub fn walk_function<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, function: &'a FunctionData) {
+    walk_generic_parameters(visitor, program, &function.generic_parameters);
+    for parameter in &function.parameters {
+        visitor.visit_type(program, parameter.typ);
+    }
+    visitor.visit_type(program, function.ret);
+    visitor.visit_block(program, &function.body);
+}
+
+pub fn walk_block<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, block: &'a BlockData) {
+    for stmt in &block.stmts {
+        visitor.visit_stmt(program, *stmt);
+    }
+}
+
+pub fn walk_stmt<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, id: StmtId) {
+    match &program[id] {
+        StmtNode::Expr(data) => visitor.visit_expr(program, data.expr),
+        StmtNode::Let(data) => {
+            if let Some(typ) = data.typ {
+                visitor.visit_type(program, typ);
+            }
+            if let Some(value) = data.value {
+                visitor.visit_expr(program, value);
+            }
+        },
+        StmtNode::Return(data) => visitor.visit_expr(program, data.expr),
+        StmtNode::If(data) => {
+            visitor.visit_expr(program, data.condition);
+            visitor.visit_block(program, &data.body);
+            if let Some(else_body) = &data.else_body {
+                visitor.visit_block(program, else_body);
+            }
+        },
+        StmtNode::While(data) => {
+            visitor.visit_expr(program, data.condition);
+            visitor.visit_block(program, &data.body);
+        },
+        StmtNode::For(data) => {
+            match data.iterable {
+                ForIterable::Range(start, end) => {
+                    visitor.visit_expr(program, start);
+                    visitor.visit_expr(program, end);
+                },
+                ForIterable::Array(array) => visitor.visit_expr(program, array)
+            }
+            visitor.visit_block(program, &data.body);
+        }
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, id: ExprId) {
+    match &program[id] {
+        ExprNode::Name(data) => visitor.visit_name(program, data.name),
+        ExprNode::Integer(_) | ExprNode::Float(_) | ExprNode::Bool(_) | ExprNode::Char(_) | ExprNode::String(_) => { },
+        ExprNode::Block(data) => visitor.visit_block(program, data),
+        ExprNode::Call(data) => {
+            visitor.visit_expr(program, data.callee);
+            for argument in &data.arguments {
+                visitor.visit_expr(program, *argument);
+            }
+        },
+        ExprNode::Field(data) => visitor.visit_expr(program, data.object),
+        ExprNode::Assign(data) => {
+            visitor.visit_expr(program, data.target);
+            visitor.visit_expr(program, data.value);
+        },
+        ExprNode::StructLiteral(data) => {
+            visitor.visit_name(program, data.name);
+            for field in &data.fields {
+                visitor.visit_expr(program, field.value);
+            }
+        },
+        ExprNode::Unary(data) => visitor.visit_expr(program, data.operand),
+        ExprNode::Binary(data) => {
+            visitor.visit_expr(program, data.left);
+            visitor.visit_expr(program, data.right);
+        },
+        ExprNode::Cast(data) => {
+            visitor.visit_expr(program, data.expr);
+            visitor.visit_type(program, data.typ);
+        },
+        ExprNode::Is(data) => {
+            visitor.visit_expr(program, data.expr);
+            visitor.visit_type(program, data.typ);
+        },
+        ExprNode::Lambda(data) => {
+            for parameter in &data.parameters {
+                if let Some(typ) = parameter.typ {
+                    visitor.visit_type(program, typ);
+                }
+            }
+            visitor.visit_expr(program, data.body);
+        },
+        ExprNode::Array(data) => {
+            for element in &data.elements {
+                visitor.visit_expr(program, *element);
+            }
+        },
+        ExprNode::Index(data) => {
+            visitor.visit_expr(program, data.object);
+            visitor.visit_expr(program, data.index);
+        }
+    }
+}
+
+pub fn walk_type<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, id: TypeId) {
+    match &program[id] {
+        TypeNode::Name(data) => {
+            visitor.visit_name(program, data.name);
+            for argument in data.generic_arguments.iter().flatten() {
+                visitor.visit_type(program, *argument);
+            }
+        },
+        TypeNode::Function(data) => {
+            for argument in &data.arguments {
+                visitor.visit_type(program, *argument);
+            }
+            visitor.visit_type(program, data.ret);
+        },
+        TypeNode::Reference(data) => visitor.visit_type(program, data.typ),
+        TypeNode::Array(data) => {
+            visitor.visit_type(program, data.element);
+            visitor.visit_expr(program, data.length);
+        }
+    }
+}
+
+pub fn walk_name<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program, id: NameId) {
+    match &program[id] {
+        QualifiedNameNode::Name(_) => { },
+        QualifiedNameNode::Namespace(data) => visitor.visit_name(program, data.source)
+    }
+}
+
+
+/// Visits the nodes of a program in source order with the program borrowed mutably, so that nodes can be
+/// changed in place through `program[id]`. A node's children are found after the node has been visited, so
+/// the walk follows any children that the visitor put in place of the old ones.
+///
+/// The statements of functions and blocks are visited as children of the declaration, statement or expression
+/// that holds them.
+#[cfg_attr(not(test), allow(dead_code))]
+pub trait VisitorMut: Sized {
+    fn visit_top_level_mut(&mut self, program: &mut Program, id: TopLevelId) {
+        walk_top_level_mut(self, program, id);
+    }
+
+    fn visit_stmt_mut(&mut self, program: &mut Program, id: StmtId) {
+        walk_stmt_mut(self, program, id);
+    }
+
+    fn visit_expr_mut(&mut self, program: &mut Program, id: ExprId) {
+        walk_expr_mut(self, program, id);
+    }
+
+    fn visit_type_mut(&mut self, program: &mut Program, id: TypeId) {
+        walk_type_mut(self, program, id);
+    }
+
+    fn visit_name_mut(&mut self, program: &mut Program, id: NameId) {
+        walk_name_mut(self, program, id);
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn walk_program_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program) {
+    for file in 0..program.files.len() {
+        let ids: Vec<TopLevelId> = program.files[file].top_level_ids().collect();
+        for id in ids {
+            visitor.visit_top_level_mut(program, id);
+        }
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn walk_top_level_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: TopLevelId) {
+    walk_children_mut(visitor, program, id.into());
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn walk_stmt_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: StmtId) {
+    walk_children_mut(visitor, program, id.into());
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: ExprId) {
+    walk_children_mut(visitor, program, id.into());
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn walk_type_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: TypeId) {
+    walk_children_mut(visitor, program, id.into());
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn walk_name_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: NameId) {
+    walk_children_mut(visitor, program, id.into());
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn walk_children_mut<V: VisitorMut>(visitor: &mut V, program: &mut Program, id: NodeId) {
+    for child in program.children(id) {
+        match child {
+            NodeId::TopLevel(id) => visitor.visit_top_level_mut(program, id),
+            NodeId::Stmt(id) => visitor.visit_stmt_mut(program, id),
+            NodeId::Expr(id) => visitor.visit_expr_mut(program, id),
+            NodeId::Type(id) => visitor.visit_type_mut(program, id),
+            NodeId::Name(id) => visitor.visit_name_mut(program, id)
+        }
+    }
+}
+
+
+/// Rebuilds a program, allocating each node anew in the arenas of a new file. Each method by default calls the
+/// matching `rebuild_` function, which folds the node's children and copies the rest of the node; an
+/// implementation can instead return a different node, such as one of the node's folded children. The new
+/// nodes keep the spans of the nodes they were made from.
+#[cfg_attr(not(test), allow(dead_code))]
+pub trait Folder: Sized {
+    fn fold_top_level(&mut self, program: &Program, into: &mut File, id: TopLevelId) -> TopLevelNode {
+        rebuild_top_level(self, program, into, id)
+    }
+
+    fn fold_function(&mut self, program: &Program, into: &mut File, function: &FunctionData) -> FunctionData {
+        rebuild_function(self, program, into, function)
+    }
+
+    fn fold_block(&mut self, program: &Program, into: &mut File, block: &BlockData) -> BlockData {
+        rebuild_block(self, program, into, block)
+    }
+
+    fn fold_stmt(&mut self, program: &Program, into: &mut File, id: StmtId) -> StmtId {
+        rebuild_stmt(self, program, into, id)
+    }
+
+    fn fold_expr(&mut self, program: &Program, into: &mut File, id: ExprId) -> ExprId {
+        rebuild_expr(self, program, into, id)
+    }
+
+    fn fold_type(&mut self, program: &Program, into: &mut File, id: TypeId) -> TypeId {
+        rebuild_type(self, program, into, id)
+    }
+
+    fn fold_name(&mut self, program: &Program, into: &mut File, id: NameId) -> NameId {
+        rebuild_name(self, program, into, id)
+    }
+}
+
+/// Folds every file of a program into a new program, whose files keep their `FileId`s.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn fold_program<F: Folder>(folder: &mut F, program: &Program) -> Program {
+    let mut files = Vec::new();
+    for file in &program.files {
+        let mut into = File::new(file.file);
+        for id in file.top_level_ids() {
+            let node = folder.fold_top_level(program, &mut into, id);
+            into.top_levels.push(node);
+        }
+        into.link_parents();
+        files.push(into);
+    }
+    return Program { files };
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn rebuild_top_level<F: Folder>(folder: &mut F, program: &Program, into: &mut File, id: TopLevelId) -> TopLevelNode {
+    match &program[id] {
+        TopLevelNode::Import(data) => TopLevelNode::Import(ImportData {
+            loc: data.loc,
+            attributes: data.attributes.clone(),
+            path: folder.fold_name(program, into, data.path),
+            file: data.file
+        }),
+        TopLevelNode::Struct(data) => TopLevelNode::Struct(StructData {
+            loc: data.loc,
+            attributes: data.attributes.clone(),
+            name: data.name.clone(),
+            name_loc: data.name_loc,
+            generic_parameters: rebuild_generic_parameters(folder, program, into, &data.generic_parameters),
+            superstruct: data.superstruct.map(|name| folder.fold_name(program, into, name)),
+            interfaces: data.interfaces.iter().map(|name| folder.fold_name(program, into, *name)).collect(),
+            fields: data.fields.iter().map(|field| StructField {
+                loc: field.loc,
+                name: field.name.clone(),
+                typ: folder.fold_type(program, into, field.typ)
+            }).collect(),
+            methods: data.methods.iter().map(|method| folder.fold_function(program, into, method)).collect()
+        }),
+        TopLevelNode::Trait(data) => TopLevelNode::Trait(TraitData {
+            loc: data.loc,
+            attributes: data.attributes.clone(),
+            name: data.name.clone(),
+            name_loc: data.name_loc,
+            methods: data.methods.iter().map(|method| TraitMethod {
+                loc: method.loc,
+                name: method.name.clone(),
+                name_loc: method.name_loc,
+                parameters: rebuild_parameters(folder, program, into, &method.parameters),
+                ret: folder.fold_type(program, into, method.ret)
+            }).collect()
+        }),
+        TopLevelNode::Function(data) => TopLevelNode::Function(folder.fold_function(program, into, data)),
+        TopLevelNode::Const(data) => TopLevelNode::Const(ConstData {
+            loc: data.loc,
+            attributes: data.attributes.clone(),
+            name: data.name.clone(),
+            name_loc: data.name_loc,
+            typ: folder.fold_type(program, into, data.typ),
+            value: folder.fold_expr(program, into, data.value)
+        })
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn rebuild_generic_parameters<F: Folder>(folder: &mut F, program: &Program, into: &mut File, parameters: &[GenericParameter]) -> Vec<GenericParameter> {
+    parameters.iter().map(|parameter| GenericParameter {
+        loc: parameter.loc,
+        name: parameter.name.clone(),
+        bounds: parameter.bounds.iter().map(|bound| folder.fold_name(program, into, *bound)).collect()
+    }).collect()
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn rebuild_parameters<F: Folder>(folder: &mut F, program: &Program, into: &mut File, parameters: &[FunctionParameter]) -> Vec<FunctionParameter> {
+    parameters.iter().map(|parameter| FunctionParameter {
+        loc: parameter.loc,
+        name: parameter.name.clone(),
+        typ: folder.fold_type(program, into, parameter.typ)
+    }).collect()
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn rebuild_function<F: Folder>(folder: &mut F, program: &Program, into: &mut File, function: &FunctionData) -> FunctionData {
+    FunctionData {
+        loc: function.loc,
+        attributes: function.attributes.clone(),
+        name: function.name.clone(),
+        name_loc: function.name_loc,
+        generic_parameters: rebuild_generic_parameters(folder, program, into, &function.generic_parameters),
+        parameters: rebuild_parameters(folder, program, into, &function.parameters),
+        ret: folder.fold_type(program, into, function.ret),
+        body: folder.fold_block(program, into, &function.body)
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn rebuild_block<F: Folder>(folder: &mut F, program: &Program, into: &mut File, block: &BlockData) -> BlockData {
+    BlockData {
+        loc: block.loc,
+        stmts: block.stmts.iter().map(|stmt| folder.fold_stmt(program, into, *stmt)).collect()
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn rebuild_stmt<F: Folder>(folder: &mut F, program: &Program, into: &mut File, id: StmtId) -> StmtId {
+    let node = match &program[id] {
+        StmtNode::Expr(data) => StmtNode::Expr(StmtExprData {
+            loc: data.loc,
+            expr: folder.fold_expr(program, into, data.expr)
+        }),
+        StmtNode::Let(data) => StmtNode::Let(StmtLetData {
+            loc: data.loc,
+            name: data.name.clone(),
+            name_loc: data.name_loc,
+            typ: data.typ.map(|typ| folder.fold_type(program, into, typ)),
+            value: data.value.map(|value| folder.fold_expr(program, into, value))
+        }),
+        StmtNode::Return(data) => StmtNode::Return(StmtReturnData {
+            loc: data.loc,
+            expr: folder.fold_expr(program, into, data.expr)
+        }),
+        StmtNode::If(data) => StmtNode::If(StmtIfData {
+            loc: data.loc,
+            condition: folder.fold_expr(program, into, data.condition),
+            body: folder.fold_block(program, into, &data.body),
+            else_body: data.else_body.as_ref().map(|else_body| folder.fold_block(program, into, else_body))
+        }),
+        StmtNode::While(data) => StmtNode::While(StmtWhileData {
+            loc: data.loc,
+            condition: folder.fold_expr(program, into, data.condition),
+            body: folder.fold_block(program, into, &data.body)
+        }),
+        StmtNode::For(data) => StmtNode::For(StmtForData {
+            loc: data.loc,
+            name: data.name.clone(),
+            name_loc: data.name_loc,
+            iterable: match data.iterable {
+                ForIterable::Range(start, end) => {
+                    let start = folder.fold_expr(program, into, start);
+                    ForIterable::Range(start, folder.fold_expr(program, into, end))
+                },
+                ForIterable::Array(array) => ForIterable::Array(folder.fold_expr(program, into, array))
+            },
+            body: folder.fold_block(program, into, &data.body)
+        })
+    };
+    return into.alloc_stmt(node);
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn rebuild_expr<F: Folder>(folder: &mut F, program: &Program, into: &mut File, id: ExprId) -> ExprId {
+    let node = match &program[id] {
+        ExprNode::Name(data) => ExprNode::Name(NameData { loc: data.loc, name: folder.fold_name(program, into, data.name) }),
+        ExprNode::Integer(data) => ExprNode::Integer(IntegerData { loc: data.loc, integer: data.integer }),
+        ExprNode::Float(data) => ExprNode::Float(FloatData { loc: data.loc, float: data.float }),
+        ExprNode::Bool(data) => ExprNode::Bool(BoolData { loc: data.loc, value: data.value }),
+        ExprNode::Char(data) => ExprNode::Char(CharData { loc: data.loc, value: data.value }),
+        ExprNode::String(data) => ExprNode::String(StringData { loc: data.loc, value: data.value.clone() }),
+        ExprNode::Block(data) => ExprNode::Block(folder.fold_block(program, into, data)),
+        ExprNode::Call(data) => ExprNode::Call(CallData {
+            loc: data.loc,
+            callee: folder.fold_expr(program, into, data.callee),
+            arguments: data.arguments.iter().map(|argument| folder.fold_expr(program, into, *argument)).collect()
+        }),
+        ExprNode::Field(data) => ExprNode::Field(FieldData {
+            loc: data.loc,
+            object: folder.fold_expr(program, into, data.object),
+            field: data.field.clone(),
+            field_loc: data.field_loc
+        }),
+        ExprNode::Assign(data) => ExprNode::Assign(AssignData {
+            loc: data.loc,
+            op: data.op,
+            target: folder.fold_expr(program, into, data.target),
+            value: folder.fold_expr(program, into, data.value)
+        }),
+        ExprNode::StructLiteral(data) => ExprNode::StructLiteral(StructLiteralData {
+            loc: data.loc,
+            name: folder.fold_name(program, into, data.name),
+            fields: data.fields.iter().map(|field| StructLiteralField {
+                loc: field.loc,
+                name: field.name.clone(),
+                name_loc: field.name_loc,
+                value: folder.fold_expr(program, into, field.value)
+            }).collect()
+        }),
+        ExprNode::Unary(data) => ExprNode::Unary(UnaryData {
+            loc: data.loc,
+            op: data.op,
+            operand: folder.fold_expr(program, into, data.operand)
+        }),
+        ExprNode::Binary(data) => ExprNode::Binary(BinaryData {
+            loc: data.loc,
+            op: data.op,
+            left: folder.fold_expr(program, into, data.left),
+            right: folder.fold_expr(program, into, data.right)
+        }),
+        ExprNode::Cast(data) => ExprNode::Cast(CastData {
+            loc: data.loc,
+            expr: folder.fold_expr(program, into, data.expr),
+            typ: folder.fold_type(program, into, data.typ)
+        }),
+        ExprNode::Is(data) => ExprNode::Is(IsData {
+            loc: data.loc,
+            expr: folder.fold_expr(program, into, data.expr),
+            typ: folder.fold_type(program, into, data.typ)
+        }),
+        ExprNode::Lambda(data) => ExprNode::Lambda(LambdaData {
+            loc: data.loc,
+            parameters: data.parameters.iter().map(|parameter| LambdaParameter {
+                loc: parameter.loc,
+                name: parameter.name.clone(),
+                typ: parameter.typ.map(|typ| folder.fold_type(program, into, typ))
+            }).collect(),
+            body: folder.fold_expr(program, into, data.body)
+        }),
+        ExprNode::Array(data) => ExprNode::Array(ArrayData {
+            loc: data.loc,
+            elements: data.elements.iter().map(|element| folder.fold_expr(program, into, *element)).collect()
+        }),
+        ExprNode::Index(data) => ExprNode::Index(IndexData {
+            loc: data.loc,
+            object: folder.fold_expr(program, into, data.object),
+            index: folder.fold_expr(program, into, data.index)
+        })
+    };
+    return into.alloc_expr(node);
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn rebuild_type<F: Folder>(folder: &mut F, program: &Program, into: &mut File, id: TypeId) -> TypeId {
+    let node = match &program[id] {
+        TypeNode::Name(data) => TypeNode::Name(TypeNameData {
+            loc: data.loc,
+            name: folder.fold_name(program, into, data.name),
+            generic_arguments: data.generic_arguments.as_ref()
+                .map(|arguments| arguments.iter().map(|argument| folder.fold_type(program, into, *argument)).collect())
+        }),
+        TypeNode::Function(data) => TypeNode::Function(TypeFunctionData {
+            loc: data.loc,
+            arguments: data.arguments.iter().map(|argument| folder.fold_type(program, into, *argument)).collect(),
+            ret: folder.fold_type(program, into, data.ret)
+        }),
+        TypeNode::Reference(data) => TypeNode::Reference(TypeReferenceData {
+            loc: data.loc,
+            typ: folder.fold_type(program, into, data.typ)
+        }),
+        TypeNode::Array(data) => TypeNode::Array(TypeArrayData {
+            loc: data.loc,
+            element: folder.fold_type(program, into, data.element),
+            length: folder.fold_expr(program, into, data.length)
+        })
+    };
+    return into.alloc_type(node);
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn rebuild_name<F: Folder>(folder: &mut F, program: &Program, into: &mut File, id: NameId) -> NameId {
+    let node = match &program[id] {
+        QualifiedNameNode::Name(data) => QualifiedNameNode::Name(data.clone()),
+        QualifiedNameNode::Namespace(data) => QualifiedNameNode::Namespace(QualNamespaceData {
+            loc: data.loc,
+            source: folder.fold_name(program, into, data.source),
+            attr: data.attr.clone()
+        })
+    };
+    return into.alloc_name(node);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_text;
+    use super::*;
+
+    /// The return statement that ends the first function, and the value it returns.
+    fn returned(program: &Program) -> (StmtId, ExprId) {
+        let id = program.files[0].top_level_ids().next().unwrap();
+        let TopLevelNode::Function(data) = &program[id] else {
+            panic!("expected a function");
+        };
+        let stmt = *data.body.stmts.last().unwrap();
+        let StmtNode::Return(ret) = &program[stmt] else {
+            panic!("expected a return statement");
+        };
+        return (stmt, ret.expr);
+    }
+
+    struct Integers {
+        values: Vec<u64>
+    }
+
+    impl<'a> Visitor<'a> for Integers {
+        fn visit_expr(&mut self, program: &'a Program, id: ExprId) {
+            if let ExprNode::Integer(data) = &program[id] {
+                self.values.push(data.integer);
+            }
+            walk_expr(self, program, id);
+        }
+    }
+
+    fn integers(program: &Program) -> Vec<u64> {
+        let mut visitor = Integers { values: Vec::new() };
+        visitor.visit_program(program);
+        return visitor.values;
+    }
+
+    /// Doubles every integer and swaps the operands of every binary expression, in place.
+    struct Rewriter {
+        visited: Vec<u64>
+    }
+
+    impl VisitorMut for Rewriter {
+        fn visit_expr_mut(&mut self, program: &mut Program, id: ExprId) {
+            match &mut program[id] {
+                ExprNode::Integer(data) => {
+                    data.integer *= 2;
+                    self.visited.push(data.integer);
+                },
+                ExprNode::Binary(data) => std::mem::swap(&mut data.left, &mut data.right),
+                _ => { }
+            }
+            walk_expr_mut(self, program, id);
+        }
+    }
+
+    #[test]
+    fn visitor_mut_rewrites_nodes_in_place() {
+        let mut program = parse_text("fn main() -> int { let x = 1 + 2; return x * 3; }");
+        let mut rewriter = Rewriter { visited: Vec::new() };
+        walk_program_mut(&mut rewriter, &mut program);
+        // The walk finds the children after the swap, so it visits them in their new order.
+        assert_eq!(rewriter.visited, [4, 2, 6]);
+        assert_eq!(integers(&program), [4, 2, 6]);
+    }
+
+    /// Folds additions and multiplications of integer literals into a single literal.
+    struct ConstantFolder;
+
+    impl Folder for ConstantFolder {
+        fn fold_expr(&mut self, program: &Program, into: &mut File, id: ExprId) -> ExprId {
+            let folded = rebuild_expr(self, program, into, id);
+            let ExprNode::Binary(data) = &into[folded] else {
+                return folded;
+            };
+            let (ExprNode::Integer(left), ExprNode::Integer(right)) = (&into[data.left], &into[data.right]) else {
+                return folded;
+            };
+            let integer = match data.op {
+                BinaryOp::Add => left.integer + right.integer,
+                BinaryOp::Multiply => left.integer * right.integer,
+                _ => return folded
+            };
+            let loc = data.loc;
+            return into.alloc_expr(ExprNode::Integer(IntegerData { loc, integer }));
+        }
+    }
+
+    #[test]
+    fn folder_folds_an_expression() {
+        let program = parse_text("fn main() -> int { return 1 + 2 * 3; }");
+        let folded = fold_program(&mut ConstantFolder, &program);
+
+        let (stmt, value) = returned(&folded);
+        let ExprNode::Integer(data) = &folded[value] else {
+            panic!("expected the return value to be folded into an integer");
+        };
+        assert_eq!(data.integer, 7);
+        assert_eq!(data.loc, program.loc(returned(&program).1));
+        assert_eq!(folded.parent(value), Some(NodeId::Stmt(stmt)));
+        // The original program is left as it was.
+        assert_eq!(integers(&program), [1, 2, 3]);
+    }
+}